@@ -4082,6 +4082,1428 @@ def migration_107_fix_gpodder_episode_actions(conn, db_type: str):
         cursor.close()
 
 
+@register_migration("038", "add_episode_search_indexes", "Add full-text search vectors and indexes for episode search", requires=["005"])
+def migration_038_add_episode_search_indexes(conn, db_type: str):
+    """
+    Add full-text search support for search_data.
+    PostgreSQL gets a generated, weighted tsvector column on Episodes plus GIN indexes;
+    MySQL/MariaDB gets FULLTEXT indexes used with MATCH ... AGAINST in boolean mode.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting episode search index migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                SELECT column_name FROM information_schema.columns
+                WHERE table_name = 'Episodes' AND column_name = 'searchvector'
+            """)
+            if not cursor.fetchone():
+                # Titles are weighted above descriptions so ts_rank_cd favours title hits
+                cursor.execute("""
+                    ALTER TABLE "Episodes"
+                    ADD COLUMN searchvector tsvector
+                    GENERATED ALWAYS AS (
+                        setweight(to_tsvector('simple', COALESCE(episodetitle, '')), 'A') ||
+                        setweight(to_tsvector('simple', COALESCE(episodedescription, '')), 'B')
+                    ) STORED
+                """)
+                logger.info("Added searchvector column to Episodes table (PostgreSQL)")
+
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_episodes_searchvector ON "Episodes" USING GIN (searchvector)', 'idx_episodes_searchvector')
+            safe_add_index(cursor, db_type, """CREATE INDEX idx_podcasts_name_search ON "Podcasts" USING GIN (to_tsvector('simple', COALESCE(podcastname, '')))""", 'idx_podcasts_name_search')
+        else:
+            safe_add_index(cursor, db_type, 'CREATE FULLTEXT INDEX idx_episodes_fulltext ON Episodes(EpisodeTitle, EpisodeDescription)', 'idx_episodes_fulltext')
+            safe_add_index(cursor, db_type, 'CREATE FULLTEXT INDEX idx_podcasts_name_fulltext ON Podcasts(PodcastName)', 'idx_podcasts_name_fulltext')
+
+        conn.commit()
+        logger.info("Episode search index migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 038: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("039", "create_episode_transcript_tables", "Store parsed episode transcripts and index their segments for search", requires=["038"])
+def migration_039_create_episode_transcript_tables(conn, db_type: str):
+    """
+    Create EpisodeTranscripts (one row per fetched transcript, including failed fetches so
+    they are not retried every refresh) and TranscriptSegments (timestamped text chunks).
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting episode transcript tables migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "EpisodeTranscripts" (
+                    TranscriptID SERIAL PRIMARY KEY,
+                    EpisodeID INT NOT NULL UNIQUE,
+                    TranscriptURL TEXT NOT NULL,
+                    MimeType VARCHAR(100),
+                    Language VARCHAR(20),
+                    SegmentCount INT DEFAULT 0,
+                    FetchError TEXT,
+                    FetchedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (EpisodeID) REFERENCES "Episodes"(EpisodeID) ON DELETE CASCADE
+                )
+            """)
+
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "TranscriptSegments" (
+                    SegmentID SERIAL PRIMARY KEY,
+                    TranscriptID INT NOT NULL,
+                    EpisodeID INT NOT NULL,
+                    StartTime DOUBLE PRECISION NOT NULL,
+                    EndTime DOUBLE PRECISION,
+                    Speaker VARCHAR(255),
+                    SegmentText TEXT NOT NULL,
+                    searchvector tsvector GENERATED ALWAYS AS (to_tsvector('simple', COALESCE(SegmentText, ''))) STORED,
+                    FOREIGN KEY (TranscriptID) REFERENCES "EpisodeTranscripts"(TranscriptID) ON DELETE CASCADE,
+                    FOREIGN KEY (EpisodeID) REFERENCES "Episodes"(EpisodeID) ON DELETE CASCADE
+                )
+            """)
+
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_transcriptsegments_episodeid ON "TranscriptSegments"(EpisodeID, StartTime)', 'idx_transcriptsegments_episodeid')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_transcriptsegments_searchvector ON "TranscriptSegments" USING GIN (searchvector)', 'idx_transcriptsegments_searchvector')
+        else:
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS EpisodeTranscripts (
+                    TranscriptID INT AUTO_INCREMENT PRIMARY KEY,
+                    EpisodeID INT NOT NULL UNIQUE,
+                    TranscriptURL TEXT NOT NULL,
+                    MimeType VARCHAR(100),
+                    Language VARCHAR(20),
+                    SegmentCount INT DEFAULT 0,
+                    FetchError TEXT,
+                    FetchedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (EpisodeID) REFERENCES Episodes(EpisodeID) ON DELETE CASCADE
+                )
+            """)
+
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS TranscriptSegments (
+                    SegmentID INT AUTO_INCREMENT PRIMARY KEY,
+                    TranscriptID INT NOT NULL,
+                    EpisodeID INT NOT NULL,
+                    StartTime DOUBLE NOT NULL,
+                    EndTime DOUBLE,
+                    Speaker VARCHAR(255),
+                    SegmentText TEXT NOT NULL,
+                    FOREIGN KEY (TranscriptID) REFERENCES EpisodeTranscripts(TranscriptID) ON DELETE CASCADE,
+                    FOREIGN KEY (EpisodeID) REFERENCES Episodes(EpisodeID) ON DELETE CASCADE
+                )
+            """)
+
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_transcriptsegments_episodeid ON TranscriptSegments(EpisodeID, StartTime)', 'idx_transcriptsegments_episodeid')
+            safe_add_index(cursor, db_type, 'CREATE FULLTEXT INDEX idx_transcriptsegments_fulltext ON TranscriptSegments(SegmentText)', 'idx_transcriptsegments_fulltext')
+
+        conn.commit()
+        logger.info("Episode transcript tables migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 039: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("040", "add_api_key_scopes", "Add label, scopes, expiry and last-used tracking to APIKeys", requires=["001"])
+def migration_040_add_api_key_scopes(conn, db_type: str):
+    """
+    Add per-key metadata to APIKeys. A NULL Scopes value means the key has every scope,
+    which keeps existing keys (and the ones handed out at login) working unchanged.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting API key scopes migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "APIKeys"
+                ADD COLUMN IF NOT EXISTS Label VARCHAR(255),
+                ADD COLUMN IF NOT EXISTS Scopes VARCHAR(255),
+                ADD COLUMN IF NOT EXISTS ExpiresAt TIMESTAMP,
+                ADD COLUMN IF NOT EXISTS LastUsed TIMESTAMP
+            """)
+        else:
+            new_columns = [
+                ("Label", "VARCHAR(255)"),
+                ("Scopes", "VARCHAR(255)"),
+                ("ExpiresAt", "TIMESTAMP NULL DEFAULT NULL"),
+                ("LastUsed", "TIMESTAMP NULL DEFAULT NULL"),
+            ]
+            for column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = 'APIKeys'
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (column_name,))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE APIKeys ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to APIKeys table (MySQL)")
+
+        conn.commit()
+        logger.info("API key scopes migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 040: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("041", "add_rate_limit_settings", "Add admin-configurable request rate limits to AppSettings", requires=["001"])
+def migration_041_add_rate_limit_settings(conn, db_type: str):
+    """
+    Add per-minute request budgets used by the API rate limiter: login endpoints,
+    expensive endpoints (refresh, search, OPML import) and everything else. Limiting
+    starts on; the budgets are loose enough that normal clients never reach them.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting rate limit settings migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "AppSettings"
+                ADD COLUMN IF NOT EXISTS RateLimitEnabled BOOLEAN DEFAULT TRUE,
+                ADD COLUMN IF NOT EXISTS RateLimitLogin INT DEFAULT 10,
+                ADD COLUMN IF NOT EXISTS RateLimitExpensive INT DEFAULT 30,
+                ADD COLUMN IF NOT EXISTS RateLimitDefault INT DEFAULT 600
+            """)
+        else:
+            new_columns = [
+                ("RateLimitEnabled", "TINYINT(1) DEFAULT 1"),
+                ("RateLimitLogin", "INT DEFAULT 10"),
+                ("RateLimitExpensive", "INT DEFAULT 30"),
+                ("RateLimitDefault", "INT DEFAULT 600"),
+            ]
+            for column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = 'AppSettings'
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (column_name,))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE AppSettings ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to AppSettings table (MySQL)")
+
+        conn.commit()
+        logger.info("Rate limit settings migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 041: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("042", "create_feed_fetch_state_table", "Track per-feed cache validators, content hashes and fetch timings", requires=["001"])
+def migration_042_create_feed_fetch_state_table(conn, db_type: str):
+    """
+    One row per podcast recording the ETag/Last-Modified validators and content hash
+    from the last processed fetch, so refreshes can send conditional requests and
+    skip parsing unchanged feeds, plus timings of the most recent fetches.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting feed fetch state migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "FeedFetchState" (
+                    PodcastID INT PRIMARY KEY,
+                    ETag VARCHAR(512),
+                    LastModified VARCHAR(128),
+                    ContentHash VARCHAR(64),
+                    LastFetchedAt TIMESTAMP,
+                    LastChangedAt TIMESTAMP,
+                    LastStatus INT,
+                    LastFetchMs INT,
+                    AvgFetchMs INT,
+                    LastBytes BIGINT,
+                    FetchCount INT DEFAULT 0,
+                    SkippedCount INT DEFAULT 0,
+                    LastError TEXT,
+                    FOREIGN KEY (PodcastID) REFERENCES "Podcasts"(PodcastID) ON DELETE CASCADE
+                )
+            """)
+        else:
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS FeedFetchState (
+                    PodcastID INT PRIMARY KEY,
+                    ETag VARCHAR(512),
+                    LastModified VARCHAR(128),
+                    ContentHash VARCHAR(64),
+                    LastFetchedAt TIMESTAMP NULL DEFAULT NULL,
+                    LastChangedAt TIMESTAMP NULL DEFAULT NULL,
+                    LastStatus INT,
+                    LastFetchMs INT,
+                    AvgFetchMs INT,
+                    LastBytes BIGINT,
+                    FetchCount INT DEFAULT 0,
+                    SkippedCount INT DEFAULT 0,
+                    LastError TEXT,
+                    FOREIGN KEY (PodcastID) REFERENCES Podcasts(PodcastID) ON DELETE CASCADE
+                )
+            """)
+
+        conn.commit()
+        logger.info("Feed fetch state migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 042: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("043", "add_adaptive_refresh_schedule", "Track per-feed refresh schedule, update hints and failure backoff", requires=["042"])
+def migration_043_add_adaptive_refresh_schedule(conn, db_type: str):
+    """
+    Add scheduling state to FeedFetchState (next refresh time, computed interval,
+    consecutive failures and the feed's own update hints) and a per-podcast
+    RefreshIntervalOverride so users can pin how often a feed is checked.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting adaptive refresh schedule migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "FeedFetchState"
+                ADD COLUMN IF NOT EXISTS NextRefreshAt TIMESTAMP,
+                ADD COLUMN IF NOT EXISTS RefreshIntervalMinutes INT,
+                ADD COLUMN IF NOT EXISTS ConsecutiveFailures INT DEFAULT 0,
+                ADD COLUMN IF NOT EXISTS TtlMinutes INT,
+                ADD COLUMN IF NOT EXISTS UpdateHintMinutes INT,
+                ADD COLUMN IF NOT EXISTS FeedComplete BOOLEAN DEFAULT FALSE
+            """)
+            cursor.execute("""
+                ALTER TABLE "Podcasts"
+                ADD COLUMN IF NOT EXISTS RefreshIntervalOverride INT
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_feedfetchstate_nextrefreshat ON "FeedFetchState"(NextRefreshAt)', 'idx_feedfetchstate_nextrefreshat')
+        else:
+            new_columns = [
+                ("FeedFetchState", "NextRefreshAt", "TIMESTAMP NULL DEFAULT NULL"),
+                ("FeedFetchState", "RefreshIntervalMinutes", "INT"),
+                ("FeedFetchState", "ConsecutiveFailures", "INT DEFAULT 0"),
+                ("FeedFetchState", "TtlMinutes", "INT"),
+                ("FeedFetchState", "UpdateHintMinutes", "INT"),
+                ("FeedFetchState", "FeedComplete", "TINYINT(1) DEFAULT 0"),
+                ("Podcasts", "RefreshIntervalOverride", "INT"),
+            ]
+            for table_name, column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = %s
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (table_name, column_name))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to {table_name} table (MySQL)")
+
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_feedfetchstate_nextrefreshat ON FeedFetchState(NextRefreshAt)', 'idx_feedfetchstate_nextrefreshat')
+
+        conn.commit()
+        logger.info("Adaptive refresh schedule migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 043: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("044", "create_websub_subscriptions_table", "Track WebSub hub subscriptions for push-based feed updates", requires=["001"])
+def migration_044_create_websub_subscriptions_table(conn, db_type: str):
+    """
+    One row per podcast whose feed advertises a WebSub hub. Refreshes record the
+    hub and topic; a background job subscribes and renews leases, and the callback
+    endpoint uses the per-subscription token and secret to verify the hub.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting WebSub subscriptions migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "WebSubSubscriptions" (
+                    PodcastID INT PRIMARY KEY,
+                    HubUrl TEXT NOT NULL,
+                    TopicUrl TEXT NOT NULL,
+                    CallbackToken VARCHAR(64) NOT NULL UNIQUE,
+                    Secret VARCHAR(64) NOT NULL,
+                    State VARCHAR(20) NOT NULL DEFAULT 'discovered',
+                    LeaseSeconds INT,
+                    LeaseExpiresAt TIMESTAMP,
+                    RequestedAt TIMESTAMP,
+                    VerifiedAt TIMESTAMP,
+                    LastNotifiedAt TIMESTAMP,
+                    NotificationCount INT DEFAULT 0,
+                    LastError TEXT,
+                    FOREIGN KEY (PodcastID) REFERENCES "Podcasts"(PodcastID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_websubsubscriptions_leaseexpiresat ON "WebSubSubscriptions"(LeaseExpiresAt)', 'idx_websubsubscriptions_leaseexpiresat')
+        else:
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS WebSubSubscriptions (
+                    PodcastID INT PRIMARY KEY,
+                    HubUrl TEXT NOT NULL,
+                    TopicUrl TEXT NOT NULL,
+                    CallbackToken VARCHAR(64) NOT NULL UNIQUE,
+                    Secret VARCHAR(64) NOT NULL,
+                    State VARCHAR(20) NOT NULL DEFAULT 'discovered',
+                    LeaseSeconds INT,
+                    LeaseExpiresAt TIMESTAMP NULL DEFAULT NULL,
+                    RequestedAt TIMESTAMP NULL DEFAULT NULL,
+                    VerifiedAt TIMESTAMP NULL DEFAULT NULL,
+                    LastNotifiedAt TIMESTAMP NULL DEFAULT NULL,
+                    NotificationCount INT DEFAULT 0,
+                    LastError TEXT,
+                    FOREIGN KEY (PodcastID) REFERENCES Podcasts(PodcastID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_websubsubscriptions_leaseexpiresat ON WebSubSubscriptions(LeaseExpiresAt)', 'idx_websubsubscriptions_leaseexpiresat')
+
+        conn.commit()
+        logger.info("WebSub subscriptions migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 044: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("045", "add_feed_health_tracking", "Track feed failures, last success and feed moves for health reporting", requires=["043"])
+def migration_045_add_feed_health_tracking(conn, db_type: str):
+    """
+    Extend FeedFetchState with what users need to spot broken subscriptions: when the
+    feed last refreshed successfully or failed, what kind of error it was, and where
+    the feed moved to (permanent redirects and <itunes:new-feed-url>).
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting feed health tracking migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "FeedFetchState"
+                ADD COLUMN IF NOT EXISTS LastSuccessAt TIMESTAMP,
+                ADD COLUMN IF NOT EXISTS LastFailureAt TIMESTAMP,
+                ADD COLUMN IF NOT EXISTS LastErrorKind VARCHAR(20),
+                ADD COLUMN IF NOT EXISTS MovedToUrl TEXT,
+                ADD COLUMN IF NOT EXISTS PreviousFeedUrl TEXT,
+                ADD COLUMN IF NOT EXISTS FeedUrlChangedAt TIMESTAMP
+            """)
+        else:
+            new_columns = [
+                ("LastSuccessAt", "TIMESTAMP NULL DEFAULT NULL"),
+                ("LastFailureAt", "TIMESTAMP NULL DEFAULT NULL"),
+                ("LastErrorKind", "VARCHAR(20)"),
+                ("MovedToUrl", "TEXT"),
+                ("PreviousFeedUrl", "TEXT"),
+                ("FeedUrlChangedAt", "TIMESTAMP NULL DEFAULT NULL"),
+            ]
+            for column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = 'FeedFetchState'
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (column_name,))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE FeedFetchState ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to FeedFetchState table (MySQL)")
+
+        conn.commit()
+        logger.info("Feed health tracking migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 045: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("046", "add_episode_guids", "Store feed GUIDs on episodes for deduplication and update detection", requires=["045"])
+def migration_046_add_episode_guids(conn, db_type: str):
+    """
+    Add Episodes.EpisodeGuid so refreshes match episodes on the feed's <guid> before
+    falling back to enclosure URL and title, and FeedFetchState.EpisodesDedupedAt to
+    record when a podcast's existing episodes were last checked for duplicates.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting episode GUID migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "Episodes"
+                ADD COLUMN IF NOT EXISTS EpisodeGuid VARCHAR(512)
+            """)
+            cursor.execute("""
+                ALTER TABLE "FeedFetchState"
+                ADD COLUMN IF NOT EXISTS EpisodesDedupedAt TIMESTAMP
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_episodes_podcastid_guid ON "Episodes"(PodcastID, EpisodeGuid)', 'idx_episodes_podcastid_guid')
+        else:
+            new_columns = [
+                ("Episodes", "EpisodeGuid", "VARCHAR(512)"),
+                ("FeedFetchState", "EpisodesDedupedAt", "TIMESTAMP NULL DEFAULT NULL"),
+            ]
+            for table_name, column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = %s
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (table_name, column_name))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to {table_name} table (MySQL)")
+
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_episodes_podcastid_guid ON Episodes(PodcastID, EpisodeGuid)', 'idx_episodes_podcastid_guid')
+
+        conn.commit()
+        logger.info("Episode GUID migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 046: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("047", "add_download_quotas", "Add per-user download quotas", requires=["046"])
+def migration_047_add_download_quotas(conn, db_type: str):
+    """
+    Add Users.DownloadQuotaBytes. NULL falls back to the server's DEFAULT_DOWNLOAD_QUOTA_MB,
+    0 means unlimited.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting download quota migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "Users"
+                ADD COLUMN IF NOT EXISTS DownloadQuotaBytes BIGINT
+            """)
+        else:
+            cursor.execute("""
+                SELECT COUNT(*)
+                FROM information_schema.columns
+                WHERE table_name = 'Users'
+                AND column_name = 'DownloadQuotaBytes'
+                AND table_schema = DATABASE()
+            """)
+            if cursor.fetchone()[0] == 0:
+                cursor.execute("ALTER TABLE Users ADD COLUMN DownloadQuotaBytes BIGINT")
+                logger.info("Added DownloadQuotaBytes column to Users table (MySQL)")
+
+        conn.commit()
+        logger.info("Download quota migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 047: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("048", "add_download_templates", "Add user and server download filename templates", requires=["047"])
+def migration_048_add_download_templates(conn, db_type: str):
+    """
+    Add Users.DownloadTemplate and AppSettings.DownloadTemplate. A user's own template
+    wins over the server default; with neither set the original layout is used.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting download template migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "Users"
+                ADD COLUMN IF NOT EXISTS DownloadTemplate VARCHAR(255)
+            """)
+            cursor.execute("""
+                ALTER TABLE "AppSettings"
+                ADD COLUMN IF NOT EXISTS DownloadTemplate VARCHAR(255)
+            """)
+        else:
+            new_columns = [
+                ("Users", "DownloadTemplate", "VARCHAR(255)"),
+                ("AppSettings", "DownloadTemplate", "VARCHAR(255)"),
+            ]
+            for table_name, column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = %s
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (table_name, column_name))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to {table_name} table (MySQL)")
+
+        conn.commit()
+        logger.info("Download template migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 048: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("049", "add_download_retention", "Add per-podcast download retention rules", requires=["048"])
+def migration_049_add_download_retention(conn, db_type: str):
+    """
+    Add retention rules to Podcasts: keep the newest N downloads, delete after
+    completion, delete N days after download, and whether saved episodes are exempt.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting download retention migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "Podcasts"
+                ADD COLUMN IF NOT EXISTS RetainNewest INT,
+                ADD COLUMN IF NOT EXISTS DeleteAfterCompletion BOOLEAN DEFAULT FALSE,
+                ADD COLUMN IF NOT EXISTS DeleteAfterDays INT,
+                ADD COLUMN IF NOT EXISTS KeepSavedDownloads BOOLEAN DEFAULT TRUE
+            """)
+        else:
+            new_columns = [
+                ("Podcasts", "RetainNewest", "INT"),
+                ("Podcasts", "DeleteAfterCompletion", "TINYINT(1) DEFAULT 0"),
+                ("Podcasts", "DeleteAfterDays", "INT"),
+                ("Podcasts", "KeepSavedDownloads", "TINYINT(1) DEFAULT 1"),
+            ]
+            for table_name, column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = %s
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (table_name, column_name))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to {table_name} table (MySQL)")
+
+        conn.commit()
+        logger.info("Download retention migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 049: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("050", "add_download_queue", "Add the persistent episode download queue", requires=["049"])
+def migration_050_add_download_queue(conn, db_type: str):
+    """
+    One row per episode waiting to be downloaded (or being downloaded, paused, or
+    failed). Rows survive restarts so workers can pick up where they left off, and
+    track attempts and the next retry time for backoff. The storage key is fixed on
+    the first attempt so a partial file can be resumed.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting download queue migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "DownloadQueue" (
+                    QueueID SERIAL PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    EpisodeID INT NOT NULL,
+                    Status VARCHAR(20) NOT NULL DEFAULT 'queued',
+                    Attempts INT NOT NULL DEFAULT 0,
+                    NextAttemptAt TIMESTAMP,
+                    LastError TEXT,
+                    StorageKey TEXT,
+                    BytesDownloaded BIGINT NOT NULL DEFAULT 0,
+                    TotalBytes BIGINT,
+                    TaskID VARCHAR(64),
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    UpdatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (UserID, EpisodeID),
+                    FOREIGN KEY (UserID) REFERENCES "Users"(UserID) ON DELETE CASCADE,
+                    FOREIGN KEY (EpisodeID) REFERENCES "Episodes"(EpisodeID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_downloadqueue_status ON "DownloadQueue"(Status, NextAttemptAt)', 'idx_downloadqueue_status')
+        else:
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS DownloadQueue (
+                    QueueID INT AUTO_INCREMENT PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    EpisodeID INT NOT NULL,
+                    Status VARCHAR(20) NOT NULL DEFAULT 'queued',
+                    Attempts INT NOT NULL DEFAULT 0,
+                    NextAttemptAt TIMESTAMP NULL DEFAULT NULL,
+                    LastError TEXT,
+                    StorageKey TEXT,
+                    BytesDownloaded BIGINT NOT NULL DEFAULT 0,
+                    TotalBytes BIGINT,
+                    TaskID VARCHAR(64),
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    UpdatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (UserID, EpisodeID),
+                    FOREIGN KEY (UserID) REFERENCES Users(UserID) ON DELETE CASCADE,
+                    FOREIGN KEY (EpisodeID) REFERENCES Episodes(EpisodeID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_downloadqueue_status ON DownloadQueue(Status, NextAttemptAt)', 'idx_downloadqueue_status')
+
+        conn.commit()
+        logger.info("Download queue migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 050: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("051", "add_audio_processing", "Add per-podcast audio post-processing settings", requires=["050"])
+def migration_051_add_audio_processing(conn, db_type: str):
+    """
+    Add post-processing settings to Podcasts, applied with ffmpeg after each download:
+    loudness normalization, silence trimming, transcoding to Opus or AAC at a chosen
+    bitrate, and embedding chapters.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting audio processing migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "Podcasts"
+                ADD COLUMN IF NOT EXISTS ProcessNormalize BOOLEAN DEFAULT FALSE,
+                ADD COLUMN IF NOT EXISTS ProcessTrimSilence BOOLEAN DEFAULT FALSE,
+                ADD COLUMN IF NOT EXISTS ProcessTranscode VARCHAR(10),
+                ADD COLUMN IF NOT EXISTS ProcessBitrate INT,
+                ADD COLUMN IF NOT EXISTS ProcessEmbedChapters BOOLEAN DEFAULT FALSE
+            """)
+        else:
+            new_columns = [
+                ("Podcasts", "ProcessNormalize", "TINYINT(1) DEFAULT 0"),
+                ("Podcasts", "ProcessTrimSilence", "TINYINT(1) DEFAULT 0"),
+                ("Podcasts", "ProcessTranscode", "VARCHAR(10)"),
+                ("Podcasts", "ProcessBitrate", "INT"),
+                ("Podcasts", "ProcessEmbedChapters", "TINYINT(1) DEFAULT 0"),
+            ]
+            for table_name, column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = %s
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (table_name, column_name))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to {table_name} table (MySQL)")
+
+        conn.commit()
+        logger.info("Audio processing migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 051: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("052", "add_download_chapters", "Store chapters read from downloaded episode files", requires=["051"])
+def migration_052_add_download_chapters(conn, db_type: str):
+    """
+    Add a Chapters column to DownloadedEpisodes holding the chapters found embedded in a
+    downloaded file (as Podcasting 2.0 chapters JSON), for feeds that publish none.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting download chapters migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "DownloadedEpisodes"
+                ADD COLUMN IF NOT EXISTS Chapters TEXT
+            """)
+        else:
+            cursor.execute("""
+                SELECT COUNT(*)
+                FROM information_schema.columns
+                WHERE table_name = 'DownloadedEpisodes'
+                AND column_name = 'Chapters'
+                AND table_schema = DATABASE()
+            """)
+            if cursor.fetchone()[0] == 0:
+                cursor.execute("ALTER TABLE DownloadedEpisodes ADD COLUMN Chapters TEXT")
+                logger.info("Added Chapters column to DownloadedEpisodes table (MySQL)")
+
+        conn.commit()
+        logger.info("Download chapters migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 052: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("053", "add_notification_destinations", "Add notification destinations and a delivery log", requires=["052"])
+def migration_053_add_notification_destinations(conn, db_type: str):
+    """
+    Create NotificationDestinations (any number of channels per user, each with a
+    provider, its settings as JSON, and optional title/body templates) and
+    NotificationDeliveries (one row per message sent to a destination, with attempts
+    and the next retry time). Existing ntfy/gotify/http settings are copied over as
+    destinations marked with the platform they came from, so the per-platform
+    settings endpoints keep working.
+    """
+    import json
+
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting notification destinations migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "NotificationDestinations" (
+                    DestinationID SERIAL PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    Name VARCHAR(255) NOT NULL,
+                    Provider VARCHAR(20) NOT NULL,
+                    Config TEXT NOT NULL,
+                    TitleTemplate TEXT,
+                    BodyTemplate TEXT,
+                    Enabled BOOLEAN NOT NULL DEFAULT TRUE,
+                    LegacyPlatform VARCHAR(20),
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (UserID, LegacyPlatform),
+                    FOREIGN KEY (UserID) REFERENCES "Users"(UserID) ON DELETE CASCADE
+                )
+            """)
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "NotificationDeliveries" (
+                    DeliveryID SERIAL PRIMARY KEY,
+                    DestinationID INT NOT NULL,
+                    UserID INT NOT NULL,
+                    EventType VARCHAR(50) NOT NULL,
+                    Title TEXT NOT NULL,
+                    Body TEXT NOT NULL,
+                    Status VARCHAR(20) NOT NULL DEFAULT 'pending',
+                    Attempts INT NOT NULL DEFAULT 0,
+                    LastError TEXT,
+                    NextAttemptAt TIMESTAMP,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    DeliveredAt TIMESTAMP,
+                    FOREIGN KEY (DestinationID) REFERENCES "NotificationDestinations"(DestinationID) ON DELETE CASCADE,
+                    FOREIGN KEY (UserID) REFERENCES "Users"(UserID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_notificationdeliveries_status ON "NotificationDeliveries"(Status, NextAttemptAt)', 'idx_notificationdeliveries_status')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_notificationdeliveries_user ON "NotificationDeliveries"(UserID, CreatedAt)', 'idx_notificationdeliveries_user')
+
+            cursor.execute("""
+                SELECT userid, platform, enabled, ntfytopic, ntfyserverurl, ntfyusername, ntfypassword,
+                       ntfyaccesstoken, gotifyurl, gotifytoken, httpurl, httptoken, httpmethod
+                FROM "UserNotificationSettings"
+            """)
+        else:
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS NotificationDestinations (
+                    DestinationID INT AUTO_INCREMENT PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    Name VARCHAR(255) NOT NULL,
+                    Provider VARCHAR(20) NOT NULL,
+                    Config TEXT NOT NULL,
+                    TitleTemplate TEXT,
+                    BodyTemplate TEXT,
+                    Enabled TINYINT(1) NOT NULL DEFAULT 1,
+                    LegacyPlatform VARCHAR(20),
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    UNIQUE (UserID, LegacyPlatform),
+                    FOREIGN KEY (UserID) REFERENCES Users(UserID) ON DELETE CASCADE
+                )
+            """)
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS NotificationDeliveries (
+                    DeliveryID INT AUTO_INCREMENT PRIMARY KEY,
+                    DestinationID INT NOT NULL,
+                    UserID INT NOT NULL,
+                    EventType VARCHAR(50) NOT NULL,
+                    Title TEXT NOT NULL,
+                    Body TEXT NOT NULL,
+                    Status VARCHAR(20) NOT NULL DEFAULT 'pending',
+                    Attempts INT NOT NULL DEFAULT 0,
+                    LastError TEXT,
+                    NextAttemptAt TIMESTAMP NULL DEFAULT NULL,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    DeliveredAt TIMESTAMP NULL DEFAULT NULL,
+                    FOREIGN KEY (DestinationID) REFERENCES NotificationDestinations(DestinationID) ON DELETE CASCADE,
+                    FOREIGN KEY (UserID) REFERENCES Users(UserID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_notificationdeliveries_status ON NotificationDeliveries(Status, NextAttemptAt)', 'idx_notificationdeliveries_status')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_notificationdeliveries_user ON NotificationDeliveries(UserID, CreatedAt)', 'idx_notificationdeliveries_user')
+
+            cursor.execute("""
+                SELECT UserID, Platform, Enabled, NtfyTopic, NtfyServerURL, NtfyUsername, NtfyPassword,
+                       NtfyAccessToken, GotifyURL, GotifyToken, HttpUrl, HttpToken, HttpMethod
+                FROM UserNotificationSettings
+            """)
+
+        # Same JSON shape the API's ProviderConfig serializes to
+        copied = 0
+        for (user_id, platform, enabled, ntfy_topic, ntfy_server_url, ntfy_username, ntfy_password,
+             ntfy_access_token, gotify_url, gotify_token, http_url, http_token, http_method) in cursor.fetchall():
+            if platform == "ntfy" and ntfy_topic:
+                config = {"provider": "ntfy", "server_url": ntfy_server_url or "https://ntfy.sh", "topic": ntfy_topic,
+                          "username": ntfy_username, "password": ntfy_password, "access_token": ntfy_access_token}
+            elif platform == "gotify" and gotify_url and gotify_token:
+                config = {"provider": "gotify", "url": gotify_url, "token": gotify_token}
+            elif platform == "http" and http_url:
+                config = {"provider": "http", "url": http_url, "token": http_token, "method": http_method or "POST"}
+            else:
+                continue
+
+            if db_type == "postgresql":
+                cursor.execute("""
+                    INSERT INTO "NotificationDestinations" (UserID, Name, Provider, Config, Enabled, LegacyPlatform)
+                    VALUES (%s, %s, %s, %s, %s, %s)
+                    ON CONFLICT (UserID, LegacyPlatform) DO NOTHING
+                """, (user_id, platform, platform, json.dumps(config), bool(enabled), platform))
+            else:
+                cursor.execute("""
+                    INSERT IGNORE INTO NotificationDestinations (UserID, Name, Provider, Config, Enabled, LegacyPlatform)
+                    VALUES (%s, %s, %s, %s, %s, %s)
+                """, (user_id, platform, platform, json.dumps(config), bool(enabled), platform))
+            copied += cursor.rowcount
+
+        conn.commit()
+        logger.info(f"Notification destinations migration completed successfully ({copied} existing settings copied)")
+
+    except Exception as e:
+        logger.error(f"Error in migration 053: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("054", "add_notification_events", "Let notification destinations subscribe to more events", requires=["053"])
+def migration_054_add_notification_events(conn, db_type: str):
+    """
+    Add Events to NotificationDestinations, the comma-separated events a destination
+    receives (NULL keeps the original new-episode-only behaviour). Add FailingSince
+    (start of the current run of failed refreshes) and BrokenNotifiedAt to
+    FeedFetchState so a broken feed is reported once per outage.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting notification events migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "NotificationDestinations"
+                ADD COLUMN IF NOT EXISTS Events TEXT
+            """)
+            cursor.execute("""
+                ALTER TABLE "FeedFetchState"
+                ADD COLUMN IF NOT EXISTS FailingSince TIMESTAMP,
+                ADD COLUMN IF NOT EXISTS BrokenNotifiedAt TIMESTAMP
+            """)
+        else:
+            new_columns = [
+                ("NotificationDestinations", "Events", "TEXT"),
+                ("FeedFetchState", "FailingSince", "TIMESTAMP NULL DEFAULT NULL"),
+                ("FeedFetchState", "BrokenNotifiedAt", "TIMESTAMP NULL DEFAULT NULL"),
+            ]
+            for table_name, column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = %s
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (table_name, column_name))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to {table_name} table (MySQL)")
+
+        conn.commit()
+        logger.info("Notification events migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 054: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("055", "add_email_digest_settings", "Add per-user email digest settings", requires=["054"])
+def migration_055_add_email_digest_settings(conn, db_type: str):
+    """
+    Create EmailDigestSettings: whether a user gets the digest email, daily or weekly,
+    the weekday and hour (in the user's timezone) it goes out, which podcasts it covers
+    (JSON array of podcast IDs, NULL for all), which sections it includes, and when it
+    was last sent.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting email digest settings migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "EmailDigestSettings" (
+                    UserID INT PRIMARY KEY,
+                    Enabled BOOLEAN NOT NULL DEFAULT FALSE,
+                    Frequency VARCHAR(10) NOT NULL DEFAULT 'weekly',
+                    Weekday INT NOT NULL DEFAULT 0,
+                    SendHour INT NOT NULL DEFAULT 8,
+                    PodcastIDs TEXT,
+                    IncludeInProgress BOOLEAN NOT NULL DEFAULT TRUE,
+                    IncludeQueue BOOLEAN NOT NULL DEFAULT TRUE,
+                    LastSentAt TIMESTAMP,
+                    FOREIGN KEY (UserID) REFERENCES "Users"(UserID) ON DELETE CASCADE
+                )
+            """)
+        else:
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS EmailDigestSettings (
+                    UserID INT PRIMARY KEY,
+                    Enabled TINYINT(1) NOT NULL DEFAULT 0,
+                    Frequency VARCHAR(10) NOT NULL DEFAULT 'weekly',
+                    Weekday INT NOT NULL DEFAULT 0,
+                    SendHour INT NOT NULL DEFAULT 8,
+                    PodcastIDs TEXT,
+                    IncludeInProgress TINYINT(1) NOT NULL DEFAULT 1,
+                    IncludeQueue TINYINT(1) NOT NULL DEFAULT 1,
+                    LastSentAt TIMESTAMP NULL DEFAULT NULL,
+                    FOREIGN KEY (UserID) REFERENCES Users(UserID) ON DELETE CASCADE
+                )
+            """)
+
+        conn.commit()
+        logger.info("Email digest settings migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 055: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("056", "add_webhooks", "Add outgoing webhooks and their delivery history", requires=["055"])
+def migration_056_add_webhooks(conn, db_type: str):
+    """
+    Create Webhooks (an endpoint URL, the secret its payloads are signed with, the
+    comma-separated events it receives, and whether it is global, i.e. an admin
+    webhook receiving every user's events) and WebhookDeliveries (one row per event
+    posted to a webhook, with the payload, attempts, the last response status and the
+    next retry time).
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting webhooks migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "Webhooks" (
+                    WebhookID SERIAL PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    Name VARCHAR(255) NOT NULL,
+                    URL TEXT NOT NULL,
+                    Secret VARCHAR(255) NOT NULL,
+                    Events TEXT NOT NULL,
+                    IsGlobal BOOLEAN NOT NULL DEFAULT FALSE,
+                    Enabled BOOLEAN NOT NULL DEFAULT TRUE,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (UserID) REFERENCES "Users"(UserID) ON DELETE CASCADE
+                )
+            """)
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "WebhookDeliveries" (
+                    DeliveryID SERIAL PRIMARY KEY,
+                    WebhookID INT NOT NULL,
+                    EventType VARCHAR(50) NOT NULL,
+                    Payload TEXT NOT NULL,
+                    Status VARCHAR(20) NOT NULL DEFAULT 'pending',
+                    Attempts INT NOT NULL DEFAULT 0,
+                    ResponseStatus INT,
+                    LastError TEXT,
+                    NextAttemptAt TIMESTAMP,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    DeliveredAt TIMESTAMP,
+                    FOREIGN KEY (WebhookID) REFERENCES "Webhooks"(WebhookID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_webhooks_user ON "Webhooks"(UserID)', 'idx_webhooks_user')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_webhookdeliveries_status ON "WebhookDeliveries"(Status, NextAttemptAt)', 'idx_webhookdeliveries_status')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_webhookdeliveries_webhook ON "WebhookDeliveries"(WebhookID, DeliveryID)', 'idx_webhookdeliveries_webhook')
+        else:
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS Webhooks (
+                    WebhookID INT AUTO_INCREMENT PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    Name VARCHAR(255) NOT NULL,
+                    URL TEXT NOT NULL,
+                    Secret VARCHAR(255) NOT NULL,
+                    Events TEXT NOT NULL,
+                    IsGlobal TINYINT(1) NOT NULL DEFAULT 0,
+                    Enabled TINYINT(1) NOT NULL DEFAULT 1,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (UserID) REFERENCES Users(UserID) ON DELETE CASCADE
+                )
+            """)
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS WebhookDeliveries (
+                    DeliveryID INT AUTO_INCREMENT PRIMARY KEY,
+                    WebhookID INT NOT NULL,
+                    EventType VARCHAR(50) NOT NULL,
+                    Payload TEXT NOT NULL,
+                    Status VARCHAR(20) NOT NULL DEFAULT 'pending',
+                    Attempts INT NOT NULL DEFAULT 0,
+                    ResponseStatus INT,
+                    LastError TEXT,
+                    NextAttemptAt TIMESTAMP NULL DEFAULT NULL,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    DeliveredAt TIMESTAMP NULL DEFAULT NULL,
+                    FOREIGN KEY (WebhookID) REFERENCES Webhooks(WebhookID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_webhooks_user ON Webhooks(UserID)', 'idx_webhooks_user')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_webhookdeliveries_status ON WebhookDeliveries(Status, NextAttemptAt)', 'idx_webhookdeliveries_status')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_webhookdeliveries_webhook ON WebhookDeliveries(WebhookID, DeliveryID)', 'idx_webhookdeliveries_webhook')
+
+        conn.commit()
+        logger.info("Webhooks migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 056: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("057", "add_oidc_discovery_and_sessions", "Add OIDC discovery metadata and sign-in sessions", requires=["056"])
+def migration_057_add_oidc_discovery_and_sessions(conn, db_type: str):
+    """
+    Add IssuerURL, JwksURI and EndSessionURL to OIDCProviders, filled in from the
+    provider's discovery document when it is configured by issuer. Create
+    OIDCSessions, one row per OIDC sign-in, linking the API key issued for it to the
+    provider's session (sid), subject and ID token, so a logout at the provider can
+    revoke that key.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting OIDC discovery and sessions migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "OIDCProviders"
+                ADD COLUMN IF NOT EXISTS IssuerURL TEXT,
+                ADD COLUMN IF NOT EXISTS JwksURI TEXT,
+                ADD COLUMN IF NOT EXISTS EndSessionURL TEXT
+            """)
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "OIDCSessions" (
+                    SessionID SERIAL PRIMARY KEY,
+                    ProviderID INT NOT NULL,
+                    UserID INT NOT NULL,
+                    APIKeyID INT NOT NULL,
+                    Sid VARCHAR(255),
+                    Subject VARCHAR(255) NOT NULL,
+                    IDToken TEXT NOT NULL,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (ProviderID) REFERENCES "OIDCProviders"(ProviderID) ON DELETE CASCADE,
+                    FOREIGN KEY (UserID) REFERENCES "Users"(UserID) ON DELETE CASCADE,
+                    FOREIGN KEY (APIKeyID) REFERENCES "APIKeys"(APIKeyID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_oidcsessions_sid ON "OIDCSessions"(ProviderID, Sid)', 'idx_oidcsessions_sid')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_oidcsessions_subject ON "OIDCSessions"(ProviderID, Subject)', 'idx_oidcsessions_subject')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_oidcsessions_apikey ON "OIDCSessions"(APIKeyID)', 'idx_oidcsessions_apikey')
+        else:
+            new_columns = [
+                ("OIDCProviders", "IssuerURL", "TEXT"),
+                ("OIDCProviders", "JwksURI", "TEXT"),
+                ("OIDCProviders", "EndSessionURL", "TEXT"),
+            ]
+            for table_name, column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = %s
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (table_name, column_name))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to {table_name} table (MySQL)")
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS OIDCSessions (
+                    SessionID INT AUTO_INCREMENT PRIMARY KEY,
+                    ProviderID INT NOT NULL,
+                    UserID INT NOT NULL,
+                    APIKeyID INT NOT NULL,
+                    Sid VARCHAR(255),
+                    Subject VARCHAR(255) NOT NULL,
+                    IDToken TEXT NOT NULL,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (ProviderID) REFERENCES OIDCProviders(ProviderID) ON DELETE CASCADE,
+                    FOREIGN KEY (UserID) REFERENCES Users(UserID) ON DELETE CASCADE,
+                    FOREIGN KEY (APIKeyID) REFERENCES APIKeys(APIKeyID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_oidcsessions_sid ON OIDCSessions(ProviderID, Sid)', 'idx_oidcsessions_sid')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_oidcsessions_subject ON OIDCSessions(ProviderID, Subject)', 'idx_oidcsessions_subject')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_oidcsessions_apikey ON OIDCSessions(APIKeyID)', 'idx_oidcsessions_apikey')
+
+        conn.commit()
+        logger.info("OIDC discovery and sessions migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 057: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("058", "add_passkeys_and_recovery_codes", "Add WebAuthn credentials and MFA recovery codes", requires=["057"])
+def migration_058_add_passkeys_and_recovery_codes(conn, db_type: str):
+    """
+    Create WebAuthnCredentials, the passkeys and security keys a user has enrolled
+    (the base64url credential ID, its COSE public key, the signature counter and a
+    name), and MfaRecoveryCodes, one-time codes stored as SHA-256 hashes with the
+    time each was used.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting passkeys and recovery codes migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "WebAuthnCredentials" (
+                    PasskeyID SERIAL PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    CredentialID VARCHAR(700) NOT NULL UNIQUE,
+                    PublicKey TEXT NOT NULL,
+                    SignCount BIGINT NOT NULL DEFAULT 0,
+                    Name VARCHAR(255) NOT NULL,
+                    Transports TEXT,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    LastUsedAt TIMESTAMP,
+                    FOREIGN KEY (UserID) REFERENCES "Users"(UserID) ON DELETE CASCADE
+                )
+            """)
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "MfaRecoveryCodes" (
+                    CodeID SERIAL PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    CodeHash CHAR(64) NOT NULL,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    UsedAt TIMESTAMP,
+                    FOREIGN KEY (UserID) REFERENCES "Users"(UserID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_webauthncredentials_user ON "WebAuthnCredentials"(UserID)', 'idx_webauthncredentials_user')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_mfarecoverycodes_user ON "MfaRecoveryCodes"(UserID, CodeHash)', 'idx_mfarecoverycodes_user')
+        else:
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS WebAuthnCredentials (
+                    PasskeyID INT AUTO_INCREMENT PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    CredentialID VARCHAR(700) NOT NULL UNIQUE,
+                    PublicKey TEXT NOT NULL,
+                    SignCount BIGINT NOT NULL DEFAULT 0,
+                    Name VARCHAR(255) NOT NULL,
+                    Transports TEXT,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    LastUsedAt TIMESTAMP NULL DEFAULT NULL,
+                    FOREIGN KEY (UserID) REFERENCES Users(UserID) ON DELETE CASCADE
+                )
+            """)
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS MfaRecoveryCodes (
+                    CodeID INT AUTO_INCREMENT PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    CodeHash CHAR(64) NOT NULL,
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    UsedAt TIMESTAMP NULL DEFAULT NULL,
+                    FOREIGN KEY (UserID) REFERENCES Users(UserID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_webauthncredentials_user ON WebAuthnCredentials(UserID)', 'idx_webauthncredentials_user')
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_mfarecoverycodes_user ON MfaRecoveryCodes(UserID, CodeHash)', 'idx_mfarecoverycodes_user')
+
+        conn.commit()
+        logger.info("Passkeys and recovery codes migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 058: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("059", "add_login_sessions", "Add login sessions with idle and absolute expiry", requires=["058"])
+def migration_059_add_login_sessions(conn, db_type: str):
+    """
+    Create LoginSessions, one row per sign-in. Each session owns the API key issued
+    for it, so revoking the session deletes the key. The row records the device,
+    user agent and address the sign-in came from, when the session was last seen,
+    and its idle and absolute expiry times.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting login sessions migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS "LoginSessions" (
+                    SessionID SERIAL PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    APIKeyID INT NOT NULL UNIQUE,
+                    DeviceName VARCHAR(255) NOT NULL,
+                    UserAgent TEXT,
+                    IPAddress VARCHAR(64),
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    LastSeenAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    IdleExpiresAt TIMESTAMP,
+                    ExpiresAt TIMESTAMP,
+                    FOREIGN KEY (UserID) REFERENCES "Users"(UserID) ON DELETE CASCADE,
+                    FOREIGN KEY (APIKeyID) REFERENCES "APIKeys"(APIKeyID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_loginsessions_user ON "LoginSessions"(UserID)', 'idx_loginsessions_user')
+        else:
+            cursor.execute("""
+                CREATE TABLE IF NOT EXISTS LoginSessions (
+                    SessionID INT AUTO_INCREMENT PRIMARY KEY,
+                    UserID INT NOT NULL,
+                    APIKeyID INT NOT NULL UNIQUE,
+                    DeviceName VARCHAR(255) NOT NULL,
+                    UserAgent TEXT,
+                    IPAddress VARCHAR(64),
+                    CreatedAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    LastSeenAt TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                    IdleExpiresAt TIMESTAMP NULL DEFAULT NULL,
+                    ExpiresAt TIMESTAMP NULL DEFAULT NULL,
+                    FOREIGN KEY (UserID) REFERENCES Users(UserID) ON DELETE CASCADE,
+                    FOREIGN KEY (APIKeyID) REFERENCES APIKeys(APIKeyID) ON DELETE CASCADE
+                )
+            """)
+            safe_add_index(cursor, db_type, 'CREATE INDEX idx_loginsessions_user ON LoginSessions(UserID)', 'idx_loginsessions_user')
+
+        conn.commit()
+        logger.info("Login sessions migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 059: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
+@register_migration("060", "add_transcript_fetch_retries", "Retry transcript downloads that failed for a passing reason", requires=["059"])
+def migration_060_add_transcript_fetch_retries(conn, db_type: str):
+    """
+    Add FetchAttempts and RetryAfter to EpisodeTranscripts. A download that failed for a
+    passing reason (a timeout, a 5xx or 429 response) gets a RetryAfter time and is tried
+    again by a later refresh, up to a few attempts; other failures keep RetryAfter empty.
+    Failures recorded before this migration are given one more attempt.
+    """
+    cursor = conn.cursor()
+
+    try:
+        logger.info("Starting transcript fetch retries migration")
+
+        if db_type == "postgresql":
+            cursor.execute("""
+                ALTER TABLE "EpisodeTranscripts"
+                ADD COLUMN IF NOT EXISTS FetchAttempts INT DEFAULT 1,
+                ADD COLUMN IF NOT EXISTS RetryAfter TIMESTAMP
+            """)
+            cursor.execute("""
+                UPDATE "EpisodeTranscripts" SET RetryAfter = CURRENT_TIMESTAMP
+                WHERE FetchError IS NOT NULL AND SegmentCount = 0
+            """)
+        else:
+            new_columns = [
+                ("EpisodeTranscripts", "FetchAttempts", "INT DEFAULT 1"),
+                ("EpisodeTranscripts", "RetryAfter", "TIMESTAMP NULL DEFAULT NULL"),
+            ]
+            for table_name, column_name, column_type in new_columns:
+                cursor.execute("""
+                    SELECT COUNT(*)
+                    FROM information_schema.columns
+                    WHERE table_name = %s
+                    AND column_name = %s
+                    AND table_schema = DATABASE()
+                """, (table_name, column_name))
+                if cursor.fetchone()[0] == 0:
+                    cursor.execute(f"ALTER TABLE {table_name} ADD COLUMN {column_name} {column_type}")
+                    logger.info(f"Added {column_name} column to {table_name} table (MySQL)")
+            cursor.execute("""
+                UPDATE EpisodeTranscripts SET RetryAfter = CURRENT_TIMESTAMP
+                WHERE FetchError IS NOT NULL AND SegmentCount = 0
+            """)
+
+        conn.commit()
+        logger.info("Transcript fetch retries migration completed successfully")
+
+    except Exception as e:
+        logger.error(f"Error in migration 060: {e}")
+        raise
+    finally:
+        cursor.close()
+
+
 if __name__ == "__main__":
     # Register all migrations and run them
     register_all_migrations()
@@ -10,7 +10,15 @@ pub struct Config {
     pub security: SecurityConfig,
     pub email: EmailConfig,
     pub oidc: OIDCConfig,
+    pub ldap: Option<LdapConfig>,
+    pub proxy_auth: Option<ProxyAuthConfig>,
+    pub webauthn: Option<WebAuthnConfig>,
+    pub sessions: SessionConfig,
     pub api: ApiConfig,
+    pub push: PushConfig,
+    pub storage: StorageConfig,
+    pub downloads: DownloadQueueConfig,
+    pub notifications: NotificationConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +27,77 @@ pub struct ApiConfig {
     pub people_api_url: String,
 }
 
+/// Push-based feed updates: WebSub hub subscriptions and Podping notifications
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushConfig {
+    pub websub_enabled: bool,
+    /// Public base URL hubs use to reach this server, e.g. https://pods.example.com
+    pub callback_base_url: Option<String>,
+    /// Shared secret a Podping relay must send; the Podping endpoint is disabled without it
+    pub podping_token: Option<String>,
+}
+
+impl PushConfig {
+    /// WebSub needs a reachable callback URL, so it stays off until one is known
+    pub fn websub_callback_base(&self) -> Option<&str> {
+        if !self.websub_enabled {
+            return None;
+        }
+        self.callback_base_url
+            .as_deref()
+            .map(|url| url.trim().trim_end_matches('/'))
+            .filter(|url| !url.is_empty())
+    }
+}
+
+/// Where downloaded episodes are kept and how much each user may keep
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageConfig {
+    /// "local" or "s3"; new downloads go here, existing ones stay where they were written
+    pub backend: String,
+    pub local_root: String,
+    pub s3: Option<S3Config>,
+    /// Quota for users without their own; None means unlimited
+    pub default_quota_mb: Option<u64>,
+}
+
+/// S3-compatible object store (AWS S3, MinIO, Garage, ...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct S3Config {
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    #[serde(skip_serializing)]
+    pub secret_access_key: String,
+    /// Key prefix inside the bucket, e.g. "pinepods/"
+    pub prefix: String,
+    /// Address the bucket as endpoint/bucket rather than bucket.endpoint (MinIO needs this)
+    pub path_style: bool,
+}
+
+/// How the download queue runs episode downloads
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadQueueConfig {
+    /// Downloads running at once across all users
+    pub workers: usize,
+    /// Downloads running at once from a single host
+    pub per_host_limit: usize,
+    /// Combined download speed cap in KiB/s; None means unlimited
+    pub bandwidth_limit_kbps: Option<u64>,
+    /// Attempts before a download is marked failed
+    pub max_attempts: i32,
+    /// ffmpeg binary used for post-processing downloads
+    pub ffmpeg_path: String,
+}
+
+/// When event notifications fire
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotificationConfig {
+    /// Days a feed has to keep failing before its owner is told it's broken
+    pub feed_broken_days: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DatabaseConfig {
     pub db_type: String,
@@ -85,6 +164,93 @@ pub struct OIDCConfig {
     pub admin_role: Option<String>,
 }
 
+/// LDAP / Active Directory login, checked when a username and password don't match a local account
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LdapConfig {
+    /// ldap:// or ldaps:// URL of the directory server
+    pub url: String,
+    /// Upgrade an ldap:// connection with StartTLS
+    pub starttls: bool,
+    /// Accept any TLS certificate, for self-signed test directories
+    pub tls_skip_verify: bool,
+    /// DN to bind as directly, with `{username}` in place of the login name,
+    /// e.g. uid={username},ou=people,dc=example,dc=com
+    pub user_dn_template: Option<String>,
+    /// Service account used to search for users when there is no DN template; anonymous when unset
+    pub bind_dn: Option<String>,
+    #[serde(skip_serializing)]
+    pub bind_password: Option<String>,
+    /// Where users are searched for when there is no DN template
+    pub search_base: Option<String>,
+    /// Filter finding a user, with `{username}` in place of the login name
+    pub user_filter: String,
+    pub username_attribute: String,
+    pub email_attribute: String,
+    pub name_attribute: String,
+    /// User attribute listing group DNs, as with the memberOf overlay or Active Directory
+    pub group_attribute: String,
+    /// Also look for groups listing the user as a member under this base
+    pub group_search_base: Option<String>,
+    /// Only members of this group may log in
+    pub user_group: Option<String>,
+    /// Members of this group are made admins; admins outside it keep their rights
+    pub admin_group: Option<String>,
+    /// Let directory users sign in to accounts LDAP didn't create, matched by email. Off by
+    /// default, since whoever can set a directory email could otherwise take over any local account.
+    pub link_existing: bool,
+}
+
+/// Logins asserted in headers by an authenticating reverse proxy (Authelia, oauth2-proxy, ...)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProxyAuthConfig {
+    /// Addresses allowed to assert a user; headers from anywhere else are ignored
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+    pub user_header: String,
+    pub email_header: String,
+    pub name_header: String,
+    /// Comma-separated group list
+    pub groups_header: String,
+    /// Only members of this group may log in
+    pub user_group: Option<String>,
+    /// Members of this group are made admins; admins outside it keep their rights
+    pub admin_group: Option<String>,
+    /// Create accounts for users PinePods hasn't seen yet
+    pub auto_create: bool,
+    /// Let the proxy sign in to accounts it didn't create, matched by username. Off by default,
+    /// since whoever controls the proxy's usernames could otherwise take over any local account.
+    pub link_existing: bool,
+    /// Ask proxy users for their PinePods second factor too. Off by default, leaving MFA to the proxy.
+    pub require_mfa: bool,
+}
+
+/// Passkeys and security keys (WebAuthn); off until the address browsers use is known
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebAuthnConfig {
+    /// Domain credentials are scoped to, e.g. pods.example.com
+    pub rp_id: String,
+    /// Origins a sign-in may come from, e.g. https://pods.example.com
+    pub origins: Vec<String>,
+}
+
+/// How long a login session lasts. None means the limit is off.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionConfig {
+    /// Hours without a request before a session is signed out
+    pub idle_timeout_hours: Option<i64>,
+    /// Days after sign-in when a session ends however active it is
+    pub max_age_days: Option<i64>,
+}
+
+impl SessionConfig {
+    pub fn idle_timeout(&self) -> Option<chrono::Duration> {
+        self.idle_timeout_hours.map(chrono::Duration::hours)
+    }
+
+    pub fn max_age(&self) -> Option<chrono::Duration> {
+        self.max_age_days.map(chrono::Duration::days)
+    }
+}
+
 impl OIDCConfig {
     pub fn is_configured(&self) -> bool {
         self.provider_name.as_ref().map_or(false, |s| !s.trim().is_empty()) &&
@@ -337,6 +503,149 @@ impl Config {
             people_api_url: env::var("PEOPLE_API_URL").unwrap(),
         };
 
+        // SERVER_URL is the externally visible address saved by the startup script
+        let push = PushConfig {
+            websub_enabled: env::var("WEBSUB_ENABLED")
+                .unwrap_or_else(|_| "false".to_string())
+                .parse()
+                .unwrap_or(false),
+            callback_base_url: env::var("WEBSUB_CALLBACK_URL")
+                .ok()
+                .filter(|s| !s.trim().is_empty())
+                .or_else(|| env::var("SERVER_URL").ok()),
+            podping_token: env::var("PODPING_TOKEN").ok().filter(|s| !s.trim().is_empty()),
+        };
+
+        let non_empty = |name: &str| env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        let s3 = match (non_empty("S3_ENDPOINT"), non_empty("S3_BUCKET"), non_empty("S3_ACCESS_KEY_ID"), non_empty("S3_SECRET_ACCESS_KEY")) {
+            (Some(endpoint), Some(bucket), Some(access_key_id), Some(secret_access_key)) => Some(S3Config {
+                endpoint: endpoint.trim_end_matches('/').to_string(),
+                bucket,
+                region: non_empty("S3_REGION").unwrap_or_else(|| "us-east-1".to_string()),
+                access_key_id,
+                secret_access_key,
+                prefix: non_empty("S3_PREFIX")
+                    .map(|p| format!("{}/", p.trim_matches('/')))
+                    .unwrap_or_default(),
+                path_style: non_empty("S3_PATH_STYLE").is_none_or(|v| v.parse().unwrap_or(true)),
+            }),
+            _ => None,
+        };
+        let storage = StorageConfig {
+            backend: non_empty("STORAGE_BACKEND").unwrap_or_else(|| "local".to_string()).to_lowercase(),
+            local_root: non_empty("DOWNLOAD_ROOT").unwrap_or_else(|| "/opt/pinepods/downloads".to_string()),
+            s3,
+            default_quota_mb: non_empty("DEFAULT_DOWNLOAD_QUOTA_MB").and_then(|v| v.parse().ok()).filter(|mb| *mb > 0),
+        };
+        let downloads = DownloadQueueConfig {
+            workers: non_empty("DOWNLOAD_WORKERS").and_then(|v| v.parse().ok()).filter(|n| *n > 0).unwrap_or(3),
+            per_host_limit: non_empty("DOWNLOAD_HOST_CONCURRENCY").and_then(|v| v.parse().ok()).filter(|n| *n > 0).unwrap_or(2),
+            bandwidth_limit_kbps: non_empty("DOWNLOAD_BANDWIDTH_LIMIT_KBPS").and_then(|v| v.parse().ok()).filter(|kbps| *kbps > 0),
+            max_attempts: non_empty("DOWNLOAD_MAX_ATTEMPTS").and_then(|v| v.parse().ok()).filter(|n| *n > 0).unwrap_or(5),
+            ffmpeg_path: non_empty("FFMPEG_PATH").unwrap_or_else(|| "ffmpeg".to_string()),
+        };
+        let ldap = non_empty("LDAP_URL").map(|url| LdapConfig {
+            url,
+            starttls: non_empty("LDAP_STARTTLS").is_some_and(|v| v.parse().unwrap_or(false)),
+            tls_skip_verify: non_empty("LDAP_TLS_SKIP_VERIFY").is_some_and(|v| v.parse().unwrap_or(false)),
+            user_dn_template: non_empty("LDAP_USER_DN_TEMPLATE"),
+            bind_dn: non_empty("LDAP_BIND_DN"),
+            bind_password: env::var("LDAP_BIND_PASSWORD").ok(),
+            search_base: non_empty("LDAP_SEARCH_BASE"),
+            user_filter: non_empty("LDAP_USER_FILTER").unwrap_or_else(|| "(uid={username})".to_string()),
+            username_attribute: non_empty("LDAP_USERNAME_ATTRIBUTE").unwrap_or_else(|| "uid".to_string()),
+            email_attribute: non_empty("LDAP_EMAIL_ATTRIBUTE").unwrap_or_else(|| "mail".to_string()),
+            name_attribute: non_empty("LDAP_NAME_ATTRIBUTE").unwrap_or_else(|| "cn".to_string()),
+            group_attribute: non_empty("LDAP_GROUP_ATTRIBUTE").unwrap_or_else(|| "memberOf".to_string()),
+            group_search_base: non_empty("LDAP_GROUP_SEARCH_BASE"),
+            user_group: non_empty("LDAP_USER_GROUP"),
+            admin_group: non_empty("LDAP_ADMIN_GROUP"),
+            link_existing: non_empty("LDAP_LINK_EXISTING").is_some_and(|v| v.parse().unwrap_or(false)),
+        });
+        if let Some(ldap) = &ldap {
+            if !ldap.url.starts_with("ldap://") && !ldap.url.starts_with("ldaps://") {
+                return Err(AppError::Config(format!("Invalid LDAP_URL '{}', expected an ldap:// or ldaps:// URL", ldap.url)));
+            }
+            if ldap.user_dn_template.is_none() && ldap.search_base.is_none() {
+                return Err(AppError::Config(
+                    "LDAP_URL requires either LDAP_USER_DN_TEMPLATE or LDAP_SEARCH_BASE to find users".to_string(),
+                ));
+            }
+            if ldap.user_dn_template.as_ref().is_some_and(|t| !t.contains("{username}"))
+                || (ldap.user_dn_template.is_none() && !ldap.user_filter.contains("{username}"))
+            {
+                return Err(AppError::Config(
+                    "LDAP_USER_DN_TEMPLATE and LDAP_USER_FILTER must contain {username}".to_string(),
+                ));
+            }
+        }
+        let proxy_auth = if non_empty("PROXY_AUTH_ENABLED").is_some_and(|v| v.parse().unwrap_or(false)) {
+            let mut trusted_proxies = Vec::new();
+            for entry in non_empty("PROXY_AUTH_TRUSTED_PROXIES").unwrap_or_default().split(',').map(str::trim).filter(|e| !e.is_empty()) {
+                let net = entry.parse::<ipnet::IpNet>()
+                    .or_else(|_| entry.parse::<std::net::IpAddr>().map(ipnet::IpNet::from))
+                    .map_err(|_| AppError::Config(format!("Invalid entry '{}' in PROXY_AUTH_TRUSTED_PROXIES, expected an IP address or CIDR", entry)))?;
+                trusted_proxies.push(net);
+            }
+            if trusted_proxies.is_empty() {
+                return Err(AppError::Config(
+                    "PROXY_AUTH_ENABLED requires PROXY_AUTH_TRUSTED_PROXIES, the addresses of the authenticating proxy".to_string(),
+                ));
+            }
+            Some(ProxyAuthConfig {
+                trusted_proxies,
+                user_header: non_empty("PROXY_AUTH_USER_HEADER").unwrap_or_else(|| "Remote-User".to_string()),
+                email_header: non_empty("PROXY_AUTH_EMAIL_HEADER").unwrap_or_else(|| "Remote-Email".to_string()),
+                name_header: non_empty("PROXY_AUTH_NAME_HEADER").unwrap_or_else(|| "Remote-Name".to_string()),
+                groups_header: non_empty("PROXY_AUTH_GROUPS_HEADER").unwrap_or_else(|| "Remote-Groups".to_string()),
+                user_group: non_empty("PROXY_AUTH_USER_GROUP"),
+                admin_group: non_empty("PROXY_AUTH_ADMIN_GROUP"),
+                auto_create: non_empty("PROXY_AUTH_AUTO_CREATE").is_none_or(|v| v.parse().unwrap_or(true)),
+                link_existing: non_empty("PROXY_AUTH_LINK_EXISTING").is_some_and(|v| v.parse().unwrap_or(false)),
+                require_mfa: non_empty("PROXY_AUTH_REQUIRE_MFA").is_some_and(|v| v.parse().unwrap_or(false)),
+            })
+        } else {
+            None
+        };
+        // Origins default to SERVER_URL (passkeys stay off if it isn't a full URL), and the
+        // RP ID to the first origin's host
+        let mut origins = Vec::new();
+        if let Some(configured) = non_empty("WEBAUTHN_ORIGINS") {
+            for entry in configured.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+                match url::Url::parse(entry) {
+                    Ok(url) if url.host_str().is_some() => origins.push(url),
+                    _ => return Err(AppError::Config(format!("Invalid entry '{}' in WEBAUTHN_ORIGINS, expected a URL such as https://pods.example.com", entry))),
+                }
+            }
+        } else if let Some(url) = non_empty("SERVER_URL").and_then(|url| url::Url::parse(&url).ok()).filter(|url| url.host_str().is_some()) {
+            origins.push(url);
+        }
+        let webauthn = non_empty("WEBAUTHN_RP_ID")
+            .or_else(|| origins.first().and_then(|url| url.host_str()).map(str::to_string))
+            .map(|rp_id| WebAuthnConfig {
+                rp_id: rp_id.to_lowercase(),
+                origins: origins.iter().map(|url| url.origin().ascii_serialization()).collect(),
+            })
+            .filter(|config| !config.origins.is_empty());
+        // Zero turns a limit off
+        let sessions = SessionConfig {
+            idle_timeout_hours: non_empty("SESSION_IDLE_TIMEOUT_HOURS").and_then(|v| v.parse().ok()).or(Some(24 * 30)).filter(|hours| *hours > 0),
+            max_age_days: non_empty("SESSION_MAX_AGE_DAYS").and_then(|v| v.parse().ok()).or(Some(90)).filter(|days| *days > 0),
+        };
+        let notifications = NotificationConfig {
+            feed_broken_days: non_empty("FEED_BROKEN_NOTIFY_DAYS").and_then(|v| v.parse().ok()).filter(|days| *days > 0).unwrap_or(3),
+        };
+        match storage.backend.as_str() {
+            "local" => {}
+            "s3" if storage.s3.is_some() => {}
+            "s3" => {
+                return Err(AppError::Config(
+                    "STORAGE_BACKEND=s3 requires S3_ENDPOINT, S3_BUCKET, S3_ACCESS_KEY_ID and S3_SECRET_ACCESS_KEY".to_string(),
+                ));
+            }
+            other => return Err(AppError::Config(format!("Unknown STORAGE_BACKEND '{}', expected local or s3", other))),
+        }
+
         // Validate OIDC configuration
         if let Err(validation_error) = oidc.validate() {
             return Err(AppError::Config(validation_error));
@@ -349,7 +658,15 @@ impl Config {
             security,
             email,
             oidc,
+            ldap,
+            proxy_auth,
+            webauthn,
+            sessions,
             api,
+            push,
+            storage,
+            downloads,
+            notifications,
         })
     }
 
@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Path, Query, State},
+    extract::{ConnectInfo, Path, Query, State},
     http::{HeaderMap, StatusCode},
     response::{Json, Html, IntoResponse},
 };
@@ -10,9 +10,18 @@ use base64::{Engine as _, engine::general_purpose::STANDARD};
 use crate::{
     error::{AppError, AppResult},
     handlers::{extract_api_key, check_user_or_admin_access},
+    config::{LdapConfig, ProxyAuthConfig},
+    services::{
+        ldap::{self, LdapIdentity},
+        oidc,
+        proxy_auth::{self, ProxyIdentity},
+        sessions::{self, LoginClient},
+        webauthn,
+    },
     AppState,
 };
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -31,6 +40,9 @@ pub struct LoginResponse {
     mfa_required: Option<bool>,
     user_id: Option<i32>,
     mfa_session_token: Option<String>,
+    // Second factors the user can complete an mfa_required login with
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mfa_methods: Option<Vec<String>>,
 }
 
 #[derive(Serialize)]
@@ -199,32 +211,217 @@ fn extract_basic_auth(headers: &HeaderMap) -> AppResult<(String, String)> {
     Ok((parts[0].to_lowercase(), parts[1].to_string()))
 }
 
-// Get API key with basic authentication (username/password)
-// Now includes MFA security check - API key only returned after MFA verification if enabled
-pub async fn get_key(
+// Usernames tried for a new account, in order: `base` itself, then with _1 to _10 appended
+fn username_candidates(base: &str) -> impl Iterator<Item = String> + '_ {
+    std::iter::once(base.to_string()).chain((1..=10).map(move |counter| format!("{}_{}", base, counter)))
+}
+
+// A free username based on `base`, as ordered by `username_candidates`
+async fn unique_username(state: &AppState, base: &str) -> AppResult<Option<String>> {
+    for candidate in username_candidates(base) {
+        if !state.db_pool.check_usernames(&candidate).await? {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+// Whether a directory user may sign in to an existing account: one LDAP created, or any when
+// linking existing accounts was opted into
+fn ldap_may_link(config: &LdapConfig, provisioned_by_ldap: bool) -> bool {
+    provisioned_by_ldap || config.link_existing
+}
+
+// Whether the admin group makes this user an admin. Membership only ever grants admin rights;
+// admins made in PinePods keep theirs.
+fn ldap_grants_admin(identity: &LdapIdentity) -> bool {
+    identity.is_admin == Some(true)
+}
+
+// Find or create the local account of a directory user, matched by email. Accounts LDAP didn't
+// create are only linked with LDAP_LINK_EXISTING, and the admin group can grant admin rights.
+async fn provision_ldap_user(state: &AppState, config: &LdapConfig, identity: &LdapIdentity) -> AppResult<i32> {
+    let email = identity.email.as_deref()
+        .ok_or_else(|| AppError::forbidden("Your directory account has no email address, which is needed to link it to a PinePods user"))?;
+
+    let user_id = match state.db_pool.get_user_by_email(email).await? {
+        Some((user_id, ..)) => {
+            if !ldap_may_link(config, state.db_pool.is_ldap_user(user_id).await?) {
+                tracing::warn!("LDAP: Refusing to sign {} in to local account {}", identity.dn, user_id);
+                return Err(AppError::forbidden("This PinePods account can't be used with a directory login"));
+            }
+            state.db_pool.set_fullname(user_id, &identity.fullname).await?;
+            user_id
+        }
+        None => {
+            let username = unique_username(state, &identity.username).await?
+                .ok_or_else(|| AppError::Conflict(format!("No free username based on '{}'", identity.username)))?;
+            let user_id = state.db_pool.create_ldap_user(email, &identity.fullname, &username).await?;
+            tracing::info!("LDAP: Created user {} for {}", username, identity.dn);
+            user_id
+        }
+    };
+
+    if ldap_grants_admin(identity) {
+        state.db_pool.set_isadmin(user_id, true).await?;
+    }
+    Ok(user_id)
+}
+
+// Whether the proxy may sign in to an existing account: one it created, or any when linking
+// existing accounts was opted into
+fn proxy_may_link(config: &ProxyAuthConfig, provisioned_by_proxy: bool) -> bool {
+    provisioned_by_proxy || config.link_existing
+}
+
+// Whether the admin group makes this user an admin. Membership only ever grants admin rights;
+// admins made in PinePods keep theirs.
+fn proxy_grants_admin(config: &ProxyAuthConfig, identity: &ProxyIdentity) -> bool {
+    config.admin_group.as_deref().is_some_and(|admin_group| identity.in_group(admin_group))
+}
+
+// Find or create the account of a user signed in by a trusted proxy. Accounts the proxy didn't
+// create are only linked with PROXY_AUTH_LINK_EXISTING, and the admin group can grant admin rights.
+async fn provision_proxy_user(state: &AppState, config: &ProxyAuthConfig, identity: &ProxyIdentity) -> AppResult<i32> {
+    if let Some(user_group) = &config.user_group {
+        if !identity.in_group(user_group) {
+            return Err(AppError::forbidden("You are not in a group allowed to use PinePods"));
+        }
+    }
+
+    let user_id = if state.db_pool.check_usernames(&identity.username).await? {
+        let user_id = state.db_pool.get_user_id_from_username(&identity.username).await?;
+        if !proxy_may_link(config, state.db_pool.is_proxy_user(user_id).await?) {
+            tracing::warn!("Proxy auth: Refusing to sign in to local account {}", identity.username);
+            return Err(AppError::forbidden("This PinePods account can't be used through the authenticating proxy"));
+        }
+        if let Some(fullname) = &identity.fullname {
+            state.db_pool.set_fullname(user_id, fullname).await?;
+        }
+        user_id
+    } else if config.auto_create {
+        let fullname = identity.fullname.as_deref().unwrap_or(&identity.username);
+        let email = identity.email.as_deref().unwrap_or_default();
+        let user_id = state.db_pool.create_proxy_user(email, fullname, &identity.username).await?;
+        tracing::info!("Proxy auth: Created user {}", identity.username);
+        user_id
+    } else {
+        return Err(AppError::forbidden("No PinePods account exists for this user"));
+    };
+
+    if proxy_grants_admin(config, identity) {
+        state.db_pool.set_isadmin(user_id, true).await?;
+    }
+    Ok(user_id)
+}
+
+// Web login behind an authenticating proxy: sends the browser back to the app with an API key,
+// the same way the OIDC callback does
+pub async fn proxy_login(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
     State(state): State<AppState>,
-) -> Result<Json<LoginResponse>, AppError> {
+) -> Result<axum::response::Response, AppError> {
+    let frontend_base = construct_base_url_from_request(&headers)?.replace("/api", "");
+
+    let Some(config) = &state.config.proxy_auth else {
+        return Ok(create_oidc_response(&frontend_base, "error=proxy_auth_disabled"));
+    };
+    let Some(identity) = proxy_auth::identity(config, peer.ip(), &headers) else {
+        return Ok(create_oidc_response(&frontend_base, "error=no_access&details=no_proxy_user"));
+    };
+    match provision_proxy_user(&state, config, &identity).await {
+        // The redirect can't carry a second factor; these users sign in through the login form
+        Ok(user_id) if config.require_mfa && !mfa_methods(&state, user_id).await?.is_empty() => {
+            Ok(create_oidc_response(&frontend_base, "error=no_access&details=mfa_required"))
+        }
+        Ok(user_id) => {
+            let api_key = start_login_session(&state, user_id, peer, &headers).await?;
+            Ok(create_oidc_response(&frontend_base, &format!("api_key={}", api_key)))
+        }
+        Err(e) => {
+            tracing::warn!("Proxy auth: Login refused for {}: {}", identity.username, e);
+            Ok(create_oidc_response(&frontend_base, "error=no_access"))
+        }
+    }
+}
+
+// Check the basic auth credentials against local accounts, then the LDAP directory
+async fn password_login(state: &AppState, headers: &HeaderMap) -> AppResult<i32> {
+    let standard_login = !state.config.oidc.disable_standard_login;
     // Check if standard login is disabled in favor of OIDC-only authentication
-    if state.config.oidc.disable_standard_login {
+    if !standard_login && state.config.ldap.is_none() {
         return Err(AppError::forbidden("Standard username/password login is disabled. Please use OIDC authentication."));
     }
 
-    let (username, password) = extract_basic_auth(&headers)?;
+    let (username, password) = extract_basic_auth(headers)?;
     
     // Verify password
-    let is_valid = state.db_pool.verify_password(&username, &password).await?;
-    if !is_valid {
-        return Err(AppError::unauthorized("Invalid username or password"));
+    let is_valid = if standard_login {
+        match state.db_pool.verify_password(&username, &password).await {
+            Ok(valid) => valid,
+            // Directory and OIDC accounts have no usable local password hash
+            Err(AppError::Auth(_)) if state.config.ldap.is_some() => false,
+            Err(e) => return Err(e),
+        }
+    } else {
+        false
+    };
+
+    if is_valid {
+        // Get user ID from username first
+        state.db_pool.get_user_id_from_username(&username).await
+    } else if let Some(ldap_config) = &state.config.ldap {
+        match ldap::authenticate(ldap_config, &username, &password).await? {
+            Some(identity) => provision_ldap_user(state, ldap_config, &identity).await,
+            None => Err(AppError::unauthorized("Invalid username or password")),
+        }
+    } else {
+        Err(AppError::unauthorized("Invalid username or password"))
+    }
+}
+
+// Get API key with basic authentication (username/password)
+// Now includes MFA security check - API key only returned after MFA verification if enabled
+// Local accounts are checked first, then the LDAP directory when one is configured.
+// A user asserted by a trusted authenticating proxy needs no password; the proxy did the login.
+// Their PinePods second factor is skipped too, on the view that the proxy enforces its own,
+// unless PROXY_AUTH_REQUIRE_MFA is set.
+pub async fn get_key(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let mut proxy_user_id = None;
+    if let Some(config) = &state.config.proxy_auth {
+        if let Some(identity) = proxy_auth::identity(config, peer.ip(), &headers) {
+            let user_id = provision_proxy_user(&state, config, &identity).await?;
+            if !config.require_mfa {
+                let api_key = start_login_session(&state, user_id, peer, &headers).await?;
+                return Ok(Json(LoginResponse {
+                    status: "success".to_string(),
+                    retrieved_key: Some(api_key),
+                    mfa_required: Some(false),
+                    user_id: Some(user_id),
+                    mfa_session_token: None,
+                    mfa_methods: None,
+                }));
+            }
+            proxy_user_id = Some(user_id);
+        }
     }
 
-    // Get user ID from username first
-    let user_id = state.db_pool.get_user_id_from_username(&username).await?;
+    let user_id = match proxy_user_id {
+        Some(user_id) => user_id,
+        None => password_login(&state, &headers).await?,
+    };
+
     
     // Check if MFA is enabled for this user - CRITICAL SECURITY CHECK
-    let mfa_enabled = state.db_pool.check_mfa_enabled(user_id).await?;
+    // An enrolled passkey counts as a second factor just like a TOTP secret
+    let mfa_methods = mfa_methods(&state, user_id).await?;
     
-    if mfa_enabled {
+    if !mfa_methods.is_empty() {
         // MFA is enabled - create secure session token and DO NOT return API key yet
         // Generate cryptographically secure session token
         use rand::Rng;
@@ -256,11 +453,12 @@ pub async fn get_key(
             mfa_required: Some(true),
             user_id: Some(user_id),
             mfa_session_token: Some(session_token),
+            mfa_methods: Some(mfa_methods),
         }));
     }
     
     // MFA not enabled - proceed with normal flow
-    let api_key = state.db_pool.create_or_get_api_key(user_id).await?;
+    let api_key = start_login_session(&state, user_id, peer, &headers).await?;
     
     Ok(Json(LoginResponse {
         status: "success".to_string(),
@@ -268,6 +466,7 @@ pub async fn get_key(
         mfa_required: Some(false),
         user_id: Some(user_id),
         mfa_session_token: None,
+        mfa_methods: None,
     }))
 }
 
@@ -502,7 +701,10 @@ pub async fn check_mfa_enabled(
 #[derive(Deserialize)]
 pub struct VerifyMfaLoginRequest {
     pub mfa_session_token: String,
+    #[serde(default)]
     pub mfa_code: String,
+    // A one-time recovery code, used in place of the TOTP code
+    pub recovery_code: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -529,9 +731,44 @@ fn cleanup_expired_mfa_sessions() -> Result<(), AppError> {
     Ok(())
 }
 
+// Sign a user in. Every login gets its own session and API key, so each device can be
+// listed, timed out and signed out on its own.
+async fn start_login_session(state: &AppState, user_id: i32, peer: SocketAddr, headers: &HeaderMap) -> AppResult<String> {
+    let client = LoginClient::from_request(peer.ip(), headers);
+    let (idle_expires_at, expires_at) = sessions::deadlines(&state.config.sessions, chrono::Utc::now().naive_utc());
+    state.db_pool.create_login_session(user_id, &client, idle_expires_at, expires_at).await
+}
+
+// Second factors a user has set up, in the order a login page should offer them
+async fn mfa_methods(state: &AppState, user_id: i32) -> AppResult<Vec<String>> {
+    let mut methods = Vec::new();
+    if state.config.webauthn.is_some() && !state.db_pool.get_webauthn_credentials(user_id).await?.is_empty() {
+        methods.push("passkey".to_string());
+    }
+    if state.db_pool.check_mfa_enabled(user_id).await? {
+        methods.push("totp".to_string());
+    }
+    if !methods.is_empty() && state.db_pool.count_recovery_codes(user_id).await? > 0 {
+        methods.push("recovery_code".to_string());
+    }
+    Ok(methods)
+}
+
+// The user a password-verified session is pending MFA for, if it hasn't expired.
+// A consumed session can't be used again.
+fn pending_mfa_user(session_token: &str, consume: bool) -> Result<Option<i32>, AppError> {
+    cleanup_expired_mfa_sessions()?;
+    let mut sessions = PENDING_MFA_SESSIONS.lock()
+        .map_err(|e| AppError::internal(format!("Failed to lock MFA sessions: {}", e)))?;
+    let session = if consume { sessions.remove(session_token) } else { sessions.get(session_token).copied() };
+    Ok(session.map(|(user_id, _)| user_id))
+}
+
 // Verify MFA code during login and return API key - SECURE TWO-FACTOR AUTHENTICATION
 // CRITICAL: This endpoint REQUIRES a valid session token proving password was verified first
 pub async fn verify_mfa_and_get_key(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     State(state): State<AppState>,
     Json(request): Json<VerifyMfaLoginRequest>,
 ) -> Result<Json<VerifyMfaLoginResponse>, AppError> {
@@ -571,6 +808,24 @@ pub async fn verify_mfa_and_get_key(
         }
     };
 
+    // Recovery codes stand in for a lost TOTP device or passkey, once each
+    if let Some(recovery_code) = request.recovery_code.as_deref().filter(|code| !code.trim().is_empty()) {
+        let hash = webauthn::hash_recovery_code(recovery_code);
+        if !state.db_pool.use_recovery_code(user_id, &hash).await? {
+            return Ok(Json(VerifyMfaLoginResponse {
+                status: "invalid_code".to_string(),
+                retrieved_key: None,
+                verified: false,
+            }));
+        }
+        let api_key = start_login_session(&state, user_id, peer, &headers).await?;
+        return Ok(Json(VerifyMfaLoginResponse {
+            status: "success".to_string(),
+            retrieved_key: Some(api_key),
+            verified: true,
+        }));
+    }
+
     // Get MFA secret for user - matches existing verify_mfa function exactly
     let mfa_secret = match state.db_pool.get_mfa_secret(user_id).await? {
         Some(secret) => secret,
@@ -603,7 +858,7 @@ pub async fn verify_mfa_and_get_key(
     if verified {
         // MFA verification successful - now safe to return API key
         // Session token was consumed above, preventing replay attacks
-        let api_key = state.db_pool.create_or_get_api_key(user_id).await?;
+        let api_key = start_login_session(&state, user_id, peer, &headers).await?;
         
         Ok(Json(VerifyMfaLoginResponse {
             status: "success".to_string(),
@@ -620,6 +875,121 @@ pub async fn verify_mfa_and_get_key(
     }
 }
 
+// Request struct for start_passkey_login. With an MFA session token the passkey is the
+// second factor of a password login; without one it's a passwordless login.
+#[derive(Deserialize)]
+pub struct StartPasskeyLoginRequest {
+    pub mfa_session_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct FinishPasskeyLoginRequest {
+    pub challenge_id: String,
+    pub credential: webauthn::PublicKeyCredential,
+}
+
+// What is kept between the two halves of a passkey login
+#[derive(Serialize, Deserialize)]
+struct PendingPasskeyLogin {
+    challenge: String,
+    user_id: Option<i32>,
+    mfa_session_token: Option<String>,
+}
+
+// Begin a passkey login: returns the options for navigator.credentials.get()
+pub async fn start_passkey_login(
+    State(state): State<AppState>,
+    Json(request): Json<StartPasskeyLoginRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let config = state.config.webauthn.as_ref()
+        .ok_or_else(|| AppError::bad_request("Passkey login is not configured on this server"))?;
+
+    let (user_id, allowed) = match request.mfa_session_token.as_deref() {
+        Some(token) => {
+            let user_id = pending_mfa_user(token, false)?
+                .ok_or_else(|| AppError::unauthorized("Login session expired, please sign in again"))?;
+            let credentials = state.db_pool.get_webauthn_credentials(user_id).await?;
+            if credentials.is_empty() {
+                return Err(AppError::bad_request("No passkeys are registered for this account"));
+            }
+            (Some(user_id), credentials)
+        }
+        None => {
+            if state.config.oidc.disable_standard_login {
+                return Err(AppError::forbidden("Standard login is disabled. Please use OIDC authentication."));
+            }
+            (None, Vec::new())
+        }
+    };
+
+    let challenge = webauthn::generate_challenge();
+    let challenge_id = webauthn::generate_challenge();
+    let options = webauthn::request_options(config, &challenge, &allowed);
+    let pending = PendingPasskeyLogin {
+        challenge,
+        user_id,
+        mfa_session_token: request.mfa_session_token,
+    };
+    state.redis_client.set_ex(
+        &format!("webauthn_login:{}", challenge_id),
+        serde_json::to_string(&pending)?,
+        webauthn::CHALLENGE_TTL_SECONDS,
+    ).await?;
+
+    Ok(Json(json!({ "challenge_id": challenge_id, "options": options })))
+}
+
+// Finish a passkey login with the browser's signed response and return an API key
+pub async fn finish_passkey_login(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(request): Json<FinishPasskeyLoginRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    let config = state.config.webauthn.as_ref()
+        .ok_or_else(|| AppError::bad_request("Passkey login is not configured on this server"))?;
+
+    // Each challenge answers one login
+    let pending: PendingPasskeyLogin = state.redis_client.get_del(&format!("webauthn_login:{}", request.challenge_id)).await?
+        .and_then(|stored| serde_json::from_str(&stored).ok())
+        .ok_or_else(|| AppError::unauthorized("Passkey login expired, please try again"))?;
+
+    let credential_id = webauthn::encode(&webauthn::decode(&request.credential.id)?);
+    let stored = state.db_pool.get_webauthn_credential(&credential_id).await?
+        .ok_or_else(|| AppError::unauthorized("This passkey is not registered"))?;
+    if pending.user_id.is_some_and(|user_id| user_id != stored.user_id) {
+        return Err(AppError::unauthorized("This passkey belongs to a different account"));
+    }
+    if let Some(user_handle) = &request.credential.response.user_handle {
+        if webauthn::decode(user_handle)? != webauthn::decode(&webauthn::user_handle(stored.user_id))? {
+            return Err(AppError::unauthorized("This passkey belongs to a different account"));
+        }
+    }
+
+    // On its own a passkey has to stand in for the password as well, so the
+    // authenticator must have verified the user with a PIN or biometric
+    let require_verification = pending.user_id.is_none();
+    let sign_count = webauthn::verify_assertion(config, &pending.challenge, &request.credential, &stored, require_verification)
+        .map_err(|e| AppError::unauthorized(e.to_string()))?;
+    state.db_pool.mark_webauthn_credential_used(stored.passkey_id, sign_count).await?;
+
+    if let Some(token) = &pending.mfa_session_token {
+        if pending_mfa_user(token, true)? != Some(stored.user_id) {
+            return Err(AppError::unauthorized("Login session expired, please sign in again"));
+        }
+    }
+
+    let api_key = start_login_session(&state, stored.user_id, peer, &headers).await?;
+    Ok(Json(LoginResponse {
+        status: "success".to_string(),
+        retrieved_key: Some(api_key),
+        mfa_required: Some(false),
+        user_id: Some(stored.user_id),
+        mfa_session_token: None,
+        mfa_methods: None,
+    }))
+}
+
 // Get theme - matches Python get_theme
 pub async fn get_theme(
     Path(user_id): Path<i32>,
@@ -862,6 +1232,7 @@ async fn process_opml_import(
         Some("Starting OPML import".to_string()),
     ).await;
     
+    let mut imported = 0;
     for (index, podcast_url) in import_request.podcasts.iter().enumerate() {
         // Update progress in Redis
         let _ = redis_client.set_ex(&progress_key, &json!({
@@ -884,6 +1255,7 @@ async fn process_opml_import(
                 podcast_values.user_id = import_request.user_id;
                 match db_pool.add_podcast(&podcast_values, 0, None, None).await {
                     Ok(_) => {
+                        imported += 1;
                         tracing::info!("✅ Successfully imported podcast: {}", podcast_url);
                     }
                     Err(e) => {
@@ -909,6 +1281,11 @@ async fn process_opml_import(
     
     // Clear progress from Redis
     let _ = redis_client.delete(&progress_key).await;
+
+    crate::services::notifications::notify_quietly(&db_pool, import_request.user_id, crate::services::notifications::NotificationEvent::ImportFinished {
+        imported,
+        failed: total_podcasts - imported,
+    }).await;
 }
 
 // Get podcast values from URL - simplified version of Python get_podcast_values
@@ -953,6 +1330,7 @@ pub struct StoreStateRequest {
     pub client_id: String,
     pub origin_url: Option<String>, // URL user was on when they clicked OIDC login
     pub code_verifier: Option<String>, // PKCE code verifier for token exchange
+    pub nonce: Option<String>, // Nonce sent in the authorization request, echoed in the ID token
 }
 
 #[derive(Serialize, Deserialize)]
@@ -960,6 +1338,8 @@ struct StoredOidcState {
     client_id: String,
     origin_url: Option<String>,
     code_verifier: Option<String>, // PKCE code verifier
+    #[serde(default)]
+    nonce: Option<String>,
 }
 
 pub async fn store_oidc_state(
@@ -973,6 +1353,7 @@ pub async fn store_oidc_state(
         client_id: request.client_id,
         origin_url: request.origin_url,
         code_verifier: request.code_verifier,
+        nonce: request.nonce,
     };
     
     let state_json = serde_json::to_string(&stored_state)
@@ -1085,6 +1466,7 @@ pub struct OIDCCallbackQuery {
 }
 
 pub async fn oidc_callback(
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
     State(state): State<crate::AppState>,
     headers: HeaderMap,
     Query(query): Query<OIDCCallbackQuery>,
@@ -1104,16 +1486,16 @@ pub async fn oidc_callback(
     let auth_code = query.code.ok_or_else(|| AppError::bad_request("Missing authorization code"))?;
     let state_param = query.state.ok_or_else(|| AppError::bad_request("Missing state parameter"))?;
 
-    // Get client_id, origin_url, and code_verifier from state
-    let (client_id, stored_origin_url, code_verifier) = match state.redis_client.get_del(&format!("oidc_state:{}", state_param)).await {
+    // Get client_id, origin_url, code_verifier and nonce from state
+    let (client_id, stored_origin_url, code_verifier, nonce) = match state.redis_client.get_del(&format!("oidc_state:{}", state_param)).await {
         Ok(Some(state_json)) => {
             // Try to parse as new JSON format first
             if let Ok(stored_state) = serde_json::from_str::<StoredOidcState>(&state_json) {
                 tracing::info!("OIDC: Retrieved state for client_id={}", stored_state.client_id);
-                (stored_state.client_id, stored_state.origin_url, stored_state.code_verifier)
+                (stored_state.client_id, stored_state.origin_url, stored_state.code_verifier, stored_state.nonce)
             } else {
                 // Fallback to old format (just client_id string) for backwards compatibility
-                (state_json, None, None)
+                (state_json, None, None, None)
             }
         },
         Ok(None) => {
@@ -1164,6 +1546,12 @@ pub async fn oidc_callback(
     // Unpack provider details - EXACT match to Python unpacking
     let (provider_id, _client_id, client_secret, token_url, userinfo_url, name_claim, email_claim, username_claim, roles_claim, user_role, admin_role) = provider_tuple;
 
+    // Providers configured by issuer have their ID tokens verified; the rest still rely on userinfo
+    let discovery = match state.db_pool.get_oidc_provider_discovery(provider_id).await {
+        Ok(discovery) => discovery.map(|(_, discovery)| discovery),
+        Err(_) => return Ok(create_oidc_response(&frontend_base, "error=internal_error")),
+    };
+
     // Exchange authorization code for access token - EXACT match to Python
     let client = reqwest::Client::new();
     let mut form_data = vec![
@@ -1217,6 +1605,23 @@ pub async fn oidc_callback(
         None => return Ok(create_oidc_response(&frontend_base, "error=token_exchange_failed")),
     };
 
+    let id_token = match &discovery {
+        Some(discovery) => {
+            let Some(raw_id_token) = token_response.get("id_token").and_then(|v| v.as_str()) else {
+                tracing::error!("OIDC: Token response has no ID token");
+                return Ok(create_oidc_response(&frontend_base, "error=invalid_id_token"));
+            };
+            match oidc::verify_id_token(&state.redis_client, discovery, &client_id, raw_id_token, nonce.as_deref()).await {
+                Ok(verified) => Some((raw_id_token.to_string(), verified)),
+                Err(e) => {
+                    tracing::error!("OIDC: ID token rejected: {}", e);
+                    return Ok(create_oidc_response(&frontend_base, "error=invalid_id_token"));
+                }
+            }
+        }
+        None => None,
+    };
+
     // Get user info from OIDC provider - EXACT match to Python
     let userinfo_response = match client.get(&userinfo_url)
         .header("Authorization", format!("Bearer {}", access_token))
@@ -1234,6 +1639,14 @@ pub async fn oidc_callback(
         _ => return Ok(create_oidc_response(&frontend_base, "error=userinfo_failed")),
     };
 
+    // Userinfo has to describe the user the ID token was issued for
+    if let Some((_, verified)) = &id_token {
+        if userinfo_response.get("sub").and_then(|v| v.as_str()) != Some(verified.subject.as_str()) {
+            tracing::error!("OIDC: Userinfo subject does not match the ID token");
+            return Ok(create_oidc_response(&frontend_base, "error=userinfo_failed"));
+        }
+    }
+
     // Extract email with GitHub special handling - EXACT match to Python
     let email_field = email_claim
         .as_deref()
@@ -1282,9 +1695,17 @@ pub async fn oidc_callback(
         None => return Ok(create_oidc_response(&frontend_base, "error=email_required")),
     };
 
+    // Group claims come from the verified ID token when it carries them, otherwise userinfo
+    let roles = roles_claim.as_deref().filter(|s| !s.is_empty()).and_then(|claim| {
+        id_token.as_ref()
+            .and_then(|(_, verified)| verified.claims.get(claim))
+            .or_else(|| userinfo_response.get(claim))
+            .and_then(|v| v.as_array())
+    });
+
     // Role verification - EXACT match to Python
-    if let (Some(roles_claim), Some(user_role)) = (roles_claim.as_ref().filter(|s| !s.is_empty()), user_role.as_ref().filter(|s| !s.is_empty())) {
-        if let Some(roles) = userinfo_response.get(roles_claim).and_then(|v| v.as_array()) {
+    if let (Some(_), Some(user_role)) = (roles_claim.as_ref().filter(|s| !s.is_empty()), user_role.as_ref().filter(|s| !s.is_empty())) {
+        if let Some(roles) = roles {
             let has_user_role = roles.iter().any(|r| r.as_str() == Some(user_role));
             let has_admin_role = admin_role.as_ref().map_or(false, |admin_role| {
                 roles.iter().any(|r| r.as_str() == Some(admin_role))
@@ -1326,12 +1747,6 @@ pub async fn oidc_callback(
         .map(|s| s.to_string());
 
     let user_id = if let Some((user_id, _email, current_username, _fullname, _is_admin)) = existing_user {
-        // Existing user - EXACT match to Python
-        let api_key = match state.db_pool.get_user_api_key(user_id).await? {
-            Some(key) => key,
-            None => state.db_pool.create_api_key(user_id).await?,
-        };
-
         // Update user info - EXACT match to Python
         state.db_pool.set_fullname(user_id, &fullname).await?;
 
@@ -1345,66 +1760,122 @@ pub async fn oidc_callback(
         }
 
         // Update admin role - EXACT match to Python
-        if let (Some(roles_claim), Some(admin_role)) = (roles_claim.as_ref().filter(|s| !s.is_empty()), admin_role.as_ref().filter(|s| !s.is_empty())) {
-            if let Some(roles) = userinfo_response.get(roles_claim).and_then(|v| v.as_array()) {
-                let is_admin = roles.iter().any(|r| r.as_str() == Some(admin_role));
-                state.db_pool.set_isadmin(user_id, is_admin).await?;
-            }
+        if let (Some(roles), Some(admin_role)) = (roles, admin_role.as_ref().filter(|s| !s.is_empty())) {
+            let is_admin = roles.iter().any(|r| r.as_str() == Some(admin_role));
+            state.db_pool.set_isadmin(user_id, is_admin).await?;
         }
 
         tracing::info!("OIDC: Login successful for existing user");
-        return Ok(create_oidc_response(&frontend_base, &format!("api_key={}", api_key)));
+        user_id
     } else {
         // Create new user - EXACT match to Python
-        let mut final_username = username.unwrap_or_else(|| email.split('@').next().unwrap_or(&email).to_lowercase());
+        let base_username = username.unwrap_or_else(|| email.split('@').next().unwrap_or(&email).to_lowercase());
         
         // Username conflict resolution - EXACT match to Python
-        if state.db_pool.check_usernames(&final_username).await? {
-            let base_username = final_username.clone();
-            let mut counter = 1;
-            const MAX_ATTEMPTS: i32 = 10;
-            
-            while counter <= MAX_ATTEMPTS {
-                final_username = format!("{}_{}", base_username, counter);
-                if !state.db_pool.check_usernames(&final_username).await? {
-                    break;
-                }
-                counter += 1;
-                if counter > MAX_ATTEMPTS {
-                    return Ok(create_oidc_response(&frontend_base, "error=username_conflict"));
-                }
-            }
-        }
+        let Some(final_username) = unique_username(&state, &base_username).await? else {
+            return Ok(create_oidc_response(&frontend_base, "error=username_conflict"));
+        };
 
         // Create user - EXACT match to Python
         match state.db_pool.create_oidc_user(&email, &fullname, &final_username).await {
             Ok(user_id) => {
-                let api_key = state.db_pool.create_api_key(user_id).await?;
-                
                 // Set admin role for new user - EXACT match to Python
-                if let (Some(roles_claim), Some(admin_role)) = (roles_claim.as_ref().filter(|s| !s.is_empty()), admin_role.as_ref().filter(|s| !s.is_empty())) {
-                    if let Some(roles) = userinfo_response.get(roles_claim).and_then(|v| v.as_array()) {
-                        let is_admin = roles.iter().any(|r| r.as_str() == Some(admin_role));
-                        state.db_pool.set_isadmin(user_id, is_admin).await?;
-                    }
+                if let (Some(roles), Some(admin_role)) = (roles, admin_role.as_ref().filter(|s| !s.is_empty())) {
+                    let is_admin = roles.iter().any(|r| r.as_str() == Some(admin_role));
+                    state.db_pool.set_isadmin(user_id, is_admin).await?;
                 }
-                
+
+                tracing::info!("OIDC: Login successful for new user");
                 user_id
             }
             Err(_) => return Ok(create_oidc_response(&frontend_base, "error=user_creation_failed")),
         }
     };
 
-    let api_key = match state.db_pool.get_user_api_key(user_id).await? {
-        Some(key) => key,
-        None => state.db_pool.create_api_key(user_id).await?,
-    };
+    // Each sign-in gets its own session key, so logging out of the provider's session revokes only it
+    let api_key = start_login_session(&state, user_id, peer, &headers).await?;
+    if let Some((raw_id_token, verified)) = id_token {
+        let api_key_id = state.db_pool.get_api_key_policy(&api_key).await?
+            .ok_or_else(|| AppError::internal("Newly created API key not found"))?
+            .api_key_id;
+        state.db_pool.create_oidc_session(provider_id, user_id, api_key_id, &verified, &raw_id_token).await?;
+    }
 
     // Success - handle both web and mobile redirects
-    tracing::info!("OIDC: Login successful for new user");
     Ok(create_oidc_response(&frontend_base, &format!("api_key={}", api_key)))
 }
 
+// RP-initiated logout for an OIDC sign-in
+#[derive(Deserialize, Default)]
+pub struct OidcLogoutRequest {
+    pub post_logout_redirect_uri: Option<String>,
+}
+
+// Revoke the API key issued for the caller's OIDC sign-in and return the provider's
+// end-session URL, when it has one, for the client to send the browser to
+pub async fn oidc_logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    request: Option<Json<OidcLogoutRequest>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    let (provider_id, api_key_id, id_token) = state.db_pool.get_oidc_session(&api_key).await?
+        .ok_or_else(|| AppError::not_found("This API key was not issued for an OIDC sign-in"))?;
+    let Json(request) = request.unwrap_or_default();
+
+    let logout_url = match state.db_pool.get_oidc_provider_discovery(provider_id).await? {
+        Some((client_id, oidc::OidcDiscovery { end_session_url: Some(endpoint), .. })) => Some(oidc::end_session_url(
+            &endpoint,
+            &id_token,
+            &client_id,
+            request.post_logout_redirect_uri.as_deref(),
+        )?),
+        _ => None,
+    };
+
+    state.db_pool.delete_api_key(api_key_id).await?;
+    if let Err(e) = state.redis_client.forget_api_key_validation(&api_key).await {
+        tracing::warn!("OIDC: Failed to drop cached validation of a revoked key: {}", e);
+    }
+    Ok(Json(json!({ "success": true, "logout_url": logout_url })))
+}
+
+// Back-channel logout, registered with the provider as /api/auth/backchannel_logout/{provider_id}
+#[derive(Deserialize)]
+pub struct BackchannelLogoutForm {
+    pub logout_token: String,
+}
+
+// The provider posts a signed logout token naming a session or a user, and the API keys
+// of the matching sign-ins are revoked
+pub async fn oidc_backchannel_logout(
+    State(state): State<AppState>,
+    Path(provider_id): Path<i32>,
+    axum::Form(form): axum::Form<BackchannelLogoutForm>,
+) -> Result<axum::response::Response, AppError> {
+    let no_store = [(axum::http::header::CACHE_CONTROL, "no-store")];
+    let Some((client_id, discovery)) = state.db_pool.get_oidc_provider_discovery(provider_id).await? else {
+        return Err(AppError::not_found("OIDC provider not found or not configured by issuer"));
+    };
+
+    let logout = match oidc::verify_logout_token(&state.redis_client, &discovery, &client_id, &form.logout_token).await {
+        Ok(logout) => logout,
+        Err(e) => {
+            tracing::warn!("OIDC: Back-channel logout token rejected: {}", e);
+            return Ok((StatusCode::BAD_REQUEST, no_store, Json(json!({ "error": "invalid_request" }))).into_response());
+        }
+    };
+
+    let revoked = state.db_pool.revoke_oidc_sessions(provider_id, logout.sid.as_deref(), logout.subject.as_deref()).await?;
+    for api_key in &revoked {
+        if let Err(e) = state.redis_client.forget_api_key_validation(api_key).await {
+            tracing::warn!("OIDC: Failed to drop cached validation of a revoked key: {}", e);
+        }
+    }
+    tracing::info!("OIDC: Back-channel logout from provider {} revoked {} API key(s)", provider_id, revoked.len());
+    Ok((StatusCode::OK, no_store).into_response())
+}
+
 // Update user timezone
 pub async fn update_timezone(
     headers: HeaderMap,
@@ -1637,12 +2108,17 @@ pub async fn verify_and_reset_password(
     }
     
     // Reset the password (the new_password should already be hashed by the frontend)
-    let message = state.db_pool.reset_password_prompt(&request.email, &request.new_password).await?;
-    
-    match message {
-        Some(msg) => Ok(Json(VerifyAndResetPasswordResponse { message: msg })),
-        None => Err(AppError::internal("Failed to reset password")),
+    let message = state.db_pool.reset_password_prompt(&request.email, &request.new_password).await?
+        .ok_or_else(|| AppError::internal("Failed to reset password"))?;
+
+    // Whoever knew the old password may still be signed in, so every session ends with it
+    if let Some((user_id, ..)) = state.db_pool.get_user_by_email(&request.email).await? {
+        let revoked = state.db_pool.revoke_login_sessions(user_id, None, None).await?;
+        sessions::forget_keys(&state.redis_client, &revoked).await;
+        tracing::info!("Signed out {} sessions after a password reset for user {}", revoked.len(), user_id);
     }
+
+    Ok(Json(VerifyAndResetPasswordResponse { message }))
 }
 
 // Construct base URL from request headers (matches Python request.base_url)
@@ -1676,4 +2152,102 @@ fn construct_base_url_from_request(headers: &HeaderMap) -> Result<String, AppErr
     }
 
     Ok(base_url)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxy_config(admin_group: Option<&str>, link_existing: bool) -> ProxyAuthConfig {
+        ProxyAuthConfig {
+            trusted_proxies: vec!["127.0.0.1/32".parse().unwrap()],
+            user_header: "Remote-User".to_string(),
+            email_header: "Remote-Email".to_string(),
+            name_header: "Remote-Name".to_string(),
+            groups_header: "Remote-Groups".to_string(),
+            user_group: None,
+            admin_group: admin_group.map(str::to_string),
+            auto_create: true,
+            link_existing,
+            require_mfa: false,
+        }
+    }
+
+    fn proxy_identity(groups: &[&str]) -> ProxyIdentity {
+        ProxyIdentity {
+            username: "alice".to_string(),
+            email: None,
+            fullname: None,
+            groups: groups.iter().map(|g| g.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn proxy_only_links_its_own_accounts_unless_opted_in() {
+        assert!(proxy_may_link(&proxy_config(None, false), true));
+        assert!(!proxy_may_link(&proxy_config(None, false), false));
+        assert!(proxy_may_link(&proxy_config(None, true), false));
+    }
+
+    #[test]
+    fn admin_group_grants_but_never_revokes_admin() {
+        let config = proxy_config(Some("admins"), false);
+        assert!(proxy_grants_admin(&config, &proxy_identity(&["users", "admins"])));
+        assert!(!proxy_grants_admin(&config, &proxy_identity(&["users"])));
+        assert!(!proxy_grants_admin(&proxy_config(None, false), &proxy_identity(&["admins"])));
+    }
+
+    fn ldap_config(link_existing: bool) -> LdapConfig {
+        LdapConfig {
+            url: "ldap://127.0.0.1".to_string(),
+            starttls: false,
+            tls_skip_verify: false,
+            user_dn_template: Some("uid={username},ou=people,dc=example,dc=com".to_string()),
+            bind_dn: None,
+            bind_password: None,
+            search_base: None,
+            user_filter: "(uid={username})".to_string(),
+            username_attribute: "uid".to_string(),
+            email_attribute: "mail".to_string(),
+            name_attribute: "cn".to_string(),
+            group_attribute: "memberOf".to_string(),
+            group_search_base: None,
+            user_group: None,
+            admin_group: Some("cn=admins,dc=example,dc=com".to_string()),
+            link_existing,
+        }
+    }
+
+    fn ldap_identity(is_admin: Option<bool>) -> LdapIdentity {
+        LdapIdentity {
+            dn: "uid=alice,ou=people,dc=example,dc=com".to_string(),
+            username: "alice".to_string(),
+            email: Some("alice@example.com".to_string()),
+            fullname: "Alice".to_string(),
+            is_admin,
+        }
+    }
+
+    #[test]
+    fn ldap_only_links_its_own_accounts_unless_opted_in() {
+        assert!(ldap_may_link(&ldap_config(false), true));
+        assert!(!ldap_may_link(&ldap_config(false), false));
+        assert!(ldap_may_link(&ldap_config(true), false));
+    }
+
+    #[test]
+    fn ldap_admin_group_grants_but_never_revokes_admin() {
+        assert!(ldap_grants_admin(&ldap_identity(Some(true))));
+        assert!(!ldap_grants_admin(&ldap_identity(Some(false))));
+        assert!(!ldap_grants_admin(&ldap_identity(None)));
+    }
+
+    #[test]
+    fn new_accounts_try_the_directory_username_first() {
+        let candidates: Vec<String> = username_candidates("alice").collect();
+        assert_eq!(candidates.len(), 11);
+        assert_eq!(candidates[0], "alice");
+        assert_eq!(candidates[1], "alice_1");
+        assert_eq!(candidates[10], "alice_10");
+    }
+}
@@ -6,11 +6,19 @@ use crate::{
     error::{AppError, AppResult},
     handlers::{extract_api_key, validate_api_key},
     models::{BulkEpisodeActionRequest, BulkEpisodeActionResponse},
+    services::webhooks::{self, WebhookEventKind, WebhookSubject},
     AppState,
 };
 
 // Bulk episode action handlers for efficient mass operations
 
+// Send one webhook event per episode of a bulk action
+fn emit_episode_events(state: &AppState, kind: WebhookEventKind, user_id: i32, episode_ids: &[i32], is_youtube: bool) {
+    for &episode_id in episode_ids {
+        webhooks::emit(&state.db_pool, kind, Some(user_id), WebhookSubject::Episode { episode_id, is_youtube });
+    }
+}
+
 // Bulk mark episodes as completed
 pub async fn bulk_mark_episodes_completed(
     State(state): State<AppState>,
@@ -26,9 +34,11 @@ pub async fn bulk_mark_episodes_completed(
     }
 
     let is_youtube = request.is_youtube.unwrap_or(false);
+    let episode_ids = request.episode_ids.clone();
     let (processed_count, failed_count) = state.db_pool
         .bulk_mark_episodes_completed(request.episode_ids, request.user_id, is_youtube)
         .await?;
+    emit_episode_events(&state, WebhookEventKind::EpisodeCompleted, request.user_id, &episode_ids, is_youtube);
 
     let message = if failed_count > 0 {
         format!("Marked {} episodes as completed, {} failed", processed_count, failed_count)
@@ -58,9 +68,11 @@ pub async fn bulk_save_episodes(
     }
 
     let is_youtube = request.is_youtube.unwrap_or(false);
+    let episode_ids = request.episode_ids.clone();
     let (processed_count, failed_count) = state.db_pool
         .bulk_save_episodes(request.episode_ids, request.user_id, is_youtube)
         .await?;
+    emit_episode_events(&state, WebhookEventKind::EpisodeSaved, request.user_id, &episode_ids, is_youtube);
 
     let message = if failed_count > 0 {
         format!("Saved {} episodes, {} failed or already saved", processed_count, failed_count)
@@ -90,9 +102,11 @@ pub async fn bulk_queue_episodes(
     }
 
     let is_youtube = request.is_youtube.unwrap_or(false);
+    let episode_ids = request.episode_ids.clone();
     let (processed_count, failed_count) = state.db_pool
         .bulk_queue_episodes(request.episode_ids, request.user_id, is_youtube)
         .await?;
+    emit_episode_events(&state, WebhookEventKind::EpisodeQueued, request.user_id, &episode_ids, is_youtube);
 
     let message = if failed_count > 0 {
         format!("Queued {} episodes, {} failed or already queued", processed_count, failed_count)
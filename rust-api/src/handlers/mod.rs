@@ -12,6 +12,7 @@ pub mod sync;
 pub mod youtube;
 pub mod tasks;
 pub mod feed;
+pub mod websub;
 
 // Common handler utilities
 use axum::{
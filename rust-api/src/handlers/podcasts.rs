@@ -8,10 +8,24 @@ use std::collections::HashMap;
 
 use crate::{
     error::AppError,
-    handlers::{extract_api_key, validate_api_key, check_user_access},
+    handlers::{extract_api_key, validate_api_key, check_user_access, check_admin_access},
+    services::webhooks::{self, WebhookEventKind, WebhookSubject},
     AppState,
 };
 
+// Tell webhooks about a podcast a user subscribed to or is about to unsubscribe from. The
+// podcast's details are read up front since they are gone once it has been removed.
+async fn emit_subscription_event(state: &AppState, kind: WebhookEventKind, user_id: i32, podcast_id: Option<i32>) {
+    let Some(podcast_id) = podcast_id else {
+        return;
+    };
+    match state.db_pool.get_webhook_podcast(podcast_id).await {
+        Ok(Some(podcast)) => webhooks::emit(&state.db_pool, kind, Some(user_id), WebhookSubject::Podcast(podcast)),
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to look up podcast {} for {} webhooks: {}", podcast_id, kind.as_str(), e),
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[allow(non_snake_case)]
 pub struct Episode {
@@ -269,6 +283,7 @@ pub async fn add_podcast(
         None, // username
         None, // password
     ).await?;
+    emit_subscription_event(&state, WebhookEventKind::SubscriptionAdded, user_id, Some(podcast_id)).await;
     
     // Spawn background task to add episodes
     let _task_id = state.task_spawner.spawn_add_podcast_episodes_task(
@@ -309,6 +324,9 @@ pub async fn remove_podcast(
         return Err(AppError::forbidden("You can only remove your own podcasts!"));
     }
 
+    let podcast_id = state.db_pool.get_podcast_id_by_feed_url(request.user_id, &request.podcast_url).await?;
+    emit_subscription_event(&state, WebhookEventKind::SubscriptionRemoved, request.user_id, podcast_id).await;
+
     // Remove podcast from database
     state.db_pool.remove_podcast(
         &request.podcast_name,
@@ -341,6 +359,8 @@ pub async fn remove_podcast_id(
         return Err(AppError::forbidden("You can only remove your own podcasts!"));
     }
 
+    emit_subscription_event(&state, WebhookEventKind::SubscriptionRemoved, request.user_id, Some(request.podcast_id)).await;
+
     // Remove podcast from database
     state.db_pool.remove_podcast_id(request.podcast_id, request.user_id).await?;
     
@@ -369,6 +389,9 @@ pub async fn remove_podcast_by_name(
         return Err(AppError::forbidden("You can only remove your own podcasts!"));
     }
 
+    let podcast_id = state.db_pool.get_podcast_id_by_feed_url(request.user_id, &request.podcast_url).await?;
+    emit_subscription_event(&state, WebhookEventKind::SubscriptionRemoved, request.user_id, podcast_id).await;
+
     // Remove podcast from database using the comprehensive method
     state.db_pool.remove_podcast_by_name_url(
         &request.podcast_name,
@@ -545,6 +568,10 @@ pub async fn queue_episode(
 
     // Queue the episode
     state.db_pool.queue_episode(request.episode_id, request.user_id, request.is_youtube).await?;
+    webhooks::emit(&state.db_pool, WebhookEventKind::EpisodeQueued, Some(request.user_id), WebhookSubject::Episode {
+        episode_id: request.episode_id,
+        is_youtube: request.is_youtube,
+    });
     
     let message = if request.is_youtube {
         "Video queued successfully"
@@ -661,6 +688,10 @@ pub async fn save_episode(
 
     // Save the episode
     state.db_pool.save_episode(request.episode_id, request.user_id, request.is_youtube).await?;
+    webhooks::emit(&state.db_pool, WebhookEventKind::EpisodeSaved, Some(request.user_id), WebhookSubject::Episode {
+        episode_id: request.episode_id,
+        is_youtube: request.is_youtube,
+    });
     
     let message = if request.is_youtube {
         "Video saved!"
@@ -751,12 +782,18 @@ pub async fn add_history(
     }
 
     // Record the history
-    state.db_pool.record_podcast_history(
+    let first_play = state.db_pool.record_podcast_history(
         request.episode_id, 
         request.user_id, 
         request.episode_pos, 
         request.is_youtube
     ).await?;
+    if first_play {
+        webhooks::emit(&state.db_pool, WebhookEventKind::EpisodePlayed, Some(request.user_id), WebhookSubject::Episode {
+            episode_id: request.episode_id,
+            is_youtube: request.is_youtube,
+        });
+    }
     
     Ok(Json(crate::models::HistoryResponse {
         detail: "History recorded successfully.".to_string(),
@@ -894,6 +931,9 @@ pub async fn download_podcast(
         return Err(AppError::forbidden("You can only download content for yourself!"));
     }
 
+    // Refuse new downloads once the user's quota is used up
+    state.storage.allowance(&state.db_pool, request.user_id).await?.check(0)?;
+
     let is_youtube = request.is_youtube.unwrap_or(false);
     
     // Check if already downloaded
@@ -938,8 +978,10 @@ pub async fn delete_episode(
 
     let is_youtube = request.is_youtube.unwrap_or(false);
     
-    // Delete the episode
-    state.db_pool.delete_episode(request.user_id, request.episode_id, is_youtube).await?;
+    // Delete the episode, then its file from download storage
+    if let Some(location) = state.db_pool.delete_episode(request.user_id, request.episode_id, is_youtube).await? {
+        state.storage.delete(&location).await?;
+    }
     
     let content_type = if is_youtube { "Video" } else { "Episode" };
     
@@ -967,6 +1009,9 @@ pub async fn download_all_podcast(
         return Err(AppError::forbidden("You can only download content for yourself!"));
     }
 
+    // Refuse new downloads once the user's quota is used up
+    state.storage.allowance(&state.db_pool, request.user_id).await?.check(0)?;
+
     let is_youtube = request.is_youtube.unwrap_or(false);
     
     // Queue the download all task using the task system
@@ -1009,6 +1054,110 @@ pub async fn download_status(
     Ok(Json(serde_json::json!(status)))
 }
 
+// List a user's download queue: waiting, running, paused and failed episode downloads
+pub async fn get_download_queue(
+    Path(user_id): Path<i32>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, user_id).await? {
+        return Err(AppError::forbidden("You can only view your own download queue!"));
+    }
+
+    let queue = state.db_pool.get_download_queue(user_id).await?;
+    Ok(Json(serde_json::json!({ "queue": queue })))
+}
+
+// Which queued downloads a pause/resume/cancel applies to; with neither ID set, all of the user's
+#[derive(Deserialize)]
+pub struct DownloadQueueActionRequest {
+    pub user_id: i32,
+    pub queue_id: Option<i32>,
+    pub episode_id: Option<i32>,
+}
+
+#[derive(Clone, Copy)]
+enum DownloadQueueAction {
+    Pause,
+    Resume,
+    Cancel,
+}
+
+async fn apply_download_queue_action(
+    headers: HeaderMap,
+    state: AppState,
+    request: DownloadQueueActionRequest,
+    action: DownloadQueueAction,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only manage your own downloads!"));
+    }
+
+    let items: Vec<_> = state.db_pool.get_download_queue(request.user_id).await?
+        .into_iter()
+        .filter(|item| request.queue_id.is_none_or(|id| item.queue_id == id))
+        .filter(|item| request.episode_id.is_none_or(|id| item.episode_id == id))
+        .collect();
+    if items.is_empty() && (request.queue_id.is_some() || request.episode_id.is_some()) {
+        return Err(AppError::not_found("Download not found in queue"));
+    }
+
+    let mut affected = 0;
+    for item in &items {
+        let changed = match action {
+            DownloadQueueAction::Pause => state.download_queue.pause(item).await?,
+            DownloadQueueAction::Resume => state.download_queue.resume(item).await?,
+            DownloadQueueAction::Cancel => state.download_queue.cancel(item).await?,
+        };
+        if changed {
+            affected += 1;
+        }
+    }
+
+    let verb = match action {
+        DownloadQueueAction::Pause => "Paused",
+        DownloadQueueAction::Resume => "Resumed",
+        DownloadQueueAction::Cancel => "Cancelled",
+    };
+    Ok(Json(serde_json::json!({
+        "detail": format!("{} {} download(s).", verb, affected),
+        "affected": affected
+    })))
+}
+
+// Pause queued or running downloads; partial files are kept for resuming
+pub async fn pause_download(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(request): Json<DownloadQueueActionRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    apply_download_queue_action(headers, state, request, DownloadQueueAction::Pause).await
+}
+
+// Put paused or failed downloads back in the queue
+pub async fn resume_download(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(request): Json<DownloadQueueActionRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    apply_download_queue_action(headers, state, request, DownloadQueueAction::Resume).await
+}
+
+// Remove downloads from the queue, discarding partial files
+pub async fn cancel_download(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Json(request): Json<DownloadQueueActionRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    apply_download_queue_action(headers, state, request, DownloadQueueAction::Cancel).await
+}
+
 // Query parameters for podcast_episodes
 #[derive(Deserialize)]
 pub struct PodcastEpisodesQuery {
@@ -1455,6 +1604,10 @@ pub async fn mark_episode_completed(
             request.user_id,
             request.is_youtube.unwrap_or(false)
         ).await?;
+        webhooks::emit(&state.db_pool, WebhookEventKind::EpisodeCompleted, Some(request.user_id), WebhookSubject::Episode {
+            episode_id: request.episode_id,
+            is_youtube: request.is_youtube.unwrap_or(false),
+        });
         
         Ok(Json(serde_json::json!({ "detail": "Episode marked as completed." })))
     } else {
@@ -1584,14 +1737,16 @@ pub async fn get_pinepods_version(
     Ok(Json(serde_json::json!({ "data": version })))
 }
 
-// Request for search_data - matches Python SearchPodcastData
+// Request for search_data - matches Python SearchPodcastData, plus optional filters and paging
 #[derive(Deserialize)]
 pub struct SearchDataRequest {
     pub search_term: String,
     pub user_id: i32,
+    #[serde(flatten)]
+    pub filters: crate::services::search::SearchFilters,
 }
 
-// Search data - matches Python search_data endpoint exactly
+// Search data - ranked full-text search; "data" keeps the Python shape, paging fields sit alongside it
 pub async fn search_data(
     headers: HeaderMap,
     State(state): State<AppState>,
@@ -1605,9 +1760,13 @@ pub async fn search_data(
         return Err(AppError::unauthorized("Your API key is either invalid or does not have correct permission"));
     }
 
-    let result = state.db_pool.search_data(&request.search_term, request.user_id).await?;
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only search your own library"));
+    }
+
+    let results = state.db_pool.search_data(&request.search_term, request.user_id, &request.filters).await?;
     
-    Ok(Json(serde_json::json!({ "data": result })))
+    Ok(Json(serde_json::to_value(results)?))
 }
 
 // Request for fetch_transcript - proxy to avoid CORS issues
@@ -1658,6 +1817,34 @@ pub async fn fetch_transcript(
     }
 }
 
+// Query parameters for get_episode_transcript
+#[derive(Deserialize)]
+pub struct EpisodeTranscriptQuery {
+    pub episode_id: i32,
+    pub user_id: i32,
+}
+
+// Get the transcript stored during feed refresh, as timestamped segments
+pub async fn get_episode_transcript(
+    Query(query): Query<EpisodeTranscriptQuery>,
+    headers: HeaderMap,
+    State(state): State<AppState>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    let is_valid = validate_api_key(&state, &api_key).await?;
+    if !is_valid {
+        return Err(AppError::unauthorized("Your API key is either invalid or does not have correct permission"));
+    }
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view transcripts for your own episodes!"));
+    }
+
+    let transcript = state.db_pool.get_episode_transcript(query.episode_id, query.user_id).await?;
+    
+    Ok(Json(transcript))
+}
+
 // Query struct for home_overview
 #[derive(Deserialize)]
 pub struct HomeOverviewQuery {
@@ -1988,12 +2175,17 @@ pub async fn remove_youtube_channel(
         }
     }
 
-    // Remove the YouTube channel
-    state.db_pool.remove_youtube_channel_by_url(
+    // Remove the YouTube channel, then its downloaded audio
+    let video_ids = state.db_pool.remove_youtube_channel_by_url(
         &data.channel_name,
         &data.channel_url,
         data.user_id,
     ).await?;
+    for video_id in &video_ids {
+        if let Err(e) = state.storage.delete_youtube_audio(video_id).await {
+            println!("Failed to delete audio for video {}: {}", video_id, e);
+        }
+    }
 
     Ok(Json(serde_json::json!({ "success": true })))
 }
@@ -2007,11 +2199,12 @@ pub struct StreamQuery {
     pub source_type: Option<String>,
 }
 
-// Stream episode - matches Python stream_episode function exactly
+// Stream episode - serves the downloaded file, or proxies the remote enclosure with Range support
 pub async fn stream_episode(
     State(state): State<crate::AppState>,
     Path(episode_id): Path<i32>,
     Query(query): Query<StreamQuery>,
+    headers: HeaderMap,
 ) -> Result<axum::response::Response, AppError> {
     let api_key = &query.api_key;
     println!("Stream request for episode {} with api_key {} and user_id {}", episode_id, api_key, query.user_id);
@@ -2020,12 +2213,15 @@ pub async fn stream_episode(
     let mut is_valid = false;
     let mut is_web_key = false;
     let mut key_user_id = None;
+    // Whose key this is, RSS or API; feed credentials are only used for the key's owner
+    let mut caller_user_id = None;
 
     println!("Trying RSS key validation first");
     match state.db_pool.get_rss_key_if_valid(api_key, None).await {
         Ok(Some(rss_info)) => {
             println!("Valid RSS key for user {}", rss_info.user_id);
             is_valid = true;
+            caller_user_id = Some(rss_info.user_id);
             // Don't set key_user_id for RSS keys - they don't need permission checks
         }
         Ok(None) => {
@@ -2048,6 +2244,7 @@ pub async fn stream_episode(
                         is_valid = true;
                         is_web_key = state.db_pool.is_web_key(api_key).await?;
                         key_user_id = Some(user_id);
+                        caller_user_id = Some(user_id);
                     }
                     Err(e) => {
                         println!("Failed to get user_id for API key (might be RSS key): {}", e);
@@ -2073,27 +2270,39 @@ pub async fn stream_episode(
     // RSS keys don't need user permission checks - they can stream any episode
 
     // Choose which lookup to use based on source_type
-    let file_path = if query.source_type.as_deref() == Some("youtube") {
+    let location = if query.source_type.as_deref() == Some("youtube") {
         println!("Looking up YouTube video file path");
-        state.db_pool.get_youtube_video_location(episode_id, query.user_id).await?
+        match state.db_pool.get_youtube_video_id(episode_id, query.user_id).await? {
+            Some(youtube_id) => state.storage.find_youtube_audio(&youtube_id).await?,
+            None => None,
+        }
     } else {
         println!("Looking up regular episode file path");
         state.db_pool.get_download_location(episode_id, query.user_id).await?
     };
+    let stored = match location {
+        Some(location) => state.storage.resolve(&location).await?,
+        None => None,
+    };
 
-    if let Some(path) = file_path {
-        println!("Found file at: {}", path);
+    if let Some(crate::services::storage::StoredObject::Remote(url)) = stored {
+        // Object storage download - proxy its presigned URL so Range requests still work
+        crate::handlers::proxy::proxy_stored_audio(&url, &headers).await
+    } else if let Some(crate::services::storage::StoredObject::Local(path)) = stored {
+        println!("Found file at: {}", path.display());
         
         // Use tower_http's ServeFile for proper file serving with range support
         use tower_http::services::ServeFile;
         use tower::ServiceExt;
         
         let service = ServeFile::new(&path);
-        let request = axum::http::Request::builder()
+        let mut request = axum::http::Request::builder()
             .method("GET")
             .uri("/")
             .body(axum::body::Body::empty())
             .map_err(|e| AppError::external_error(&format!("Failed to build request: {}", e)))?;
+        // Pass the player's Range/If-Range/ETag headers so ServeFile can answer with 206/304
+        *request.headers_mut() = crate::handlers::proxy::forwarded_request_headers(&headers);
             
         let response = service.oneshot(request).await
             .map_err(|e| AppError::external_error(&format!("Failed to serve file: {}", e)))?;
@@ -2104,8 +2313,33 @@ pub async fn stream_episode(
         let response = axum::response::Response::from_parts(parts, body);
             
         Ok(response)
-    } else {
+    } else if query.source_type.as_deref() == Some("youtube") {
         Err(AppError::not_found("Episode not found or not downloaded"))
+    } else {
+        // Not downloaded - proxy the original enclosure so players can still seek
+        let (episode_url, username, password) = state.db_pool
+            .get_episode_stream_source(episode_id, query.user_id)
+            .await?
+            .filter(|(url, _, _)| !url.is_empty())
+            .ok_or_else(|| AppError::not_found("Episode not found"))?;
+
+        let caller_owns_feed = is_web_key || caller_user_id == Some(query.user_id);
+        let credentials = stream_credentials(caller_owns_feed, username.as_deref(), password.as_deref())?;
+        crate::handlers::proxy::proxy_audio(&episode_url, &headers, credentials).await
+    }
+}
+
+// A private feed's credentials only go upstream for the feed's owner; anyone else
+// streaming its episodes (with an RSS key, say) is turned away rather than riding on them
+fn stream_credentials<'a>(
+    caller_owns_feed: bool,
+    username: Option<&'a str>,
+    password: Option<&'a str>,
+) -> Result<Option<(&'a str, &'a str)>, AppError> {
+    match (username, password) {
+        (Some(user), Some(pass)) if caller_owns_feed => Ok(Some((user, pass))),
+        (Some(_), Some(_)) => Err(AppError::forbidden("This episode is from a private feed you don't own")),
+        _ => Ok(None),
     }
 }
 
@@ -2337,6 +2571,152 @@ pub async fn update_feed_cutoff_days(
     }
 }
 
+// Query parameters for get_refresh_schedule
+#[derive(Deserialize)]
+pub struct RefreshScheduleQuery {
+    pub podcast_id: i32,
+    pub user_id: i32,
+}
+
+// Get when a podcast will next be refreshed and why
+pub async fn get_refresh_schedule(
+    State(state): State<crate::AppState>,
+    headers: HeaderMap,
+    Query(query): Query<RefreshScheduleQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    let is_valid = validate_api_key(&state, &api_key).await?;
+    if !is_valid {
+        return Err(AppError::unauthorized("Your API key is either invalid or does not have correct permission"));
+    }
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view settings of your own podcasts!"));
+    }
+
+    match state.db_pool.get_refresh_schedule(query.podcast_id, query.user_id).await? {
+        Some(schedule) => Ok(Json(schedule)),
+        None => Err(AppError::not_found("Podcast not found")),
+    }
+}
+
+// Query parameters for get_feed_health
+#[derive(Deserialize)]
+pub struct FeedHealthQuery {
+    pub user_id: i32,
+    pub include_healthy: Option<bool>,
+}
+
+// List subscriptions whose feeds are failing or have moved, so users can fix or drop them
+pub async fn get_feed_health(
+    State(state): State<crate::AppState>,
+    headers: HeaderMap,
+    Query(query): Query<FeedHealthQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    let is_valid = validate_api_key(&state, &api_key).await?;
+    if !is_valid {
+        return Err(AppError::unauthorized("Your API key is either invalid or does not have correct permission"));
+    }
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view the health of your own podcasts!"));
+    }
+
+    let feeds = state.db_pool.get_feed_health(query.user_id, query.include_healthy.unwrap_or(false)).await?;
+    let unhealthy = feeds.iter().filter(|feed| !feed.is_healthy()).count();
+    Ok(Json(serde_json::json!({
+        "feeds": feeds,
+        "unhealthy_count": unhealthy,
+    })))
+}
+
+// Merge duplicate episodes across every podcast - admin only. Refreshes already do this once per
+// podcast; this reruns it, e.g. after a feed re-issued its episodes under new GUIDs
+pub async fn dedupe_episodes(
+    State(state): State<crate::AppState>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    let is_valid = validate_api_key(&state, &api_key).await?;
+    if !is_valid {
+        return Err(AppError::unauthorized("Your API key is either invalid or does not have correct permission"));
+    }
+
+    if !check_admin_access(&state, &api_key).await? {
+        return Err(AppError::forbidden("Admin access required"));
+    }
+
+    let db_pool = state.db_pool.clone();
+    let task_id = state.task_spawner.spawn_progress_task(
+        "dedupe_episodes".to_string(),
+        0, // System user
+        move |reporter| async move {
+            let podcast_ids = db_pool.get_all_podcast_ids().await?;
+            let mut merged = 0;
+            for (index, podcast_id) in podcast_ids.iter().enumerate() {
+                let progress = 100.0 * (index as f64) / (podcast_ids.len() as f64);
+                reporter.update_progress(progress, Some(format!("Checking podcast {}/{}", index + 1, podcast_ids.len()))).await?;
+                match db_pool.dedupe_podcast_episodes(*podcast_id).await {
+                    Ok(count) => merged += count,
+                    Err(e) => tracing::warn!("Episode deduplication failed for podcast {}: {}", podcast_id, e),
+                }
+            }
+
+            reporter.update_progress(100.0, Some(format!("Merged {} duplicate episodes", merged))).await?;
+            Ok(serde_json::json!({"merged_episodes": merged}))
+        },
+    ).await?;
+
+    Ok(Json(serde_json::json!({
+        "detail": "Episode deduplication started",
+        "task_id": task_id
+    })))
+}
+
+// Request struct for set_refresh_interval
+#[derive(Deserialize)]
+pub struct SetRefreshIntervalData {
+    pub podcast_id: i32,
+    pub user_id: i32,
+    pub interval_minutes: Option<i32>, // None returns the podcast to the adaptive schedule
+}
+
+// Pin how often a podcast is refreshed, overriding the adaptive schedule
+pub async fn set_refresh_interval(
+    State(state): State<crate::AppState>,
+    headers: HeaderMap,
+    Json(data): Json<SetRefreshIntervalData>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    use crate::services::refresh_schedule::{MAX_REFRESH_MINUTES, MIN_REFRESH_MINUTES};
+
+    let api_key = extract_api_key(&headers)?;
+    let is_valid = validate_api_key(&state, &api_key).await?;
+    if !is_valid {
+        return Err(AppError::unauthorized("Your API key is either invalid or does not have correct permission"));
+    }
+
+    if !check_user_access(&state, &api_key, data.user_id).await? {
+        return Err(AppError::forbidden("You can only modify settings of your own podcasts!"));
+    }
+
+    if let Some(minutes) = data.interval_minutes {
+        if !(MIN_REFRESH_MINUTES..=MAX_REFRESH_MINUTES).contains(&(minutes as i64)) {
+            return Err(AppError::bad_request(format!(
+                "interval_minutes must be between {} and {}",
+                MIN_REFRESH_MINUTES, MAX_REFRESH_MINUTES
+            )));
+        }
+    }
+
+    if !state.db_pool.set_refresh_interval_override(data.podcast_id, data.user_id, data.interval_minutes).await? {
+        return Err(AppError::not_found("Podcast not found"));
+    }
+
+    let schedule = state.db_pool.get_refresh_schedule(data.podcast_id, data.user_id).await?;
+    Ok(Json(serde_json::json!({ "detail": "Refresh interval updated", "schedule": schedule })))
+}
+
 // Query struct for fetch_podcast_feed
 #[derive(Deserialize)]
 pub struct FetchPodcastFeedQuery {
@@ -2551,4 +2931,21 @@ pub async fn get_merged_podcasts(
     Ok(Json(MergedPodcastsResponse {
         merged_podcast_ids: merged_ids,
     }))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feed_credentials_are_only_sent_for_the_owner() {
+        assert_eq!(stream_credentials(true, Some("me"), Some("pw")).unwrap(), Some(("me", "pw")));
+        assert!(stream_credentials(false, Some("me"), Some("pw")).is_err());
+    }
+
+    #[test]
+    fn public_enclosures_stream_for_anyone() {
+        assert_eq!(stream_credentials(false, None, None).unwrap(), None);
+        assert_eq!(stream_credentials(false, Some("me"), None).unwrap(), None);
+        assert_eq!(stream_credentials(true, None, None).unwrap(), None);
+    }
+}
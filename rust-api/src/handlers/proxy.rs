@@ -1,10 +1,15 @@
 use axum::{
     extract::Query,
-    http::{HeaderMap, StatusCode},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
 };
 use serde::Deserialize;
 
+use crate::{
+    error::{AppError, AppResult},
+    services::outbound,
+};
+
 #[derive(Deserialize)]
 pub struct ImageProxyQuery {
     pub url: String,
@@ -75,4 +80,264 @@ fn is_valid_image_url(url: &str) -> bool {
     } else {
         false
     }
-}
\ No newline at end of file
+}
+
+// Player headers passed through to the upstream enclosure so seeking and revalidation work
+const FORWARDED_REQUEST_HEADERS: [header::HeaderName; 5] = [
+    header::RANGE,
+    header::IF_RANGE,
+    header::IF_NONE_MATCH,
+    header::IF_MODIFIED_SINCE,
+    header::ACCEPT,
+];
+
+// Upstream headers passed back to the player
+const FORWARDED_RESPONSE_HEADERS: [header::HeaderName; 6] = [
+    header::CONTENT_LENGTH,
+    header::CONTENT_RANGE,
+    header::ACCEPT_RANGES,
+    header::ETAG,
+    header::LAST_MODIFIED,
+    header::CACHE_CONTROL,
+];
+
+// Tracking prefixes (podtrac, chartable, ...) commonly chain a few redirects before the CDN
+const MAX_AUDIO_REDIRECTS: usize = 8;
+
+/// Copy the range/conditional headers a player sent into a new request
+pub fn forwarded_request_headers(headers: &HeaderMap) -> HeaderMap {
+    let mut forwarded = HeaderMap::new();
+    for name in FORWARDED_REQUEST_HEADERS {
+        if let Some(value) = headers.get(&name) {
+            forwarded.insert(name, value.clone());
+        }
+    }
+    forwarded
+}
+
+/// Stream a remote enclosure to the player, honouring Range/If-Range/ETag and
+/// returning the upstream 200/206/304/416 as-is. The enclosure, and anything it
+/// redirects to, must be on a public address.
+pub async fn proxy_audio(
+    url: &str,
+    request_headers: &HeaderMap,
+    credentials: Option<(&str, &str)>,
+) -> AppResult<Response> {
+    let parsed = url::Url::parse(url).map_err(|_| AppError::bad_request("Episode has an invalid enclosure URL"))?;
+    outbound::check_url(&parsed).map_err(|e| AppError::bad_request(format!("Episode enclosure is not allowed: {}", e)))?;
+
+    let client = outbound::client_builder()
+        .redirect(outbound::redirect_policy(MAX_AUDIO_REDIRECTS))
+        .connect_timeout(std::time::Duration::from_secs(15))
+        .user_agent("PinePods/1.0")
+        .build()?;
+    forward_audio(&client, parsed, request_headers, credentials).await
+}
+
+/// Stream a download kept in object storage, from the presigned URL the storage backend
+/// handed out. Unlike enclosures it may be on the local network, since the admin set
+/// the storage up.
+pub async fn proxy_stored_audio(url: &str, request_headers: &HeaderMap) -> AppResult<Response> {
+    let parsed = url::Url::parse(url).map_err(|_| AppError::internal("Object storage returned an invalid URL"))?;
+
+    // Only follow http(s) redirects, and not forever
+    let redirect_policy = reqwest::redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_AUDIO_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if !matches!(attempt.url().scheme(), "http" | "https") {
+            attempt.stop()
+        } else {
+            attempt.follow()
+        }
+    });
+
+    let client = reqwest::Client::builder()
+        .redirect(redirect_policy)
+        .connect_timeout(std::time::Duration::from_secs(15))
+        .user_agent("PinePods/1.0")
+        .build()?;
+    forward_audio(&client, parsed, request_headers, None).await
+}
+
+async fn forward_audio(
+    client: &reqwest::Client,
+    url: url::Url,
+    request_headers: &HeaderMap,
+    credentials: Option<(&str, &str)>,
+) -> AppResult<Response> {
+    // reqwest drops the Authorization header when a redirect crosses hosts
+    let mut request = client.get(url.clone()).headers(forwarded_request_headers(request_headers));
+    if let Some((username, password)) = credentials {
+        request = request.basic_auth(username, Some(password));
+    }
+
+    let upstream = request.send().await?;
+    let status = upstream.status();
+    if !(status.is_success() || status == StatusCode::NOT_MODIFIED || status == StatusCode::RANGE_NOT_SATISFIABLE) {
+        tracing::warn!("Upstream audio request for {} returned {}", url, status);
+        return Ok((StatusCode::BAD_GATEWAY, format!("Upstream returned {}", status)).into_response());
+    }
+
+    let mut headers = HeaderMap::new();
+    for name in FORWARDED_RESPONSE_HEADERS {
+        if let Some(value) = upstream.headers().get(&name) {
+            headers.insert(name, value.clone());
+        }
+    }
+    if !headers.contains_key(header::ACCEPT_RANGES) && status == StatusCode::PARTIAL_CONTENT {
+        headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    }
+
+    let content_type = audio_content_type(
+        upstream.headers().get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()),
+        upstream.url().path(),
+    );
+    if let Ok(value) = HeaderValue::from_str(&content_type) {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+
+    let body = if status == StatusCode::NOT_MODIFIED {
+        axum::body::Body::empty()
+    } else {
+        axum::body::Body::from_stream(upstream.bytes_stream())
+    };
+
+    let mut response = Response::new(body);
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+    Ok(response)
+}
+
+/// Enclosures are often served as octet-stream; prefer a real audio/video type so players
+/// and podcast apps consuming the generated RSS feed agree on what they're getting.
+pub fn audio_content_type(upstream: Option<&str>, path: &str) -> String {
+    let upstream = upstream
+        .map(|ct| ct.trim().to_string())
+        .filter(|ct| !ct.is_empty() && !ct.ends_with("/octet-stream") && ct != "application/x-download");
+
+    upstream.unwrap_or_else(|| {
+        mime_guess::from_path(path)
+            .first()
+            .filter(|mime| matches!(mime.type_().as_str(), "audio" | "video"))
+            .map(|mime| mime.essence_str().to_string())
+            .unwrap_or_else(|| "audio/mpeg".to_string())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{response::Redirect, routing::get, Router};
+
+    const AUDIO: &[u8] = b"0123456789";
+
+    // An enclosure host that answers ranges and revalidation the way CDNs do
+    async fn enclosure(headers: HeaderMap) -> Response {
+        let header = |name: header::HeaderName| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        if header(header::IF_NONE_MATCH).as_deref() == Some("\"v1\"") {
+            return (StatusCode::NOT_MODIFIED, [(header::ETAG, "\"v1\"")]).into_response();
+        }
+        let common = [(header::CONTENT_TYPE, "application/octet-stream"), (header::ETAG, "\"v1\"")];
+        match header(header::RANGE).as_deref() {
+            Some("bytes=2-5") => (
+                StatusCode::PARTIAL_CONTENT,
+                common,
+                [(header::CONTENT_RANGE, "bytes 2-5/10")],
+                &AUDIO[2..6],
+            ).into_response(),
+            Some(_) => (StatusCode::RANGE_NOT_SATISFIABLE, [(header::CONTENT_RANGE, "bytes */10")]).into_response(),
+            None => (common, [(header::ACCEPT_RANGES, "bytes")], AUDIO).into_response(),
+        }
+    }
+
+    async fn serve() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new()
+            .route("/episode.ogg", get(enclosure))
+            .route("/tracking/episode.ogg", get(|| async { Redirect::temporary("/episode.ogg") }))
+            .route("/gone.mp3", get(|| async { StatusCode::NOT_FOUND }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        base
+    }
+
+    fn request(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        pairs.iter().map(|(name, value)| (name.clone(), HeaderValue::from_str(value).unwrap())).collect()
+    }
+
+    async fn body(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()
+    }
+
+    #[test]
+    fn only_range_and_conditional_headers_are_forwarded() {
+        let headers = request(&[
+            (header::RANGE, "bytes=0-"),
+            (header::IF_NONE_MATCH, "\"v1\""),
+            (header::AUTHORIZATION, "Bearer secret"),
+            (header::COOKIE, "session=1"),
+        ]);
+        let forwarded = forwarded_request_headers(&headers);
+        assert_eq!(forwarded.len(), 2);
+        assert_eq!(forwarded[header::RANGE], "bytes=0-");
+        assert!(!forwarded.contains_key(header::AUTHORIZATION));
+    }
+
+    #[test]
+    fn octet_streams_get_a_type_from_the_path() {
+        assert_eq!(audio_content_type(Some("audio/mp4"), "/a.mp3"), "audio/mp4");
+        assert_eq!(audio_content_type(Some("application/octet-stream"), "/a.ogg"), "audio/ogg");
+        assert_eq!(audio_content_type(Some("application/x-download"), "/a.mp3"), "audio/mpeg");
+        assert_eq!(audio_content_type(None, "/video.mp4"), "video/mp4");
+        assert_eq!(audio_content_type(Some(" "), "/notes.txt"), "audio/mpeg");
+    }
+
+    #[tokio::test]
+    async fn ranges_pass_through() {
+        let base = serve().await;
+        let url = format!("{}/episode.ogg", base);
+
+        let full = proxy_stored_audio(&url, &HeaderMap::new()).await.unwrap();
+        assert_eq!(full.status(), StatusCode::OK);
+        assert_eq!(full.headers()[header::CONTENT_TYPE], "audio/ogg");
+        assert_eq!(full.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(body(full).await, AUDIO);
+
+        let partial = proxy_stored_audio(&url, &request(&[(header::RANGE, "bytes=2-5")])).await.unwrap();
+        assert_eq!(partial.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(partial.headers()[header::CONTENT_RANGE], "bytes 2-5/10");
+        assert_eq!(partial.headers()[header::ACCEPT_RANGES], "bytes");
+        assert_eq!(partial.headers()[header::ETAG], "\"v1\"");
+        assert_eq!(body(partial).await, b"2345");
+
+        let unsatisfiable = proxy_stored_audio(&url, &request(&[(header::RANGE, "bytes=50-")])).await.unwrap();
+        assert_eq!(unsatisfiable.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(unsatisfiable.headers()[header::CONTENT_RANGE], "bytes */10");
+    }
+
+    #[tokio::test]
+    async fn revalidation_and_redirects_pass_through() {
+        let base = serve().await;
+        let not_modified = proxy_stored_audio(&format!("{}/episode.ogg", base), &request(&[(header::IF_NONE_MATCH, "\"v1\"")]))
+            .await
+            .unwrap();
+        assert_eq!(not_modified.status(), StatusCode::NOT_MODIFIED);
+        assert!(body(not_modified).await.is_empty());
+
+        let redirected = proxy_stored_audio(&format!("{}/tracking/episode.ogg", base), &HeaderMap::new()).await.unwrap();
+        assert_eq!(redirected.status(), StatusCode::OK);
+        assert_eq!(body(redirected).await, AUDIO);
+
+        let gone = proxy_stored_audio(&format!("{}/gone.mp3", base), &HeaderMap::new()).await.unwrap();
+        assert_eq!(gone.status(), StatusCode::BAD_GATEWAY);
+    }
+
+    #[tokio::test]
+    async fn enclosures_on_private_addresses_are_refused() {
+        let base = serve().await;
+        assert!(proxy_audio(&format!("{}/episode.ogg", base), &HeaderMap::new(), None).await.is_err());
+        let by_name = base.replace("127.0.0.1", "localhost");
+        assert!(proxy_audio(&format!("{}/episode.ogg", by_name), &HeaderMap::new(), None).await.is_err());
+        assert!(proxy_audio("not a url", &HeaderMap::new(), None).await.is_err());
+    }
+}
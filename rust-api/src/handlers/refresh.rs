@@ -69,7 +69,7 @@ pub async fn refresh_pods_admin(
     let state_clone = state.clone();
     tokio::spawn(async move {
         // This matches the Python refresh_pods function exactly
-        if let Err(e) = refresh_all_podcasts_background(&state_clone).await {
+        if let Err(e) = refresh_all_podcasts_background(&state_clone, false).await {
             tracing::error!("Background refresh failed: {}", e);
         }
     });
@@ -125,6 +125,7 @@ pub async fn refresh_gpodder_subscriptions_admin(
                             failed_syncs += 1;
                             println!("gPodder sync failed for user {}: {}", user_id, e);
                             tracing::error!("gPodder sync failed for user {}: {}", user_id, e);
+                            notify_sync_failed(&state, *user_id, "gPodder", &e).await;
                             // Continue with other users
                         }
                     }
@@ -156,41 +157,52 @@ pub async fn refresh_gpodder_subscriptions_admin(
     })))
 }
 
-// Background refresh function that matches Python refresh_pods exactly - NO WebSocket
-async fn refresh_all_podcasts_background(state: &AppState) -> AppResult<()> {
+// Only one full refresh runs at a time, across all instances; an overlapping run would
+// fetch the same feeds and insert their new episodes twice. The lock expires in case the
+// process dies mid-run.
+const REFRESH_LOCK_KEY: &str = "refresh_all_podcasts:lock";
+const REFRESH_LOCK_SECONDS: u64 = 2 * 60 * 60;
+
+// Background refresh function that matches Python refresh_pods exactly - NO WebSocket.
+// With `only_due` set, only podcasts whose scheduled next refresh has passed are fetched.
+// Skipped if another refresh is still running.
+async fn refresh_all_podcasts_background(state: &AppState, only_due: bool) -> AppResult<()> {
+    let token = uuid::Uuid::new_v4().to_string();
+    if !state.redis_client.try_lock(REFRESH_LOCK_KEY, &token, REFRESH_LOCK_SECONDS).await? {
+        tracing::info!("A podcast refresh is already running; skipping this one");
+        return Ok(());
+    }
+
+    let result = refresh_podcasts(state, only_due).await;
+    if let Err(e) = state.redis_client.unlock(REFRESH_LOCK_KEY, &token).await {
+        tracing::warn!("Failed to release the podcast refresh lock: {}", e);
+    }
+    result
+}
+
+async fn refresh_podcasts(state: &AppState, only_due: bool) -> AppResult<()> {
     println!("Running refresh");
-    
-    // Get ALL podcasts from ALL users - matches Python exactly
-    // Handle the different database types properly
-    let total_podcasts = match &state.db_pool {
-        crate::database::DatabasePool::Postgres(pool) => {
-            let count_row = sqlx::query(r#"SELECT COUNT(*) as total FROM "Podcasts""#)
-                .fetch_one(pool)
-                .await?;
-            count_row.try_get::<i64, _>("total")? as usize
-        }
-        crate::database::DatabasePool::MySQL(pool) => {
-            let count_row = sqlx::query("SELECT COUNT(*) as total FROM Podcasts")
-                .fetch_one(pool)
-                .await?;
-            count_row.try_get::<i64, _>("total")? as usize
-        }
-    };
-    
-    println!("Running refresh for {total_podcasts} podcasts");
+    let now = chrono::Utc::now().naive_utc();
     let mut current_podcast = 0;
     
     match &state.db_pool {
         crate::database::DatabasePool::Postgres(pool) => {
             let rows = sqlx::query(
-                r#"SELECT podcastid, feedurl, artworkurl, autodownload, username, password,
-                          isyoutubechannel, userid, COALESCE(feedurl, '') as channel_id, feedcutoffdays, podcastname
-                   FROM "Podcasts" 
-                   WHERE COALESCE(refreshpodcast, TRUE) = TRUE"#
+                r#"SELECT p.podcastid, p.feedurl, p.artworkurl, p.autodownload, p.username, p.password,
+                          p.isyoutubechannel, p.userid, COALESCE(p.feedurl, '') as channel_id, p.feedcutoffdays, p.podcastname
+                   FROM "Podcasts" p
+                   LEFT JOIN "FeedFetchState" f ON f.podcastid = p.podcastid
+                   WHERE COALESCE(p.refreshpodcast, TRUE) = TRUE
+                   AND ($1 = FALSE OR f.nextrefreshat IS NULL OR f.nextrefreshat <= $2)"#
             )
+            .bind(only_due)
+            .bind(now)
             .fetch_all(pool)
             .await?;
             
+            let total_podcasts = rows.len();
+            println!("Running refresh for {total_podcasts} podcasts");
+            
             for result in rows {
                 let podcast_id: i32 = result.try_get("podcastid")?;
                 let feed_url: String = result.try_get("feedurl")?;
@@ -227,9 +239,11 @@ async fn refresh_all_podcasts_background(state: &AppState) -> AppResult<()> {
                     ).await {
                         Ok(_) => {
                             println!("Successfully refreshed YouTube channel {}", podcast_id);
+                            schedule_after_refresh(state, podcast_id, true).await;
                         }
                         Err(e) => {
                             println!("Error refreshing YouTube channel {}: {}", podcast_id, e);
+                            schedule_after_refresh(state, podcast_id, false).await;
                             // Continue with other podcasts - matches Python behavior
                         }
                     }
@@ -244,6 +258,7 @@ async fn refresh_all_podcasts_background(state: &AppState) -> AppResult<()> {
                     ).await {
                         Ok(new_episodes) => {
                             println!("Successfully refreshed podcast {}: {} new episodes", podcast_id, new_episodes.len());
+                            schedule_after_refresh(state, podcast_id, true).await;
                             
                             // Handle auto-download for background refresh - matches Python implementation exactly
                             if auto_download {
@@ -273,6 +288,7 @@ async fn refresh_all_podcasts_background(state: &AppState) -> AppResult<()> {
                         }
                         Err(e) => {
                             println!("Error refreshing podcast {}: {}", podcast_id, e);
+                            schedule_after_refresh(state, podcast_id, false).await;
                             // Continue with other podcasts - matches Python behavior
                         }
                     }
@@ -281,14 +297,21 @@ async fn refresh_all_podcasts_background(state: &AppState) -> AppResult<()> {
         }
         crate::database::DatabasePool::MySQL(pool) => {
             let rows = sqlx::query(
-                "SELECT PodcastID, FeedURL, ArtworkURL, AutoDownload, Username, Password,
-                        IsYouTubeChannel, UserID, COALESCE(FeedURL, '') as channel_id, FeedCutoffDays, PodcastName
-                 FROM Podcasts
-                 WHERE COALESCE(RefreshPodcast, 1) = 1"
+                "SELECT p.PodcastID, p.FeedURL, p.ArtworkURL, p.AutoDownload, p.Username, p.Password,
+                        p.IsYouTubeChannel, p.UserID, COALESCE(p.FeedURL, '') as channel_id, p.FeedCutoffDays, p.PodcastName
+                 FROM Podcasts p
+                 LEFT JOIN FeedFetchState f ON f.PodcastID = p.PodcastID
+                 WHERE COALESCE(p.RefreshPodcast, 1) = 1
+                 AND (? = FALSE OR f.NextRefreshAt IS NULL OR f.NextRefreshAt <= ?)"
             )
+            .bind(only_due)
+            .bind(now)
             .fetch_all(pool)
             .await?;
             
+            let total_podcasts = rows.len();
+            println!("Running refresh for {total_podcasts} podcasts");
+            
             for result in rows {
                 let podcast_id: i32 = result.try_get("PodcastID")?;
                 let feed_url: String = result.try_get("FeedURL")?;
@@ -325,9 +348,11 @@ async fn refresh_all_podcasts_background(state: &AppState) -> AppResult<()> {
                     ).await {
                         Ok(_) => {
                             println!("Successfully refreshed YouTube channel {}", podcast_id);
+                            schedule_after_refresh(state, podcast_id, true).await;
                         }
                         Err(e) => {
                             println!("Error refreshing YouTube channel {}: {}", podcast_id, e);
+                            schedule_after_refresh(state, podcast_id, false).await;
                             // Continue with other podcasts - matches Python behavior
                         }
                     }
@@ -342,6 +367,7 @@ async fn refresh_all_podcasts_background(state: &AppState) -> AppResult<()> {
                     ).await {
                         Ok(new_episodes) => {
                             println!("Successfully refreshed podcast {}: {} new episodes", podcast_id, new_episodes.len());
+                            schedule_after_refresh(state, podcast_id, true).await;
                             
                             // Handle auto-download for background refresh - matches Python implementation exactly
                             if auto_download {
@@ -371,6 +397,7 @@ async fn refresh_all_podcasts_background(state: &AppState) -> AppResult<()> {
                         }
                         Err(e) => {
                             println!("Error refreshing podcast {}: {}", podcast_id, e);
+                            schedule_after_refresh(state, podcast_id, false).await;
                             // Continue with other podcasts - matches Python behavior
                         }
                     }
@@ -416,6 +443,13 @@ async fn refresh_all_podcasts_background(state: &AppState) -> AppResult<()> {
     Ok(())
 }
 
+// Record the outcome of a refresh and pick the podcast's next refresh time
+async fn schedule_after_refresh(state: &AppState, podcast_id: i32, succeeded: bool) {
+    if let Err(e) = state.db_pool.schedule_next_refresh(podcast_id, Some(succeeded)).await {
+        tracing::warn!("Failed to schedule next refresh for podcast {}: {}", podcast_id, e);
+    }
+}
+
 // Helper function for admin gPodder sync
 async fn run_admin_gpodder_sync(state: &AppState, user_id: i32, sync_type: &str) -> AppResult<SyncResult> {
     match sync_type {
@@ -763,13 +797,15 @@ async fn refresh_single_podcast(
     
     tracing::info!("Refreshing podcast: {} (ID: {})", podcast.name, podcast.id);
     
-    if podcast.is_youtube {
+    let result = if podcast.is_youtube {
         // Handle YouTube channel refresh
         refresh_youtube_channel(state, podcast, user_id).await
     } else {
         // Handle regular RSS feed refresh
         refresh_rss_feed(state, podcast, user_id).await
-    }
+    };
+    schedule_after_refresh(state, podcast.id, result.is_ok()).await;
+    result
 }
 
 async fn refresh_rss_feed(
@@ -920,9 +956,10 @@ async fn handle_gpodder_sync(state: &AppState, user_id: i32, sync_type: &str) ->
 }
 
 // Internal functions for scheduler (no HTTP context needed)
-pub async fn refresh_pods_admin_internal(state: &AppState) -> AppResult<()> {
-    tracing::info!("Starting internal podcast refresh (scheduler)");
-    refresh_all_podcasts_background(state).await
+// Refresh only the podcasts whose adaptive schedule says they are due
+pub async fn refresh_due_podcasts_internal(state: &AppState) -> AppResult<()> {
+    tracing::info!("Starting scheduled refresh of due podcasts");
+    refresh_all_podcasts_background(state, true).await
 }
 
 pub async fn refresh_gpodder_subscriptions_admin_internal(state: &AppState) -> AppResult<()> {
@@ -955,6 +992,7 @@ pub async fn refresh_gpodder_subscriptions_admin_internal(state: &AppState) -> A
                 Err(e) => {
                     failed_syncs += 1;
                     tracing::error!("GPodder sync failed for user {}: {}", user_id, e);
+                    notify_sync_failed(state, *user_id, "gPodder", &e).await;
                 }
             }
         }
@@ -1016,6 +1054,7 @@ pub async fn refresh_nextcloud_subscriptions_admin(
                     Err(e) => {
                         failed_syncs += 1;
                         println!("Nextcloud sync failed for user {}: {}", user_id, e);
+                        notify_sync_failed(&state, *user_id, "Nextcloud", &e).await;
                     }
                 }
             }
@@ -1062,6 +1101,7 @@ pub async fn refresh_nextcloud_subscriptions_admin_internal(state: &AppState) ->
             Err(e) => {
                 failed_syncs += 1;
                 tracing::error!("Nextcloud sync failed for user {}: {}", user_id, e);
+                notify_sync_failed(state, *user_id, "Nextcloud", &e).await;
             }
         }
     }
@@ -1072,4 +1112,10 @@ pub async fn refresh_nextcloud_subscriptions_admin_internal(state: &AppState) ->
     Ok(())
 }
 
-
+// Tell a user their scheduled sync failed, through any destination subscribed to sync failures
+async fn notify_sync_failed(state: &AppState, user_id: i32, service: &str, error: &AppError) {
+    crate::services::notifications::notify_quietly(&state.db_pool, user_id, crate::services::notifications::NotificationEvent::SyncFailed {
+        service: service.to_string(),
+        error: error.to_string(),
+    }).await;
+}
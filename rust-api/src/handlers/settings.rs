@@ -5,9 +5,19 @@ use axum::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    config::WebAuthnConfig,
     error::AppError,
-    handlers::{extract_api_key, validate_api_key, check_user_access},
+    handlers::{extract_api_key, validate_api_key, check_user_access, check_admin_access},
     models::{AvailableLanguage, LanguageUpdateRequest, UserLanguageResponse, AvailableLanguagesResponse},
+    services::{
+        download_template::{DownloadTemplate, DEFAULT_TEMPLATE},
+        email_digest::{self, DigestSettings},
+        notifications::{self, DestinationSettings, NotificationEvent, ProviderConfig},
+        oidc::{self, OidcDiscovery},
+        sessions,
+        webauthn,
+        webhooks::{self, WebhookSettings},
+    },
     AppState,
 };
 use sqlx::{Row, ValueRef};
@@ -380,6 +390,187 @@ pub async fn final_admin(
     Ok(Json(serde_json::json!({ "final_admin": is_final_admin })))
 }
 
+// Request struct for set_download_quota
+#[derive(Deserialize)]
+pub struct SetDownloadQuotaRequest {
+    pub user_id: i32,
+    /// Quota in MB; null falls back to the server default, 0 means unlimited
+    pub quota_mb: Option<i64>,
+}
+
+// Set a user's download quota (admin only)
+pub async fn set_download_quota(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SetDownloadQuotaRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_admin_access(&state, &api_key).await? {
+        return Err(AppError::forbidden("Admin access required"));
+    }
+
+    let quota_bytes = match request.quota_mb {
+        Some(mb) if mb < 0 => return Err(AppError::bad_request("quota_mb cannot be negative")),
+        Some(mb) => Some(mb.saturating_mul(1024 * 1024)),
+        None => None,
+    };
+    if !state.db_pool.set_user_download_quota(request.user_id, quota_bytes).await? {
+        return Err(AppError::not_found("User not found"));
+    }
+    Ok(Json(serde_json::json!({ "detail": "Download quota updated." })))
+}
+
+// Download storage usage per user and per podcast (admin only)
+pub async fn get_storage_usage(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_admin_access(&state, &api_key).await? {
+        return Err(AppError::forbidden("Admin access required"));
+    }
+
+    let (users, podcasts) = state.db_pool.get_storage_usage().await?;
+    let total_bytes: i64 = users.iter().map(|u| u.used_bytes).sum();
+    Ok(Json(serde_json::json!({
+        "backend": state.storage.active().name(),
+        "default_quota_bytes": state.storage.default_quota_bytes(),
+        "total_bytes": total_bytes,
+        "users": users,
+        "podcasts": podcasts,
+    })))
+}
+
+// Download filename templates in effect for a user
+pub async fn get_download_template(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<DownloadTemplateQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own download settings!"));
+    }
+
+    let (user_template, server_template) = state.db_pool.get_download_templates(query.user_id).await?;
+    let effective = [user_template.as_deref(), server_template.as_deref()]
+        .into_iter()
+        .flatten()
+        .find(|t| DownloadTemplate::parse(t).is_ok())
+        .unwrap_or(DEFAULT_TEMPLATE)
+        .to_string();
+    Ok(Json(serde_json::json!({
+        "template": user_template,
+        "server_template": server_template,
+        "default_template": DEFAULT_TEMPLATE,
+        "effective_template": effective,
+    })))
+}
+
+#[derive(Deserialize)]
+pub struct DownloadTemplateQuery {
+    pub user_id: i32,
+}
+
+// Request struct for set_download_template; a null or empty template clears it
+#[derive(Deserialize)]
+pub struct SetDownloadTemplateRequest {
+    pub user_id: i32,
+    pub template: Option<String>,
+}
+
+// Validate a template from a request, mapping empty to "not set"
+fn requested_template(template: Option<&str>) -> Result<Option<&str>, AppError> {
+    match template.map(str::trim).filter(|t| !t.is_empty()) {
+        Some(template) => {
+            DownloadTemplate::parse(template)?;
+            Ok(Some(template))
+        }
+        None => Ok(None),
+    }
+}
+
+// Set a user's own download filename template. New downloads use it; existing ones move on re-organize
+pub async fn set_download_template(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SetDownloadTemplateRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only change your own download settings!"));
+    }
+
+    let template = requested_template(request.template.as_deref())?;
+    if !state.db_pool.set_user_download_template(request.user_id, template).await? {
+        return Err(AppError::not_found("User not found"));
+    }
+    Ok(Json(serde_json::json!({ "detail": "Download template updated." })))
+}
+
+#[derive(Deserialize)]
+pub struct SetServerDownloadTemplateRequest {
+    pub template: Option<String>,
+}
+
+// Set the download filename template for users without their own (admin only)
+pub async fn set_server_download_template(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SetServerDownloadTemplateRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_admin_access(&state, &api_key).await? {
+        return Err(AppError::forbidden("Admin access required"));
+    }
+
+    let template = requested_template(request.template.as_deref())?;
+    state.db_pool.set_server_download_template(template).await?;
+    Ok(Json(serde_json::json!({ "detail": "Server download template updated." })))
+}
+
+#[derive(Deserialize)]
+pub struct ReorganizeDownloadsRequest {
+    pub user_id: i32,
+    /// Re-organize every user's downloads (admin only)
+    pub all_users: Option<bool>,
+}
+
+// Move existing downloads to match the current download templates, in the background
+pub async fn reorganize_downloads(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<ReorganizeDownloadsRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    let all_users = request.all_users.unwrap_or(false);
+    if all_users {
+        if !check_admin_access(&state, &api_key).await? {
+            return Err(AppError::forbidden("Admin access required"));
+        }
+    } else if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only re-organize your own downloads!"));
+    }
+
+    let task_id = state.task_spawner.spawn_reorganize_downloads(request.user_id, all_users).await?;
+    Ok(Json(serde_json::json!({
+        "detail": "Download re-organization started",
+        "task_id": task_id
+    })))
+}
+
 // Enable/disable guest - matches Python api_enable_disable_guest function exactly (admin only)
 pub async fn enable_disable_guest(
     State(state): State<AppState>,
@@ -490,6 +681,47 @@ pub async fn download_status(
     Ok(Json(result))
 }
 
+// Get request rate limits - admin only
+pub async fn get_rate_limit_settings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<crate::services::rate_limit::RateLimitSettings>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    let is_valid = validate_api_key(&state, &api_key).await?;
+    if !is_valid {
+        return Err(AppError::unauthorized("Your API key is either invalid or does not have correct permission"));
+    }
+
+    if !check_admin_access(&state, &api_key).await? {
+        return Err(AppError::forbidden("Admin access required"));
+    }
+
+    let settings = state.db_pool.get_rate_limit_settings().await?;
+    Ok(Json(settings))
+}
+
+// Update request rate limits - admin only
+pub async fn update_rate_limit_settings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(settings): Json<crate::services::rate_limit::RateLimitSettings>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    let is_valid = validate_api_key(&state, &api_key).await?;
+    if !is_valid {
+        return Err(AppError::unauthorized("Your API key is either invalid or does not have correct permission"));
+    }
+
+    if !check_admin_access(&state, &api_key).await? {
+        return Err(AppError::forbidden("Admin access required"));
+    }
+
+    settings.validate()?;
+    state.db_pool.update_rate_limit_settings(&settings).await?;
+    crate::middleware::rate_limit::invalidate_settings_cache(&state).await;
+    Ok(Json(serde_json::json!({ "success": true })))
+}
+
 // Get self service status - matches Python api_self_service_status function exactly  
 pub async fn self_service_status(
     State(state): State<AppState>,
@@ -932,16 +1164,6 @@ pub async fn send_email_with_settings(
     settings: &EmailSettingsResponse,
     request: &SendEmailRequest,
 ) -> Result<String, AppError> {
-    use lettre::{
-        message::{header::ContentType, Message},
-        transport::smtp::{authentication::Credentials, client::Tls, client::TlsParameters},
-        AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
-    };
-    use tokio::time::{timeout, Duration};
-
-    // Read logo and create HTML content
-    let logo_base64 = read_logo_as_base64().await.unwrap_or_default();
-    
     // Check if this is a password reset email and format accordingly
     let (html_content, final_subject) = if request.subject.contains("Password Reset") {
         // Extract the reset code from the message
@@ -970,16 +1192,34 @@ pub async fn send_email_with_settings(
         "#, request.subject, request.message.replace("\n", "<br>"));
         (content, request.subject.clone())
     };
-    
-    let html_body = create_html_email_template(&final_subject, &html_content, &logo_base64);
+
+    send_html_email(settings, &request.to_email, &final_subject, &html_content).await
+}
+
+// Send `html_content` wrapped in the PinePods email template
+pub async fn send_html_email(
+    settings: &EmailSettingsResponse,
+    to_email: &str,
+    subject: &str,
+    html_content: &str,
+) -> Result<String, AppError> {
+    use lettre::{
+        message::{header::ContentType, Message},
+        transport::smtp::{authentication::Credentials, client::Tls, client::TlsParameters},
+        AsyncSmtpTransport, AsyncTransport, Tokio1Executor,
+    };
+    use tokio::time::{timeout, Duration};
+
+    let logo_base64 = read_logo_as_base64().await.unwrap_or_default();
+    let html_body = create_html_email_template(subject, html_content, &logo_base64);
 
     // Create email message with HTML
     let email = Message::builder()
         .from(settings.from_email.parse()
             .map_err(|_| AppError::bad_request("Invalid from email in settings"))?)
-        .to(request.to_email.parse()
+        .to(to_email.parse()
             .map_err(|_| AppError::bad_request("Invalid to email"))?)
-        .subject(&final_subject)
+        .subject(subject)
         .header(ContentType::TEXT_HTML)
         .body(html_body)
         .map_err(|e| AppError::internal(&format!("Failed to build email: {}", e)))?;
@@ -1083,6 +1323,10 @@ pub struct ApiInfo {
     pub lastfourdigits: String,
     pub created: String,
     pub podcastids: Vec<i32>,
+    pub label: Option<String>,
+    pub scopes: Vec<String>,
+    pub expiresat: Option<String>,
+    pub lastused: Option<String>,
 }
 
 // Get API info - matches Python api_get_api_info function exactly
@@ -1115,6 +1359,9 @@ pub struct CreateApiKeyRequest {
     pub user_id: i32,
     pub rssonly: bool,
     pub podcast_ids: Option<Vec<i32>>,
+    pub label: Option<String>,
+    pub scopes: Option<Vec<String>>, // read, playback, subscriptions, admin; omitted = all
+    pub expires_in_days: Option<u32>,
 }
 
 // Create API key - matches Python api_create_api_key function exactly
@@ -1138,7 +1385,20 @@ pub async fn create_api_key(
         let new_key = state.db_pool.create_rss_key(request.user_id, request.podcast_ids).await?;
         Ok(Json(serde_json::json!({ "rss_key": new_key })))
     } else {
-        let new_key = state.db_pool.create_api_key(request.user_id).await?;
+        let label = request.label.as_deref().map(str::trim).filter(|l| !l.is_empty());
+        if label.is_some_and(|l| l.chars().count() > 255) {
+            return Err(AppError::bad_request("API key label must be 255 characters or fewer"));
+        }
+        let scopes = request
+            .scopes
+            .as_deref()
+            .map(crate::services::api_scopes::parse_requested_scopes)
+            .transpose()?;
+        let expires_at = crate::services::api_scopes::expiry_from_days(chrono::Utc::now().naive_utc(), request.expires_in_days)?;
+
+        let new_key = state.db_pool
+            .create_scoped_api_key(request.user_id, label, scopes.as_deref(), expires_at)
+            .await?;
         Ok(Json(serde_json::json!({ "api_key": new_key })))
     }
 }
@@ -2322,10 +2582,16 @@ pub struct OidcProviderRequest {
     pub provider_name: String,
     pub client_id: String,
     pub client_secret: String,
+    // When set, the endpoint URLs below are read from the issuer's discovery document
+    pub issuer_url: Option<String>,
+    #[serde(default)]
     pub authorization_url: String,
+    #[serde(default)]
     pub token_url: String,
+    #[serde(default)]
     pub user_info_url: String,
     pub button_text: String,
+    #[serde(default)]
     pub scope: String,
     pub button_color: String,
     pub button_text_color: String,
@@ -2506,6 +2772,7 @@ pub async fn import_opml(
     let user_id = request.user_id;
 
     tokio::spawn(async move {
+        let mut imported = 0;
         for (index, feed_url) in podcasts.iter().enumerate() {
             // Update progress
             let _ = state_clone.import_progress_manager.update_progress(
@@ -2517,13 +2784,16 @@ pub async fn import_opml(
             // Process podcast (with error handling to continue on failures)
             match state_clone.db_pool.get_podcast_values(feed_url, user_id, None, None).await {
                 Ok(podcast_values) => {
-                    let _ = state_clone.db_pool.add_podcast_from_values(
+                    match state_clone.db_pool.add_podcast_from_values(
                         &podcast_values,
                         user_id,
                         30,  // feed_cutoff
                         None, // username
                         None  // password
-                    ).await;
+                    ).await {
+                        Ok(_) => imported += 1,
+                        Err(e) => tracing::error!("Failed to add imported podcast {}: {}", feed_url, e),
+                    }
                 }
                 Err(e) => {
                     tracing::error!("Failed to import podcast {}: {}", feed_url, e);
@@ -2537,6 +2807,11 @@ pub async fn import_opml(
 
         // Clear progress when complete
         let _ = state_clone.import_progress_manager.clear_progress(user_id).await;
+
+        notifications::notify_quietly(&state_clone.db_pool, user_id, NotificationEvent::ImportFinished {
+            imported,
+            failed: podcasts.len() - imported,
+        }).await;
     });
 
     Ok(Json(serde_json::json!({
@@ -2652,125 +2927,778 @@ pub async fn test_notification(
         .find(|s| s.get("platform").and_then(|p| p.as_str()) == Some(&request.platform))
         .ok_or_else(|| AppError::bad_request(&format!("No settings found for platform: {}", request.platform)))?;
     
-    let success = state.notification_manager.send_test_notification(request.user_id, &request.platform, platform_settings).await?;
+    let config = ProviderConfig::from_legacy(&request.platform, platform_settings)
+        .ok_or_else(|| AppError::bad_request("Failed to send test notification - check your settings"))?;
     
-    if success {
-        Ok(Json(serde_json::json!({ "detail": "Test notification sent successfully" })))
-    } else {
-        Err(AppError::bad_request("Failed to send test notification - check your settings"))
+    match notifications::send_test(&state.db_pool, request.user_id, &config, None, None).await {
+        Ok(()) => Ok(Json(serde_json::json!({ "detail": "Test notification sent successfully" }))),
+        Err(e) => Err(AppError::bad_request(format!("Failed to send test notification - check your settings: {}", e))),
     }
 }
 
-// Add OIDC provider - matches Python add_oidc_provider function exactly  
-pub async fn add_oidc_provider(
+// Request struct for add/update_notification_destination
+#[derive(Deserialize)]
+pub struct NotificationDestinationRequest {
+    pub user_id: i32,
+    #[serde(flatten)]
+    pub settings: DestinationSettings,
+}
+
+#[derive(Deserialize)]
+pub struct NotificationDeliveriesQuery {
+    pub user_id: i32,
+    pub limit: Option<i64>,
+}
+
+async fn check_destination_settings(state: &AppState, user_id: i32, settings: &DestinationSettings) -> Result<(), AppError> {
+    settings.validate().map_err(AppError::bad_request)?;
+    check_destination_reach(state, user_id, &settings.config).await
+}
+
+// Destinations are sent to from the server, so only admins may reach hosts on its own
+// network, or mail an address other than their own through its SMTP account
+async fn check_destination_reach(state: &AppState, user_id: i32, config: &ProviderConfig) -> Result<(), AppError> {
+    if state.db_pool.user_admin_check(user_id).await? {
+        return Ok(());
+    }
+    config.check_endpoint(false).map_err(AppError::bad_request)?;
+    if let ProviderConfig::Email(email) = config {
+        let own_email = state.db_pool.get_user_details_by_id(user_id).await?.Email.unwrap_or_default();
+        if own_email.trim().is_empty() || !email.sends_to(&own_email) {
+            return Err(AppError::forbidden("Email notifications can only be sent to your own email address."));
+        }
+    }
+    Ok(())
+}
+
+// List a user's notification destinations, with tokens and passwords redacted
+pub async fn get_notification_destinations(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(request): Json<OidcProviderRequest>,
+    Query(query): Query<UserIdQuery>,
 ) -> Result<Json<serde_json::Value>, AppError> {
     let api_key = extract_api_key(&headers)?;
     validate_api_key(&state, &api_key).await?;
 
-    // Check if user is admin - OIDC provider management requires admin access
-    let user_id = state.db_pool.get_user_id_from_api_key(&api_key).await?;
-    let is_admin = state.db_pool.user_admin_check(user_id).await?;
-
-    if !is_admin {
-        return Err(AppError::forbidden("Admin access required to add OIDC providers"));
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own notification destinations."));
     }
 
-    let provider_id = state.db_pool.add_oidc_provider(
-        &request.provider_name,
-        &request.client_id,
-        &request.client_secret,
-        &request.authorization_url,
-        &request.token_url,
-        &request.user_info_url,
-        &request.button_text,
-        &request.scope,
-        &request.button_color,
-        &request.button_text_color,
-        request.icon_svg.as_deref().unwrap_or(""),
-        request.name_claim.as_deref().unwrap_or("name"),
-        request.email_claim.as_deref().unwrap_or("email"),
-        request.username_claim.as_deref().unwrap_or("username"),
-        request.roles_claim.as_deref().unwrap_or(""),
-        request.user_role.as_deref().unwrap_or(""),
-        request.admin_role.as_deref().unwrap_or(""),
-        false // initialized_from_env = false (added via UI)
-    ).await?;
-    Ok(Json(serde_json::json!({ "provider_id": provider_id })))
+    let destinations: Vec<_> = state.db_pool.get_notification_destinations(query.user_id).await?
+        .into_iter()
+        .map(|destination| destination.redacted())
+        .collect();
+    Ok(Json(serde_json::json!({ "destinations": destinations })))
 }
 
-// Update OIDC provider - updates an existing provider
-pub async fn update_oidc_provider(
+// Add a notification destination
+pub async fn add_notification_destination(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Path(provider_id): Path<i32>,
-    Json(request): Json<OidcProviderRequest>,
+    Json(request): Json<NotificationDestinationRequest>,
 ) -> Result<Json<serde_json::Value>, AppError> {
     let api_key = extract_api_key(&headers)?;
     validate_api_key(&state, &api_key).await?;
 
-    // Check if user is admin - OIDC provider management requires admin access
-    let user_id = state.db_pool.get_user_id_from_api_key(&api_key).await?;
-    let is_admin = state.db_pool.user_admin_check(user_id).await?;
-
-    if !is_admin {
-        return Err(AppError::forbidden("Admin access required to update OIDC providers"));
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only add your own notification destinations."));
     }
 
-    // Only update client_secret if it's not empty
-    let client_secret_to_update = if request.client_secret.is_empty() {
-        None
-    } else {
-        Some(request.client_secret.as_str())
-    };
-
-    let success = state.db_pool.update_oidc_provider(
-        provider_id,
-        &request.provider_name,
-        &request.client_id,
-        client_secret_to_update,
-        &request.authorization_url,
-        &request.token_url,
-        &request.user_info_url,
-        &request.button_text,
-        &request.scope,
-        &request.button_color,
-        &request.button_text_color,
-        request.icon_svg.as_deref().unwrap_or(""),
-        request.name_claim.as_deref().unwrap_or("name"),
-        request.email_claim.as_deref().unwrap_or("email"),
-        request.username_claim.as_deref().unwrap_or("username"),
-        request.roles_claim.as_deref().unwrap_or(""),
-        request.user_role.as_deref().unwrap_or(""),
-        request.admin_role.as_deref().unwrap_or("")
-    ).await?;
-
-    if success {
-        Ok(Json(serde_json::json!({ "message": "OIDC provider updated successfully" })))
-    } else {
-        Err(AppError::not_found("OIDC provider not found"))
-    }
+    check_destination_settings(&state, request.user_id, &request.settings).await?;
+    let destination_id = state.db_pool.add_notification_destination(request.user_id, &request.settings).await?;
+    Ok(Json(serde_json::json!({ "destination_id": destination_id })))
 }
 
-// List OIDC providers - matches Python list_oidc_providers function exactly
-pub async fn list_oidc_providers(
+// Replace the settings of a notification destination. Secrets sent back redacted keep
+// their stored values.
+pub async fn update_notification_destination(
     State(state): State<AppState>,
     headers: HeaderMap,
+    Path(destination_id): Path<i32>,
+    Json(mut request): Json<NotificationDestinationRequest>,
 ) -> Result<Json<serde_json::Value>, AppError> {
     let api_key = extract_api_key(&headers)?;
     validate_api_key(&state, &api_key).await?;
 
-    let providers = state.db_pool.list_oidc_providers().await?;
-    Ok(Json(serde_json::json!({ "providers": providers })))
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only modify your own notification destinations."));
+    }
+
+    let stored = state.db_pool.get_notification_destination(destination_id, request.user_id).await?
+        .ok_or_else(|| AppError::not_found("Notification destination not found"))?;
+    request.settings.config.restore_secrets(&stored.config);
+    check_destination_settings(&state, request.user_id, &request.settings).await?;
+    if !state.db_pool.update_notification_destination(destination_id, request.user_id, &request.settings).await? {
+        return Err(AppError::not_found("Notification destination not found"));
+    }
+    Ok(Json(serde_json::json!({ "detail": "Notification destination updated." })))
 }
 
-// Remove OIDC provider - matches Python remove_oidc_provider function exactly
-pub async fn remove_oidc_provider(
+// Delete a notification destination and its delivery log
+pub async fn delete_notification_destination(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(destination_id): Path<i32>,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only delete your own notification destinations."));
+    }
+
+    if !state.db_pool.delete_notification_destination(destination_id, query.user_id).await? {
+        return Err(AppError::not_found("Notification destination not found"));
+    }
+    Ok(Json(serde_json::json!({ "detail": "Notification destination deleted." })))
+}
+
+// Send a test message to a notification destination, using its templates
+pub async fn test_notification_destination(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(destination_id): Path<i32>,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only test your own notification destinations."));
+    }
+
+    let destination = state.db_pool.get_notification_destination(destination_id, query.user_id).await?
+        .ok_or_else(|| AppError::not_found("Notification destination not found"))?;
+    check_destination_reach(&state, query.user_id, &destination.config).await?;
+    notifications::send_test(
+        &state.db_pool,
+        query.user_id,
+        &destination.config,
+        destination.title_template.as_deref(),
+        destination.body_template.as_deref(),
+    ).await.map_err(|e| AppError::bad_request(format!("Failed to send test notification: {}", e)))?;
+    Ok(Json(serde_json::json!({ "detail": "Test notification sent successfully" })))
+}
+
+// Recent notification deliveries of a user, newest first
+pub async fn get_notification_deliveries(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<NotificationDeliveriesQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own notification deliveries."));
+    }
+
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let deliveries = state.db_pool.get_notification_deliveries(query.user_id, limit).await?;
+    Ok(Json(serde_json::json!({ "deliveries": deliveries })))
+}
+
+// Request struct for update_email_digest_settings
+#[derive(Deserialize)]
+pub struct EmailDigestSettingsRequest {
+    pub user_id: i32,
+    #[serde(flatten)]
+    pub settings: DigestSettings,
+}
+
+// Get a user's digest email settings
+pub async fn get_email_digest_settings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own email digest settings."));
+    }
+
+    let settings = state.db_pool.get_email_digest_settings(query.user_id).await?;
+    Ok(Json(serde_json::json!({ "settings": settings })))
+}
+
+// Save a user's digest email settings
+pub async fn update_email_digest_settings(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<EmailDigestSettingsRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only update your own email digest settings."));
+    }
+
+    request.settings.validate().map_err(AppError::bad_request)?;
+    state.db_pool.save_email_digest_settings(request.user_id, &request.settings).await?;
+    Ok(Json(serde_json::json!({ "detail": "Email digest settings updated." })))
+}
+
+// Email a user their digest right away, to preview it
+pub async fn send_email_digest(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only send your own email digest."));
+    }
+
+    let detail = if email_digest::send_now(&state.db_pool, query.user_id).await? {
+        "Email digest sent."
+    } else {
+        "Nothing to put in the digest yet, so no email was sent."
+    };
+    Ok(Json(serde_json::json!({ "detail": detail })))
+}
+
+// Request struct for add_webhook and update_webhook
+#[derive(Deserialize)]
+pub struct WebhookRequest {
+    pub user_id: i32,
+    #[serde(flatten)]
+    pub settings: WebhookSettings,
+}
+
+#[derive(Deserialize)]
+pub struct WebhookDeliveriesQuery {
+    pub user_id: i32,
+    pub limit: Option<i64>,
+}
+
+// Only admins may create webhooks that receive every user's events
+async fn check_webhook_settings(state: &AppState, user_id: i32, settings: &WebhookSettings) -> Result<(), AppError> {
+    settings.validate().map_err(AppError::bad_request)?;
+    if settings.is_global && !state.db_pool.user_admin_check(user_id).await? {
+        return Err(AppError::forbidden("Only admins can create global webhooks."));
+    }
+    Ok(())
+}
+
+// List a user's webhooks
+pub async fn get_webhooks(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own webhooks."));
+    }
+
+    let webhooks = state.db_pool.get_webhooks(query.user_id).await?;
+    Ok(Json(serde_json::json!({ "webhooks": webhooks })))
+}
+
+// Add a webhook; the generated signing secret is returned with it
+pub async fn add_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<WebhookRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only add your own webhooks."));
+    }
+
+    check_webhook_settings(&state, request.user_id, &request.settings).await?;
+    let secret = webhooks::generate_secret();
+    let webhook_id = state.db_pool.add_webhook(request.user_id, &request.settings, &secret).await?;
+    Ok(Json(serde_json::json!({ "webhook_id": webhook_id, "secret": secret })))
+}
+
+// Replace the settings of a webhook, keeping its secret
+pub async fn update_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(webhook_id): Path<i32>,
+    Json(request): Json<WebhookRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only modify your own webhooks."));
+    }
+
+    check_webhook_settings(&state, request.user_id, &request.settings).await?;
+    if !state.db_pool.update_webhook(webhook_id, request.user_id, &request.settings).await? {
+        return Err(AppError::not_found("Webhook not found"));
+    }
+    Ok(Json(serde_json::json!({ "detail": "Webhook updated." })))
+}
+
+// Replace a webhook's signing secret; the new secret is returned once, here
+pub async fn rotate_webhook_secret(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(webhook_id): Path<i32>,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only modify your own webhooks."));
+    }
+
+    let secret = webhooks::generate_secret();
+    if !state.db_pool.rotate_webhook_secret(webhook_id, query.user_id, &secret).await? {
+        return Err(AppError::not_found("Webhook not found"));
+    }
+    Ok(Json(serde_json::json!({ "webhook_id": webhook_id, "secret": secret })))
+}
+
+// Delete a webhook and its delivery history
+pub async fn delete_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(webhook_id): Path<i32>,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only delete your own webhooks."));
+    }
+
+    if !state.db_pool.delete_webhook(webhook_id, query.user_id).await? {
+        return Err(AppError::not_found("Webhook not found"));
+    }
+    Ok(Json(serde_json::json!({ "detail": "Webhook deleted." })))
+}
+
+// Post a signed test event to a webhook and report how the endpoint answered
+pub async fn test_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(webhook_id): Path<i32>,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only test your own webhooks."));
+    }
+
+    let webhook = state.db_pool.get_webhook(webhook_id, query.user_id).await?
+        .ok_or_else(|| AppError::not_found("Webhook not found"))?;
+    let outcome = webhooks::test_fire(&state.db_pool, &webhook).await?;
+    Ok(Json(serde_json::json!({ "result": outcome })))
+}
+
+// Recent deliveries of a webhook, newest first
+pub async fn get_webhook_deliveries(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(webhook_id): Path<i32>,
+    Query(query): Query<WebhookDeliveriesQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own webhook deliveries."));
+    }
+
+    if state.db_pool.get_webhook(webhook_id, query.user_id).await?.is_none() {
+        return Err(AppError::not_found("Webhook not found"));
+    }
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+    let deliveries = state.db_pool.get_webhook_deliveries(webhook_id, limit).await?;
+    Ok(Json(serde_json::json!({ "deliveries": deliveries })))
+}
+
+// Request struct for finish_passkey_registration
+#[derive(Deserialize)]
+pub struct FinishPasskeyRegistrationRequest {
+    pub user_id: i32,
+    pub name: Option<String>,
+    pub credential: webauthn::PublicKeyCredential,
+}
+
+// Request struct for start_passkey_registration and generate_recovery_codes
+#[derive(Deserialize)]
+pub struct UserIdRequest {
+    pub user_id: i32,
+}
+
+fn webauthn_config(state: &AppState) -> Result<&WebAuthnConfig, AppError> {
+    state.config.webauthn.as_ref()
+        .ok_or_else(|| AppError::bad_request("Passkeys need WEBAUTHN_ORIGINS or SERVER_URL to be set"))
+}
+
+// Begin enrolling a passkey: returns the options for navigator.credentials.create()
+pub async fn start_passkey_registration(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<UserIdRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only add passkeys to your own account."));
+    }
+
+    let config = webauthn_config(&state)?;
+    let user = state.db_pool.get_user_details_by_id(request.user_id).await?;
+    let username = user.Username.unwrap_or_default();
+    let display_name = user.Fullname.filter(|name| !name.is_empty()).unwrap_or_else(|| username.clone());
+    let existing = state.db_pool.get_webauthn_credentials(request.user_id).await?;
+
+    let challenge = webauthn::generate_challenge();
+    state.redis_client.set_ex(
+        &format!("webauthn_register:{}", request.user_id),
+        &challenge,
+        webauthn::CHALLENGE_TTL_SECONDS,
+    ).await?;
+
+    let options = webauthn::creation_options(config, &challenge, request.user_id, &username, &display_name, &existing);
+    Ok(Json(serde_json::json!({ "options": options })))
+}
+
+// Finish enrolling a passkey with the browser's response to the registration challenge
+pub async fn finish_passkey_registration(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<FinishPasskeyRegistrationRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only add passkeys to your own account."));
+    }
+
+    let config = webauthn_config(&state)?;
+    // Each challenge answers one registration
+    let challenge = state.redis_client.get_del(&format!("webauthn_register:{}", request.user_id)).await?
+        .ok_or_else(|| AppError::bad_request("Passkey registration expired, please start again"))?;
+    let credential = webauthn::verify_registration(config, &challenge, &request.credential)?;
+
+    if state.db_pool.get_webauthn_credential(&credential.credential_id).await?.is_some() {
+        return Err(AppError::Conflict("This passkey is already registered".to_string()));
+    }
+
+    let name = request.name.as_deref().map(str::trim).filter(|name| !name.is_empty()).unwrap_or("Passkey");
+    let passkey_id = state.db_pool.add_webauthn_credential(request.user_id, name, &credential).await?;
+    Ok(Json(serde_json::json!({ "passkey_id": passkey_id })))
+}
+
+// List a user's passkeys
+pub async fn get_passkeys(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own passkeys."));
+    }
+
+    let passkeys = state.db_pool.get_webauthn_credentials(query.user_id).await?;
+    Ok(Json(serde_json::json!({ "passkeys": passkeys })))
+}
+
+// Remove one of a user's passkeys
+pub async fn delete_passkey(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(passkey_id): Path<i32>,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only remove your own passkeys."));
+    }
+
+    if !state.db_pool.delete_webauthn_credential(passkey_id, query.user_id).await? {
+        return Err(AppError::not_found("Passkey not found"));
+    }
+    Ok(Json(serde_json::json!({ "detail": "Passkey removed." })))
+}
+
+// Issue a new set of recovery codes, replacing any earlier ones. The codes are only
+// shown this once; just their hashes are kept.
+pub async fn generate_recovery_codes(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<UserIdRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only create recovery codes for yourself."));
+    }
+
+    let codes = webauthn::generate_recovery_codes();
+    let hashes: Vec<String> = codes.iter().map(|code| webauthn::hash_recovery_code(code)).collect();
+    state.db_pool.replace_recovery_codes(request.user_id, &hashes).await?;
+    Ok(Json(serde_json::json!({ "recovery_codes": codes })))
+}
+
+// How many of a user's recovery codes are left
+pub async fn get_recovery_codes_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own recovery codes."));
+    }
+
+    let remaining = state.db_pool.count_recovery_codes(query.user_id).await?;
+    Ok(Json(serde_json::json!({ "remaining": remaining })))
+}
+
+// Request struct for revoke_all_sessions
+#[derive(Deserialize)]
+pub struct RevokeAllSessionsRequest {
+    pub user_id: i32,
+    // Also sign out the session making the request
+    #[serde(default)]
+    pub include_current: bool,
+}
+
+// The key ID behind the caller's API key, to tell their own session apart
+async fn current_api_key_id(state: &AppState, api_key: &str) -> Result<Option<i32>, AppError> {
+    Ok(state.db_pool.get_api_key_policy(api_key).await?.map(|policy| policy.api_key_id))
+}
+
+// List a user's signed-in sessions
+pub async fn get_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own sessions."));
+    }
+
+    let current = current_api_key_id(&state, &api_key).await?;
+    let mut login_sessions = state.db_pool.get_login_sessions(query.user_id).await?;
+    for session in &mut login_sessions {
+        session.current = Some(session.api_key_id) == current;
+    }
+    Ok(Json(serde_json::json!({ "sessions": login_sessions })))
+}
+
+// Sign out one of a user's sessions
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(session_id): Path<i32>,
+    Query(query): Query<UserIdQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only sign out your own sessions."));
+    }
+
+    let revoked = state.db_pool.revoke_login_sessions(query.user_id, Some(session_id), None).await?;
+    if revoked.is_empty() {
+        return Err(AppError::not_found("Session not found"));
+    }
+    sessions::forget_keys(&state.redis_client, &revoked).await;
+    Ok(Json(serde_json::json!({ "detail": "Session signed out." })))
+}
+
+// Log out everywhere: signs out all of a user's sessions, except by default the one asking
+pub async fn revoke_all_sessions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<RevokeAllSessionsRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only sign out your own sessions."));
+    }
+
+    let keep = if request.include_current { None } else { current_api_key_id(&state, &api_key).await? };
+    let revoked = state.db_pool.revoke_login_sessions(request.user_id, None, keep).await?;
+    sessions::forget_keys(&state.redis_client, &revoked).await;
+    Ok(Json(serde_json::json!({ "revoked": revoked.len() })))
+}
+
+// Fill in a provider's endpoints from its issuer's discovery document. Providers without
+// an issuer are configured by hand and must give all three URLs.
+async fn resolve_oidc_endpoints(request: &mut OidcProviderRequest) -> Result<Option<OidcDiscovery>, AppError> {
+    let Some(issuer) = request.issuer_url.as_deref().map(str::trim).filter(|i| !i.is_empty()) else {
+        if request.authorization_url.is_empty() || request.token_url.is_empty() || request.user_info_url.is_empty() {
+            return Err(AppError::bad_request("Provide an issuer URL, or the authorization, token and user info URLs"));
+        }
+        if request.scope.is_empty() {
+            request.scope = "openid email profile".to_string();
+        }
+        return Ok(None);
+    };
+
+    let metadata = oidc::discover(issuer).await?;
+    request.authorization_url = metadata.authorization_endpoint;
+    request.token_url = metadata.token_endpoint;
+    request.user_info_url = metadata.userinfo_endpoint.unwrap_or_default();
+    // Without the openid scope there is no ID token to verify
+    if request.scope.is_empty() {
+        request.scope = "openid email profile".to_string();
+    } else if !request.scope.split_whitespace().any(|scope| scope == "openid") {
+        request.scope = format!("openid {}", request.scope);
+    }
+    Ok(Some(OidcDiscovery {
+        issuer: metadata.issuer,
+        jwks_uri: metadata.jwks_uri,
+        end_session_url: metadata.end_session_endpoint,
+    }))
+}
+
+// Add OIDC provider - matches Python add_oidc_provider function exactly  
+pub async fn add_oidc_provider(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut request): Json<OidcProviderRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    // Check if user is admin - OIDC provider management requires admin access
+    let user_id = state.db_pool.get_user_id_from_api_key(&api_key).await?;
+    let is_admin = state.db_pool.user_admin_check(user_id).await?;
+
+    if !is_admin {
+        return Err(AppError::forbidden("Admin access required to add OIDC providers"));
+    }
+
+    let discovery = resolve_oidc_endpoints(&mut request).await?;
+
+    let provider_id = state.db_pool.add_oidc_provider(
+        &request.provider_name,
+        &request.client_id,
+        &request.client_secret,
+        &request.authorization_url,
+        &request.token_url,
+        &request.user_info_url,
+        &request.button_text,
+        &request.scope,
+        &request.button_color,
+        &request.button_text_color,
+        request.icon_svg.as_deref().unwrap_or(""),
+        request.name_claim.as_deref().unwrap_or("name"),
+        request.email_claim.as_deref().unwrap_or("email"),
+        request.username_claim.as_deref().unwrap_or("username"),
+        request.roles_claim.as_deref().unwrap_or(""),
+        request.user_role.as_deref().unwrap_or(""),
+        request.admin_role.as_deref().unwrap_or(""),
+        false // initialized_from_env = false (added via UI)
+    ).await?;
+    state.db_pool.set_oidc_provider_discovery(provider_id, discovery.as_ref()).await?;
+    Ok(Json(serde_json::json!({ "provider_id": provider_id })))
+}
+
+// Update OIDC provider - updates an existing provider
+pub async fn update_oidc_provider(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(provider_id): Path<i32>,
+    Json(mut request): Json<OidcProviderRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    // Check if user is admin - OIDC provider management requires admin access
+    let user_id = state.db_pool.get_user_id_from_api_key(&api_key).await?;
+    let is_admin = state.db_pool.user_admin_check(user_id).await?;
+
+    if !is_admin {
+        return Err(AppError::forbidden("Admin access required to update OIDC providers"));
+    }
+
+    let discovery = resolve_oidc_endpoints(&mut request).await?;
+
+    // Only update client_secret if it's not empty
+    let client_secret_to_update = if request.client_secret.is_empty() {
+        None
+    } else {
+        Some(request.client_secret.as_str())
+    };
+
+    let success = state.db_pool.update_oidc_provider(
+        provider_id,
+        &request.provider_name,
+        &request.client_id,
+        client_secret_to_update,
+        &request.authorization_url,
+        &request.token_url,
+        &request.user_info_url,
+        &request.button_text,
+        &request.scope,
+        &request.button_color,
+        &request.button_text_color,
+        request.icon_svg.as_deref().unwrap_or(""),
+        request.name_claim.as_deref().unwrap_or("name"),
+        request.email_claim.as_deref().unwrap_or("email"),
+        request.username_claim.as_deref().unwrap_or("username"),
+        request.roles_claim.as_deref().unwrap_or(""),
+        request.user_role.as_deref().unwrap_or(""),
+        request.admin_role.as_deref().unwrap_or("")
+    ).await?;
+
+    if success {
+        state.db_pool.set_oidc_provider_discovery(provider_id, discovery.as_ref()).await?;
+        Ok(Json(serde_json::json!({ "message": "OIDC provider updated successfully" })))
+    } else {
+        Err(AppError::not_found("OIDC provider not found"))
+    }
+}
+
+// List OIDC providers - matches Python list_oidc_providers function exactly
+pub async fn list_oidc_providers(
     State(state): State<AppState>,
     headers: HeaderMap,
-    Json(provider_id): Json<i32>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    let providers = state.db_pool.list_oidc_providers().await?;
+    Ok(Json(serde_json::json!({ "providers": providers })))
+}
+
+// Remove OIDC provider - matches Python remove_oidc_provider function exactly
+pub async fn remove_oidc_provider(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(provider_id): Json<i32>,
 ) -> Result<Json<serde_json::Value>, AppError> {
     let api_key = extract_api_key(&headers)?;
     validate_api_key(&state, &api_key).await?;
@@ -3048,6 +3976,145 @@ pub async fn enable_auto_download(
     Ok(Json(serde_json::json!({ "detail": "Auto-download status updated." })))
 }
 
+#[derive(Deserialize)]
+pub struct RetentionPolicyQuery {
+    pub podcast_id: i32,
+    pub user_id: i32,
+}
+
+// Get the download retention rules of a podcast
+pub async fn get_retention_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<RetentionPolicyQuery>,
+) -> Result<Json<crate::services::retention::RetentionPolicy>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own podcasts."));
+    }
+
+    let policy = state.db_pool.get_retention_policy(query.podcast_id, query.user_id).await?
+        .ok_or_else(|| AppError::not_found("Podcast not found"))?;
+    Ok(Json(policy))
+}
+
+// Request struct for set_retention_policy
+#[derive(Deserialize)]
+pub struct SetRetentionPolicyRequest {
+    pub podcast_id: i32,
+    pub user_id: i32,
+    #[serde(flatten)]
+    pub policy: crate::services::retention::RetentionPolicy,
+}
+
+// Set the download retention rules of a podcast; the scheduled cleanup applies them
+pub async fn set_retention_policy(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SetRetentionPolicyRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only modify your own podcasts."));
+    }
+
+    request.policy.validate().map_err(AppError::bad_request)?;
+    if !state.db_pool.set_retention_policy(request.podcast_id, request.user_id, &request.policy).await? {
+        return Err(AppError::not_found("Podcast not found"));
+    }
+    Ok(Json(serde_json::json!({ "detail": "Retention policy updated." })))
+}
+
+#[derive(Deserialize)]
+pub struct ProcessingPipelineQuery {
+    pub podcast_id: i32,
+    pub user_id: i32,
+}
+
+// Get the audio post-processing settings of a podcast
+pub async fn get_processing_pipeline(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ProcessingPipelineQuery>,
+) -> Result<Json<crate::services::audio_processing::ProcessingPipeline>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, query.user_id).await? {
+        return Err(AppError::forbidden("You can only view your own podcasts."));
+    }
+
+    let pipeline = state.db_pool.get_processing_pipeline(query.podcast_id, query.user_id).await?
+        .ok_or_else(|| AppError::not_found("Podcast not found"))?;
+    Ok(Json(pipeline))
+}
+
+// Request struct for set_processing_pipeline
+#[derive(Deserialize)]
+pub struct SetProcessingPipelineRequest {
+    pub podcast_id: i32,
+    pub user_id: i32,
+    #[serde(flatten)]
+    pub pipeline: crate::services::audio_processing::ProcessingPipeline,
+}
+
+// Set how a podcast's downloads are processed with ffmpeg; applies to downloads from now on
+pub async fn set_processing_pipeline(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<SetProcessingPipelineRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only modify your own podcasts."));
+    }
+
+    request.pipeline.validate().map_err(AppError::bad_request)?;
+    if !state.db_pool.set_processing_pipeline(request.podcast_id, request.user_id, &request.pipeline).await? {
+        return Err(AppError::not_found("Podcast not found"));
+    }
+    Ok(Json(serde_json::json!({ "detail": "Processing settings updated." })))
+}
+
+#[derive(Deserialize)]
+pub struct RunRetentionCleanupRequest {
+    pub user_id: i32,
+    /// Apply every user's retention rules (admin only)
+    pub all_users: Option<bool>,
+}
+
+// Apply retention rules now instead of waiting for the scheduled cleanup
+pub async fn run_retention_cleanup(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(request): Json<RunRetentionCleanupRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let api_key = extract_api_key(&headers)?;
+    validate_api_key(&state, &api_key).await?;
+
+    let all_users = request.all_users.unwrap_or(false);
+    if all_users {
+        if !check_admin_access(&state, &api_key).await? {
+            return Err(AppError::forbidden("Admin access required"));
+        }
+    } else if !check_user_access(&state, &api_key, request.user_id).await? {
+        return Err(AppError::forbidden("You can only clean up your own downloads!"));
+    }
+
+    let only_user = (!all_users).then_some(request.user_id);
+    let task_id = state.task_spawner.spawn_retention_cleanup(request.user_id, only_user).await?;
+    Ok(Json(serde_json::json!({
+        "detail": "Retention cleanup started",
+        "task_id": task_id
+    })))
+}
+
 // Request struct for toggle_podcast_notifications - matches Python TogglePodcastNotificationData model
 #[derive(Deserialize)]
 pub struct TogglePodcastNotificationData {
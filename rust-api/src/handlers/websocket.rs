@@ -209,9 +209,9 @@ pub async fn get_active_tasks(
     if let Some(user_id) = user_id {
         // Get active tasks for specific user
         let tasks = state.task_manager.get_user_tasks(user_id).await?;
-        // Filter only active tasks (status = Running or Pending)
+        // Filter only active tasks (status = Running, Pending, or a paused download that can resume)
         let active_tasks: Vec<_> = tasks.into_iter()
-            .filter(|task| matches!(task.status, crate::services::task_manager::TaskStatus::Pending | crate::services::task_manager::TaskStatus::Running))
+            .filter(|task| matches!(task.status, crate::services::task_manager::TaskStatus::Pending | crate::services::task_manager::TaskStatus::Running | crate::services::task_manager::TaskStatus::Paused))
             .collect();
         Ok(axum::Json(active_tasks))
     } else {
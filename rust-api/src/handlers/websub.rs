@@ -0,0 +1,119 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use std::collections::HashMap;
+
+use crate::{
+    error::{AppError, AppResult},
+    services::websub::{refresh_pushed_podcast, verify_signature, PodpingNotification, SubscriptionState},
+    AppState,
+};
+
+// Intent verification from a hub - echo hub.challenge when the request matches what we asked for
+pub async fn websub_verify(
+    State(state): State<AppState>,
+    Path(callback_token): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> AppResult<Response> {
+    let subscription = state.db_pool.get_websub_subscription(&callback_token).await?
+        .ok_or_else(|| AppError::not_found("Unknown subscription"))?;
+
+    let mode = params.get("hub.mode").map(String::as_str).unwrap_or("");
+    if mode == "denied" {
+        tracing::info!("WebSub hub denied subscription for podcast {}", subscription.podcast_id);
+        state.db_pool.mark_websub_denied(subscription.podcast_id, params.get("hub.reason").map(String::as_str)).await?;
+        return Ok(StatusCode::OK.into_response());
+    }
+
+    let topic_matches = params.get("hub.topic").is_some_and(|topic| *topic == subscription.topic_url);
+    if mode != subscription.state.expected_mode() || !topic_matches {
+        return Err(AppError::not_found("No matching subscription intent"));
+    }
+    let challenge = params.get("hub.challenge").filter(|c| !c.is_empty())
+        .ok_or_else(|| AppError::bad_request("Missing hub.challenge"))?;
+
+    let lease_seconds = params.get("hub.lease_seconds").and_then(|v| v.parse::<i64>().ok()).filter(|v| *v > 0);
+    state.db_pool.confirm_websub_intent(subscription.podcast_id, mode == "subscribe", lease_seconds).await?;
+    tracing::info!("WebSub {} verified for podcast {}", mode, subscription.podcast_id);
+
+    Ok(([(header::CONTENT_TYPE, "text/plain")], challenge.clone()).into_response())
+}
+
+// Content distribution from a hub - refresh just that podcast
+pub async fn websub_notify(
+    State(state): State<AppState>,
+    Path(callback_token): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<StatusCode> {
+    let subscription = state.db_pool.get_websub_subscription(&callback_token).await?
+        .ok_or_else(|| AppError::not_found("Unknown subscription"))?;
+
+    // Hubs get a 2xx either way; unsigned or forged notifications are simply dropped
+    let signed = headers
+        .get("X-Hub-Signature-256")
+        .or_else(|| headers.get("X-Hub-Signature"))
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|signature| verify_signature(&subscription.secret, signature, &body));
+    if !signed {
+        tracing::warn!("Ignoring WebSub notification for podcast {} with a missing or invalid signature", subscription.podcast_id);
+        return Ok(StatusCode::ACCEPTED);
+    }
+    if subscription.state == SubscriptionState::Unsubscribing {
+        return Ok(StatusCode::ACCEPTED);
+    }
+
+    state.db_pool.record_websub_notification(subscription.podcast_id).await?;
+    tracing::info!("WebSub notification received for podcast {}", subscription.podcast_id);
+
+    let podcast_id = subscription.podcast_id;
+    tokio::spawn(async move {
+        refresh_pushed_podcast(&state, podcast_id).await;
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+// Podping relay - refresh every subscription to the feeds named in the notification.
+// Only enabled when PODPING_TOKEN is set; relays send it as a bearer token.
+pub async fn podping_notify(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(notification): Json<PodpingNotification>,
+) -> AppResult<Json<serde_json::Value>> {
+    let Some(expected) = state.config.push.podping_token.as_deref() else {
+        return Err(AppError::not_found("Podping notifications are not enabled"));
+    };
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(str::trim);
+    if provided != Some(expected.trim()) {
+        return Err(AppError::unauthorized("Invalid Podping token"));
+    }
+
+    let mut podcast_ids = Vec::new();
+    for url in notification.feed_urls() {
+        podcast_ids.extend(state.db_pool.get_podcast_ids_by_feed_url(&url).await?);
+    }
+
+    let refreshed = podcast_ids.len();
+    if refreshed > 0 {
+        tracing::info!("Podping notification refreshing {} podcasts", refreshed);
+        tokio::spawn(async move {
+            for podcast_id in podcast_ids {
+                refresh_pushed_podcast(&state, podcast_id).await;
+            }
+        });
+    }
+
+    Ok(Json(serde_json::json!({
+        "detail": "Notification accepted",
+        "podcasts_refreshed": refreshed,
+    })))
+}
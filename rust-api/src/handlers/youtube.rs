@@ -495,25 +495,25 @@ pub async fn process_youtube_channel(
             let video_id = video.get("id").and_then(|v| v.as_str()).unwrap_or("");
             let title = video.get("title").and_then(|v| v.as_str()).unwrap_or("");
             
-            let output_path = format!("/opt/pinepods/downloads/youtube/{}.mp3", video_id);
-            let output_path_double = format!("{}.mp3", output_path);
+            let key = crate::services::storage::youtube_key(video_id);
 
             println!("Processing download for video: {}", video_id);
             println!("Title: {}", title);
-            println!("Target path: {}", output_path);
+            println!("Target key: {}", key);
 
             // Check if file already exists
-            if tokio::fs::metadata(&output_path).await.is_ok() || 
-               tokio::fs::metadata(&output_path_double).await.is_ok() {
+            if state.storage.find_youtube_audio(video_id).await?.is_some() {
                 println!("Audio file already exists, skipping download");
                 continue;
             }
 
+            let staged = state.storage.active().staging_path(&key).await?;
+            let output_path = staged.to_string_lossy().to_string();
+
             println!("Starting download...");
             match download_youtube_audio(video_id, &output_path).await {
                 Ok(_) => {
                     println!("Download completed successfully");
-                    successful_downloads += 1;
                     
                     // Get duration from the downloaded MP3 file and update database
                     if let Some(duration) = get_mp3_duration(&output_path) {
@@ -525,6 +525,14 @@ pub async fn process_youtube_channel(
                     } else {
                         println!("Could not read duration from MP3 file: {}", output_path);
                     }
+
+                    match state.storage.active().commit(&key, &staged).await {
+                        Ok(_) => successful_downloads += 1,
+                        Err(e) => {
+                            failed_downloads += 1;
+                            println!("Failed to store video {}: {}", video_id, e);
+                        }
+                    }
                 }
                 Err(e) => {
                     failed_downloads += 1;
@@ -15,6 +15,7 @@ mod config;
 mod database;
 mod error;
 mod handlers;
+mod middleware;
 mod models;
 mod redis_client;
 mod redis_manager;
@@ -24,9 +25,9 @@ use config::Config;
 use database::DatabasePool;
 use error::AppResult;
 use redis_client::RedisClient;
-use services::{scheduler::BackgroundScheduler, task_manager::TaskManager, tasks::TaskSpawner};
+use services::{download_queue::DownloadQueue, scheduler::BackgroundScheduler, storage::Storage, task_manager::TaskManager, tasks::TaskSpawner};
 use handlers::websocket::WebSocketManager;
-use redis_manager::{ImportProgressManager, NotificationManager};
+use redis_manager::ImportProgressManager;
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -38,7 +39,8 @@ pub struct AppState {
     pub task_spawner: Arc<TaskSpawner>,
     pub websocket_manager: Arc<WebSocketManager>,
     pub import_progress_manager: Arc<ImportProgressManager>,
-    pub notification_manager: Arc<NotificationManager>,
+    pub storage: Arc<Storage>,
+    pub download_queue: Arc<DownloadQueue>,
 }
 
 #[tokio::main]
@@ -72,14 +74,21 @@ async fn main() -> AppResult<()> {
     let redis_client = RedisClient::new(&config).await?;
     info!("Redis/Valkey client initialized");
 
+    // Initialize download storage
+    let storage = Arc::new(Storage::from_config(&config.storage)?);
+    info!("Download storage initialized ({} backend)", storage.active().name());
+
     // Initialize task management
     let task_manager = Arc::new(TaskManager::new(redis_client.clone()));
-    let task_spawner = Arc::new(TaskSpawner::new(task_manager.clone(), db_pool.clone()));
+    let download_queue = Arc::new(DownloadQueue::new(task_manager.clone(), db_pool.clone(), storage.clone(), config.downloads.clone()));
+    let task_spawner = Arc::new(TaskSpawner::new(task_manager.clone(), db_pool.clone(), storage.clone(), download_queue.clone()));
     let websocket_manager = Arc::new(WebSocketManager::new());
     let import_progress_manager = Arc::new(ImportProgressManager::new(redis_client.clone()));
-    let notification_manager = Arc::new(NotificationManager::new(redis_client.clone()));
     info!("Task management system initialized");
 
+    // Start the download queue workers
+    download_queue.start();
+
     // Create shared application state
     let app_state = AppState {
         db_pool,
@@ -89,7 +98,8 @@ async fn main() -> AppResult<()> {
         task_spawner,
         websocket_manager,
         import_progress_manager,
-        notification_manager,
+        storage,
+        download_queue,
     };
 
     // Build the application with routes
@@ -123,7 +133,7 @@ async fn main() -> AppResult<()> {
     // Start the server
     let listener = tokio::net::TcpListener::bind(addr).await?;
     println!("✅ PinePods Rust API server started successfully!");
-    axum::serve(listener, app)
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .with_graceful_shutdown(shutdown_signal())
         .await?;
 
@@ -148,9 +158,18 @@ fn create_app(state: AppState) -> Router {
         .nest("/api/gpodder", create_gpodder_routes())
         .nest("/api/feed", create_feed_routes())
         .nest("/api/auth", create_auth_routes())
+        .nest("/api/websub", create_websub_routes())
         .nest("/ws", create_websocket_routes())
         
         // Middleware stack
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::api_scopes::enforce_api_key_scopes,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            middleware::rate_limit::enforce_rate_limits,
+        ))
         .layer(
             ServiceBuilder::new()
                 .layer(
@@ -170,6 +189,8 @@ fn create_data_routes() -> Router<AppState> {
     Router::new()
         .route("/get_key", get(handlers::auth::get_key))
         .route("/verify_mfa_and_get_key", post(handlers::auth::verify_mfa_and_get_key))
+        .route("/passkey_login/start", post(handlers::auth::start_passkey_login))
+        .route("/passkey_login/finish", post(handlers::auth::finish_passkey_login))
         .route("/verify_key", get(handlers::auth::verify_api_key_endpoint))
         .route("/get_user", get(handlers::auth::get_user))
         .route("/user_details_id/{user_id}", get(handlers::auth::get_user_details_by_id))
@@ -219,6 +240,10 @@ fn create_data_routes() -> Router<AppState> {
         .route("/delete_episode", post(handlers::podcasts::delete_episode))
         .route("/download_all_podcast", post(handlers::podcasts::download_all_podcast))
         .route("/download_status/{user_id}", get(handlers::podcasts::download_status))
+        .route("/download_queue/{user_id}", get(handlers::podcasts::get_download_queue))
+        .route("/pause_download", post(handlers::podcasts::pause_download))
+        .route("/resume_download", post(handlers::podcasts::resume_download))
+        .route("/cancel_download", post(handlers::podcasts::cancel_download))
         .route("/podcast_episodes", get(handlers::podcasts::podcast_episodes))
         .route("/get_podcast_id_from_ep_name", get(handlers::podcasts::get_podcast_id_from_ep_name))
         .route("/get_episode_id_ep_name", get(handlers::podcasts::get_episode_id_ep_name))
@@ -245,6 +270,7 @@ fn create_data_routes() -> Router<AppState> {
         .route("/get_pinepods_version", get(handlers::podcasts::get_pinepods_version))
         .route("/search_data", post(handlers::podcasts::search_data))
         .route("/fetch_transcript", post(handlers::podcasts::fetch_transcript))
+        .route("/episode_transcript", get(handlers::podcasts::get_episode_transcript))
         .route("/home_overview", get(handlers::podcasts::home_overview))
         .route("/get_playlists", get(handlers::podcasts::get_playlists))
         .route("/get_playlist_episodes", get(handlers::podcasts::get_playlist_episodes))
@@ -254,6 +280,10 @@ fn create_data_routes() -> Router<AppState> {
         .route("/get_podcast_details_dynamic", get(handlers::podcasts::get_podcast_details_dynamic))
         .route("/podpeople/host_podcasts", get(handlers::podcasts::get_host_podcasts))
         .route("/update_feed_cutoff_days", post(handlers::podcasts::update_feed_cutoff_days))
+        .route("/podcast/refresh_schedule", get(handlers::podcasts::get_refresh_schedule))
+        .route("/podcast/set_refresh_interval", post(handlers::podcasts::set_refresh_interval))
+        .route("/feed_health", get(handlers::podcasts::get_feed_health))
+        .route("/dedupe_episodes", post(handlers::podcasts::dedupe_episodes))
         .route("/fetch_podcast_feed", get(handlers::podcasts::fetch_podcast_feed))
         .route("/youtube_episodes", get(handlers::podcasts::youtube_episodes))
         .route("/remove_youtube_channel", post(handlers::podcasts::remove_youtube_channel))
@@ -271,6 +301,12 @@ fn create_data_routes() -> Router<AppState> {
         .route("/user/set_email", put(handlers::settings::set_email))
         .route("/user/set_username", put(handlers::settings::set_username))
         .route("/user/set_isadmin", put(handlers::settings::set_isadmin))
+        .route("/user/set_download_quota", put(handlers::settings::set_download_quota))
+        .route("/storage_usage", get(handlers::settings::get_storage_usage))
+        .route("/download_template", get(handlers::settings::get_download_template))
+        .route("/user/set_download_template", put(handlers::settings::set_download_template))
+        .route("/set_server_download_template", put(handlers::settings::set_server_download_template))
+        .route("/reorganize_downloads", post(handlers::settings::reorganize_downloads))
         .route("/user/final_admin/{user_id}", get(handlers::settings::final_admin))
         .route("/enable_disable_guest", post(handlers::settings::enable_disable_guest))
         .route("/enable_disable_downloads", post(handlers::settings::enable_disable_downloads))
@@ -279,6 +315,8 @@ fn create_data_routes() -> Router<AppState> {
         .route("/rss_feed_status", get(handlers::settings::rss_feed_status))
         .route("/toggle_rss_feeds", post(handlers::settings::toggle_rss_feeds))
         .route("/download_status", get(handlers::settings::download_status))
+        .route("/get_rate_limit_settings", get(handlers::settings::get_rate_limit_settings))
+        .route("/update_rate_limit_settings", post(handlers::settings::update_rate_limit_settings))
         .route("/admin_self_service_status", get(handlers::settings::self_service_status))
         .route("/save_email_settings", post(handlers::settings::save_email_settings))
         .route("/get_email_settings", get(handlers::settings::get_email_settings))
@@ -320,6 +358,31 @@ fn create_data_routes() -> Router<AppState> {
         .route("/user/set_global_podcast_cover_preference", post(handlers::settings::set_global_podcast_cover_preference))
         .route("/user/get_podcast_cover_preference", get(handlers::settings::get_global_podcast_cover_preference))
         .route("/user/test_notification", post(handlers::settings::test_notification))
+        .route("/user/notification_destinations", get(handlers::settings::get_notification_destinations))
+        .route("/user/notification_destinations", post(handlers::settings::add_notification_destination))
+        .route("/user/notification_destinations/{destination_id}", put(handlers::settings::update_notification_destination))
+        .route("/user/notification_destinations/{destination_id}", delete(handlers::settings::delete_notification_destination))
+        .route("/user/notification_destinations/{destination_id}/test", post(handlers::settings::test_notification_destination))
+        .route("/user/notification_deliveries", get(handlers::settings::get_notification_deliveries))
+        .route("/user/email_digest", get(handlers::settings::get_email_digest_settings))
+        .route("/user/email_digest", put(handlers::settings::update_email_digest_settings))
+        .route("/user/email_digest/send", post(handlers::settings::send_email_digest))
+        .route("/user/webhooks", get(handlers::settings::get_webhooks))
+        .route("/user/webhooks", post(handlers::settings::add_webhook))
+        .route("/user/webhooks/{webhook_id}", put(handlers::settings::update_webhook))
+        .route("/user/webhooks/{webhook_id}", delete(handlers::settings::delete_webhook))
+        .route("/user/webhooks/{webhook_id}/rotate_secret", post(handlers::settings::rotate_webhook_secret))
+        .route("/user/webhooks/{webhook_id}/test", post(handlers::settings::test_webhook))
+        .route("/user/webhooks/{webhook_id}/deliveries", get(handlers::settings::get_webhook_deliveries))
+        .route("/user/passkeys", get(handlers::settings::get_passkeys))
+        .route("/user/passkeys/register/start", post(handlers::settings::start_passkey_registration))
+        .route("/user/passkeys/register/finish", post(handlers::settings::finish_passkey_registration))
+        .route("/user/passkeys/{passkey_id}", delete(handlers::settings::delete_passkey))
+        .route("/user/recovery_codes", get(handlers::settings::get_recovery_codes_status))
+        .route("/user/recovery_codes", post(handlers::settings::generate_recovery_codes))
+        .route("/user/sessions", get(handlers::settings::get_sessions))
+        .route("/user/sessions/revoke_all", post(handlers::settings::revoke_all_sessions))
+        .route("/user/sessions/{session_id}", delete(handlers::settings::revoke_session))
         .route("/add_oidc_provider", post(handlers::settings::add_oidc_provider))
         .route("/update_oidc_provider/{provider_id}", put(handlers::settings::update_oidc_provider))
         .route("/list_oidc_providers", get(handlers::settings::list_oidc_providers))
@@ -334,6 +397,11 @@ fn create_data_routes() -> Router<AppState> {
         .route("/youtube/subscribe", post(handlers::youtube::subscribe_to_youtube_channel))
         .route("/check_youtube_channel", get(handlers::youtube::check_youtube_channel))
         .route("/enable_auto_download", post(handlers::settings::enable_auto_download))
+        .route("/get_retention_policy", get(handlers::settings::get_retention_policy))
+        .route("/set_retention_policy", put(handlers::settings::set_retention_policy))
+        .route("/run_retention_cleanup", post(handlers::settings::run_retention_cleanup))
+        .route("/get_processing_pipeline", get(handlers::settings::get_processing_pipeline))
+        .route("/set_processing_pipeline", put(handlers::settings::set_processing_pipeline))
         .route("/adjust_skip_times", post(handlers::settings::adjust_skip_times))
         .route("/remove_category", post(handlers::settings::remove_category))
         .route("/add_category", post(handlers::settings::add_category))
@@ -429,6 +497,15 @@ fn create_auth_routes() -> Router<AppState> {
     Router::new()
         .route("/store_state", post(handlers::auth::store_oidc_state))
         .route("/callback", get(handlers::auth::oidc_callback))
+        .route("/proxy_login", get(handlers::auth::proxy_login))
+        .route("/oidc_logout", post(handlers::auth::oidc_logout))
+        .route("/backchannel_logout/{provider_id}", post(handlers::auth::oidc_backchannel_logout))
+}
+
+fn create_websub_routes() -> Router<AppState> {
+    Router::new()
+        .route("/callback/{callback_token}", get(handlers::websub::websub_verify).post(handlers::websub::websub_notify))
+        .route("/podping", post(handlers::websub::podping_notify))
 }
 
 async fn shutdown_signal() {
@@ -0,0 +1,129 @@
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use crate::{
+    error::{AppError, AppResult},
+    services::api_scopes::{policy_cache_key, required_scope, ApiKeyPolicy},
+    AppState,
+};
+
+// Scopes are cached briefly so the check doesn't add a query to every request
+const POLICY_CACHE_SECONDS: u64 = 60;
+// LastUsed, and a login session's last-seen time, are only written once per key in this window
+const LAST_USED_WRITE_INTERVAL_SECONDS: u64 = 60;
+
+/// Reject requests whose API key has expired, whose login session has timed out,
+/// or which lack the scope the route needs.
+/// Requests without a key, or with an unknown key, pass through so the handler's
+/// own authentication produces the usual error.
+pub async fn enforce_api_key_scopes(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let Some(api_key) = request_api_key(&request) else {
+        return next.run(request).await;
+    };
+
+    let policy = match load_policy(&state, &api_key).await {
+        Ok(Some(policy)) => policy,
+        Ok(None) => return next.run(request).await,
+        Err(e) => return e.into_response(),
+    };
+
+    if policy.is_expired(chrono::Utc::now().naive_utc()) {
+        return AppError::unauthorized("This API key or its session has expired").into_response();
+    }
+
+    let scope = required_scope(request.method(), request.uri().path());
+    if !policy.allows(scope) {
+        return AppError::forbidden(format!("This API key does not have the '{}' scope", scope.as_str())).into_response();
+    }
+
+    record_last_used(&state, &api_key, policy).await;
+    next.run(request).await
+}
+
+// The key from the usual headers, or from the `api_key` query parameter that streams,
+// feeds and websockets take it in since players and browsers can't set headers there
+fn request_api_key(request: &Request) -> Option<String> {
+    crate::handlers::extract_api_key(request.headers()).ok().or_else(|| {
+        url::form_urlencoded::parse(request.uri().query()?.as_bytes())
+            .find(|(name, _)| name == "api_key")
+            .map(|(_, value)| value.into_owned())
+            .filter(|value| !value.is_empty())
+    })
+}
+
+async fn load_policy(state: &AppState, api_key: &str) -> AppResult<Option<ApiKeyPolicy>> {
+    if let Ok(Some(cached)) = state.redis_client.get::<String>(&policy_cache_key(api_key)).await {
+        if let Ok(policy) = serde_json::from_str(&cached) {
+            return Ok(Some(policy));
+        }
+    }
+
+    let policy = state.db_pool.get_api_key_policy(api_key).await?;
+    if let Some(policy) = &policy {
+        cache_policy(state, api_key, policy).await;
+    }
+    Ok(policy)
+}
+
+async fn cache_policy(state: &AppState, api_key: &str, policy: &ApiKeyPolicy) {
+    let Ok(json) = serde_json::to_string(policy) else { return };
+    if let Err(e) = state.redis_client.set_ex(&policy_cache_key(api_key), json, POLICY_CACHE_SECONDS).await {
+        tracing::warn!("Failed to cache API key policy: {}", e);
+    }
+}
+
+async fn record_last_used(state: &AppState, api_key: &str, mut policy: ApiKeyPolicy) {
+    let api_key_id = policy.api_key_id;
+    let marker = format!("api_key_last_used:{}", api_key_id);
+    if state.redis_client.exists(&marker).await.unwrap_or(false) {
+        return;
+    }
+    if let Err(e) = state.redis_client.set_ex(&marker, 1, LAST_USED_WRITE_INTERVAL_SECONDS).await {
+        tracing::warn!("Failed to set API key last-used marker: {}", e);
+    }
+
+    // Activity keeps a login session from idling out
+    let idle_expires_at = state.config.sessions.idle_timeout().map(|idle| chrono::Utc::now().naive_utc() + idle);
+    if policy.idle_expires_at.is_some() {
+        // Keep the cached copy in step so it isn't judged against the old deadline
+        policy.idle_expires_at = idle_expires_at;
+        cache_policy(state, api_key, &policy).await;
+    }
+    let db_pool = state.db_pool.clone();
+    tokio::spawn(async move {
+        if let Err(e) = db_pool.touch_api_key_last_used(api_key_id).await {
+            tracing::warn!("Failed to update API key last-used time: {}", e);
+        }
+        if let Err(e) = db_pool.touch_login_session(api_key_id, idle_expires_at).await {
+            tracing::warn!("Failed to update login session last-seen time: {}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(uri: &str, header: Option<&str>) -> Request {
+        let mut builder = Request::builder().uri(uri);
+        if let Some(key) = header {
+            builder = builder.header("Api-Key", key);
+        }
+        builder.body(axum::body::Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn keys_are_read_from_headers_then_the_query_string() {
+        assert_eq!(request_api_key(&request("/api/data/return_pods/2", Some("header-key"))).as_deref(), Some("header-key"));
+        assert_eq!(
+            request_api_key(&request("/api/data/stream/5?user_id=2&api_key=query%2Bkey", None)).as_deref(),
+            Some("query+key")
+        );
+        assert_eq!(request_api_key(&request("/ws/api/tasks/2?api_key=q", Some("h"))).as_deref(), Some("h"));
+        assert_eq!(request_api_key(&request("/api/feed/2?api_key=", None)), None);
+        assert_eq!(request_api_key(&request("/api/feed/2?limit=5", None)), None);
+        assert_eq!(request_api_key(&request("/api/feed/2", None)), None);
+    }
+}
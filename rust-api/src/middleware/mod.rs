@@ -0,0 +1,2 @@
+pub mod api_scopes;
+pub mod rate_limit;
@@ -0,0 +1,113 @@
+use std::net::{IpAddr, SocketAddr};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use crate::{
+    error::AppResult,
+    services::{
+        proxy_auth,
+        rate_limit::{bucket, RateLimitClient, RateLimitSettings, RateLimitTier, RATE_LIMIT_WINDOW_SECONDS},
+    },
+    AppState,
+};
+
+const SETTINGS_CACHE_KEY: &str = "rate_limit_settings";
+const SETTINGS_CACHE_SECONDS: u64 = 30;
+
+/// Count each request against a budget for the route's tier: the API key's when a valid
+/// one is sent, otherwise the client address's. Login routes are always budgeted per
+/// address. Redis failures let the request through rather than taking the API down with
+/// the cache.
+pub async fn enforce_rate_limits(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let settings = match load_settings(&state).await {
+        Ok(settings) => settings,
+        Err(e) => {
+            tracing::warn!("Failed to load rate limit settings: {}", e);
+            RateLimitSettings::default()
+        }
+    };
+    if !settings.enabled {
+        return next.run(request).await;
+    }
+
+    let tier = RateLimitTier::classify(request.uri().path());
+    let limit = settings.limit_for(tier);
+
+    let api_key = crate::handlers::extract_api_key(request.headers()).ok();
+    let client = match api_key.as_deref() {
+        // Made-up keys fall back to the address budget, so they can't dodge it
+        Some(api_key) if tier != RateLimitTier::Login
+            && crate::handlers::validate_api_key(&state, api_key).await.unwrap_or(false) => RateLimitClient::ApiKey(api_key),
+        _ => match client_ip(&request) {
+            Some(ip) => RateLimitClient::Ip(ip),
+            None => return next.run(request).await,
+        },
+    };
+
+    let identifier = bucket(tier, client);
+    match state.redis_client.check_rate_limit(&identifier, limit, RATE_LIMIT_WINDOW_SECONDS).await {
+        Ok(true) => {}
+        Ok(false) => {
+            let retry_after = state
+                .redis_client
+                .rate_limit_ttl(&identifier)
+                .await
+                .ok()
+                .filter(|ttl| *ttl > 0)
+                .unwrap_or(RATE_LIMIT_WINDOW_SECONDS as i64);
+            return too_many_requests(retry_after);
+        }
+        Err(e) => tracing::warn!("Rate limit check failed: {}", e),
+    }
+
+    next.run(request).await
+}
+
+async fn load_settings(state: &AppState) -> AppResult<RateLimitSettings> {
+    if let Ok(Some(cached)) = state.redis_client.get::<String>(SETTINGS_CACHE_KEY).await {
+        if let Ok(settings) = serde_json::from_str(&cached) {
+            return Ok(settings);
+        }
+    }
+
+    let settings = state.db_pool.get_rate_limit_settings().await?;
+    if let Err(e) = state.redis_client.set_ex(SETTINGS_CACHE_KEY, serde_json::to_string(&settings)?, SETTINGS_CACHE_SECONDS).await {
+        tracing::warn!("Failed to cache rate limit settings: {}", e);
+    }
+    Ok(settings)
+}
+
+/// Drop the cached settings so an admin's change applies immediately
+pub async fn invalidate_settings_cache(state: &AppState) {
+    if let Err(e) = state.redis_client.delete(SETTINGS_CACHE_KEY).await {
+        tracing::warn!("Failed to clear cached rate limit settings: {}", e);
+    }
+}
+
+// Same source address the proxy login and session list use, so clients can't pick their
+// own bucket with forwarding headers
+fn client_ip(request: &Request) -> Option<IpAddr> {
+    request.extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| proxy_auth::source_ip(info.0.ip(), request.headers()))
+}
+
+fn too_many_requests(retry_after: i64) -> Response {
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(serde_json::json!({
+            "error": "Too many requests",
+            "message": format!("Rate limit exceeded, retry in {} seconds", retry_after),
+            "status_code": StatusCode::TOO_MANY_REQUESTS.as_u16(),
+        })),
+    )
+        .into_response();
+    if let Ok(value) = HeaderValue::from_str(&retry_after.to_string()) {
+        response.headers_mut().insert(header::RETRY_AFTER, value);
+    }
+    response
+}
@@ -114,6 +114,14 @@ impl RedisClient {
         self.get(&cache_key).await
     }
 
+    // Drop a revoked key's cached validation so it stops working right away
+    pub async fn forget_api_key_validation(&self, api_key: &str) -> AppResult<bool> {
+        let cache_key = format!("api_key:{}", api_key);
+        // The scope middleware's copy carries the key's session deadlines
+        self.delete(&crate::services::api_scopes::policy_cache_key(api_key)).await?;
+        self.delete(&cache_key).await
+    }
+
     // Rate limiting
     pub async fn check_rate_limit(&self, identifier: &str, limit: u32, window_seconds: u64) -> AppResult<bool> {
         let rate_key = format!("rate_limit:{}", identifier);
@@ -128,6 +136,14 @@ impl RedisClient {
         Ok(current_count <= limit as i64)
     }
 
+    // Seconds until a rate limit window resets
+    pub async fn rate_limit_ttl(&self, identifier: &str) -> AppResult<i64> {
+        let rate_key = format!("rate_limit:{}", identifier);
+        let mut conn = self.connection.clone();
+        let ttl: i64 = conn.ttl(&rate_key).await?;
+        Ok(ttl)
+    }
+
     // Background task tracking
     pub async fn store_task_status(&self, task_id: &str, status: &str, ttl_seconds: u64) -> AppResult<()> {
         let task_key = format!("task:{}", task_id);
@@ -155,6 +171,30 @@ impl RedisClient {
         self.delete(&refresh_key).await
     }
 
+    // Take a lock held under `token` for at most `ttl_seconds`; false if someone else holds it
+    pub async fn try_lock(&self, key: &str, token: &str, ttl_seconds: u64) -> AppResult<bool> {
+        let mut conn = self.connection.clone();
+        let result: Option<String> = redis::cmd("SET")
+            .arg(key)
+            .arg(token)
+            .arg("NX")
+            .arg("EX")
+            .arg(ttl_seconds)
+            .query_async(&mut conn)
+            .await?;
+        Ok(result.is_some())
+    }
+
+    // Release a lock, unless it expired and was taken by someone else meanwhile
+    pub async fn unlock(&self, key: &str, token: &str) -> AppResult<bool> {
+        let mut conn = self.connection.clone();
+        let script = redis::Script::new(
+            "if redis.call('GET', KEYS[1]) == ARGV[1] then return redis.call('DEL', KEYS[1]) else return 0 end",
+        );
+        let deleted: i64 = script.key(key).arg(token).invoke_async(&mut conn).await?;
+        Ok(deleted > 0)
+    }
+
     // Atomic get and delete operation - critical for OIDC state management
     pub async fn get_del(&self, key: &str) -> AppResult<Option<String>> {
         let mut conn = self.connection.clone();
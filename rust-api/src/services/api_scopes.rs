@@ -0,0 +1,547 @@
+use axum::http::Method;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::error::{AppError, AppResult};
+
+/// What an API key is allowed to do. Keys with no stored scopes (login keys and keys
+/// created before scopes existed) carry all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiScope {
+    /// Read the library, history, queue and settings
+    Read,
+    /// Record listening progress, queue, save and complete episodes
+    Playback,
+    /// Add, remove and configure podcasts and downloads
+    Subscriptions,
+    /// Account, key and server administration; implies every other scope
+    Admin,
+}
+
+pub const ALL_SCOPES: [ApiScope; 4] = [ApiScope::Read, ApiScope::Playback, ApiScope::Subscriptions, ApiScope::Admin];
+
+impl ApiScope {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiScope::Read => "read",
+            ApiScope::Playback => "playback",
+            ApiScope::Subscriptions => "subscriptions",
+            ApiScope::Admin => "admin",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "read" => Some(ApiScope::Read),
+            "playback" => Some(ApiScope::Playback),
+            "subscriptions" => Some(ApiScope::Subscriptions),
+            "admin" => Some(ApiScope::Admin),
+            _ => None,
+        }
+    }
+}
+
+/// Validate scopes sent by a client, rejecting unknown names and empty lists
+pub fn parse_requested_scopes(values: &[String]) -> AppResult<Vec<ApiScope>> {
+    let mut scopes = Vec::new();
+    for value in values {
+        let scope = ApiScope::parse(value)
+            .ok_or_else(|| AppError::bad_request(format!("Unknown API key scope: {}", value)))?;
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    if scopes.is_empty() {
+        return Err(AppError::bad_request("An API key needs at least one scope"));
+    }
+    Ok(scopes)
+}
+
+/// Comma separated form stored in APIKeys.Scopes
+pub fn scopes_to_column(scopes: &[ApiScope]) -> String {
+    scopes.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(",")
+}
+
+/// NULL or empty means every scope; unknown names are ignored rather than granted
+pub fn scopes_from_column(value: Option<&str>) -> Option<Vec<ApiScope>> {
+    let value = value.map(str::trim).filter(|v| !v.is_empty())?;
+    Some(value.split(',').filter_map(ApiScope::parse).collect())
+}
+
+/// Everything the scope middleware needs to know about a key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKeyPolicy {
+    pub api_key_id: i32,
+    pub scopes: Option<Vec<ApiScope>>,
+    /// The key's expiry, or its login session's absolute timeout if that comes first
+    pub expires_at: Option<NaiveDateTime>,
+    /// When the key's login session times out unless it is used again
+    #[serde(default)]
+    pub idle_expires_at: Option<NaiveDateTime>,
+}
+
+impl ApiKeyPolicy {
+    pub fn allows(&self, scope: ApiScope) -> bool {
+        match &self.scopes {
+            None => true,
+            Some(scopes) => scopes.contains(&ApiScope::Admin) || scopes.contains(&scope),
+        }
+    }
+
+    pub fn is_expired(&self, now: NaiveDateTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+            || self.idle_expires_at.is_some_and(|idle_expires_at| idle_expires_at <= now)
+    }
+}
+
+/// Redis key the scope middleware caches a key's policy under. The API key is hashed so
+/// the cache doesn't hold working credentials.
+pub fn policy_cache_key(api_key: &str) -> String {
+    format!("api_key_policy:{}", hex::encode(Sha256::digest(api_key.as_bytes())))
+}
+
+/// Longest expiry a new key may be given, about ten years
+pub const MAX_EXPIRY_DAYS: u32 = 3650;
+
+/// When a key created at `now` to last `days` expires; None means it never does
+pub fn expiry_from_days(now: NaiveDateTime, days: Option<u32>) -> AppResult<Option<NaiveDateTime>> {
+    let Some(days) = days else { return Ok(None) };
+    if !(1..=MAX_EXPIRY_DAYS).contains(&days) {
+        return Err(AppError::bad_request(format!("expires_in_days must be between 1 and {}", MAX_EXPIRY_DAYS)));
+    }
+    now.checked_add_signed(chrono::Duration::days(days as i64))
+        .map(Some)
+        .ok_or_else(|| AppError::bad_request("expires_in_days is too far in the future"))
+}
+
+/// The sooner of two optional deadlines
+pub fn earliest(a: Option<NaiveDateTime>, b: Option<NaiveDateTime>) -> Option<NaiveDateTime> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+// POST endpoints under /api/data that only read
+const READ_ONLY_POSTS: &[&str] = &[
+    "/search_data",
+    "/get_playback_speed",
+    "/get_episode_metadata",
+    "/get_auto_download_status",
+    "/get_play_episode_details",
+    "/fetch_transcript",
+    "/podcast/notification_status",
+    "/get_unmatched_podcasts",
+    "/get_ignored_podcasts",
+];
+
+// GET endpoints under /api/data a read-only key may call. GETs that aren't listed
+// need `Admin`, so new endpoints are locked down until classified.
+const READ_GETS: &[&str] = &[
+    "/get_key",
+    "/verify_key",
+    "/get_user",
+    "/self_service_status",
+    "/public_oidc_providers",
+    "/config",
+    "/first_login_done",
+    "/get_theme",
+    "/get_auto_complete_seconds",
+    "/user_admin_check",
+    "/import_progress",
+    "/return_episodes",
+    "/user_history",
+    "/return_pods",
+    "/return_pods_extra",
+    "/get_time_info",
+    "/check_podcast",
+    "/check_episode_in_db",
+    "/get_queued_episodes",
+    "/saved_episode_list",
+    "/get_podcast_id",
+    "/download_episode_list",
+    "/download_status",
+    "/download_queue",
+    "/podcast_episodes",
+    "/get_podcast_id_from_ep_name",
+    "/get_episode_id_ep_name",
+    "/fetch_podcasting_2_data",
+    "/get_feed_cutoff_days",
+    "/fetch_podcasting_2_pod_data",
+    "/episode_by_url",
+    "/get_podcast_id_from_ep_id",
+    "/get_stats",
+    "/get_pinepods_version",
+    "/episode_transcript",
+    "/home_overview",
+    "/get_playlists",
+    "/get_playlist_episodes",
+    "/get_podcast_details",
+    "/get_podcast_details_dynamic",
+    "/podpeople/host_podcasts",
+    "/podcast/refresh_schedule",
+    "/feed_health",
+    "/fetch_podcast_feed",
+    "/youtube_episodes",
+    "/stream",
+    "/get_user_info",
+    "/my_user_info",
+    "/storage_usage",
+    "/download_template",
+    "/user/final_admin",
+    "/guest_status",
+    "/rss_feed_status",
+    "/admin_self_service_status",
+    "/check_mfa_enabled",
+    "/get_gpodder_settings",
+    "/check_gpodder_settings",
+    "/gpodder/status",
+    "/user/get_podcast_cover_preference",
+    "/user/notification_deliveries",
+    "/user/email_digest",
+    "/list_oidc_providers",
+    "/startpage",
+    "/person/subscriptions",
+    "/person/episodes",
+    "/search_youtube_channels",
+    "/check_youtube_channel",
+    "/get_retention_policy",
+    "/get_processing_pipeline",
+    "/get_user_language",
+    "/get_available_languages",
+    "/get_server_default_language",
+];
+
+// GET endpoints that refresh subscriptions
+const SUBSCRIPTION_GETS: &[&str] = &[
+    "/refresh_pods",
+    "/refresh_gpodder_subscriptions",
+    "/refresh_nextcloud_subscriptions",
+];
+
+/// Endpoints under /api/data whose responses carry passwords, tokens, keys or signing
+/// secrets. None of them may appear in the read-only list; they always need `Admin`.
+pub const SECRET_ROUTES: &[&str] = &[
+    "/user_details_id",
+    "/get_api_info",
+    "/get_email_settings",
+    "/generate_mfa_secret",
+    "/get_rss_key",
+    "/rss_key",
+    "/user/notification_settings",
+    "/user/notification_destinations",
+    "/user/webhooks",
+    "/user/sessions",
+    "/user/passkeys",
+    "/user/recovery_codes",
+];
+
+// Read-only GET endpoints outside /api/data
+const OTHER_READ_GETS: &[&str] = &[
+    "/api/pinepods_check",
+    "/api/health",
+    "/api/episodes",
+    "/api/tasks",
+    "/api/proxy/image",
+    "/api/feed",
+    "/ws/api/tasks",
+];
+
+// GET endpoints outside /api/data that change subscriptions, like the websocket that
+// refreshes them (the same work as /refresh_pods)
+const OTHER_SUBSCRIPTION_GETS: &[&str] = &[
+    "/ws/api/data/episodes",
+];
+
+// POST endpoints outside /api/data a read-only key may call: a read, and signing the key itself out
+const OTHER_READ_POSTS: &[&str] = &[
+    "/api/podcasts/notification_status",
+    "/api/auth/oidc_logout",
+];
+
+const PLAYBACK_ROUTES: &[&str] = &[
+    "/increment_listen_time",
+    "/increment_played",
+    "/record_listen_duration",
+    "/record_podcast_history",
+    "/queue_pod",
+    "/remove_queued_pod",
+    "/reorder_queue",
+    "/save_episode",
+    "/remove_saved_episode",
+    "/mark_episode_completed",
+    "/mark_episode_uncompleted",
+    "/update_episode_duration",
+    "/bulk_mark_episodes_completed",
+    "/bulk_save_episodes",
+    "/bulk_queue_episodes",
+    "/share_episode",
+    "/create_playlist",
+    "/delete_playlist",
+    "/user/set_playback_speed",
+    "/podcast/set_playback_speed",
+];
+
+const SUBSCRIPTION_ROUTES: &[&str] = &[
+    "/import_opml",
+    "/add_podcast",
+    "/add_custom_podcast",
+    "/update_podcast_info",
+    "/remove_podcast",
+    "/remove_podcast_id",
+    "/remove_podcast_name",
+    "/remove_podcast_sync",
+    "/download_podcast",
+    "/download_all_podcast",
+    "/pause_download",
+    "/resume_download",
+    "/cancel_download",
+    "/delete_episode",
+    "/bulk_download_episodes",
+    "/bulk_delete_downloaded_episodes",
+    "/user/set_download_template",
+    "/user/email_digest",
+    "/update_feed_cutoff_days",
+    "/set_retention_policy",
+    "/set_processing_pipeline",
+    "/run_retention_cleanup",
+    "/remove_youtube_channel",
+    "/youtube/subscribe",
+    "/person/subscribe",
+    "/person/unsubscribe",
+    "/enable_auto_download",
+    "/adjust_skip_times",
+    "/add_category",
+    "/remove_category",
+    "/podcast/set_cover_preference",
+    "/podcast/clear_cover_preference",
+    "/podcast/toggle_notifications",
+    "/podcast/set_refresh_interval",
+    "/update_podcast_index_id",
+    "/ignore_podcast_index_id",
+];
+
+// True when `path` is `route` itself or `route` followed by path parameters
+fn matches_route(path: &str, route: &str) -> bool {
+    path.strip_prefix(route).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Work out which scope a request needs. Anything not listed falls back to `Admin`,
+/// so new endpoints are locked down until classified.
+pub fn required_scope(method: &Method, path: &str) -> ApiScope {
+    if let Some(route) = path.strip_prefix("/api/data") {
+        if SECRET_ROUTES.iter().any(|r| matches_route(route, r)) {
+            return ApiScope::Admin;
+        }
+        if *method == Method::GET {
+            if SUBSCRIPTION_GETS.iter().any(|r| matches_route(route, r)) {
+                return ApiScope::Subscriptions;
+            }
+            // /{podcast_id}/merged
+            if READ_GETS.iter().any(|r| matches_route(route, r)) || route.ends_with("/merged") {
+                return ApiScope::Read;
+            }
+            return ApiScope::Admin;
+        }
+        if READ_ONLY_POSTS.iter().any(|r| matches_route(route, r)) {
+            return ApiScope::Read;
+        }
+        if PLAYBACK_ROUTES.iter().any(|r| matches_route(route, r)) {
+            return ApiScope::Playback;
+        }
+        // /{podcast_id}/merge and /{podcast_id}/unmerge/{target_podcast_id}
+        if SUBSCRIPTION_ROUTES.iter().any(|r| matches_route(route, r))
+            || route.ends_with("/merge")
+            || route.contains("/unmerge/")
+        {
+            return ApiScope::Subscriptions;
+        }
+        return ApiScope::Admin;
+    }
+
+    if path.starts_with("/api/gpodder") {
+        return if *method == Method::GET { ApiScope::Read } else { ApiScope::Subscriptions };
+    }
+
+    if *method == Method::GET && OTHER_SUBSCRIPTION_GETS.iter().any(|r| matches_route(path, r)) {
+        return ApiScope::Subscriptions;
+    }
+    let read_only = if *method == Method::GET { OTHER_READ_GETS } else { OTHER_READ_POSTS };
+    if read_only.iter().any(|r| matches_route(path, r)) {
+        return ApiScope::Read;
+    }
+
+    ApiScope::Admin
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(path: &str) -> ApiScope {
+        required_scope(&Method::GET, path)
+    }
+
+    fn post(path: &str) -> ApiScope {
+        required_scope(&Method::POST, path)
+    }
+
+    #[test]
+    fn secret_routes_need_admin() {
+        for route in SECRET_ROUTES {
+            let path = format!("/api/data{}", route);
+            assert_eq!(get(&path), ApiScope::Admin, "GET {}", path);
+            assert_eq!(get(&format!("{}/3", path)), ApiScope::Admin, "GET {}/3", path);
+            assert_eq!(post(&path), ApiScope::Admin, "POST {}", path);
+        }
+    }
+
+    #[test]
+    fn secret_routes_are_never_read_only() {
+        for route in SECRET_ROUTES {
+            assert!(!READ_GETS.iter().any(|r| matches_route(route, r) || matches_route(r, route)), "{} is listed as read-only", route);
+            assert!(!READ_ONLY_POSTS.iter().any(|r| matches_route(route, r) || matches_route(r, route)), "{} is listed as read-only", route);
+        }
+    }
+
+    #[test]
+    fn unlisted_routes_need_admin() {
+        assert_eq!(get("/api/data/some_new_endpoint"), ApiScope::Admin);
+        assert_eq!(post("/api/data/some_new_endpoint"), ApiScope::Admin);
+        assert_eq!(get("/api/some_new_area/list"), ApiScope::Admin);
+        assert_eq!(post("/api/init/startup_tasks"), ApiScope::Admin);
+        assert_eq!(required_scope(&Method::DELETE, "/api/data/delete_api_key"), ApiScope::Admin);
+    }
+
+    #[test]
+    fn listed_routes_get_their_scope() {
+        assert_eq!(get("/api/data/return_pods/2"), ApiScope::Read);
+        assert_eq!(get("/api/data/12/merged"), ApiScope::Read);
+        assert_eq!(get("/api/data/refresh_pods"), ApiScope::Subscriptions);
+        assert_eq!(get("/api/data/refresh_hosts"), ApiScope::Admin);
+        assert_eq!(post("/api/data/search_data"), ApiScope::Read);
+        assert_eq!(post("/api/data/queue_pod"), ApiScope::Playback);
+        assert_eq!(required_scope(&Method::PUT, "/api/data/increment_listen_time/2"), ApiScope::Playback);
+        assert_eq!(post("/api/data/add_podcast"), ApiScope::Subscriptions);
+        assert_eq!(post("/api/data/12/merge"), ApiScope::Subscriptions);
+        assert_eq!(post("/api/data/12/unmerge/13"), ApiScope::Subscriptions);
+        assert_eq!(get("/api/gpodder/devices"), ApiScope::Read);
+        assert_eq!(post("/api/gpodder/sync"), ApiScope::Subscriptions);
+        assert_eq!(get("/api/episodes/5/download"), ApiScope::Read);
+        assert_eq!(post("/api/podcasts/notification_status"), ApiScope::Read);
+        assert_eq!(get("/ws/api/data/episodes/2"), ApiScope::Subscriptions);
+        assert_eq!(required_scope(&Method::PUT, "/api/data/user/email_digest"), ApiScope::Subscriptions);
+        assert_eq!(get("/api/data/user/email_digest"), ApiScope::Read);
+        assert_eq!(required_scope(&Method::PUT, "/api/data/set_processing_pipeline"), ApiScope::Subscriptions);
+        assert_eq!(get("/api/data/get_processing_pipeline"), ApiScope::Read);
+        assert_eq!(post("/api/data/pause_download"), ApiScope::Subscriptions);
+        assert_eq!(post("/api/data/resume_download"), ApiScope::Subscriptions);
+        assert_eq!(post("/api/data/cancel_download"), ApiScope::Subscriptions);
+        assert_eq!(required_scope(&Method::PUT, "/api/data/set_retention_policy"), ApiScope::Subscriptions);
+        assert_eq!(post("/api/data/run_retention_cleanup"), ApiScope::Subscriptions);
+        assert_eq!(get("/api/data/get_retention_policy"), ApiScope::Read);
+        assert_eq!(required_scope(&Method::PUT, "/api/data/user/set_download_template"), ApiScope::Subscriptions);
+        assert_eq!(required_scope(&Method::PUT, "/api/data/set_server_download_template"), ApiScope::Admin);
+        assert_eq!(get("/ws/api/tasks/2"), ApiScope::Read);
+        assert_eq!(get("/api/data/stream/5"), ApiScope::Read);
+        assert_eq!(get("/api/feed/2"), ApiScope::Read);
+    }
+
+    #[test]
+    fn routes_match_whole_segments() {
+        // A listed route covers its path parameters, not other routes sharing its prefix
+        assert!(matches_route("/stream/5", "/stream"));
+        assert!(!matches_route("/streaming", "/stream"));
+        assert!(!matches_route("/rss_key_extra", "/rss_key"));
+    }
+
+    #[test]
+    fn policies_check_scope_and_expiry() {
+        let now = chrono::Utc::now().naive_utc();
+        let read_only = ApiKeyPolicy {
+            api_key_id: 1,
+            scopes: Some(vec![ApiScope::Read]),
+            expires_at: Some(now + chrono::Duration::hours(1)),
+            idle_expires_at: None,
+        };
+        assert!(read_only.allows(ApiScope::Read));
+        assert!(!read_only.allows(ApiScope::Admin));
+        assert!(!read_only.is_expired(now));
+        assert!(read_only.is_expired(now + chrono::Duration::hours(2)));
+
+        let admin = ApiKeyPolicy { api_key_id: 2, scopes: Some(vec![ApiScope::Admin]), expires_at: None, idle_expires_at: None };
+        assert!(admin.allows(ApiScope::Subscriptions));
+        assert!(!admin.is_expired(now));
+    }
+
+    fn session_policy(expires_at: NaiveDateTime, idle_expires_at: NaiveDateTime) -> ApiKeyPolicy {
+        ApiKeyPolicy { api_key_id: 3, scopes: None, expires_at: Some(expires_at), idle_expires_at: Some(idle_expires_at) }
+    }
+
+    #[test]
+    fn idle_expired_session_key_is_expired() {
+        let now = chrono::Utc::now().naive_utc();
+        let policy = session_policy(now + chrono::Duration::days(60), now - chrono::Duration::minutes(1));
+        assert!(policy.is_expired(now));
+        // Still inside the idle window
+        let policy = session_policy(now + chrono::Duration::days(60), now + chrono::Duration::hours(3));
+        assert!(!policy.is_expired(now));
+    }
+
+    #[test]
+    fn absolutely_expired_session_key_is_expired_however_active() {
+        let now = chrono::Utc::now().naive_utc();
+        let policy = session_policy(now - chrono::Duration::seconds(1), now + chrono::Duration::hours(700));
+        assert!(policy.is_expired(now));
+        assert!(session_policy(now, now + chrono::Duration::hours(1)).is_expired(now));
+    }
+
+    #[test]
+    fn cached_policies_are_keyed_by_a_hash() {
+        let key = policy_cache_key("secret-api-key");
+        assert!(!key.contains("secret-api-key"));
+        assert_eq!(key, policy_cache_key("secret-api-key"));
+        assert_ne!(key, policy_cache_key("other-api-key"));
+    }
+
+    #[test]
+    fn expiry_is_capped() {
+        let now = chrono::Utc::now().naive_utc();
+        assert_eq!(expiry_from_days(now, None).unwrap(), None);
+        assert_eq!(expiry_from_days(now, Some(30)).unwrap(), Some(now + chrono::Duration::days(30)));
+        assert!(expiry_from_days(now, Some(MAX_EXPIRY_DAYS)).is_ok());
+        assert!(expiry_from_days(now, Some(0)).is_err());
+        assert!(expiry_from_days(now, Some(MAX_EXPIRY_DAYS + 1)).is_err());
+        assert!(expiry_from_days(now, Some(u32::MAX)).is_err());
+        // Near the end of chrono's range the addition itself fails rather than panicking
+        assert!(expiry_from_days(NaiveDateTime::MAX, Some(1)).is_err());
+    }
+
+    #[test]
+    fn earliest_deadline_wins() {
+        let now = chrono::Utc::now().naive_utc();
+        let later = now + chrono::Duration::days(1);
+        assert_eq!(earliest(Some(later), Some(now)), Some(now));
+        assert_eq!(earliest(None, Some(later)), Some(later));
+        assert_eq!(earliest(Some(now), None), Some(now));
+        assert_eq!(earliest(None, None), None);
+    }
+
+    #[test]
+    fn cached_policies_without_idle_deadline_still_load() {
+        let policy: ApiKeyPolicy = serde_json::from_str(r#"{"api_key_id":4,"scopes":null,"expires_at":null}"#).unwrap();
+        assert_eq!(policy.idle_expires_at, None);
+        assert!(!policy.is_expired(chrono::Utc::now().naive_utc()));
+    }
+
+    #[test]
+    fn scope_columns_round_trip() {
+        let scopes = parse_requested_scopes(&["Read".to_string(), "playback".to_string(), "read".to_string()]).unwrap();
+        assert_eq!(scopes, vec![ApiScope::Read, ApiScope::Playback]);
+        assert_eq!(scopes_from_column(Some(&scopes_to_column(&scopes))), Some(scopes));
+        assert_eq!(scopes_from_column(None), None);
+        assert!(parse_requested_scopes(&["everything".to_string()]).is_err());
+        assert!(parse_requested_scopes(&[]).is_err());
+    }
+}
@@ -0,0 +1,428 @@
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::watch;
+use crate::{
+    error::{AppError, AppResult},
+    services::{
+        chapters::{self, Chapter},
+        tasks::ProgressReporter,
+    },
+};
+
+/// Lowest and highest bitrate accepted for re-encoding, in kbps
+pub const MIN_BITRATE_KBPS: i32 = 16;
+pub const MAX_BITRATE_KBPS: i32 = 320;
+// EBU R128 podcast target: -16 LUFS integrated, -1.5 dBTP peak
+const LOUDNORM_FILTER: &str = "loudnorm=I=-16:TP=-1.5:LRA=11";
+// Drop leading silence and cut pauses over two seconds down to one
+const SILENCE_FILTER: &str = "silenceremove=start_periods=1:start_threshold=-50dB:stop_periods=-1:stop_duration=2:stop_silence=1:stop_threshold=-50dB";
+// Lines of ffmpeg's stderr kept for the error message when it fails
+const STDERR_TAIL_LINES: usize = 5;
+
+/// Codec a downloaded episode can be transcoded to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioCodec {
+    Opus,
+    Aac,
+}
+
+impl AudioCodec {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AudioCodec::Opus => "opus",
+            AudioCodec::Aac => "aac",
+        }
+    }
+
+    pub fn parse(codec: &str) -> Option<Self> {
+        match codec {
+            "opus" => Some(AudioCodec::Opus),
+            "aac" => Some(AudioCodec::Aac),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            AudioCodec::Opus => "opus",
+            AudioCodec::Aac => "m4a",
+        }
+    }
+
+    fn default_bitrate_kbps(&self) -> i32 {
+        match self {
+            AudioCodec::Opus => 64,
+            AudioCodec::Aac => 96,
+        }
+    }
+}
+
+/// What a podcast's downloads go through after they are fetched. Steps run in one ffmpeg
+/// pass; with nothing enabled the file is kept exactly as downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProcessingPipeline {
+    /// Normalize loudness to -16 LUFS
+    pub normalize: bool,
+    /// Cut leading silence and shorten long pauses. Embedded chapter times come from the
+    /// feed, so they drift by however much silence was cut before them.
+    pub trim_silence: bool,
+    /// Re-encode to this codec to save space
+    pub transcode: Option<AudioCodec>,
+    /// Bitrate for re-encoding in kbps; defaults to 64 for Opus and 96 for AAC
+    pub bitrate_kbps: Option<i32>,
+    /// Write the feed's chapters into the processed file. Without this, chapters already
+    /// in the download are carried over as they are.
+    pub embed_chapters: bool,
+}
+
+impl ProcessingPipeline {
+    pub fn is_active(&self) -> bool {
+        self.normalize || self.trim_silence || self.transcode.is_some() || self.embed_chapters
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bitrate_kbps.is_some_and(|kbps| !(MIN_BITRATE_KBPS..=MAX_BITRATE_KBPS).contains(&kbps)) {
+            return Err(format!("bitrate_kbps must be between {} and {}", MIN_BITRATE_KBPS, MAX_BITRATE_KBPS));
+        }
+        Ok(())
+    }
+
+    // Whether the audio has to be decoded and encoded again, rather than copied
+    fn reencodes(&self) -> bool {
+        self.normalize || self.trim_silence || self.transcode.is_some()
+    }
+}
+
+// Encoder to re-encode a file in its own format; None for lossless formats that need no bitrate
+fn same_format_encoder(ext: &str) -> (&'static str, bool) {
+    match ext {
+        "m4a" | "m4b" | "aac" | "mp4" => ("aac", true),
+        "ogg" | "oga" => ("libvorbis", true),
+        "opus" => ("libopus", true),
+        "flac" => ("flac", false),
+        "wav" => ("pcm_s16le", false),
+        _ => ("libmp3lame", true),
+    }
+}
+
+fn ffmpeg_args(pipeline: &ProcessingPipeline, input: &Path, output: &Path, chapters: Option<&Path>, output_ext: &str) -> Vec<OsString> {
+    let mut args: Vec<OsString> = ["-hide_banner", "-nostdin", "-y", "-i"].iter().map(OsString::from).collect();
+    args.push(input.into());
+    if let Some(chapters) = chapters {
+        args.extend(["-i".into(), chapters.into(), "-map_chapters".into(), "1".into()]);
+    }
+    args.extend(["-map_metadata", "0", "-map", "0:a:0"].iter().map(OsString::from));
+    // Keep cover art where the container can hold it
+    if matches!(output_ext, "mp3" | "m4a" | "m4b" | "mp4") {
+        args.extend(["-map", "0:v?", "-c:v", "copy"].iter().map(OsString::from));
+    }
+
+    if pipeline.reencodes() {
+        let mut filters = Vec::new();
+        if pipeline.trim_silence {
+            filters.push(SILENCE_FILTER);
+        }
+        if pipeline.normalize {
+            filters.push(LOUDNORM_FILTER);
+        }
+        if !filters.is_empty() {
+            args.extend(["-af".into(), filters.join(",").into()]);
+        }
+        // loudnorm works at 192 kHz; bring it back to something every encoder accepts
+        if pipeline.normalize {
+            args.extend(["-ar", "48000"].iter().map(OsString::from));
+        }
+
+        let (encoder, lossy, default_bitrate) = match pipeline.transcode {
+            Some(AudioCodec::Opus) => ("libopus", true, Some(AudioCodec::Opus.default_bitrate_kbps())),
+            Some(AudioCodec::Aac) => ("aac", true, Some(AudioCodec::Aac.default_bitrate_kbps())),
+            None => {
+                let (encoder, lossy) = same_format_encoder(output_ext);
+                (encoder, lossy, None)
+            }
+        };
+        args.extend(["-c:a".into(), encoder.into()]);
+        if let Some(kbps) = pipeline.bitrate_kbps.or(default_bitrate).filter(|_| lossy) {
+            args.extend(["-b:a".into(), format!("{}k", kbps).into()]);
+        }
+    } else {
+        args.extend(["-c:a", "copy"].iter().map(OsString::from));
+    }
+    if output_ext == "mp3" {
+        args.extend(["-id3v2_version", "3"].iter().map(OsString::from));
+    }
+
+    args.extend(["-progress", "pipe:1", "-nostats"].iter().map(OsString::from));
+    args.push(output.into());
+    args
+}
+
+// "Duration: 01:02:03.45," from ffmpeg's input summary, in seconds
+fn parse_duration(line: &str) -> Option<f64> {
+    let rest = line.trim().strip_prefix("Duration: ")?;
+    let time = rest.split(',').next()?;
+    let mut parts = time.split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// A processed download, written next to the original
+pub struct ProcessedAudio {
+    pub path: PathBuf,
+    pub extension: &'static str,
+}
+
+/// Run `input` through the pipeline with ffmpeg, reporting progress from 0 to 100. The
+/// original is left in place; the caller decides what to keep.
+pub async fn process(
+    ffmpeg_path: &str,
+    pipeline: &ProcessingPipeline,
+    input: &Path,
+    input_ext: &'static str,
+    chapters: &[Chapter],
+    duration_secs: Option<f64>,
+    reporter: &dyn ProgressReporter,
+) -> AppResult<ProcessedAudio> {
+    let extension = pipeline.transcode.map(|codec| codec.extension()).unwrap_or(input_ext);
+    let output = input.with_extension(format!("processing.{}", extension));
+
+    let chapters_file = if pipeline.embed_chapters && !chapters.is_empty() {
+        let path = input.with_extension("chapters.txt");
+        tokio::fs::write(&path, chapters::ffmetadata(chapters, duration_secs)).await
+            .map_err(|e| AppError::internal(format!("Failed to write chapters: {}", e)))?;
+        Some(path)
+    } else {
+        None
+    };
+
+    let result = run_ffmpeg(
+        ffmpeg_path,
+        ffmpeg_args(pipeline, input, &output, chapters_file.as_deref(), extension),
+        duration_secs,
+        reporter,
+    ).await;
+
+    if let Some(path) = &chapters_file {
+        let _ = tokio::fs::remove_file(path).await;
+    }
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&output).await;
+        return Err(e);
+    }
+    Ok(ProcessedAudio { path: output, extension })
+}
+
+async fn run_ffmpeg(ffmpeg_path: &str, args: Vec<OsString>, duration_secs: Option<f64>, reporter: &dyn ProgressReporter) -> AppResult<()> {
+    let mut child = Command::new(ffmpeg_path)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| AppError::external_error(format!("Failed to run {}: {}", ffmpeg_path, e)))?;
+
+    // ffmpeg prints the input duration on stderr before it starts writing progress
+    let (duration_tx, duration_rx) = watch::channel(duration_secs.filter(|d| *d > 0.0));
+    let stderr = child.stderr.take().map(|stderr| tokio::spawn(async move {
+        let mut tail = Vec::new();
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            if duration_tx.borrow().is_none() {
+                if let Some(duration) = parse_duration(&line) {
+                    let _ = duration_tx.send(Some(duration));
+                }
+            }
+            tail.push(line);
+            if tail.len() > STDERR_TAIL_LINES {
+                tail.remove(0);
+            }
+        }
+        tail.join("\n")
+    }));
+
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        let mut last_reported = 0.0;
+        while let Ok(Some(line)) = lines.next_line().await {
+            // out_time_ms is in microseconds too, despite the name
+            let Some(micros) = line.strip_prefix("out_time_us=").or_else(|| line.strip_prefix("out_time_ms="))
+                .and_then(|value| value.trim().parse::<f64>().ok()) else { continue };
+            let Some(duration) = *duration_rx.borrow() else { continue };
+            let percent = (micros / 1_000_000.0 / duration * 100.0).clamp(0.0, 100.0);
+            if percent - last_reported >= 5.0 {
+                last_reported = percent;
+                let _ = reporter.update_progress(percent, None).await;
+            }
+        }
+    }
+
+    let status = child.wait().await
+        .map_err(|e| AppError::external_error(format!("ffmpeg did not finish: {}", e)))?;
+    let stderr = match stderr {
+        Some(handle) => handle.await.unwrap_or_default(),
+        None => String::new(),
+    };
+    if !status.success() {
+        return Err(AppError::external_error(format!("ffmpeg failed ({}): {}", status, stderr.trim())));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<f64>>);
+
+    #[async_trait::async_trait]
+    impl ProgressReporter for Recorder {
+        async fn update_progress(&self, progress: f64, _message: Option<String>) -> AppResult<()> {
+            self.0.lock().unwrap().push(progress);
+            Ok(())
+        }
+    }
+
+    fn arg_list(pipeline: &ProcessingPipeline, chapters: Option<&Path>, output_ext: &str) -> Vec<String> {
+        ffmpeg_args(pipeline, Path::new("in.mp3"), Path::new("out.file"), chapters, output_ext)
+            .into_iter()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    fn value_after<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+        args.iter().position(|arg| arg == flag).map(|i| args[i + 1].as_str())
+    }
+
+    // Run a shell script in place of ffmpeg
+    async fn run_script(script: &str, duration_secs: Option<f64>, reporter: &Recorder) -> AppResult<()> {
+        run_ffmpeg("sh", vec!["-c".into(), script.into()], duration_secs, reporter).await
+    }
+
+    #[test]
+    fn nothing_enabled_copies_the_audio() {
+        let pipeline = ProcessingPipeline::default();
+        assert!(!pipeline.is_active());
+        let args = arg_list(&pipeline, None, "mp3");
+        assert_eq!(value_after(&args, "-c:a"), Some("copy"));
+        assert_eq!(value_after(&args, "-c:v"), Some("copy"));
+        assert_eq!(value_after(&args, "-id3v2_version"), Some("3"));
+        assert!(!args.contains(&"-af".to_string()));
+        assert_eq!(args.last().map(String::as_str), Some("out.file"));
+    }
+
+    #[test]
+    fn filters_run_in_one_pass_and_reencode_in_the_same_format() {
+        let pipeline = ProcessingPipeline { normalize: true, trim_silence: true, ..Default::default() };
+        let args = arg_list(&pipeline, None, "mp3");
+        assert_eq!(value_after(&args, "-af"), Some(format!("{},{}", SILENCE_FILTER, LOUDNORM_FILTER).as_str()));
+        assert_eq!(value_after(&args, "-ar"), Some("48000"));
+        assert_eq!(value_after(&args, "-c:a"), Some("libmp3lame"));
+        assert_eq!(value_after(&args, "-b:a"), None);
+
+        let flac = arg_list(&ProcessingPipeline { trim_silence: true, bitrate_kbps: Some(128), ..Default::default() }, None, "flac");
+        assert_eq!(value_after(&flac, "-c:a"), Some("flac"));
+        assert_eq!(value_after(&flac, "-b:a"), None);
+        assert_eq!(value_after(&flac, "-ar"), None);
+        assert_eq!(value_after(&flac, "-c:v"), None);
+    }
+
+    #[test]
+    fn transcoding_uses_the_codec_bitrate() {
+        let opus = arg_list(&ProcessingPipeline { transcode: Some(AudioCodec::Opus), ..Default::default() }, None, "opus");
+        assert_eq!(value_after(&opus, "-c:a"), Some("libopus"));
+        assert_eq!(value_after(&opus, "-b:a"), Some("64k"));
+        assert_eq!(value_after(&opus, "-c:v"), None);
+
+        let aac = arg_list(&ProcessingPipeline { transcode: Some(AudioCodec::Aac), bitrate_kbps: Some(128), ..Default::default() }, None, "m4a");
+        assert_eq!(value_after(&aac, "-c:a"), Some("aac"));
+        assert_eq!(value_after(&aac, "-b:a"), Some("128k"));
+        assert_eq!(value_after(&aac, "-c:v"), Some("copy"));
+    }
+
+    #[test]
+    fn chapters_come_from_a_second_input() {
+        let pipeline = ProcessingPipeline { embed_chapters: true, ..Default::default() };
+        assert!(pipeline.is_active());
+        let args = arg_list(&pipeline, Some(Path::new("in.chapters.txt")), "m4a");
+        assert_eq!(args.iter().filter(|arg| *arg == "-i").count(), 2);
+        assert_eq!(value_after(&args, "-map_chapters"), Some("1"));
+        assert_eq!(value_after(&args, "-c:a"), Some("copy"));
+    }
+
+    #[test]
+    fn codecs_and_bitrates_are_checked() {
+        for codec in [AudioCodec::Opus, AudioCodec::Aac] {
+            assert_eq!(AudioCodec::parse(codec.as_str()), Some(codec));
+        }
+        assert_eq!(AudioCodec::parse("mp3"), None);
+        assert!(ProcessingPipeline { bitrate_kbps: Some(MIN_BITRATE_KBPS - 1), ..Default::default() }.validate().is_err());
+        assert!(ProcessingPipeline { bitrate_kbps: Some(MAX_BITRATE_KBPS + 1), ..Default::default() }.validate().is_err());
+        assert!(ProcessingPipeline { bitrate_kbps: Some(96), ..Default::default() }.validate().is_ok());
+    }
+
+    #[test]
+    fn durations_are_read_from_the_input_summary() {
+        assert_eq!(parse_duration("  Duration: 01:02:03.50, start: 0.025057, bitrate: 128 kb/s"), Some(3723.5));
+        assert_eq!(parse_duration("Duration: N/A, bitrate: N/A"), None);
+        assert_eq!(parse_duration("Stream #0:0: Audio: mp3"), None);
+    }
+
+    #[tokio::test]
+    async fn progress_follows_the_duration_ffmpeg_reports() {
+        let reporter = Recorder::default();
+        let script = "echo '  Duration: 00:00:10.00, start: 0.000000, bitrate: 128 kb/s' >&2; sleep 0.2; \
+                      for t in 1000000 2500000 2600000 5000000 10000000; do echo out_time_us=$t; done";
+        run_script(script, None, &reporter).await.unwrap();
+        assert_eq!(*reporter.0.lock().unwrap(), vec![10.0, 25.0, 50.0, 100.0]);
+    }
+
+    #[tokio::test]
+    async fn known_durations_are_used_without_waiting_for_stderr() {
+        let reporter = Recorder::default();
+        run_script("echo out_time_ms=20000000", Some(40.0), &reporter).await.unwrap();
+        assert_eq!(*reporter.0.lock().unwrap(), vec![50.0]);
+    }
+
+    #[tokio::test]
+    async fn failures_keep_the_end_of_stderr() {
+        let reporter = Recorder::default();
+        let script = "for i in 1 2 3 4 5 6 7; do echo line $i >&2; done; exit 1";
+        let error = run_script(script, None, &reporter).await.unwrap_err().to_string();
+        assert!(error.contains("line 3") && error.contains("line 7"), "{}", error);
+        assert!(!error.contains("line 2"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn failed_runs_leave_no_files_behind() {
+        use rand::Rng;
+
+        let dir = std::env::temp_dir().join(format!("pinepods-processing-test-{}", rand::rng().random::<u64>()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let input = dir.join("episode.mp3");
+        tokio::fs::write(&input, b"audio").await.unwrap();
+
+        let pipeline = ProcessingPipeline { embed_chapters: true, transcode: Some(AudioCodec::Aac), ..Default::default() };
+        let chapters = [Chapter { start_secs: 0.0, end_secs: None, title: "Intro".to_string() }];
+        let result = process("/nonexistent/ffmpeg", &pipeline, &input, "mp3", &chapters, Some(60.0), &Recorder::default()).await;
+        assert!(result.is_err());
+
+        let mut remaining = Vec::new();
+        let mut entries = tokio::fs::read_dir(&dir).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            remaining.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        assert_eq!(remaining, vec!["episode.mp3"]);
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,382 @@
+use std::ffi::OsString;
+use std::path::Path;
+use std::process::Stdio;
+use tokio::process::Command;
+use crate::error::{AppError, AppResult};
+
+// Containers whose chapters ffmpeg writes as MP4 chapter atoms
+const MP4_EXTENSIONS: &[&str] = &["m4a", "m4b", "mp4"];
+
+/// A chapter, as in the Podcasting 2.0 chapters JSON
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chapter {
+    pub start_secs: f64,
+    pub end_secs: Option<f64>,
+    pub title: String,
+}
+
+/// Chapters from the `chapters` array of a Podcasting 2.0 chapters document, in order.
+/// Chapters marked `"toc": false` aren't meant to be listed, so they are left out.
+pub fn from_json(chapters: &serde_json::Value) -> Vec<Chapter> {
+    let mut result: Vec<Chapter> = chapters.as_array().into_iter().flatten()
+        .filter(|c| c.get("toc").and_then(|toc| toc.as_bool()) != Some(false))
+        .filter_map(|c| {
+            let start_secs = c.get("startTime")?.as_f64()?;
+            Some(Chapter {
+                start_secs: start_secs.max(0.0),
+                end_secs: c.get("endTime").and_then(|end| end.as_f64()).filter(|end| *end > start_secs),
+                title: c.get("title").and_then(|t| t.as_str()).unwrap_or_default().trim().to_string(),
+            })
+        })
+        .collect();
+    result.sort_by(|a, b| a.start_secs.total_cmp(&b.start_secs));
+    result
+}
+
+/// Chapters as a Podcasting 2.0 `chapters` array, the shape `fetch_podcasting_2_data` serves
+pub fn to_json(chapters: &[Chapter]) -> serde_json::Value {
+    serde_json::Value::Array(chapters.iter().map(|chapter| {
+        let mut value = serde_json::json!({
+            "startTime": chapter.start_secs,
+            "title": chapter.title,
+        });
+        if let Some(end) = chapter.end_secs {
+            value["endTime"] = serde_json::json!(end);
+        }
+        value
+    }).collect())
+}
+
+// A chapter ends where the next one starts, the last one at its own end time or the end of the episode
+fn end_secs(chapters: &[Chapter], index: usize, duration_secs: Option<f64>) -> f64 {
+    let chapter = &chapters[index];
+    chapters.get(index + 1).map(|next| next.start_secs)
+        .or(chapter.end_secs)
+        .or(duration_secs)
+        .unwrap_or(chapter.start_secs)
+        .max(chapter.start_secs)
+}
+
+fn display_title(chapter: &Chapter, index: usize) -> String {
+    if chapter.title.is_empty() { format!("Chapter {}", index + 1) } else { chapter.title.clone() }
+}
+
+/// Replace a tag's chapters with ID3v2 CHAP frames and a top-level CTOC listing them in order
+pub fn set_id3_chapters(tag: &mut id3::Tag, chapters: &[Chapter], duration_secs: Option<f64>) {
+    use id3::TagLike;
+
+    tag.remove_all_chapters();
+    tag.remove_all_tables_of_contents();
+    if chapters.is_empty() {
+        return;
+    }
+
+    let mut elements = Vec::with_capacity(chapters.len());
+    for (index, chapter) in chapters.iter().enumerate() {
+        let element_id = format!("chp{}", index);
+        tag.add_frame(id3::frame::Chapter {
+            element_id: element_id.clone(),
+            start_time: (chapter.start_secs * 1000.0) as u32,
+            end_time: (end_secs(chapters, index, duration_secs) * 1000.0) as u32,
+            // Byte offsets unused; times only
+            start_offset: u32::MAX,
+            end_offset: u32::MAX,
+            frames: vec![id3::Frame::text("TIT2", display_title(chapter, index))],
+        });
+        elements.push(element_id);
+    }
+    tag.add_frame(id3::frame::TableOfContents {
+        element_id: "toc".to_string(),
+        top_level: true,
+        ordered: true,
+        elements,
+        frames: Vec::new(),
+    });
+}
+
+/// Chapters from the CHAP frames of an ID3v2 tag
+pub fn from_id3(tag: &id3::Tag) -> Vec<Chapter> {
+    let mut chapters: Vec<Chapter> = tag.chapters().map(|chapter| Chapter {
+        start_secs: chapter.start_time as f64 / 1000.0,
+        end_secs: Some(chapter.end_time as f64 / 1000.0).filter(|end| *end > chapter.start_time as f64 / 1000.0),
+        title: chapter.frames.iter()
+            .find(|frame| frame.id() == "TIT2")
+            .and_then(|frame| frame.content().text())
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }).collect();
+    chapters.sort_by(|a, b| a.start_secs.total_cmp(&b.start_secs));
+    chapters
+}
+
+/// ffmpeg metadata file carrying the chapters, for `-map_chapters`
+pub fn ffmetadata(chapters: &[Chapter], duration_secs: Option<f64>) -> String {
+    let escape = |value: &str| {
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    };
+
+    let mut metadata = String::from(";FFMETADATA1\n");
+    for (index, chapter) in chapters.iter().enumerate() {
+        metadata.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (chapter.start_secs * 1000.0) as i64,
+            (end_secs(chapters, index, duration_secs) * 1000.0) as i64,
+            escape(&display_title(chapter, index)),
+        ));
+    }
+    metadata
+}
+
+// Chapters from an ffmpeg metadata dump; global and stream metadata are skipped
+fn parse_ffmetadata(metadata: &str) -> Vec<Chapter> {
+    struct Section {
+        timebase: f64,
+        start: Option<i64>,
+        end: Option<i64>,
+        title: String,
+    }
+    let finish = |section: Section| {
+        section.start.map(|start| Chapter {
+            start_secs: start as f64 * section.timebase,
+            end_secs: section.end.filter(|end| *end > start).map(|end| end as f64 * section.timebase),
+            title: section.title.trim().to_string(),
+        })
+    };
+
+    let mut chapters = Vec::new();
+    let mut current: Option<Section> = None;
+    let mut lines = metadata.lines();
+    while let Some(line) = lines.next() {
+        // A trailing backslash continues the value on the next line
+        let mut line = line.to_string();
+        while line.ends_with('\\') && !line.ends_with("\\\\") {
+            line.pop();
+            line.push('\n');
+            match lines.next() {
+                Some(next) => line.push_str(next),
+                None => break,
+            }
+        }
+
+        if line.starts_with('[') {
+            chapters.extend(current.take().and_then(finish));
+            if line.trim() == "[CHAPTER]" {
+                current = Some(Section { timebase: 1.0 / 1000.0, start: None, end: None, title: String::new() });
+            }
+            continue;
+        }
+        let Some(section) = current.as_mut() else { continue };
+        let Some((key, value)) = line.split_once('=') else { continue };
+        match key.to_ascii_lowercase().as_str() {
+            "timebase" => {
+                if let Some((num, den)) = value.split_once('/') {
+                    if let (Ok(num), Ok(den)) = (num.trim().parse::<f64>(), den.trim().parse::<f64>()) {
+                        if den > 0.0 {
+                            section.timebase = num / den;
+                        }
+                    }
+                }
+            }
+            "start" => section.start = value.trim().parse().ok(),
+            "end" => section.end = value.trim().parse().ok(),
+            "title" => {
+                let mut title = String::with_capacity(value.len());
+                let mut chars = value.chars();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '\\' => title.extend(chars.next()),
+                        c => title.push(c),
+                    }
+                }
+                section.title = title;
+            }
+            _ => {}
+        }
+    }
+    chapters.extend(current.take().and_then(finish));
+    chapters.sort_by(|a, b| a.start_secs.total_cmp(&b.start_secs));
+    chapters
+}
+
+/// Whether chapters in a file of this type are written with ffmpeg rather than in its ID3 tag
+pub fn is_mp4(ext: &str) -> bool {
+    MP4_EXTENSIONS.contains(&ext)
+}
+
+/// Write chapters into an M4A/MP4 file as MP4 chapter atoms, remuxing it with ffmpeg
+pub async fn embed_mp4_chapters(ffmpeg_path: &str, path: &Path, chapters: &[Chapter], duration_secs: Option<f64>) -> AppResult<()> {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or("m4a");
+    let metadata_path = path.with_extension("chapters.txt");
+    let output = path.with_extension(format!("chapters.{}", ext));
+    tokio::fs::write(&metadata_path, ffmetadata(chapters, duration_secs)).await
+        .map_err(|e| AppError::internal(format!("Failed to write chapters: {}", e)))?;
+
+    let args: Vec<OsString> = vec![
+        "-hide_banner".into(), "-nostdin".into(), "-y".into(),
+        "-i".into(), path.into(),
+        "-i".into(), metadata_path.clone().into(),
+        "-map".into(), "0".into(),
+        "-map_metadata".into(), "0".into(),
+        "-map_chapters".into(), "1".into(),
+        "-c".into(), "copy".into(),
+        output.clone().into(),
+    ];
+    let result = run_ffmpeg(ffmpeg_path, args).await;
+    let _ = tokio::fs::remove_file(&metadata_path).await;
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&output).await;
+        return Err(e);
+    }
+    tokio::fs::rename(&output, path).await
+        .map_err(|e| AppError::internal(format!("Failed to replace file with chaptered copy: {}", e)))
+}
+
+/// Chapters already embedded in a downloaded file: ID3 CHAP frames for MP3, anything
+/// ffmpeg can read (MP4 chapter atoms, Vorbis comments) for other formats
+pub async fn read_embedded(ffmpeg_path: &str, path: &Path) -> AppResult<Vec<Chapter>> {
+    let ext = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default().to_ascii_lowercase();
+    if ext == "mp3" {
+        let path = path.to_path_buf();
+        return match tokio::task::spawn_blocking(move || id3::Tag::read_from_path(&path)).await {
+            Ok(Ok(tag)) => Ok(from_id3(&tag)),
+            Ok(Err(e)) if matches!(e.kind, id3::ErrorKind::NoTag) => Ok(Vec::new()),
+            Ok(Err(e)) => Err(AppError::internal(format!("Failed to read ID3 tag: {}", e))),
+            Err(e) => Err(AppError::internal(format!("Failed to read ID3 tag: {}", e))),
+        };
+    }
+
+    let args: Vec<OsString> = vec![
+        "-hide_banner".into(), "-nostdin".into(),
+        "-i".into(), path.into(),
+        "-map_metadata".into(), "0".into(),
+        "-map_chapters".into(), "0".into(),
+        "-c".into(), "copy".into(),
+        "-f".into(), "ffmetadata".into(),
+        "-".into(),
+    ];
+    let metadata = run_ffmpeg(ffmpeg_path, args).await?;
+    Ok(parse_ffmetadata(&metadata))
+}
+
+// Run ffmpeg to completion, returning what it wrote to stdout
+async fn run_ffmpeg(ffmpeg_path: &str, args: Vec<OsString>) -> AppResult<String> {
+    let output = Command::new(ffmpeg_path)
+        .args(&args)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await
+        .map_err(|e| AppError::external_error(format!("Failed to run {}: {}", ffmpeg_path, e)))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let tail: Vec<&str> = stderr.lines().rev().take(5).collect();
+        let tail: Vec<&str> = tail.into_iter().rev().collect();
+        return Err(AppError::external_error(format!("ffmpeg failed ({}): {}", output.status, tail.join("\n"))));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(start_secs: f64, end_secs: Option<f64>, title: &str) -> Chapter {
+        Chapter { start_secs, end_secs, title: title.to_string() }
+    }
+
+    #[test]
+    fn json_chapters_are_sorted_and_hidden_ones_dropped() {
+        let document = serde_json::json!([
+            {"startTime": 120.5, "title": " Interview "},
+            {"startTime": 60, "endTime": 30, "title": "Backwards end"},
+            {"startTime": 90, "title": "Ad", "toc": false},
+            {"title": "No start"},
+            {"startTime": -5, "endTime": 10}
+        ]);
+        assert_eq!(from_json(&document), vec![
+            chapter(0.0, Some(10.0), ""),
+            chapter(60.0, None, "Backwards end"),
+            chapter(120.5, None, "Interview"),
+        ]);
+        assert!(from_json(&serde_json::json!({"chapters": []})).is_empty());
+    }
+
+    #[test]
+    fn json_round_trips() {
+        let chapters = vec![chapter(0.0, None, "Intro"), chapter(30.0, Some(95.5), "Main")];
+        assert_eq!(from_json(&to_json(&chapters)), chapters);
+        assert!(to_json(&chapters)[0].get("endTime").is_none());
+    }
+
+    #[test]
+    fn chapters_end_where_the_next_one_starts() {
+        let chapters = vec![chapter(0.0, Some(5.0), ""), chapter(30.0, None, "Last")];
+        assert_eq!(end_secs(&chapters, 0, Some(100.0)), 30.0);
+        assert_eq!(end_secs(&chapters, 1, Some(100.0)), 100.0);
+        assert_eq!(end_secs(&chapters, 1, None), 30.0);
+        assert_eq!(end_secs(&[chapter(30.0, Some(45.0), "")], 0, Some(100.0)), 45.0);
+        assert_eq!(display_title(&chapters[0], 0), "Chapter 1");
+    }
+
+    #[test]
+    fn ffmetadata_escapes_titles() {
+        let chapters = vec![chapter(0.0, None, "Q&A = fun; #1"), chapter(61.5, None, "")];
+        assert_eq!(
+            ffmetadata(&chapters, Some(120.0)),
+            ";FFMETADATA1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=61500\ntitle=Q&A \\= fun\\; \\#1\n\
+             [CHAPTER]\nTIMEBASE=1/1000\nSTART=61500\nEND=120000\ntitle=Chapter 2\n"
+        );
+    }
+
+    #[test]
+    fn ffmetadata_round_trips() {
+        let chapters = vec![
+            chapter(0.0, Some(12.0), "Intro = start"),
+            chapter(12.0, Some(40.0), "Back\\slash; #2"),
+            chapter(40.0, Some(90.0), "Two\nlines"),
+        ];
+        assert_eq!(parse_ffmetadata(&ffmetadata(&chapters, Some(90.0))), chapters);
+    }
+
+    #[test]
+    fn dumped_chapters_use_their_own_timebase() {
+        let dump = ";FFMETADATA1\ntitle=Episode\n[STREAM]\ntitle=Not a chapter\n\
+                    [CHAPTER]\nTIMEBASE=1/44100\nSTART=441000\nEND=882000\ntitle=Second\n\
+                    [CHAPTER]\nTIMEBASE=1/0\nSTART=0\nEND=0\ntitle=First\n\
+                    [CHAPTER]\nTIMEBASE=1/1000\ntitle=No start\n";
+        assert_eq!(parse_ffmetadata(dump), vec![chapter(0.0, None, "First"), chapter(10.0, Some(20.0), "Second")]);
+    }
+
+    #[test]
+    fn id3_chapters_round_trip_with_a_table_of_contents() {
+        let chapters = vec![chapter(0.0, None, "Intro"), chapter(30.5, None, "")];
+        let mut tag = id3::Tag::new();
+        set_id3_chapters(&mut tag, &chapters, Some(90.0));
+        assert_eq!(from_id3(&tag), vec![chapter(0.0, Some(30.5), "Intro"), chapter(30.5, Some(90.0), "Chapter 2")]);
+        let toc: Vec<_> = tag.tables_of_contents().collect();
+        assert_eq!(toc.len(), 1);
+        assert_eq!(toc[0].elements, vec!["chp0", "chp1"]);
+
+        // Replacing chapters leaves no stale frames behind
+        set_id3_chapters(&mut tag, &chapters[..1], None);
+        assert_eq!(tag.chapters().count(), 1);
+        set_id3_chapters(&mut tag, &[], None);
+        assert_eq!(tag.chapters().count(), 0);
+        assert_eq!(tag.tables_of_contents().count(), 0);
+    }
+
+    #[test]
+    fn only_mp4_containers_use_chapter_atoms() {
+        assert!(is_mp4("m4a") && is_mp4("m4b") && is_mp4("mp4"));
+        assert!(!is_mp4("mp3") && !is_mp4("opus"));
+    }
+}
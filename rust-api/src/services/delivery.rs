@@ -0,0 +1,109 @@
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use std::time::Duration;
+use crate::{database::DatabasePool, error::AppResult};
+
+// Notifications and webhooks are logged before they're sent and retried from the log:
+// a failed attempt waits RETRY_BASE_SECS, and each further failure RETRY_FACTOR times
+// longer (1, 4, 16, 64, 256 minutes), until the caller's attempt limit is reached.
+const RETRY_BASE_SECS: i64 = 60;
+const RETRY_FACTOR: i64 = 4;
+// Deliveries retried per scheduler run
+const RETRY_BATCH: i64 = 100;
+/// How long a single send may take
+pub const SEND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A logged delivery that `retry_due` can pick up again
+#[async_trait]
+pub trait Delivery: Sized + Send {
+    /// Up to `limit` deliveries due another attempt at `now`
+    async fn load_due(db: &DatabasePool, now: NaiveDateTime, limit: i64) -> AppResult<Vec<Self>>;
+
+    /// Client the deliveries are sent with
+    fn client() -> AppResult<reqwest::Client>;
+
+    /// Make one attempt and record how it went, scheduling the next with `next_attempt_at`
+    async fn attempt(self, db: &DatabasePool, client: &reqwest::Client);
+}
+
+/// Retry deliveries whose backoff has run out; called from the scheduler
+pub async fn retry_due<D: Delivery>(db: &DatabasePool) -> AppResult<usize> {
+    let due = D::load_due(db, Utc::now().naive_utc(), RETRY_BATCH).await?;
+    if due.is_empty() {
+        return Ok(0);
+    }
+    let client = D::client()?;
+    let count = due.len();
+    for delivery in due {
+        delivery.attempt(db, &client).await;
+    }
+    Ok(count)
+}
+
+/// When a delivery is due while an attempt is under way. Until the attempt finishes the
+/// delivery counts as due after the first retry delay, so a send lost to a restart is
+/// picked up by the retry job.
+pub fn lease_until() -> NaiveDateTime {
+    Utc::now().naive_utc() + chrono::Duration::seconds(RETRY_BASE_SECS)
+}
+
+/// When to try again after the `attempts`th failed attempt, or None once `max_attempts`
+/// have been made
+pub fn next_attempt_at(attempts: i32, max_attempts: i32, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    (attempts >= 1 && attempts < max_attempts).then(|| {
+        now + chrono::Duration::seconds(RETRY_BASE_SECS * RETRY_FACTOR.pow(attempts as u32 - 1))
+    })
+}
+
+/// Event kinds stored as a comma-separated list; names `parse` doesn't know are dropped
+pub fn parse_list<K>(value: &str, parse: impl Fn(&str) -> Option<K>) -> Vec<K> {
+    value.split(',').filter_map(|kind| parse(kind.trim())).collect()
+}
+
+pub fn format_list<K>(kinds: &[K], as_str: impl Fn(&K) -> &'static str) -> String {
+    kinds.iter().map(as_str).collect::<Vec<_>>().join(",")
+}
+
+/// Destinations and webhooks are enabled unless a request says otherwise
+pub fn default_enabled() -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_back_off_by_a_factor_of_four() {
+        let now = Utc::now().naive_utc();
+        let delays: Vec<i64> = (1..6)
+            .map(|attempts| (next_attempt_at(attempts, 6, now).unwrap() - now).num_minutes())
+            .collect();
+        assert_eq!(delays, vec![1, 4, 16, 64, 256]);
+    }
+
+    #[test]
+    fn retries_stop_at_the_attempt_limit() {
+        let now = Utc::now().naive_utc();
+        assert!(next_attempt_at(4, 5, now).is_some());
+        assert_eq!(next_attempt_at(5, 5, now), None);
+        assert_eq!(next_attempt_at(9, 5, now), None);
+        assert_eq!(next_attempt_at(0, 5, now), None);
+    }
+
+    #[test]
+    fn lease_expires_after_the_first_retry_delay() {
+        let lease = lease_until() - Utc::now().naive_utc();
+        assert!(lease > chrono::Duration::seconds(RETRY_BASE_SECS - 5));
+        assert!(lease <= chrono::Duration::seconds(RETRY_BASE_SECS));
+    }
+
+    #[test]
+    fn lists_round_trip() {
+        let parse = |kind: &str| ["a", "b"].into_iter().find(|k| *k == kind);
+        let kinds = parse_list(" a,b , c,,a", parse);
+        assert_eq!(kinds, vec!["a", "b", "a"]);
+        assert_eq!(format_list(&kinds, |k| *k), "a,b,a");
+        assert!(parse_list("", parse).is_empty());
+    }
+}
@@ -0,0 +1,735 @@
+use chrono::{NaiveDateTime, Utc};
+use reqwest::{header, StatusCode};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, Notify, Semaphore};
+use crate::{
+    config::DownloadQueueConfig,
+    database::DatabasePool,
+    error::{AppError, AppResult},
+    services::{
+        audio_processing::{self, ProcessedAudio},
+        chapters::{self, Chapter},
+        download_template::{extension_from_url, DownloadTemplate, EpisodeNaming},
+        notifications::{self, NotificationEvent},
+        storage::{self, Storage},
+        task_manager::TaskManager,
+        tasks::ProgressReporter,
+        webhooks::{self, WebhookEventKind, WebhookSubject},
+    },
+};
+
+// The dispatcher also wakes on this interval to pick up retries whose backoff has passed
+const DISPATCH_INTERVAL: Duration = Duration::from_secs(30);
+// Ready downloads fetched per dispatch; more than the worker pool so busy hosts can be skipped
+const DISPATCH_BATCH: i64 = 50;
+const RETRY_BASE_SECS: i64 = 30;
+const RETRY_MAX_SECS: i64 = 60 * 60;
+// Progress goes to the websocket and the queue table at most this often per download
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(2);
+
+// What a running download has been asked to do
+const SIGNAL_RUN: u8 = 0;
+const SIGNAL_PAUSE: u8 = 1;
+const SIGNAL_CANCEL: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueueStatus {
+    Queued,
+    Downloading,
+    Paused,
+    Failed,
+}
+
+impl QueueStatus {
+    pub fn parse(status: &str) -> Self {
+        match status {
+            "downloading" => QueueStatus::Downloading,
+            "paused" => QueueStatus::Paused,
+            "failed" => QueueStatus::Failed,
+            _ => QueueStatus::Queued,
+        }
+    }
+}
+
+/// An episode in the download queue
+#[derive(Debug, Clone, Serialize)]
+pub struct QueuedDownload {
+    pub queue_id: i32,
+    pub user_id: i32,
+    pub episode_id: i32,
+    pub episode_title: String,
+    pub episode_url: String,
+    pub status: QueueStatus,
+    pub attempts: i32,
+    /// When a failed attempt is retried
+    pub next_attempt_at: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    /// Where the download is being written, fixed on the first attempt so it can resume
+    #[serde(skip)]
+    pub storage_key: Option<String>,
+    pub bytes_downloaded: i64,
+    pub total_bytes: Option<i64>,
+    pub task_id: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+/// What a queued download needs to fetch, name and tag an episode
+#[derive(Debug, Clone)]
+pub struct EpisodeDownloadInfo {
+    pub url: String,
+    pub title: String,
+    pub duration_secs: Option<i32>,
+    pub podcast_id: i32,
+    pub podcast_name: String,
+    pub pub_date: Option<NaiveDateTime>,
+    pub author: Option<String>,
+    pub artwork_url: Option<String>,
+}
+
+enum Outcome {
+    Downloaded { podcast_name: String, title: String, location: String, size: u64 },
+    Paused,
+    Cancelled,
+}
+
+struct Failure {
+    message: String,
+    retryable: bool,
+}
+
+impl Failure {
+    fn retry(message: String) -> Self {
+        Failure { message, retryable: true }
+    }
+
+    fn fatal(message: String) -> Self {
+        Failure { message, retryable: false }
+    }
+}
+
+impl From<AppError> for Failure {
+    // Quota, permission and missing-episode errors won't change on retry; anything else might
+    fn from(e: AppError) -> Self {
+        let retryable = !matches!(
+            e,
+            AppError::Authorization(_) | AppError::NotFound(_) | AppError::BadRequest(_)
+        );
+        Failure { message: e.to_string(), retryable }
+    }
+}
+
+// Seconds to wait after the `attempts`th failed attempt: doubling from RETRY_BASE_SECS up to RETRY_MAX_SECS
+fn retry_delay_secs(attempts: i32) -> i64 {
+    (RETRY_BASE_SECS << (attempts.max(1) - 1).min(16)).min(RETRY_MAX_SECS)
+}
+
+// Server trouble and rate limiting may clear up; other error statuses won't
+fn is_retryable_status(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::REQUEST_TIMEOUT || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+// Token bucket shared by every running download; one second of burst
+struct BandwidthLimiter {
+    bytes_per_sec: f64,
+    state: Mutex<(f64, Instant)>,
+}
+
+impl BandwidthLimiter {
+    fn new(kbps: u64) -> Self {
+        let bytes_per_sec = kbps as f64 * 1024.0;
+        BandwidthLimiter { bytes_per_sec, state: Mutex::new((bytes_per_sec, Instant::now())) }
+    }
+
+    async fn throttle(&self, bytes: usize) {
+        let wait = {
+            let mut state = self.state.lock().await;
+            let (available, last) = &mut *state;
+            let now = Instant::now();
+            *available = (*available + now.duration_since(*last).as_secs_f64() * self.bytes_per_sec).min(self.bytes_per_sec);
+            *last = now;
+            *available -= bytes as f64;
+            if *available < 0.0 { Duration::from_secs_f64(-*available / self.bytes_per_sec) } else { Duration::ZERO }
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// Persistent episode download queue. Downloads are rows in the DownloadQueue table; a
+/// dispatcher hands ready rows to a bounded pool of workers, limiting how many run
+/// against one host and sharing an optional bandwidth cap. Interrupted downloads resume
+/// with HTTP Range requests and failed attempts are retried with exponential backoff.
+pub struct DownloadQueue {
+    task_manager: Arc<TaskManager>,
+    db_pool: DatabasePool,
+    storage: Arc<Storage>,
+    config: DownloadQueueConfig,
+    client: reqwest::Client,
+    workers: Arc<Semaphore>,
+    hosts: StdMutex<HashMap<String, Arc<Semaphore>>>,
+    running: StdMutex<HashMap<i32, Arc<AtomicU8>>>,
+    limiter: Option<BandwidthLimiter>,
+    wake: Notify,
+}
+
+impl DownloadQueue {
+    pub fn new(task_manager: Arc<TaskManager>, db_pool: DatabasePool, storage: Arc<Storage>, config: DownloadQueueConfig) -> Self {
+        DownloadQueue {
+            task_manager,
+            db_pool,
+            storage,
+            workers: Arc::new(Semaphore::new(config.workers)),
+            limiter: config.bandwidth_limit_kbps.map(BandwidthLimiter::new),
+            config,
+            client: reqwest::Client::new(),
+            hosts: StdMutex::new(HashMap::new()),
+            running: StdMutex::new(HashMap::new()),
+            wake: Notify::new(),
+        }
+    }
+
+    /// Start the dispatcher. Downloads left running by a previous process are queued again first.
+    pub fn start(self: &Arc<Self>) {
+        let queue = self.clone();
+        tokio::spawn(async move {
+            match queue.db_pool.reset_interrupted_downloads().await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Resuming {} interrupted downloads", count),
+                Err(e) => tracing::error!("Failed to reset interrupted downloads: {}", e),
+            }
+            tracing::info!(
+                "Download queue started with {} workers, {} per host, bandwidth limit {}",
+                queue.config.workers,
+                queue.config.per_host_limit,
+                queue.config.bandwidth_limit_kbps.map(|kbps| format!("{} KiB/s", kbps)).unwrap_or_else(|| "none".to_string())
+            );
+            loop {
+                if let Err(e) = queue.dispatch().await {
+                    tracing::warn!("Download queue dispatch failed: {}", e);
+                }
+                tokio::select! {
+                    _ = queue.wake.notified() => {}
+                    _ = tokio::time::sleep(DISPATCH_INTERVAL) => {}
+                }
+            }
+        });
+    }
+
+    /// Queue an episode for download, returning the task that reports its progress. An
+    /// episode already waiting or downloading keeps its existing task.
+    pub async fn enqueue(&self, user_id: i32, episode_id: i32) -> AppResult<String> {
+        if let Some(existing) = self.db_pool.get_download_queue_entry(user_id, episode_id).await? {
+            if let (QueueStatus::Queued | QueueStatus::Downloading, Some(task_id)) = (existing.status, existing.task_id) {
+                return Ok(task_id);
+            }
+        }
+
+        // Episode ID as the item ID for frontend compatibility
+        let task_id = self.task_manager.create_task_with_item_id(
+            "download_episode".to_string(),
+            user_id,
+            Some(episode_id),
+        ).await?;
+        self.db_pool.queue_episode_download(user_id, episode_id, &task_id).await?;
+        self.wake.notify_one();
+        Ok(task_id)
+    }
+
+    /// Pause a queued or running download. A running download keeps what it has so far.
+    pub async fn pause(&self, item: &QueuedDownload) -> AppResult<bool> {
+        if !self.db_pool.pause_queued_download(item.queue_id, item.user_id).await? {
+            return Ok(false);
+        }
+        self.signal(item.queue_id, SIGNAL_PAUSE);
+        if let Some(task_id) = &item.task_id {
+            self.report(self.task_manager.set_task_paused(task_id, true, Some(item.episode_id), format!("Paused {}", item.episode_title)).await);
+        }
+        Ok(true)
+    }
+
+    /// Queue a paused or failed download again
+    pub async fn resume(&self, item: &QueuedDownload) -> AppResult<bool> {
+        if !self.db_pool.resume_queued_download(item.queue_id, item.user_id).await? {
+            return Ok(false);
+        }
+        if let Some(task_id) = &item.task_id {
+            self.report(self.task_manager.set_task_paused(task_id, false, Some(item.episode_id), format!("Queued {}", item.episode_title)).await);
+        }
+        self.wake.notify_one();
+        Ok(true)
+    }
+
+    /// Remove a download from the queue and throw away anything downloaded so far
+    pub async fn cancel(&self, item: &QueuedDownload) -> AppResult<bool> {
+        if !self.db_pool.remove_download_queue_item(item.queue_id, item.user_id).await? {
+            return Ok(false);
+        }
+        // A running download cleans up after itself once it sees the signal
+        if !self.signal(item.queue_id, SIGNAL_CANCEL) {
+            self.discard_partial(item.storage_key.as_deref()).await;
+            if let Some(task_id) = &item.task_id {
+                self.report(self.task_manager.fail_task(task_id, "Download cancelled".to_string()).await);
+            }
+        }
+        Ok(true)
+    }
+
+    // Tell a running download to stop; false if it isn't running
+    fn signal(&self, queue_id: i32, signal: u8) -> bool {
+        match self.running.lock().unwrap().get(&queue_id) {
+            Some(flag) => {
+                flag.store(signal, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn host_limit(&self, url: &str) -> Arc<Semaphore> {
+        let host = url::Url::parse(url).ok().and_then(|u| u.host_str().map(str::to_string)).unwrap_or_default();
+        self.hosts.lock().unwrap()
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(self.config.per_host_limit)))
+            .clone()
+    }
+
+    // Start as many ready downloads as the worker pool and host limits allow
+    async fn dispatch(self: &Arc<Self>) -> AppResult<()> {
+        if self.workers.available_permits() == 0 {
+            return Ok(());
+        }
+        for item in self.db_pool.get_ready_downloads(Utc::now().naive_utc(), DISPATCH_BATCH).await? {
+            let Ok(worker) = self.workers.clone().try_acquire_owned() else { break };
+            // Host busy: leave it queued and look for a download from somewhere else
+            let Ok(host) = self.host_limit(&item.episode_url).try_acquire_owned() else { continue };
+            if !self.db_pool.claim_download(item.queue_id).await? {
+                continue;
+            }
+
+            let flag = Arc::new(AtomicU8::new(SIGNAL_RUN));
+            self.running.lock().unwrap().insert(item.queue_id, flag.clone());
+            let queue = self.clone();
+            tokio::spawn(async move {
+                queue.run(item.clone(), &flag).await;
+                queue.running.lock().unwrap().remove(&item.queue_id);
+                drop((worker, host));
+                queue.wake.notify_one();
+            });
+        }
+        Ok(())
+    }
+
+    async fn run(&self, item: QueuedDownload, flag: &AtomicU8) {
+        let task_id = item.task_id.clone().unwrap_or_default();
+        tracing::info!("Downloading episode {} for user {} (attempt {})", item.episode_id, item.user_id, item.attempts + 1);
+
+        match self.download(&item, &task_id, flag).await {
+            Ok(Outcome::Downloaded { podcast_name, title, location, size }) => {
+                if let Err(e) = self.db_pool.remove_download_queue_item(item.queue_id, item.user_id).await {
+                    tracing::error!("Failed to remove finished download {} from the queue: {}", item.queue_id, e);
+                }
+                tracing::info!("Successfully downloaded episode {} - {}", item.episode_id, title);
+                self.report(self.task_manager.complete_task(&task_id, Some(serde_json::json!({
+                    "episode_id": item.episode_id,
+                    "user_id": item.user_id,
+                    "status": "downloaded",
+                    "file_path": location,
+                    "file_size": size
+                })), Some(format!("Downloaded {}", title))).await);
+                notifications::notify_quietly(&self.db_pool, item.user_id, NotificationEvent::DownloadFinished {
+                    podcast_name,
+                    episode_title: title,
+                }).await;
+                webhooks::emit(&self.db_pool, WebhookEventKind::EpisodeDownloaded, Some(item.user_id), WebhookSubject::Episode {
+                    episode_id: item.episode_id,
+                    is_youtube: false,
+                });
+            }
+            // The pause/cancel request already updated the queue row and the task
+            Ok(Outcome::Paused) => {}
+            Ok(Outcome::Cancelled) => {
+                self.discard_partial(item.storage_key.as_deref()).await;
+                self.report(self.task_manager.fail_task(&task_id, "Download cancelled".to_string()).await);
+            }
+            Err(failure) => {
+                let attempts = item.attempts + 1;
+                if failure.retryable && attempts < self.config.max_attempts {
+                    let delay = retry_delay_secs(attempts);
+                    let next_attempt_at = Utc::now().naive_utc() + chrono::Duration::seconds(delay);
+                    tracing::warn!("Download of episode {} failed, retrying in {}s: {}", item.episode_id, delay, failure.message);
+                    if let Err(e) = self.db_pool.reschedule_download(item.queue_id, attempts, next_attempt_at, &failure.message).await {
+                        tracing::error!("Failed to reschedule download {}: {}", item.queue_id, e);
+                    }
+                    self.report(self.task_manager.update_task_progress_with_details(
+                        &task_id,
+                        0.0,
+                        Some(format!("Retrying in {}s ({}/{}): {}", delay, attempts, self.config.max_attempts, failure.message)),
+                        Some(item.episode_id),
+                        Some("podcast_download".to_string()),
+                        Some(item.episode_title.clone()),
+                    ).await);
+                } else {
+                    tracing::warn!("Download of episode {} failed: {}", item.episode_id, failure.message);
+                    if let Err(e) = self.db_pool.fail_queued_download(item.queue_id, attempts, &failure.message).await {
+                        tracing::error!("Failed to mark download {} as failed: {}", item.queue_id, e);
+                    }
+                    self.report(self.task_manager.fail_task(&task_id, failure.message.clone()).await);
+                    let podcast_name = match self.db_pool.get_episode_download_info(item.episode_id).await {
+                        Ok(Some(info)) => info.podcast_name,
+                        _ => String::new(),
+                    };
+                    notifications::notify_quietly(&self.db_pool, item.user_id, NotificationEvent::DownloadFailed {
+                        podcast_name,
+                        episode_title: item.episode_title.clone(),
+                        error: failure.message,
+                    }).await;
+                }
+            }
+        }
+    }
+
+    async fn download(&self, item: &QueuedDownload, task_id: &str, flag: &AtomicU8) -> Result<Outcome, Failure> {
+        let info = self.db_pool.get_episode_download_info(item.episode_id).await?
+            .ok_or_else(|| Failure::fatal("Episode no longer exists".to_string()))?;
+        let progress = |percent: f64, message: String| {
+            self.task_manager.update_task_progress_with_details(
+                task_id,
+                percent,
+                Some(message),
+                Some(item.episode_id),
+                Some("podcast_download".to_string()),
+                Some(info.title.clone()),
+            )
+        };
+        self.report(progress(1.0, format!("Connecting to {}", info.title)).await);
+
+        let allowance = self.storage.allowance(&self.db_pool, item.user_id).await?;
+        allowance.check(0)?;
+
+        // Named by the user's download template (by default the podcast folder with date, title, and IDs)
+        let key = match &item.storage_key {
+            Some(key) => key.clone(),
+            None => {
+                let key = new_download_key(&self.db_pool, &self.storage, &EpisodeNaming {
+                    podcast: &info.podcast_name,
+                    title: &info.title,
+                    author: info.author.as_deref(),
+                    pub_date: info.pub_date,
+                    episode_id: item.episode_id,
+                    user_id: item.user_id,
+                    ext: extension_from_url(&info.url),
+                }).await?;
+                self.db_pool.set_download_storage_key(item.queue_id, &key).await?;
+                key
+            }
+        };
+        let backend = self.storage.active();
+        let file_path = backend.staging_path(&key).await?;
+
+        // Pick up where an earlier attempt stopped
+        let offset = tokio::fs::metadata(&file_path).await.map(|m| m.len()).unwrap_or(0);
+        let mut request = self.client.get(&info.url);
+        if offset > 0 {
+            request = request.header(header::RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = request.send().await
+            .map_err(|e| Failure::retry(format!("Failed to start download: {}", e)))?;
+
+        let status = response.status();
+        let already_complete = offset > 0 && status == StatusCode::RANGE_NOT_SATISFIABLE;
+        if !status.is_success() && !already_complete {
+            return Err(Failure { message: format!("Server returned error: {}", status), retryable: is_retryable_status(status) });
+        }
+
+        let mut downloaded = offset;
+        if !already_complete {
+            // A server that ignores Range sends the whole file again
+            let resuming = offset > 0 && status == StatusCode::PARTIAL_CONTENT;
+            if !resuming {
+                downloaded = 0;
+            }
+            let total = response.content_length().map(|length| length + downloaded);
+            if let Some(total) = total {
+                allowance.check(total)?;
+            }
+
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(resuming)
+                .truncate(!resuming)
+                .open(&file_path)
+                .await
+                .map_err(|e| Failure::from(AppError::internal(format!("Failed to create file: {}", e))))?;
+
+            let verb = if resuming { "Resuming" } else { "Downloading" };
+            let mut last_report = Instant::now();
+            while let Some(chunk) = response.chunk().await
+                .map_err(|e| Failure::retry(format!("Download failed: {}", e)))?
+            {
+                match flag.load(Ordering::SeqCst) {
+                    SIGNAL_PAUSE => {
+                        let _ = file.flush().await;
+                        self.db_pool.update_download_progress(item.queue_id, downloaded as i64, total.map(|t| t as i64)).await?;
+                        return Ok(Outcome::Paused);
+                    }
+                    SIGNAL_CANCEL => return Ok(Outcome::Cancelled),
+                    _ => {}
+                }
+                if let Some(limiter) = &self.limiter {
+                    limiter.throttle(chunk.len()).await;
+                }
+
+                downloaded += chunk.len() as u64;
+                if let Err(e) = allowance.check(downloaded) {
+                    drop(file);
+                    let _ = tokio::fs::remove_file(&file_path).await;
+                    return Err(e.into());
+                }
+                file.write_all(&chunk).await
+                    .map_err(|e| Failure::from(AppError::internal(format!("Failed to write file: {}", e))))?;
+
+                if last_report.elapsed() >= PROGRESS_INTERVAL {
+                    last_report = Instant::now();
+                    let percent = total.filter(|t| *t > 0).map(|t| downloaded as f64 / t as f64 * 90.0).unwrap_or(1.0);
+                    self.report(progress(percent.max(1.0), format!("{} {}", verb, info.title)).await);
+                    self.report(self.db_pool.update_download_progress(item.queue_id, downloaded as i64, total.map(|t| t as i64)).await);
+                }
+            }
+            file.flush().await
+                .map_err(|e| Failure::from(AppError::internal(format!("Failed to flush file: {}", e))))?;
+            drop(file);
+
+            if total.is_some_and(|total| downloaded < total) {
+                self.report(self.db_pool.update_download_progress(item.queue_id, downloaded as i64, total.map(|t| t as i64)).await);
+                return Err(Failure::retry("Connection closed before the download finished".to_string()));
+            }
+        }
+
+        // Chapters from the feed's chapters JSON; feeds without any keep the ones already in the file
+        let feed_chapters = self.feed_chapters(item).await;
+        let embedded_chapters = if feed_chapters.is_empty() {
+            chapters::read_embedded(&self.config.ffmpeg_path, &file_path).await.unwrap_or_else(|e| {
+                tracing::debug!("No embedded chapters read from {}: {}", file_path.display(), e);
+                Vec::new()
+            })
+        } else {
+            Vec::new()
+        };
+        let episode_chapters = if feed_chapters.is_empty() { &embedded_chapters } else { &feed_chapters };
+        let duration_secs = info.duration_secs.map(f64::from);
+
+        self.report(progress(90.0, format!("Processing {}", info.title)).await);
+        if let Err(e) = crate::services::tasks::add_podcast_metadata(
+            &file_path,
+            &info.title,
+            info.author.as_deref().unwrap_or("Unknown"),
+            &info.podcast_name,
+            info.pub_date.as_ref(),
+            info.artwork_url.as_deref(),
+            episode_chapters,
+            duration_secs,
+        ).await {
+            tracing::warn!("Failed to add metadata to {}: {}", file_path.display(), e);
+        }
+        let is_mp4 = file_path.extension().and_then(|ext| ext.to_str()).is_some_and(chapters::is_mp4);
+        if is_mp4 && !feed_chapters.is_empty() {
+            if let Err(e) = chapters::embed_mp4_chapters(&self.config.ffmpeg_path, &file_path, &feed_chapters, duration_secs).await {
+                tracing::warn!("Failed to add chapters to {}: {}", file_path.display(), e);
+            }
+        }
+        let (key, file_path) = self.post_process(item, task_id, &info, episode_chapters, key, file_path).await;
+
+        let size = tokio::fs::metadata(&file_path).await.map(|m| m.len()).unwrap_or(downloaded);
+        self.report(progress(96.0, format!("Finalizing {}", info.title)).await);
+        let location = backend.commit(&key, &file_path).await?;
+        // Chapters found only in the file are kept so they can be served like feed chapters
+        let stored_chapters = (!embedded_chapters.is_empty()).then(|| chapters::to_json(&embedded_chapters));
+        self.db_pool.record_episode_download(item.user_id, item.episode_id, size as i64, &location, stored_chapters.as_ref()).await?;
+
+        Ok(Outcome::Downloaded { podcast_name: info.podcast_name, title: info.title, location, size })
+    }
+
+    // Run the podcast's processing pipeline over a finished download. If ffmpeg fails the
+    // download is kept as fetched rather than failing it.
+    async fn post_process(
+        &self,
+        item: &QueuedDownload,
+        task_id: &str,
+        info: &EpisodeDownloadInfo,
+        episode_chapters: &[Chapter],
+        key: String,
+        file_path: PathBuf,
+    ) -> (String, PathBuf) {
+        let pipeline = match self.db_pool.get_processing_pipeline(info.podcast_id, item.user_id).await {
+            Ok(Some(pipeline)) if pipeline.is_active() => pipeline,
+            Ok(_) => return (key, file_path),
+            Err(e) => {
+                tracing::warn!("Failed to load processing settings for podcast {}: {}", info.podcast_id, e);
+                return (key, file_path);
+            }
+        };
+
+        let reporter = ProcessingProgress { queue: self, task_id, episode_id: item.episode_id, title: &info.title };
+        self.report(reporter.update_progress(0.0, None).await);
+
+        let processed = audio_processing::process(
+            &self.config.ffmpeg_path,
+            &pipeline,
+            &file_path,
+            extension_from_url(&info.url),
+            episode_chapters,
+            info.duration_secs.map(f64::from),
+            &reporter,
+        ).await;
+        let kept = match processed {
+            Ok(processed) => self.keep_processed(&key, &file_path, processed).await,
+            Err(e) => Err(e),
+        };
+        match kept {
+            Ok(kept) => kept,
+            Err(e) => {
+                tracing::warn!("Post-processing episode {} failed, keeping the original: {}", item.episode_id, e);
+                self.report(reporter.update_progress(100.0, Some(format!("Post-processing failed, keeping the original {}", info.title))).await);
+                (key, file_path)
+            }
+        }
+    }
+
+    // The episode's Podcasting 2.0 chapters; a feed that can't be fetched just means no chapters
+    async fn feed_chapters(&self, item: &QueuedDownload) -> Vec<Chapter> {
+        match self.db_pool.fetch_episode_chapters(item.episode_id, item.user_id).await {
+            Ok(json) => chapters::from_json(&json),
+            Err(e) => {
+                tracing::warn!("Failed to fetch chapters for episode {}: {}", item.episode_id, e);
+                Vec::new()
+            }
+        }
+    }
+
+    // Put the processed file in place of the original, under a new name if its extension changed
+    async fn keep_processed(&self, key: &str, original: &Path, processed: ProcessedAudio) -> AppResult<(String, PathBuf)> {
+        let original_ext = original.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+        let (key, target) = if original_ext == processed.extension {
+            (key.to_string(), original.to_path_buf())
+        } else {
+            let stem = key.strip_suffix(&format!(".{}", original_ext)).unwrap_or(key);
+            let backend = self.storage.active();
+            let new_key = storage::available_key(backend, &format!("{}.{}", stem, processed.extension)).await?;
+            let target = backend.staging_path(&new_key).await?;
+            (new_key, target)
+        };
+
+        if let Err(e) = tokio::fs::rename(&processed.path, &target).await {
+            let _ = tokio::fs::remove_file(&processed.path).await;
+            return Err(AppError::internal(format!("Failed to replace download with processed file: {}", e)));
+        }
+        if target != original {
+            let _ = tokio::fs::remove_file(original).await;
+        }
+        Ok((key, target))
+    }
+
+    // Remove the partial file of a download that won't be finished
+    async fn discard_partial(&self, storage_key: Option<&str>) {
+        let Some(key) = storage_key else { return };
+        if let Ok(path) = self.storage.active().staging_path(key).await {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+    }
+
+    // Progress and bookkeeping updates shouldn't stop a download
+    fn report(&self, result: AppResult<()>) {
+        if let Err(e) = result {
+            tracing::debug!("Download queue update failed: {}", e);
+        }
+    }
+}
+
+// Where a new download goes under the user's filename template, numbered if that name is taken
+async fn new_download_key(db_pool: &DatabasePool, storage: &Storage, naming: &EpisodeNaming<'_>) -> AppResult<String> {
+    let template = DownloadTemplate::for_user(db_pool, naming.user_id).await?;
+    storage::available_key(storage.active(), &template.render(naming)).await
+}
+
+// Post-processing progress, shown between downloading (up to 90%) and finalizing
+struct ProcessingProgress<'a> {
+    queue: &'a DownloadQueue,
+    task_id: &'a str,
+    episode_id: i32,
+    title: &'a str,
+}
+
+#[async_trait::async_trait]
+impl ProgressReporter for ProcessingProgress<'_> {
+    async fn update_progress(&self, progress: f64, message: Option<String>) -> AppResult<()> {
+        self.queue.task_manager.update_task_progress_with_details(
+            self.task_id,
+            90.0 + progress.clamp(0.0, 100.0) * 0.05,
+            Some(message.unwrap_or_else(|| format!("Post-processing {}", self.title))),
+            Some(self.episode_id),
+            Some("podcast_download".to_string()),
+            Some(self.title.to_string()),
+        ).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retries_back_off_up_to_an_hour() {
+        let delays: Vec<i64> = (1..=9).map(retry_delay_secs).collect();
+        assert_eq!(delays, vec![30, 60, 120, 240, 480, 960, 1920, 3600, 3600]);
+        assert_eq!(retry_delay_secs(100), RETRY_MAX_SECS);
+    }
+
+    #[test]
+    fn only_passing_server_errors_are_retried() {
+        for status in [StatusCode::INTERNAL_SERVER_ERROR, StatusCode::BAD_GATEWAY, StatusCode::REQUEST_TIMEOUT, StatusCode::TOO_MANY_REQUESTS] {
+            assert!(is_retryable_status(status), "{} should be retried", status);
+        }
+        for status in [StatusCode::NOT_FOUND, StatusCode::GONE, StatusCode::FORBIDDEN, StatusCode::UNAUTHORIZED] {
+            assert!(!is_retryable_status(status), "{} should not be retried", status);
+        }
+    }
+
+    #[test]
+    fn quota_and_missing_episode_errors_are_not_retried() {
+        assert!(!Failure::from(AppError::forbidden("Download quota exceeded (0 MB left)")).retryable);
+        assert!(!Failure::from(AppError::not_found("Episode not found")).retryable);
+        assert!(!Failure::from(AppError::bad_request("Invalid template")).retryable);
+        assert!(Failure::from(AppError::internal("Failed to write file")).retryable);
+    }
+
+    #[test]
+    fn unknown_statuses_read_as_queued() {
+        assert_eq!(QueueStatus::parse("downloading"), QueueStatus::Downloading);
+        assert_eq!(QueueStatus::parse("paused"), QueueStatus::Paused);
+        assert_eq!(QueueStatus::parse("failed"), QueueStatus::Failed);
+        assert_eq!(QueueStatus::parse("queued"), QueueStatus::Queued);
+        assert_eq!(QueueStatus::parse(""), QueueStatus::Queued);
+    }
+
+    #[tokio::test]
+    async fn bandwidth_limit_allows_a_second_of_burst_then_waits() {
+        let limiter = BandwidthLimiter::new(1000);
+        let started = Instant::now();
+        limiter.throttle(1000 * 1024).await;
+        assert!(started.elapsed() < Duration::from_millis(200));
+
+        limiter.throttle(500 * 1024).await;
+        let elapsed = started.elapsed();
+        assert!(elapsed >= Duration::from_millis(400), "waited only {:?}", elapsed);
+        assert!(elapsed < Duration::from_secs(2), "waited {:?}", elapsed);
+    }
+}
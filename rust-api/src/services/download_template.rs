@@ -0,0 +1,375 @@
+use chrono::{Datelike, NaiveDateTime, Utc};
+use chrono::format::{Item, StrftimeItems};
+use std::fmt::Write;
+use crate::{
+    database::DatabasePool,
+    error::{AppError, AppResult},
+};
+
+/// Layout downloads used before templates existed, kept as the default so existing
+/// libraries don't change shape
+pub const DEFAULT_TEMPLATE: &str = "{podcast}/{pubdate:%Y-%m-%d}_{title}_{user_id}_{episode_id}.{ext}";
+/// Longest template accepted from an admin or user
+pub const MAX_TEMPLATE_LEN: usize = 255;
+// Filesystems cap a path segment at 255 bytes; leave room for a " (n)" collision suffix
+const MAX_SEGMENT_BYTES: usize = 200;
+// Enclosure extensions kept as-is; anything else is saved as mp3 like before
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "m4a", "m4b", "aac", "ogg", "oga", "opus", "flac", "wav", "mp4"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Podcast,
+    Title,
+    Author,
+    Year,
+    Month,
+    Day,
+    PubDate(String),
+    EpisodeId,
+    UserId,
+    Ext,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Field(Field),
+}
+
+/// What a template can refer to for one downloaded episode
+#[derive(Debug, Clone)]
+pub struct EpisodeNaming<'a> {
+    pub podcast: &'a str,
+    pub title: &'a str,
+    pub author: Option<&'a str>,
+    pub pub_date: Option<NaiveDateTime>,
+    pub episode_id: i32,
+    pub user_id: i32,
+    pub ext: &'a str,
+}
+
+/// Parsed download filename template, e.g. `{podcast}/{year}/{pubdate:%Y-%m-%d} - {title}.{ext}`.
+///
+/// `/` in the template separates folders. Placeholders: `{podcast}`, `{title}`, `{author}`,
+/// `{year}`, `{month}`, `{day}`, `{pubdate}` or `{pubdate:<strftime>}`, `{episode_id}`,
+/// `{user_id}` and `{ext}`. Substituted values are sanitized so they can't add folders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadTemplate {
+    parts: Vec<Part>,
+    has_ext: bool,
+}
+
+impl DownloadTemplate {
+    pub fn parse(template: &str) -> AppResult<Self> {
+        let template = template.trim();
+        if template.is_empty() {
+            return Err(AppError::bad_request("Download template cannot be empty"));
+        }
+        if template.len() > MAX_TEMPLATE_LEN {
+            return Err(AppError::bad_request(format!("Download template cannot be longer than {} characters", MAX_TEMPLATE_LEN)));
+        }
+        if template.starts_with('/') || template.contains('\\') {
+            return Err(AppError::bad_request("Download template must be a relative path using '/' between folders"));
+        }
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(AppError::bad_request("Unclosed '{' in download template")),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Field(Self::parse_field(&name)?));
+                }
+                '}' => return Err(AppError::bad_request("Unmatched '}' in download template")),
+                c if c.is_control() || matches!(c, ':' | '*' | '?' | '"' | '<' | '>' | '|') => {
+                    return Err(AppError::bad_request(format!("Download template cannot contain '{}'", c.escape_default())));
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        // Folder names come from the literal text, so refuse ones that would escape the root
+        let literal_text: String = parts.iter().map(|p| match p {
+            Part::Literal(text) => text.as_str(),
+            Part::Field(_) => "x",
+        }).collect();
+        if literal_text.split('/').any(|segment| segment.trim().is_empty() || segment.trim() == "." || segment.trim() == "..") {
+            return Err(AppError::bad_request("Download template has an empty, '.' or '..' folder"));
+        }
+        if !parts.iter().any(|p| matches!(p, Part::Field(Field::Title | Field::EpisodeId))) {
+            return Err(AppError::bad_request("Download template must include {title} or {episode_id}"));
+        }
+
+        let has_ext = parts.iter().any(|p| matches!(p, Part::Field(Field::Ext)));
+        Ok(DownloadTemplate { parts, has_ext })
+    }
+
+    fn parse_field(name: &str) -> AppResult<Field> {
+        let field = match name.trim() {
+            "podcast" => Field::Podcast,
+            "title" => Field::Title,
+            "author" => Field::Author,
+            "year" => Field::Year,
+            "month" => Field::Month,
+            "day" => Field::Day,
+            "pubdate" => Field::PubDate("%Y-%m-%d".to_string()),
+            "episode_id" => Field::EpisodeId,
+            "user_id" => Field::UserId,
+            "ext" => Field::Ext,
+            other => match other.strip_prefix("pubdate:") {
+                Some(format) => {
+                    // Some specifiers parse but can't be written for a date without a
+                    // timezone (%z, %Z), so try formatting one
+                    let mut trial = String::new();
+                    if format.is_empty()
+                        || StrftimeItems::new(format).any(|item| matches!(item, Item::Error))
+                        || write!(trial, "{}", NaiveDateTime::default().format(format)).is_err()
+                    {
+                        return Err(AppError::bad_request(format!("Invalid date format in {{{}}}", other)));
+                    }
+                    Field::PubDate(format.to_string())
+                }
+                None => return Err(AppError::bad_request(format!("Unknown download template placeholder {{{}}}", other))),
+            },
+        };
+        Ok(field)
+    }
+
+    /// Storage key for an episode, e.g. `My Show/2024/2024-05-01 - Pilot.mp3`
+    pub fn render(&self, naming: &EpisodeNaming) -> String {
+        let pub_date = naming.pub_date.unwrap_or_else(|| Utc::now().naive_utc());
+        let mut rendered = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => rendered.push_str(text),
+                Part::Field(field) => rendered.push_str(&safe_component(&match field {
+                    Field::Podcast => naming.podcast.to_string(),
+                    Field::Title => naming.title.to_string(),
+                    Field::Author => naming.author.filter(|a| !a.trim().is_empty()).unwrap_or("Unknown").to_string(),
+                    Field::Year => pub_date.year().to_string(),
+                    Field::Month => format!("{:02}", pub_date.month()),
+                    Field::Day => format!("{:02}", pub_date.day()),
+                    Field::PubDate(format) => format_date(pub_date, format),
+                    Field::EpisodeId => naming.episode_id.to_string(),
+                    Field::UserId => naming.user_id.to_string(),
+                    Field::Ext => naming.ext.to_string(),
+                })),
+            }
+        }
+        if !self.has_ext {
+            rendered.push('.');
+            rendered.push_str(naming.ext);
+        }
+
+        let segments: Vec<String> = rendered.split('/').map(|segment| {
+            let segment = segment.trim();
+            let segment = if segment.is_empty() || segment.chars().all(|c| c == '.') { "_" } else { segment };
+            truncate_segment(segment)
+        }).collect();
+        segments.join("/")
+    }
+
+    /// Template in effect for a user: their own, else the admin's server default, else the built-in layout
+    pub async fn for_user(db_pool: &DatabasePool, user_id: i32) -> AppResult<Self> {
+        let (user_template, server_template) = db_pool.get_download_templates(user_id).await?;
+        for template in [user_template, server_template].into_iter().flatten() {
+            match Self::parse(&template) {
+                Ok(parsed) => return Ok(parsed),
+                Err(e) => tracing::warn!("Ignoring invalid download template '{}': {}", template, e),
+            }
+        }
+        Self::parse(DEFAULT_TEMPLATE)
+    }
+}
+
+// A date in a template's format; parse() refuses formats that can't be written, but fall
+// back to the default rather than panic if one gets through
+fn format_date(date: NaiveDateTime, format: &str) -> String {
+    let mut formatted = String::new();
+    if write!(formatted, "{}", date.format(format)).is_err() {
+        formatted.clear();
+        let _ = write!(formatted, "{}", date.format("%Y-%m-%d"));
+    }
+    formatted
+}
+
+/// Keep names readable while making them safe as path segments and object keys
+pub fn safe_component(value: &str) -> String {
+    value.chars()
+        .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' { c } else { '_' })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+// Shorten a path segment to MAX_SEGMENT_BYTES, keeping its extension
+fn truncate_segment(segment: &str) -> String {
+    if segment.len() <= MAX_SEGMENT_BYTES {
+        return segment.to_string();
+    }
+    let (stem, ext) = match segment.rfind('.') {
+        Some(dot) if segment.len() - dot <= 8 => segment.split_at(dot),
+        _ => (segment, ""),
+    };
+    let mut end = MAX_SEGMENT_BYTES.saturating_sub(ext.len());
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{}", stem[..end].trim_end(), ext)
+}
+
+/// File extension for a downloaded enclosure, from its URL
+pub fn extension_from_url(url: &str) -> &'static str {
+    let path = url::Url::parse(url).map(|u| u.path().to_string()).unwrap_or_default();
+    let ext = path.rsplit('/').next().and_then(|name| name.rsplit_once('.')).map(|(_, ext)| ext.to_ascii_lowercase());
+    ext.and_then(|ext| AUDIO_EXTENSIONS.iter().find(|known| **known == ext).copied())
+        .unwrap_or("mp3")
+}
+
+/// A downloaded episode as the re-organize task sees it
+#[derive(Debug, Clone)]
+pub struct StoredDownload {
+    pub download_id: i32,
+    pub user_id: i32,
+    pub episode_id: i32,
+    pub episode_url: String,
+    pub episode_title: String,
+    pub podcast_name: String,
+    pub author: Option<String>,
+    pub pub_date: Option<NaiveDateTime>,
+    pub location: String,
+}
+
+impl StoredDownload {
+    pub fn naming(&self) -> EpisodeNaming<'_> {
+        EpisodeNaming {
+            podcast: &self.podcast_name,
+            title: &self.episode_title,
+            author: self.author.as_deref(),
+            pub_date: self.pub_date,
+            episode_id: self.episode_id,
+            user_id: self.user_id,
+            ext: extension_from_url(&self.episode_url),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn naming<'a>(title: &'a str) -> EpisodeNaming<'a> {
+        EpisodeNaming {
+            podcast: "My Show",
+            title,
+            author: Some("Jane Doe"),
+            pub_date: chrono::NaiveDate::from_ymd_opt(2024, 5, 1).and_then(|d| d.and_hms_opt(9, 30, 0)),
+            episode_id: 42,
+            user_id: 7,
+            ext: "m4a",
+        }
+    }
+
+    fn render(template: &str, naming: &EpisodeNaming) -> String {
+        DownloadTemplate::parse(template).unwrap().render(naming)
+    }
+
+    #[test]
+    fn default_template_keeps_the_old_layout() {
+        assert_eq!(render(DEFAULT_TEMPLATE, &naming("Pilot")), "My Show/2024-05-01_Pilot_7_42.m4a");
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        assert_eq!(
+            render("{author}/{podcast}/{year}/{month}-{day} {pubdate:%b %d} - {title}.{ext}", &naming("Pilot")),
+            "Jane Doe/My Show/2024/05-01 May 01 - Pilot.m4a"
+        );
+        let anonymous = EpisodeNaming { author: Some("  "), ..naming("Pilot") };
+        assert_eq!(render("{author}/{episode_id}", &anonymous), "Unknown/42.m4a");
+    }
+
+    #[test]
+    fn values_cannot_add_folders_or_climb_out() {
+        assert_eq!(render("{podcast}/{title}", &naming("Part 1/2: ../../etc")), "My Show/Part 1_2_ ______etc.m4a");
+        assert_eq!(render("{title}/{episode_id}", &naming("..")), "__/42.m4a");
+        assert_eq!(render("{title}/{episode_id}", &naming("")), "_/42.m4a");
+    }
+
+    #[test]
+    fn extension_is_added_when_the_template_has_none() {
+        assert_eq!(render("{title}", &naming("Pilot")), "Pilot.m4a");
+        assert_eq!(render("{title}.{ext}", &naming("Pilot")), "Pilot.m4a");
+    }
+
+    #[test]
+    fn long_segments_are_shortened_keeping_the_extension() {
+        let title = "é".repeat(150);
+        let rendered = render("{podcast}/{title}.{ext}", &naming(&title));
+        let file = rendered.rsplit('/').next().unwrap();
+        assert!(file.len() <= MAX_SEGMENT_BYTES);
+        assert!(file.ends_with("é.m4a"));
+    }
+
+    #[test]
+    fn invalid_templates_are_refused() {
+        for template in [
+            "",
+            "/abs/{title}",
+            "C:\\{title}",
+            "{podcast}/{title",
+            "{title}}",
+            "{podcast}/{season}/{title}",
+            "{pubdate:%Q}/{title}",
+            "{pubdate:}/{title}",
+            "{pubdate:%z}/{title}",
+            "{pubdate:%:z}/{title}",
+            "{pubdate:%Z}/{title}",
+            "../{title}",
+            "{podcast}//{title}",
+            "{podcast}/./{title}",
+            "{podcast}/{year}",
+            "{title}?.mp3",
+        ] {
+            assert!(DownloadTemplate::parse(template).is_err(), "{:?} should be refused", template);
+        }
+        assert!(DownloadTemplate::parse(&format!("{{title}}{}", "a".repeat(MAX_TEMPLATE_LEN))).is_err());
+        assert!(DownloadTemplate::parse("  {episode_id}  ").is_ok());
+    }
+
+    #[test]
+    fn dates_that_cannot_be_written_fall_back_to_the_default_format() {
+        let date = chrono::NaiveDate::from_ymd_opt(2024, 5, 1).and_then(|d| d.and_hms_opt(9, 30, 0)).unwrap();
+        assert_eq!(format_date(date, "%z"), "2024-05-01");
+        assert_eq!(format_date(date, "%Y/%m"), "2024/05");
+    }
+
+    #[test]
+    fn extensions_come_from_the_enclosure_url() {
+        assert_eq!(extension_from_url("https://cdn.example.com/ep.M4A?token=abc"), "m4a");
+        assert_eq!(extension_from_url("https://cdn.example.com/ep.opus"), "opus");
+        assert_eq!(extension_from_url("https://cdn.example.com/stream.php?file=ep.ogg"), "mp3");
+        assert_eq!(extension_from_url("https://cdn.example.com.au/episode"), "mp3");
+        assert_eq!(extension_from_url("not a url"), "mp3");
+    }
+
+    #[test]
+    fn safe_components_keep_readable_characters() {
+        assert_eq!(safe_component(" Café: Ep. #3 "), "Café_ Ep_ _3");
+        assert_eq!(safe_component("dQw4w9WgXcQ"), "dQw4w9WgXcQ");
+    }
+}
@@ -0,0 +1,458 @@
+use chrono::{Datelike, Duration, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use crate::{
+    database::DatabasePool,
+    error::{AppError, AppResult},
+};
+
+// Episodes listed per section before the rest are summed up as "and N more"
+const SECTION_LIMIT: usize = 20;
+
+/// How often a user's digest email goes out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestFrequency {
+    Daily,
+    Weekly,
+}
+
+impl DigestFrequency {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly => "weekly",
+        }
+    }
+
+    pub fn parse(frequency: &str) -> Option<Self> {
+        match frequency {
+            "daily" => Some(DigestFrequency::Daily),
+            "weekly" => Some(DigestFrequency::Weekly),
+            _ => None,
+        }
+    }
+
+    fn period(&self) -> Duration {
+        match self {
+            DigestFrequency::Daily => Duration::days(1),
+            DigestFrequency::Weekly => Duration::days(7),
+        }
+    }
+}
+
+/// What goes into a user's digest email and when it is sent
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DigestSettings {
+    pub enabled: bool,
+    pub frequency: DigestFrequency,
+    /// Day a weekly digest goes out, 0 for Monday through 6 for Sunday
+    pub weekday: i32,
+    /// Hour of the day the digest goes out, in the user's timezone
+    pub send_hour: i32,
+    /// Podcasts the digest covers; every subscription when unset
+    pub podcast_ids: Option<Vec<i32>>,
+    /// List episodes the user started but hasn't finished
+    pub include_in_progress: bool,
+    /// List what is waiting in the queue
+    pub include_queue: bool,
+}
+
+impl Default for DigestSettings {
+    fn default() -> Self {
+        DigestSettings {
+            enabled: false,
+            frequency: DigestFrequency::Weekly,
+            weekday: 0,
+            send_hour: 8,
+            podcast_ids: None,
+            include_in_progress: true,
+            include_queue: true,
+        }
+    }
+}
+
+impl DigestSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(0..=6).contains(&self.weekday) {
+            return Err("weekday must be between 0 (Monday) and 6 (Sunday)".to_string());
+        }
+        if !(0..=23).contains(&self.send_hour) {
+            return Err("send_hour must be between 0 and 23".to_string());
+        }
+        if self.podcast_ids.as_ref().is_some_and(|ids| ids.is_empty()) {
+            return Err("podcast_ids cannot be empty; leave it out to cover every podcast".to_string());
+        }
+        Ok(())
+    }
+
+    /// The latest time at or before `now` (UTC) the digest was due, for a user in `tz`
+    pub fn last_due(&self, tz: Tz, now: NaiveDateTime) -> NaiveDateTime {
+        let local_now = tz.from_utc_datetime(&now).naive_local();
+        let mut date = local_now.date();
+        if self.frequency == DigestFrequency::Weekly {
+            let days_back = (date.weekday().num_days_from_monday() as i64 - self.weekday as i64).rem_euclid(7);
+            date -= Duration::days(days_back);
+        }
+        let mut due = date.and_hms_opt(self.send_hour as u32, 0, 0).unwrap_or(local_now);
+        if due > local_now {
+            due -= self.frequency.period();
+        }
+        // An hour skipped by a DST change falls through to the hour after it
+        tz.from_local_datetime(&due).earliest()
+            .or_else(|| tz.from_local_datetime(&(due + Duration::hours(1))).earliest())
+            .map(|due| due.naive_utc())
+            .unwrap_or(due)
+    }
+
+    fn covers(&self, podcast_id: i32) -> bool {
+        self.podcast_ids.as_ref().is_none_or(|ids| ids.contains(&podcast_id))
+    }
+}
+
+/// A user the digest can be sent to, from `get_email_digest_recipients`
+pub struct DigestRecipient {
+    pub user_id: i32,
+    pub email: String,
+    pub timezone: Option<String>,
+    pub settings: DigestSettings,
+    pub last_sent_at: Option<NaiveDateTime>,
+}
+
+impl DigestRecipient {
+    fn tz(&self) -> Tz {
+        self.timezone.as_deref().and_then(|tz| tz.parse().ok()).unwrap_or(Tz::UTC)
+    }
+}
+
+/// An episode listed in a digest
+#[derive(Debug, Clone)]
+pub struct DigestEpisode {
+    pub podcast_id: i32,
+    pub podcast_name: String,
+    pub episode_title: String,
+    pub pub_date: Option<NaiveDateTime>,
+    pub duration_secs: i32,
+    /// How far the user got, for in-progress episodes
+    pub listened_secs: Option<i32>,
+}
+
+/// The sections of one digest email
+pub struct DigestContent {
+    pub new_episodes: Vec<DigestEpisode>,
+    pub in_progress: Vec<DigestEpisode>,
+    pub queue: Vec<DigestEpisode>,
+}
+
+impl DigestContent {
+    pub fn is_empty(&self) -> bool {
+        self.new_episodes.is_empty() && self.in_progress.is_empty() && self.queue.is_empty()
+    }
+}
+
+/// Gather what a digest covering the time since `since` lists for a user
+pub async fn build(db: &DatabasePool, user_id: i32, settings: &DigestSettings, since: NaiveDateTime) -> AppResult<DigestContent> {
+    let new_episodes = db.get_digest_new_episodes(user_id, since).await?
+        .into_iter()
+        .filter(|episode| settings.covers(episode.podcast_id))
+        .collect();
+    let in_progress = if settings.include_in_progress {
+        db.get_digest_in_progress_episodes(user_id).await?
+            .into_iter()
+            .filter(|episode| settings.covers(episode.podcast_id))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    // The queue is the user's own list, so it isn't narrowed to the chosen podcasts
+    let queue = if settings.include_queue {
+        db.get_digest_queued_episodes(user_id).await?
+    } else {
+        Vec::new()
+    };
+    Ok(DigestContent { new_episodes, in_progress, queue })
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn format_duration(secs: i32) -> String {
+    let minutes = (secs.max(0) + 30) / 60;
+    if minutes >= 60 {
+        format!("{}h {}m", minutes / 60, minutes % 60)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+fn render_section(html: &mut String, heading: &str, episodes: &[DigestEpisode], detail: impl Fn(&DigestEpisode) -> Option<String>) {
+    html.push_str(&format!("<h3 style=\"color: #539e8a; margin: 24px 0 8px 0;\">{} ({})</h3>", escape(heading), episodes.len()));
+    html.push_str("<ul style=\"margin: 0; padding-left: 20px;\">");
+    for episode in episodes.iter().take(SECTION_LIMIT) {
+        html.push_str(&format!(
+            "<li style=\"margin-bottom: 8px;\"><strong>{}</strong><br><span style=\"color: #6c757d;\">{}",
+            escape(&episode.episode_title),
+            escape(&episode.podcast_name),
+        ));
+        if let Some(detail) = detail(episode) {
+            html.push_str(&format!(" &middot; {}", escape(&detail)));
+        }
+        html.push_str("</span></li>");
+    }
+    html.push_str("</ul>");
+    if episodes.len() > SECTION_LIMIT {
+        html.push_str(&format!("<p style=\"color: #6c757d;\">and {} more</p>", episodes.len() - SECTION_LIMIT));
+    }
+}
+
+/// Subject and HTML body of a digest, with dates shown in `tz`
+pub fn render(content: &DigestContent, settings: &DigestSettings, tz: Tz) -> (String, String) {
+    let period = match settings.frequency {
+        DigestFrequency::Daily => "today",
+        DigestFrequency::Weekly => "this week",
+    };
+    let subject = match content.new_episodes.len() {
+        0 => format!("PinePods - Your {} digest", settings.frequency.as_str()),
+        1 => format!("PinePods - 1 new episode {}", period),
+        n => format!("PinePods - {} new episodes {}", n, period),
+    };
+
+    let mut html = format!("<h2>🎧 Your {} digest</h2>", settings.frequency.as_str());
+    if content.new_episodes.is_empty() {
+        html.push_str(&format!("<p>No new episodes from your podcasts {}.</p>", period));
+    } else {
+        render_section(&mut html, "New episodes", &content.new_episodes, |episode| {
+            let date = episode.pub_date.map(|date| tz.from_utc_datetime(&date).format("%b %-d").to_string());
+            match (date, episode.duration_secs > 0) {
+                (Some(date), true) => Some(format!("{} · {}", date, format_duration(episode.duration_secs))),
+                (Some(date), false) => Some(date),
+                (None, true) => Some(format_duration(episode.duration_secs)),
+                (None, false) => None,
+            }
+        });
+    }
+    if !content.in_progress.is_empty() {
+        render_section(&mut html, "Pick up where you left off", &content.in_progress, |episode| {
+            let listened = episode.listened_secs.unwrap_or(0);
+            (episode.duration_secs > listened).then(|| format!("{} left", format_duration(episode.duration_secs - listened)))
+        });
+    }
+    if settings.include_queue {
+        if content.queue.is_empty() {
+            html.push_str("<h3 style=\"color: #539e8a; margin: 24px 0 8px 0;\">Queue</h3><p>Your queue is empty.</p>");
+        } else {
+            render_section(&mut html, "Up next in your queue", &content.queue, |episode| {
+                (episode.duration_secs > 0).then(|| format_duration(episode.duration_secs))
+            });
+        }
+    }
+    (subject, html)
+}
+
+async fn send(db: &DatabasePool, recipient: &DigestRecipient, since: NaiveDateTime) -> AppResult<bool> {
+    let email_settings = db.get_email_settings().await?
+        .ok_or_else(|| AppError::bad_request("Email is not configured on this server"))?;
+    let content = build(db, recipient.user_id, &recipient.settings, since).await?;
+    if content.is_empty() {
+        return Ok(false);
+    }
+    let (subject, html) = render(&content, &recipient.settings, recipient.tz());
+    crate::handlers::settings::send_html_email(&email_settings, &recipient.email, &subject, &html).await?;
+    Ok(true)
+}
+
+/// Email the digest to every opted-in user whose send time has come since their last
+/// one. Each digest covers one period back from when it was due, so a late send
+/// doesn't leave a gap or repeat episodes. Called hourly from the scheduler.
+pub async fn send_due_digests(db: &DatabasePool) -> AppResult<usize> {
+    if db.get_email_settings().await?.is_none() {
+        return Ok(0);
+    }
+
+    let now = Utc::now().naive_utc();
+    let mut sent = 0;
+    for recipient in db.get_email_digest_recipients(None).await? {
+        let due = recipient.settings.last_due(recipient.tz(), now);
+        if recipient.last_sent_at.is_some_and(|last| last >= due) {
+            continue;
+        }
+        match send(db, &recipient, due - recipient.settings.frequency.period()).await {
+            Ok(delivered) => {
+                db.mark_email_digest_sent(recipient.user_id, now).await?;
+                if delivered {
+                    sent += 1;
+                }
+            }
+            // Left unmarked so the next run tries again
+            Err(e) => tracing::warn!("Failed to send email digest to user {}: {}", recipient.user_id, e),
+        }
+    }
+    Ok(sent)
+}
+
+/// Send a user their digest for the period up to now, whether or not it is enabled
+/// or due, without moving the regular schedule
+pub async fn send_now(db: &DatabasePool, user_id: i32) -> AppResult<bool> {
+    let recipient = db.get_email_digest_recipients(Some(user_id)).await?
+        .pop()
+        .ok_or_else(|| AppError::bad_request("Add an email address to your account to receive the digest"))?;
+    let since = Utc::now().naive_utc() - recipient.settings.frequency.period();
+    send(db, &recipient, since).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(value: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn settings(frequency: DigestFrequency, weekday: i32, send_hour: i32) -> DigestSettings {
+        DigestSettings { enabled: true, frequency, weekday, send_hour, ..DigestSettings::default() }
+    }
+
+    fn episode(podcast_id: i32, title: &str, duration_secs: i32) -> DigestEpisode {
+        DigestEpisode {
+            podcast_id,
+            podcast_name: "Show".to_string(),
+            episode_title: title.to_string(),
+            pub_date: Some(at("2025-01-14 23:30")),
+            duration_secs,
+            listened_secs: None,
+        }
+    }
+
+    #[test]
+    fn daily_digests_are_due_at_the_send_hour() {
+        let daily = settings(DigestFrequency::Daily, 0, 8);
+        assert_eq!(daily.last_due(Tz::UTC, at("2025-01-15 07:59")), at("2025-01-14 08:00"));
+        assert_eq!(daily.last_due(Tz::UTC, at("2025-01-15 08:00")), at("2025-01-15 08:00"));
+        // Already the 16th in Auckland (UTC+13), but 08:00 there hasn't come yet
+        assert_eq!(daily.last_due(chrono_tz::Pacific::Auckland, at("2025-01-15 12:00")), at("2025-01-14 19:00"));
+    }
+
+    #[test]
+    fn weekly_digests_are_due_on_their_weekday() {
+        let berlin = chrono_tz::Europe::Berlin;
+        // Wednesday the 15th: the Monday before
+        assert_eq!(settings(DigestFrequency::Weekly, 0, 8).last_due(berlin, at("2025-01-15 12:00")), at("2025-01-13 07:00"));
+        // Monday before the send hour: the Monday a week earlier
+        assert_eq!(settings(DigestFrequency::Weekly, 0, 8).last_due(berlin, at("2025-01-13 06:00")), at("2025-01-06 07:00"));
+        // Sunday digests, seen on a Monday
+        assert_eq!(settings(DigestFrequency::Weekly, 6, 20).last_due(berlin, at("2025-01-13 06:00")), at("2025-01-12 19:00"));
+    }
+
+    #[test]
+    fn daylight_saving_changes_do_not_skip_a_digest() {
+        let new_york = chrono_tz::America::New_York;
+        let daily = settings(DigestFrequency::Daily, 0, 2);
+        // 02:00 doesn't exist on the spring-forward day; 03:00 EDT is used
+        assert_eq!(daily.last_due(new_york, at("2025-03-09 12:00")), at("2025-03-09 07:00"));
+        let one_am = settings(DigestFrequency::Daily, 0, 1);
+        // 01:00 happens twice on the fall-back day; the first one counts
+        assert_eq!(one_am.last_due(new_york, at("2025-11-02 12:00")), at("2025-11-02 05:00"));
+    }
+
+    #[test]
+    fn out_of_range_settings_are_refused() {
+        assert!(settings(DigestFrequency::Weekly, 7, 8).validate().is_err());
+        assert!(settings(DigestFrequency::Weekly, -1, 8).validate().is_err());
+        assert!(settings(DigestFrequency::Daily, 0, 24).validate().is_err());
+        assert!(DigestSettings { podcast_ids: Some(vec![]), ..DigestSettings::default() }.validate().is_err());
+        assert!(DigestSettings { podcast_ids: Some(vec![3]), ..DigestSettings::default() }.validate().is_ok());
+    }
+
+    #[test]
+    fn chosen_podcasts_narrow_the_digest() {
+        let all = DigestSettings::default();
+        assert!(all.covers(1) && all.covers(2));
+        let some = DigestSettings { podcast_ids: Some(vec![2]), ..DigestSettings::default() };
+        assert!(!some.covers(1) && some.covers(2));
+    }
+
+    #[test]
+    fn settings_fill_in_defaults() {
+        let parsed: DigestSettings = serde_json::from_str(r#"{"enabled":true,"frequency":"daily"}"#).unwrap();
+        assert_eq!(parsed, DigestSettings { enabled: true, frequency: DigestFrequency::Daily, ..DigestSettings::default() });
+        assert_eq!(DigestFrequency::parse("weekly"), Some(DigestFrequency::Weekly));
+        assert_eq!(DigestFrequency::parse("monthly"), None);
+    }
+
+    #[test]
+    fn unknown_timezones_fall_back_to_utc() {
+        let recipient = |timezone: Option<&str>| DigestRecipient {
+            user_id: 1,
+            email: "user@example.com".to_string(),
+            timezone: timezone.map(str::to_string),
+            settings: DigestSettings::default(),
+            last_sent_at: None,
+        };
+        assert_eq!(recipient(Some("Europe/Berlin")).tz(), chrono_tz::Europe::Berlin);
+        assert_eq!(recipient(Some("Mars/Olympus")).tz(), Tz::UTC);
+        assert_eq!(recipient(None).tz(), Tz::UTC);
+    }
+
+    #[test]
+    fn durations_round_to_the_minute() {
+        assert_eq!(format_duration(0), "0m");
+        assert_eq!(format_duration(89), "1m");
+        assert_eq!(format_duration(90), "2m");
+        assert_eq!(format_duration(3600 + 25 * 60), "1h 25m");
+        assert_eq!(format_duration(-5), "0m");
+    }
+
+    #[test]
+    fn digests_list_each_section_with_escaped_titles() {
+        let content = DigestContent {
+            new_episodes: vec![episode(1, "<b>Q&A</b>", 3600)],
+            in_progress: vec![DigestEpisode { listened_secs: Some(600), ..episode(1, "Half done", 1800) }],
+            queue: vec![],
+        };
+        let (subject, html) = render(&content, &settings(DigestFrequency::Weekly, 0, 8), chrono_tz::Europe::Berlin);
+        assert_eq!(subject, "PinePods - 1 new episode this week");
+        assert!(html.contains("&lt;b&gt;Q&amp;A&lt;/b&gt;"));
+        assert!(!html.contains("<b>Q&A"));
+        // Published late on the 14th UTC, which is the 15th in Berlin
+        assert!(html.contains("Jan 15 · 1h 0m"));
+        assert!(html.contains("20m left"));
+        assert!(html.contains("Your queue is empty."));
+    }
+
+    #[test]
+    fn long_sections_are_summed_up() {
+        let content = DigestContent {
+            new_episodes: (0..SECTION_LIMIT + 3).map(|i| episode(1, &format!("Episode {}", i), 0)).collect(),
+            in_progress: vec![],
+            queue: vec![],
+        };
+        let no_queue = DigestSettings { include_queue: false, ..settings(DigestFrequency::Daily, 0, 8) };
+        let (subject, html) = render(&content, &no_queue, Tz::UTC);
+        assert_eq!(subject, format!("PinePods - {} new episodes today", SECTION_LIMIT + 3));
+        assert_eq!(html.matches("<li").count(), SECTION_LIMIT);
+        assert!(html.contains("and 3 more"));
+        assert!(!html.contains("queue"));
+    }
+
+    #[test]
+    fn quiet_periods_still_get_a_digest() {
+        let content = DigestContent { new_episodes: vec![], in_progress: vec![], queue: vec![] };
+        assert!(content.is_empty());
+        let (subject, html) = render(&content, &settings(DigestFrequency::Daily, 0, 8), Tz::UTC);
+        assert_eq!(subject, "PinePods - Your daily digest");
+        assert!(html.contains("No new episodes from your podcasts today."));
+    }
+}
@@ -0,0 +1,444 @@
+use chrono::NaiveDateTime;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use crate::database::EpisodeData;
+
+/// Widest GUID stored as-is; longer ones are replaced by their SHA-256 to fit EpisodeGuid
+pub const MAX_GUID_LEN: usize = 512;
+// Same title this close together is a re-post of the same episode
+const SAME_TITLE_WINDOW_SECS: i64 = 12 * 60 * 60;
+// Retitled episode: published this close together with (almost) the same length
+const SAME_LENGTH_WINDOW_SECS: i64 = 2 * 60 * 60;
+const SAME_LENGTH_TOLERANCE: f64 = 0.01;
+
+/// Trim a feed `<guid>`/`<id>`, dropping empty ones
+pub fn normalize_guid(raw: &str) -> Option<String> {
+    let guid = raw.trim();
+    if guid.is_empty() {
+        None
+    } else if guid.len() > MAX_GUID_LEN {
+        Some(hex::encode(Sha256::digest(guid.as_bytes())))
+    } else {
+        Some(guid.to_string())
+    }
+}
+
+/// Some feeds give every item the same `<guid>` (often the feed or site URL). Such a
+/// GUID identifies nothing, so those items are matched as if they had none.
+pub fn drop_ambiguous_guids(episodes: &mut [EpisodeData]) {
+    let mut seen: HashMap<&str, (&str, &str)> = HashMap::new();
+    let mut ambiguous: HashSet<String> = HashSet::new();
+    for episode in episodes.iter() {
+        let Some(guid) = &episode.guid else { continue };
+        match seen.get(guid.as_str()) {
+            // The same item listed twice is harmless
+            Some(&(url, title)) if url == episode.url && title == episode.title => {}
+            Some(_) => {
+                ambiguous.insert(guid.clone());
+            }
+            None => {
+                seen.insert(guid, (&episode.url, &episode.title));
+            }
+        }
+    }
+
+    if !ambiguous.is_empty() {
+        for episode in episodes.iter_mut() {
+            if episode.guid.as_ref().is_some_and(|g| ambiguous.contains(g)) {
+                episode.guid = None;
+            }
+        }
+    }
+}
+
+/// An episode row already stored for a podcast, as far as matching needs it
+#[derive(Debug, Clone)]
+pub struct KnownEpisode {
+    pub id: i32,
+    pub guid: Option<String>,
+    pub url: String,
+    pub title: String,
+    pub pub_date: NaiveDateTime,
+    pub duration: i32,
+}
+
+impl KnownEpisode {
+    fn published_within(&self, pub_date: NaiveDateTime, window_secs: i64) -> bool {
+        (self.pub_date - pub_date).num_seconds().abs() <= window_secs
+    }
+
+    fn same_length(&self, duration: i32) -> bool {
+        if self.duration <= 0 || duration <= 0 {
+            return false;
+        }
+        let longest = self.duration.max(duration) as f64;
+        ((self.duration - duration).abs() as f64) <= longest * SAME_LENGTH_TOLERANCE
+    }
+}
+
+/// Matches parsed feed items to stored episodes during a refresh.
+///
+/// The feed GUID is authoritative. Rows without one (stored before GUIDs were kept)
+/// or whose GUID the feed no longer lists fall back to enclosure URL, title, and
+/// finally publish date plus duration, so a re-hosted or retitled episode updates
+/// in place instead of showing up twice.
+pub struct EpisodeMatcher {
+    known: Vec<KnownEpisode>,
+    feed_guids: HashSet<String>,
+    claimed: HashSet<i32>,
+    // Rows some feed item matches exactly; heuristics leave them to that item
+    reserved: HashSet<i32>,
+}
+
+impl EpisodeMatcher {
+    pub fn new(known: Vec<KnownEpisode>, feed: &[EpisodeData]) -> Self {
+        let feed_guids = feed.iter().filter_map(|e| e.guid.clone()).collect();
+        let mut matcher = EpisodeMatcher { known, feed_guids, claimed: HashSet::new(), reserved: HashSet::new() };
+        matcher.reserved = feed.iter().filter_map(|e| matcher.exact_match(e).map(|k| k.id)).collect();
+        matcher
+    }
+
+    /// Stored episode this feed item corresponds to, if any
+    pub fn find(&mut self, episode: &EpisodeData) -> Option<i32> {
+        let pub_date = episode.pub_date.naive_utc();
+        let found = self
+            .exact_match(episode)
+            .or_else(|| {
+                self.fallback_candidates().find(|k| {
+                    !self.reserved.contains(&k.id)
+                        && ((k.title == episode.title && k.published_within(pub_date, SAME_TITLE_WINDOW_SECS))
+                            || (k.published_within(pub_date, SAME_LENGTH_WINDOW_SECS) && k.same_length(episode.duration)))
+                })
+            })
+            .map(|k| k.id);
+
+        if let Some(id) = found {
+            self.claimed.insert(id);
+        }
+        found
+    }
+
+    fn exact_match(&self, episode: &EpisodeData) -> Option<&KnownEpisode> {
+        if let Some(guid) = &episode.guid {
+            if let Some(known) = self.known.iter().find(|k| k.guid.as_ref() == Some(guid)) {
+                return Some(known);
+            }
+        }
+
+        let mut by_title = None;
+        for known in self.fallback_candidates() {
+            if !episode.url.is_empty() && known.url == episode.url {
+                return Some(known);
+            }
+            // Titles get reused, so only trust them for rows stored before GUIDs were kept
+            if by_title.is_none() && known.guid.is_none() && known.title == episode.title {
+                by_title = Some(known);
+            }
+        }
+        by_title
+    }
+
+    // Rows a non-GUID match may take: not matched yet this refresh, and not owned by
+    // a GUID the feed still lists
+    fn fallback_candidates(&self) -> impl Iterator<Item = &KnownEpisode> {
+        self.known.iter().filter(|k| {
+            !self.claimed.contains(&k.id) && k.guid.as_ref().is_none_or(|g| !self.feed_guids.contains(g))
+        })
+    }
+
+    /// Track a row inserted during this refresh so later copies of the item update it
+    pub fn remember(&mut self, id: i32, episode: &EpisodeData) {
+        self.known.push(KnownEpisode {
+            id,
+            guid: episode.guid.clone(),
+            url: episode.url.clone(),
+            title: episode.title.clone(),
+            pub_date: episode.pub_date.naive_utc(),
+            duration: episode.duration,
+        });
+    }
+}
+
+/// Stored rows that are the same episode; `duplicates` get merged into `survivor`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub survivor: i32,
+    pub duplicates: Vec<i32>,
+}
+
+/// Group a podcast's stored episodes that duplicate each other: same GUID, same
+/// enclosure URL, or same title published within a few hours. Rows carrying two
+/// different GUIDs are never grouped, since the feed says they are distinct.
+pub fn find_duplicate_groups(episodes: &[KnownEpisode]) -> Vec<DuplicateGroup> {
+    let mut order: Vec<usize> = (0..episodes.len()).collect();
+    order.sort_by_key(|&i| episodes[i].id);
+
+    let mut groups = DisjointGroups::new(episodes);
+    let mut by_guid: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut by_url: HashMap<&str, Vec<usize>> = HashMap::new();
+    let mut by_title: HashMap<&str, Vec<usize>> = HashMap::new();
+    for &i in &order {
+        let episode = &episodes[i];
+        if let Some(guid) = &episode.guid {
+            by_guid.entry(guid).or_default().push(i);
+        }
+        if !episode.url.is_empty() {
+            by_url.entry(&episode.url).or_default().push(i);
+        }
+        by_title.entry(&episode.title).or_default().push(i);
+    }
+
+    for bucket in by_guid.values().chain(by_url.values()) {
+        for (n, &i) in bucket.iter().enumerate().skip(1) {
+            for &j in &bucket[..n] {
+                groups.union(j, i);
+            }
+        }
+    }
+    for bucket in by_title.values() {
+        for (n, &i) in bucket.iter().enumerate().skip(1) {
+            for &j in &bucket[..n] {
+                if episodes[j].published_within(episodes[i].pub_date, SAME_TITLE_WINDOW_SECS) {
+                    groups.union(j, i);
+                }
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for &i in &order {
+        members.entry(groups.find(i)).or_default().push(i);
+    }
+
+    let mut result: Vec<DuplicateGroup> = members
+        .into_values()
+        .filter(|m| m.len() > 1)
+        .map(|m| {
+            // Keep the oldest row the feed can still match by GUID, else the oldest row
+            let survivor = m
+                .iter()
+                .copied()
+                .find(|&i| episodes[i].guid.is_some())
+                .unwrap_or(m[0]);
+            DuplicateGroup {
+                survivor: episodes[survivor].id,
+                duplicates: m.iter().filter(|&&i| i != survivor).map(|&i| episodes[i].id).collect(),
+            }
+        })
+        .collect();
+    result.sort_by_key(|g| g.survivor);
+    result
+}
+
+// Union-find over episode indexes that refuses to join groups holding different GUIDs
+struct DisjointGroups<'a> {
+    parent: Vec<usize>,
+    guid: Vec<Option<&'a str>>,
+}
+
+impl<'a> DisjointGroups<'a> {
+    fn new(episodes: &'a [KnownEpisode]) -> Self {
+        DisjointGroups {
+            parent: (0..episodes.len()).collect(),
+            guid: episodes.iter().map(|e| e.guid.as_deref()).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra == rb {
+            return;
+        }
+        let guid = match (self.guid[ra], self.guid[rb]) {
+            (Some(x), Some(y)) if x != y => return,
+            (x, y) => x.or(y),
+        };
+        self.parent[rb] = ra;
+        self.guid[ra] = guid;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn at(hours: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_700_000_000, 0).unwrap() + Duration::hours(hours)
+    }
+
+    fn item(guid: Option<&str>, url: &str, title: &str, hours: i64, duration: i32) -> EpisodeData {
+        EpisodeData {
+            title: title.to_string(),
+            description: String::new(),
+            url: url.to_string(),
+            artwork_url: String::new(),
+            pub_date: at(hours),
+            duration,
+            guid: guid.map(str::to_string),
+        }
+    }
+
+    fn known(id: i32, guid: Option<&str>, url: &str, title: &str, hours: i64, duration: i32) -> KnownEpisode {
+        KnownEpisode {
+            id,
+            guid: guid.map(str::to_string),
+            url: url.to_string(),
+            title: title.to_string(),
+            pub_date: at(hours).naive_utc(),
+            duration,
+        }
+    }
+
+    #[test]
+    fn guids_are_trimmed_and_long_ones_hashed() {
+        assert_eq!(normalize_guid("  abc-123 \n").as_deref(), Some("abc-123"));
+        assert_eq!(normalize_guid(" \t"), None);
+        let long = "g".repeat(MAX_GUID_LEN + 1);
+        let hashed = normalize_guid(&long).unwrap();
+        assert_eq!(hashed.len(), 64);
+        assert_eq!(normalize_guid(&long).unwrap(), hashed);
+        assert_eq!(normalize_guid(&"g".repeat(MAX_GUID_LEN)).unwrap().len(), MAX_GUID_LEN);
+    }
+
+    #[test]
+    fn guids_shared_by_different_items_are_dropped() {
+        let mut feed = vec![
+            item(Some("https://show.test/"), "https://cdn.test/1.mp3", "One", 0, 60),
+            item(Some("https://show.test/"), "https://cdn.test/2.mp3", "Two", 24, 60),
+            item(Some("ep-3"), "https://cdn.test/3.mp3", "Three", 48, 60),
+            item(Some("ep-3"), "https://cdn.test/3.mp3", "Three", 48, 60),
+        ];
+        drop_ambiguous_guids(&mut feed);
+        let guids: Vec<Option<&str>> = feed.iter().map(|e| e.guid.as_deref()).collect();
+        assert_eq!(guids, vec![None, None, Some("ep-3"), Some("ep-3")]);
+    }
+
+    #[test]
+    fn guid_matches_win_over_urls() {
+        let stored = vec![
+            known(1, Some("ep-1"), "https://cdn.test/a.mp3", "One", 0, 60),
+            known(2, None, "https://cdn.test/b.mp3", "Two", 24, 60),
+        ];
+        let feed = vec![item(Some("ep-1"), "https://cdn.test/b.mp3", "One", 0, 60)];
+        let mut matcher = EpisodeMatcher::new(stored, &feed);
+        assert_eq!(matcher.find(&feed[0]), Some(1));
+    }
+
+    #[test]
+    fn rows_stored_before_guids_match_by_url_then_title() {
+        let stored = vec![
+            known(1, None, "https://cdn.test/1.mp3", "One", 0, 60),
+            known(2, None, "https://old.test/2.mp3", "Two", 24, 60),
+        ];
+        let feed = vec![
+            item(Some("ep-1"), "https://cdn.test/1.mp3", "One (remastered)", 0, 60),
+            item(Some("ep-2"), "https://new.test/2.mp3", "Two", 500, 60),
+            item(Some("ep-3"), "https://new.test/3.mp3", "Three", 48, 60),
+        ];
+        let mut matcher = EpisodeMatcher::new(stored, &feed);
+        assert_eq!(matcher.find(&feed[0]), Some(1));
+        assert_eq!(matcher.find(&feed[1]), Some(2));
+        assert_eq!(matcher.find(&feed[2]), None);
+    }
+
+    #[test]
+    fn titles_are_not_trusted_for_rows_with_guids() {
+        // A yearly "Trailer" re-uses its title; the stored row's GUID is gone from the feed
+        let stored = vec![known(1, Some("trailer-2023"), "https://cdn.test/t23.mp3", "Trailer", 0, 60)];
+        let feed = vec![item(Some("trailer-2024"), "https://cdn.test/t24.mp3", "Trailer", 24 * 365, 90)];
+        let mut matcher = EpisodeMatcher::new(stored, &feed);
+        assert_eq!(matcher.find(&feed[0]), None);
+    }
+
+    #[test]
+    fn reposts_match_by_title_within_hours() {
+        let stored = vec![known(1, Some("old-guid"), "https://cdn.test/1.mp3", "Big News", 0, 60)];
+        let soon = vec![item(Some("new-guid"), "https://host.test/1.mp3", "Big News", 6, 61)];
+        assert_eq!(EpisodeMatcher::new(stored.clone(), &soon).find(&soon[0]), Some(1));
+        let later = vec![item(Some("new-guid"), "https://host.test/1.mp3", "Big News", 48, 61)];
+        assert_eq!(EpisodeMatcher::new(stored, &later).find(&later[0]), None);
+    }
+
+    #[test]
+    fn retitled_episodes_match_by_date_and_length() {
+        let stored = vec![known(1, Some("old-guid"), "https://cdn.test/1.mp3", "Episode 12", 0, 3600)];
+        let retitled = vec![item(Some("new-guid"), "https://host.test/1.mp3", "12: The Sequel", 1, 3630)];
+        assert_eq!(EpisodeMatcher::new(stored.clone(), &retitled).find(&retitled[0]), Some(1));
+        let longer = vec![item(Some("new-guid"), "https://host.test/1.mp3", "12: The Sequel", 1, 3800)];
+        assert_eq!(EpisodeMatcher::new(stored.clone(), &longer).find(&longer[0]), None);
+        // Unknown lengths never count as the same
+        let stored = vec![known(1, Some("old-guid"), "https://cdn.test/1.mp3", "Episode 12", 0, 0)];
+        let unknown = vec![item(Some("new-guid"), "https://host.test/1.mp3", "12: The Sequel", 1, 0)];
+        assert_eq!(EpisodeMatcher::new(stored, &unknown).find(&unknown[0]), None);
+    }
+
+    #[test]
+    fn rows_an_item_matches_exactly_are_left_to_it() {
+        let stored = vec![known(1, None, "https://cdn.test/1.mp3", "Part 1", 0, 1800)];
+        let feed = vec![
+            // Published alongside with the same length, but a different episode
+            item(Some("part-2"), "https://cdn.test/2.mp3", "Part 2", 0, 1800),
+            item(Some("part-1"), "https://cdn.test/1.mp3", "Part 1", 0, 1800),
+        ];
+        let mut matcher = EpisodeMatcher::new(stored, &feed);
+        assert_eq!(matcher.find(&feed[0]), None);
+        assert_eq!(matcher.find(&feed[1]), Some(1));
+    }
+
+    #[test]
+    fn rows_are_claimed_once_and_new_rows_are_remembered() {
+        let stored = vec![known(1, None, "https://cdn.test/1.mp3", "One", 0, 60)];
+        let feed = vec![
+            item(None, "https://cdn.test/1.mp3", "One", 0, 60),
+            item(None, "https://cdn.test/1.mp3", "One", 0, 60),
+            item(None, "https://cdn.test/2.mp3", "Two", 72, 60),
+            item(None, "https://cdn.test/2.mp3", "Two", 72, 60),
+        ];
+        let mut matcher = EpisodeMatcher::new(stored, &feed);
+        assert_eq!(matcher.find(&feed[0]), Some(1));
+        assert_eq!(matcher.find(&feed[1]), None);
+        assert_eq!(matcher.find(&feed[2]), None);
+        matcher.remember(7, &feed[2]);
+        assert_eq!(matcher.find(&feed[3]), Some(7));
+    }
+
+    #[test]
+    fn duplicates_group_by_guid_url_or_nearby_title() {
+        let stored = vec![
+            known(5, Some("ep-1"), "https://cdn.test/1b.mp3", "One", 1, 60),
+            known(2, None, "https://cdn.test/1.mp3", "One", 0, 60),
+            known(3, Some("ep-1"), "https://cdn.test/1.mp3", "One", 0, 60),
+            known(4, None, "https://cdn.test/2.mp3", "Two", 24, 60),
+            known(6, None, "https://cdn.test/2-repost.mp3", "Two", 30, 60),
+            known(7, None, "https://cdn.test/2-anniversary.mp3", "Two", 24 * 365, 60),
+            known(8, None, "https://cdn.test/3.mp3", "Three", 48, 60),
+        ];
+        assert_eq!(find_duplicate_groups(&stored), vec![
+            // The oldest row with a GUID survives so the feed keeps matching it
+            DuplicateGroup { survivor: 3, duplicates: vec![2, 5] },
+            DuplicateGroup { survivor: 4, duplicates: vec![6] },
+        ]);
+    }
+
+    #[test]
+    fn rows_with_different_guids_are_never_grouped() {
+        let stored = vec![
+            known(1, Some("ep-1"), "https://cdn.test/same.mp3", "Same", 0, 60),
+            known(2, Some("ep-2"), "https://cdn.test/same.mp3", "Same", 0, 60),
+            // Would bridge the two through its URL and title
+            known(3, None, "https://cdn.test/same.mp3", "Same", 0, 60),
+        ];
+        let groups = find_duplicate_groups(&stored);
+        assert_eq!(groups, vec![DuplicateGroup { survivor: 1, duplicates: vec![3] }]);
+        assert!(find_duplicate_groups(&[]).is_empty());
+    }
+}
@@ -0,0 +1,386 @@
+use regex::Regex;
+use reqwest::{header, redirect, RequestBuilder, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::{Arc, Mutex};
+use crate::error::{AppError, AppResult};
+
+// Same hop limit as reqwest's default redirect policy
+const MAX_REDIRECTS: usize = 10;
+const STATUS_ERROR_PREFIX: &str = "Feed request failed: HTTP ";
+
+/// Cache validators remembered from the last successful fetch of a feed
+#[derive(Debug, Clone, Default)]
+pub struct FeedValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_hash: Option<String>,
+}
+
+/// Result of one feed request. `body` is None when the publisher answered 304.
+#[derive(Debug, Clone)]
+pub struct FetchedFeed {
+    pub status: u16,
+    pub body: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Where an unbroken chain of 301/308 redirects led, if the feed has moved
+    pub permanent_redirect: Option<String>,
+}
+
+impl FetchedFeed {
+    pub fn not_modified(&self) -> bool {
+        self.status == StatusCode::NOT_MODIFIED.as_u16()
+    }
+}
+
+/// What happened on a refresh, stored in FeedFetchState
+#[derive(Debug, Clone)]
+pub struct FeedFetchRecord {
+    pub status: Option<u16>,
+    pub duration_ms: i64,
+    pub bytes: i64,
+    pub changed: bool,
+    pub error: Option<String>,
+    pub error_kind: Option<FeedErrorKind>,
+}
+
+/// Broad reason a refresh failed, shown to users in the feed health list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedErrorKind {
+    /// The server answered with an error status (404, 410, 500...)
+    HttpStatus,
+    /// DNS, TLS or connection failure
+    Network,
+    Timeout,
+    /// The response was not a feed we could parse
+    InvalidFeed,
+}
+
+impl FeedErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FeedErrorKind::HttpStatus => "http_status",
+            FeedErrorKind::Network => "network",
+            FeedErrorKind::Timeout => "timeout",
+            FeedErrorKind::InvalidFeed => "invalid_feed",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "http_status" => Some(FeedErrorKind::HttpStatus),
+            "network" => Some(FeedErrorKind::Network),
+            "timeout" => Some(FeedErrorKind::Timeout),
+            "invalid_feed" => Some(FeedErrorKind::InvalidFeed),
+            _ => None,
+        }
+    }
+
+    /// Classify a fetch error, along with the HTTP status when there was one
+    pub fn classify(error: &AppError) -> (Self, Option<u16>) {
+        match error {
+            AppError::Http(e) if e.is_timeout() => (FeedErrorKind::Timeout, None),
+            AppError::Http(e) => match e.status() {
+                Some(status) => (FeedErrorKind::HttpStatus, Some(status.as_u16())),
+                None => (FeedErrorKind::Network, None),
+            },
+            other => match status_from_error(other) {
+                Some(status) => (FeedErrorKind::HttpStatus, Some(status)),
+                None => (FeedErrorKind::Network, None),
+            },
+        }
+    }
+}
+
+/// Error for a feed request that ended in a non-success status
+pub fn status_error(status: StatusCode) -> AppError {
+    AppError::bad_request(format!("{}{}", STATUS_ERROR_PREFIX, status))
+}
+
+fn status_from_error(error: &AppError) -> Option<u16> {
+    let message = error.to_string();
+    let rest = &message[message.find(STATUS_ERROR_PREFIX)? + STATUS_ERROR_PREFIX.len()..];
+    rest.split_whitespace().next()?.parse().ok()
+}
+
+/// Redirect policy that follows like reqwest's default while remembering where an
+/// unbroken chain of permanent redirects from the requested URL ends up
+#[derive(Debug, Clone, Default)]
+pub struct RedirectTracker {
+    state: Arc<Mutex<RedirectState>>,
+}
+
+#[derive(Debug, Default)]
+struct RedirectState {
+    permanent_target: Option<String>,
+    saw_temporary: bool,
+}
+
+impl RedirectTracker {
+    pub fn policy(&self) -> redirect::Policy {
+        let state = self.state.clone();
+        redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > MAX_REDIRECTS {
+                return attempt.error("too many redirects");
+            }
+            let permanent = matches!(attempt.status(), StatusCode::MOVED_PERMANENTLY | StatusCode::PERMANENT_REDIRECT);
+            if let Ok(mut state) = state.lock() {
+                if !permanent {
+                    state.saw_temporary = true;
+                } else if !state.saw_temporary {
+                    state.permanent_target = Some(attempt.url().to_string());
+                }
+            }
+            attempt.follow()
+        })
+    }
+
+    pub fn permanent_target(&self) -> Option<String> {
+        self.state.lock().ok().and_then(|state| state.permanent_target.clone())
+    }
+}
+
+/// `<itunes:new-feed-url>` from the channel header, used when a show moves hosts
+pub fn new_feed_url(body: &str) -> Option<String> {
+    let header = match body.find("<item") {
+        Some(pos) => &body[..pos],
+        None => body,
+    };
+    let tag = Regex::new(r"(?s)<itunes:new-feed-url>\s*(?:<!\[CDATA\[)?\s*([^<\]\s]+)\s*(?:\]\]>)?\s*</itunes:new-feed-url>").unwrap();
+    let url = tag.captures(header)?[1].replace("&amp;", "&");
+    url::Url::parse(&url)
+        .ok()
+        .filter(|u| matches!(u.scheme(), "http" | "https"))
+        .map(|_| url)
+}
+
+/// Add If-None-Match / If-Modified-Since when we have validators from a previous fetch
+pub fn apply_conditional_headers(mut request: RequestBuilder, validators: Option<&FeedValidators>) -> RequestBuilder {
+    if let Some(validators) = validators {
+        if let Some(etag) = validators.etag.as_deref().filter(|v| !v.is_empty()) {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = validators.last_modified.as_deref().filter(|v| !v.is_empty()) {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified);
+        }
+    }
+    request
+}
+
+/// Read the body (unless 304) and the validators the publisher sent back
+pub async fn read_feed_response(response: Response) -> AppResult<FetchedFeed> {
+    let status = response.status();
+    let header_value = |name: header::HeaderName| {
+        response.headers().get(name).and_then(|v| v.to_str().ok()).map(|v| v.to_string())
+    };
+    let etag = header_value(header::ETAG);
+    let last_modified = header_value(header::LAST_MODIFIED);
+
+    let body = if status == StatusCode::NOT_MODIFIED {
+        None
+    } else {
+        Some(response.text().await.map_err(AppError::Http)?)
+    };
+
+    Ok(FetchedFeed {
+        status: status.as_u16(),
+        body,
+        etag,
+        last_modified,
+        permanent_redirect: None,
+    })
+}
+
+/// SHA-256 of the feed with build timestamps and comments removed, so feeds that
+/// regenerate `<lastBuildDate>` on every request still compare as unchanged
+pub fn content_hash(body: &str) -> String {
+    let volatile = Regex::new(r"(?s)<!--.*?-->|<lastBuildDate>.*?</lastBuildDate>").unwrap();
+    let normalized = volatile.replace_all(body, "");
+    format!("{:x}", Sha256::digest(normalized.trim().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FEED: &str = "<rss><channel><title>Show</title><lastBuildDate>Mon, 06 Oct 2025 10:00:00 GMT</lastBuildDate>\
+        <!-- generated in 12ms --><item><guid>ep-1</guid></item></channel></rss>";
+
+    fn validators(etag: Option<&str>, last_modified: Option<&str>) -> FeedValidators {
+        FeedValidators {
+            etag: etag.map(str::to_string),
+            last_modified: last_modified.map(str::to_string),
+            content_hash: None,
+        }
+    }
+
+    fn request_headers(validators: Option<&FeedValidators>) -> reqwest::header::HeaderMap {
+        let request = reqwest::Client::new().get("https://feeds.example.com/show.xml");
+        apply_conditional_headers(request, validators).build().unwrap().headers().clone()
+    }
+
+    #[test]
+    fn regenerated_build_dates_and_comments_do_not_change_the_hash() {
+        let rebuilt = FEED
+            .replace("Mon, 06 Oct 2025 10:00:00 GMT", "Mon, 06 Oct 2025 11:00:00 GMT")
+            .replace("12ms", "9ms");
+        assert_eq!(content_hash(FEED), content_hash(&rebuilt));
+        assert_eq!(content_hash(FEED), content_hash(&format!("\n{}\n", FEED)));
+        assert_eq!(content_hash(FEED).len(), 64);
+    }
+
+    #[test]
+    fn new_episodes_change_the_hash() {
+        let updated = FEED.replace("</channel>", "<item><guid>ep-2</guid></item></channel>");
+        assert_ne!(content_hash(FEED), content_hash(&updated));
+    }
+
+    #[test]
+    fn validators_become_conditional_headers() {
+        let headers = request_headers(Some(&validators(Some("\"abc\""), Some("Mon, 06 Oct 2025 10:00:00 GMT"))));
+        assert_eq!(headers[header::IF_NONE_MATCH], "\"abc\"");
+        assert_eq!(headers[header::IF_MODIFIED_SINCE], "Mon, 06 Oct 2025 10:00:00 GMT");
+
+        let headers = request_headers(Some(&validators(Some(""), None)));
+        assert!(!headers.contains_key(header::IF_NONE_MATCH));
+        assert!(!headers.contains_key(header::IF_MODIFIED_SINCE));
+        assert!(request_headers(None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn responses_keep_their_validators_and_skip_bodies_when_unchanged() {
+        use axum::{http::HeaderMap, routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/feed.xml", listener.local_addr().unwrap());
+        let app = Router::new().route("/feed.xml", get(|headers: HeaderMap| async move {
+            let validators = [(header::ETAG, "\"v2\""), (header::LAST_MODIFIED, "Tue, 07 Oct 2025 08:00:00 GMT")];
+            if headers.get(header::IF_NONE_MATCH).is_some_and(|v| v == "\"v2\"") {
+                (StatusCode::NOT_MODIFIED, validators, String::new())
+            } else {
+                (StatusCode::OK, validators, FEED.to_string())
+            }
+        }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let fresh = read_feed_response(apply_conditional_headers(client.get(&url), None).send().await.unwrap()).await.unwrap();
+        assert!(!fresh.not_modified());
+        assert_eq!(fresh.body.as_deref(), Some(FEED));
+        assert_eq!(fresh.etag.as_deref(), Some("\"v2\""));
+
+        let known = validators(fresh.etag.as_deref(), fresh.last_modified.as_deref());
+        let unchanged = read_feed_response(apply_conditional_headers(client.get(&url), Some(&known)).send().await.unwrap()).await.unwrap();
+        assert!(unchanged.not_modified());
+        assert_eq!(unchanged.body, None);
+        assert_eq!(unchanged.last_modified.as_deref(), Some("Tue, 07 Oct 2025 08:00:00 GMT"));
+    }
+
+    #[test]
+    fn error_kinds_round_trip() {
+        for kind in [FeedErrorKind::HttpStatus, FeedErrorKind::Network, FeedErrorKind::Timeout, FeedErrorKind::InvalidFeed] {
+            assert_eq!(FeedErrorKind::parse(kind.as_str()), Some(kind));
+        }
+        assert_eq!(FeedErrorKind::parse("HTTP_STATUS"), None);
+    }
+
+    #[test]
+    fn status_errors_keep_their_status() {
+        let error = status_error(StatusCode::GONE);
+        assert_eq!(FeedErrorKind::classify(&error), (FeedErrorKind::HttpStatus, Some(410)));
+        assert_eq!(
+            FeedErrorKind::classify(&AppError::bad_request("Feed has no channel")),
+            (FeedErrorKind::Network, None)
+        );
+    }
+
+    #[tokio::test]
+    async fn request_failures_are_classified() {
+        use axum::{routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/missing", get(|| async { StatusCode::NOT_FOUND }))
+            .route("/slow", get(|| async {
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                "late"
+            }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = reqwest::Client::new();
+        let missing = client.get(format!("http://{}/missing", addr)).send().await.unwrap().error_for_status().unwrap_err();
+        assert_eq!(FeedErrorKind::classify(&AppError::Http(missing)), (FeedErrorKind::HttpStatus, Some(404)));
+
+        let slow = client
+            .get(format!("http://{}/slow", addr))
+            .timeout(std::time::Duration::from_millis(100))
+            .send()
+            .await
+            .unwrap_err();
+        assert_eq!(FeedErrorKind::classify(&AppError::Http(slow)), (FeedErrorKind::Timeout, None));
+
+        let closed = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let closed_addr = closed.local_addr().unwrap();
+        drop(closed);
+        let refused = client.get(format!("http://{}/", closed_addr)).send().await.unwrap_err();
+        assert_eq!(FeedErrorKind::classify(&AppError::Http(refused)), (FeedErrorKind::Network, None));
+    }
+
+    #[tokio::test]
+    async fn only_unbroken_permanent_redirects_count_as_a_move() {
+        use axum::{response::Redirect, routing::get, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let app = Router::new()
+            .route("/old", get(|| async { Redirect::permanent("/older") }))
+            .route("/older", get(|| async {
+                (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, "/feed.xml")])
+            }))
+            .route("/temporary", get(|| async { Redirect::temporary("/old") }))
+            .route("/moved-then-cdn", get(|| async {
+                (StatusCode::MOVED_PERMANENTLY, [(header::LOCATION, "/cdn")])
+            }))
+            .route("/cdn", get(|| async { Redirect::to("/feed.xml") }))
+            .route("/feed.xml", get(|| async { FEED }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let fetch = |path: &'static str| {
+            let base = base.clone();
+            async move {
+                let tracker = RedirectTracker::default();
+                let client = reqwest::Client::builder().redirect(tracker.policy()).build().unwrap();
+                let response = client.get(format!("{}{}", base, path)).send().await.unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                tracker.permanent_target()
+            }
+        };
+
+        assert_eq!(fetch("/old").await, Some(format!("{}/feed.xml", base)));
+        assert_eq!(fetch("/temporary").await, None);
+        assert_eq!(fetch("/moved-then-cdn").await, Some(format!("{}/cdn", base)));
+        assert_eq!(fetch("/feed.xml").await, None);
+    }
+
+    #[test]
+    fn new_feed_url_is_read_from_the_channel_header() {
+        let feed = |tag: &str| format!("<rss><channel><title>Show</title>{}<item><guid>1</guid></item></channel></rss>", tag);
+        assert_eq!(
+            new_feed_url(&feed("<itunes:new-feed-url> https://new.example/feed?a=1&amp;b=2 </itunes:new-feed-url>")),
+            Some("https://new.example/feed?a=1&b=2".to_string())
+        );
+        assert_eq!(
+            new_feed_url(&feed("<itunes:new-feed-url><![CDATA[https://new.example/feed]]></itunes:new-feed-url>")),
+            Some("https://new.example/feed".to_string())
+        );
+        assert_eq!(new_feed_url(&feed("<itunes:new-feed-url>ftp://new.example/feed</itunes:new-feed-url>")), None);
+        assert_eq!(new_feed_url(&feed("")), None);
+        assert_eq!(
+            new_feed_url("<channel><item><itunes:new-feed-url>https://new.example/</itunes:new-feed-url></item></channel>"),
+            None
+        );
+    }
+}
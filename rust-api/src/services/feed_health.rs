@@ -0,0 +1,77 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use crate::services::feed_fetch::FeedErrorKind;
+
+/// Consecutive failed refreshes before a feed counts as failing rather than degraded
+pub const FAILING_AFTER_FAILURES: i32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedHealthStatus {
+    Healthy,
+    /// Recent refreshes failed, but it may be a temporary outage
+    Degraded,
+    /// Refreshes keep failing; the feed likely needs fixing or removing
+    Failing,
+    /// The feed moved and we couldn't follow it automatically
+    Moved,
+}
+
+impl FeedHealthStatus {
+    pub fn classify(consecutive_failures: i32, last_error: Option<&str>, unresolved_move: bool) -> Self {
+        if unresolved_move {
+            FeedHealthStatus::Moved
+        } else if consecutive_failures >= FAILING_AFTER_FAILURES {
+            FeedHealthStatus::Failing
+        } else if consecutive_failures > 0 || last_error.is_some() {
+            FeedHealthStatus::Degraded
+        } else {
+            FeedHealthStatus::Healthy
+        }
+    }
+}
+
+/// Health of one subscription, as listed by `/feed_health`
+#[derive(Debug, Clone, Serialize)]
+pub struct FeedHealth {
+    pub podcast_id: i32,
+    pub podcast_name: String,
+    pub feed_url: String,
+    pub status: FeedHealthStatus,
+    pub consecutive_failures: i32,
+    pub last_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub last_error_kind: Option<FeedErrorKind>,
+    pub last_fetched_at: Option<NaiveDateTime>,
+    pub last_success_at: Option<NaiveDateTime>,
+    pub last_failure_at: Option<NaiveDateTime>,
+    /// Where the feed says it moved to, while that differs from `feed_url`
+    pub moved_to_url: Option<String>,
+    pub previous_feed_url: Option<String>,
+    pub feed_url_changed_at: Option<NaiveDateTime>,
+}
+
+impl FeedHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.status == FeedHealthStatus::Healthy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_follows_the_failure_count() {
+        assert_eq!(FeedHealthStatus::classify(0, None, false), FeedHealthStatus::Healthy);
+        assert_eq!(FeedHealthStatus::classify(0, Some("HTTP 500"), false), FeedHealthStatus::Degraded);
+        assert_eq!(FeedHealthStatus::classify(FAILING_AFTER_FAILURES - 1, Some("HTTP 500"), false), FeedHealthStatus::Degraded);
+        assert_eq!(FeedHealthStatus::classify(FAILING_AFTER_FAILURES, Some("HTTP 500"), false), FeedHealthStatus::Failing);
+    }
+
+    #[test]
+    fn unresolved_moves_win_over_failures() {
+        assert_eq!(FeedHealthStatus::classify(0, None, true), FeedHealthStatus::Moved);
+        assert_eq!(FeedHealthStatus::classify(10, Some("HTTP 410"), true), FeedHealthStatus::Moved);
+    }
+}
@@ -0,0 +1,618 @@
+use ldap3::{dn_escape, ldap_escape, Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry};
+use std::time::Duration;
+use crate::{
+    config::LdapConfig,
+    error::{AppError, AppResult},
+};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+// LDAP result code for a wrong password or unknown bind DN
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// A directory user whose password checked out
+#[derive(Debug, Clone)]
+pub struct LdapIdentity {
+    pub dn: String,
+    pub username: String,
+    pub email: Option<String>,
+    pub fullname: String,
+    /// Whether the user is in the admin group; None when no admin group is configured
+    pub is_admin: Option<bool>,
+}
+
+async fn connect(config: &LdapConfig) -> AppResult<Ldap> {
+    let settings = LdapConnSettings::new()
+        .set_conn_timeout(CONNECT_TIMEOUT)
+        .set_starttls(config.starttls)
+        .set_no_tls_verify(config.tls_skip_verify);
+    let (conn, ldap) = LdapConnAsync::with_settings(settings, &config.url)
+        .await
+        .map_err(|e| AppError::external_error(format!("Failed to connect to LDAP server: {}", e)))?;
+    tokio::spawn(async move {
+        if let Err(e) = conn.drive().await {
+            tracing::warn!("LDAP connection error: {}", e);
+        }
+    });
+    Ok(ldap)
+}
+
+// Bind as `dn`; false when the directory rejects the credentials
+async fn bind(ldap: &mut Ldap, dn: &str, password: &str) -> AppResult<bool> {
+    let result = ldap.simple_bind(dn, password)
+        .await
+        .map_err(|e| AppError::external_error(format!("LDAP bind failed: {}", e)))?;
+    if result.rc == INVALID_CREDENTIALS {
+        return Ok(false);
+    }
+    result.success().map_err(|e| AppError::external_error(format!("LDAP bind failed: {}", e)))?;
+    Ok(true)
+}
+
+async fn search(ldap: &mut Ldap, base: &str, scope: Scope, filter: &str, attrs: &[&str]) -> AppResult<Vec<SearchEntry>> {
+    let (entries, _) = ldap.search(base, scope, filter, attrs.to_vec())
+        .await
+        .and_then(|result| result.success())
+        .map_err(|e| AppError::external_error(format!("LDAP search failed: {}", e)))?;
+    Ok(entries.into_iter().map(SearchEntry::construct).collect())
+}
+
+// Attribute names are case-insensitive, and servers don't all echo the requested case
+fn attr_values<'a>(entry: &'a SearchEntry, name: &str) -> &'a [String] {
+    entry.attrs.iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, values)| values.as_slice())
+        .unwrap_or(&[])
+}
+
+fn first_attr(entry: &SearchEntry, name: &str) -> Option<String> {
+    attr_values(entry, name).iter()
+        .map(|value| value.trim())
+        .find(|value| !value.is_empty())
+        .map(str::to_string)
+}
+
+fn normalize_dn(dn: &str) -> String {
+    dn.split(',').map(|rdn| rdn.trim().to_lowercase()).collect::<Vec<_>>().join(",")
+}
+
+/// Whether `groups` (group DNs) include `wanted`, given either as a full DN or just the group's name
+pub fn in_group(groups: &[String], wanted: &str) -> bool {
+    if wanted.contains('=') {
+        let wanted = normalize_dn(wanted);
+        groups.iter().any(|group| normalize_dn(group) == wanted)
+    } else {
+        groups.iter().any(|group| {
+            group.split(',').next()
+                .and_then(|rdn| rdn.split_once('='))
+                .is_some_and(|(_, name)| name.trim().eq_ignore_ascii_case(wanted.trim()))
+        })
+    }
+}
+
+// Groups the user belongs to: the group attribute on their entry, plus groups under
+// the group search base that list them as a member
+async fn find_groups(ldap: &mut Ldap, config: &LdapConfig, entry: &SearchEntry, username: &str) -> AppResult<Vec<String>> {
+    let mut groups = attr_values(entry, &config.group_attribute).to_vec();
+    if let Some(base) = &config.group_search_base {
+        let filter = format!(
+            "(|(member={dn})(uniqueMember={dn})(memberUid={username}))",
+            dn = ldap_escape(entry.dn.as_str()),
+            username = ldap_escape(username),
+        );
+        groups.extend(search(ldap, base, Scope::Subtree, &filter, &["cn"]).await?.into_iter().map(|group| group.dn));
+    }
+    Ok(groups)
+}
+
+// Find the user's entry: at the templated DN after binding as it, or by searching as the
+// service account. Returns None when there is no such user or the password is wrong.
+async fn find_user(ldap: &mut Ldap, config: &LdapConfig, username: &str, password: &str, attrs: &[&str]) -> AppResult<Option<SearchEntry>> {
+    if let Some(template) = &config.user_dn_template {
+        let dn = template.replace("{username}", &dn_escape(username));
+        if !bind(ldap, &dn, password).await? {
+            return Ok(None);
+        }
+        return Ok(search(ldap, &dn, Scope::Base, "(objectClass=*)", attrs).await?.pop());
+    }
+
+    let bound = match &config.bind_dn {
+        Some(bind_dn) => bind(ldap, bind_dn, config.bind_password.as_deref().unwrap_or("")).await?,
+        None => true,
+    };
+    if !bound {
+        return Err(AppError::external_error("LDAP service account credentials were rejected"));
+    }
+    let base = config.search_base.as_deref().unwrap_or_default();
+    let filter = config.user_filter.replace("{username}", &ldap_escape(username));
+    let mut entries = search(ldap, base, Scope::Subtree, &filter, attrs).await?;
+    if entries.len() != 1 {
+        if entries.len() > 1 {
+            tracing::warn!("LDAP: {} entries match the login filter for one user, refusing to pick one", entries.len());
+        }
+        return Ok(None);
+    }
+    let entry = entries.remove(0);
+    if !bind(ldap, &entry.dn, password).await? {
+        return Ok(None);
+    }
+    // Groups are read as the service account, which usually sees more than the user can
+    if let Some(bind_dn) = &config.bind_dn {
+        bind(ldap, bind_dn, config.bind_password.as_deref().unwrap_or("")).await?;
+    }
+    Ok(Some(entry))
+}
+
+/// Check a username and password against the directory. Returns None when the credentials
+/// are wrong, the user can't be found, or they aren't in the required user group.
+pub async fn authenticate(config: &LdapConfig, username: &str, password: &str) -> AppResult<Option<LdapIdentity>> {
+    // An empty password is an unauthenticated bind, which most servers accept for any DN
+    if username.trim().is_empty() || password.is_empty() {
+        return Ok(None);
+    }
+
+    let attrs = [
+        config.username_attribute.as_str(),
+        config.email_attribute.as_str(),
+        config.name_attribute.as_str(),
+        config.group_attribute.as_str(),
+    ];
+    let mut ldap = connect(config).await?;
+    let result = async {
+        let Some(entry) = find_user(&mut ldap, config, username, password, &attrs).await? else {
+            return Ok(None);
+        };
+        let groups = if config.user_group.is_some() || config.admin_group.is_some() {
+            find_groups(&mut ldap, config, &entry, username).await?
+        } else {
+            Vec::new()
+        };
+
+        if let Some(user_group) = &config.user_group {
+            if !in_group(&groups, user_group) {
+                tracing::info!("LDAP: {} is not in the user group, refusing login", entry.dn);
+                return Ok(None);
+            }
+        }
+
+        let username = first_attr(&entry, &config.username_attribute).unwrap_or_else(|| username.to_string());
+        Ok(Some(LdapIdentity {
+            fullname: first_attr(&entry, &config.name_attribute).unwrap_or_else(|| username.clone()),
+            email: first_attr(&entry, &config.email_attribute),
+            is_admin: config.admin_group.as_deref().map(|admin_group| in_group(&groups, admin_group)),
+            username: username.to_lowercase(),
+            dn: entry.dn,
+        }))
+    }.await;
+    let _ = ldap.unbind().await;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    const PEOPLE: &str = "ou=people,dc=example,dc=com";
+    const GROUPS: &str = "ou=groups,dc=example,dc=com";
+    const READER: &str = "cn=reader,ou=services,dc=example,dc=com";
+
+    // An in-process stand-in for an OpenLDAP server, speaking just enough LDAPv3 for the
+    // login flows: simple binds, searches with and/or/not/equality/presence filters, unbind
+    struct Entry {
+        dn: &'static str,
+        password: Option<&'static str>,
+        attrs: Vec<(&'static str, Vec<&'static str>)>,
+    }
+
+    impl Entry {
+        fn values<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'static str> + 'a {
+            self.attrs.iter()
+                .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+                .flat_map(|(_, values)| values.iter().copied())
+        }
+    }
+
+    #[derive(Clone)]
+    struct Directory {
+        entries: Arc<Vec<Entry>>,
+        // (DN bound at the time, search base) of every search
+        searches: Arc<Mutex<Vec<(String, String)>>>,
+    }
+
+    fn directory() -> Vec<Entry> {
+        vec![
+            Entry {
+                dn: "uid=alice,ou=people,dc=example,dc=com",
+                password: Some("alice-pw"),
+                attrs: vec![
+                    ("objectClass", vec!["inetOrgPerson"]),
+                    ("uid", vec!["Alice"]),
+                    ("mail", vec!["alice@example.com"]),
+                    ("cn", vec!["Alice Liddell"]),
+                    ("memberOf", vec!["cn=podcasters,ou=groups,dc=example,dc=com"]),
+                ],
+            },
+            Entry {
+                dn: "uid=bob,ou=people,dc=example,dc=com",
+                password: Some("bob-pw"),
+                attrs: vec![
+                    ("objectClass", vec!["inetOrgPerson"]),
+                    ("uid", vec!["bob"]),
+                    ("mail", vec!["bob@example.com"]),
+                    ("cn", vec!["Bob"]),
+                ],
+            },
+            Entry {
+                dn: "uid=carol,ou=people,dc=example,dc=com",
+                password: Some("carol-pw"),
+                attrs: vec![("objectClass", vec!["inetOrgPerson"]), ("uid", vec!["carol"])],
+            },
+            Entry {
+                dn: "uid=dave,ou=people,dc=example,dc=com",
+                password: Some("dave-pw"),
+                attrs: vec![("objectClass", vec!["inetOrgPerson"]), ("uid", vec!["dave"])],
+            },
+            Entry {
+                dn: "uid=dave,ou=contractors,dc=example,dc=com",
+                password: Some("dave-pw"),
+                attrs: vec![("objectClass", vec!["inetOrgPerson"]), ("uid", vec!["dave"])],
+            },
+            Entry { dn: READER, password: Some("reader-pw"), attrs: vec![("cn", vec!["reader"])] },
+            Entry {
+                dn: "cn=podcasters,ou=groups,dc=example,dc=com",
+                password: None,
+                attrs: vec![
+                    ("objectClass", vec!["groupOfNames"]),
+                    ("cn", vec!["podcasters"]),
+                    ("member", vec!["uid=alice,ou=people,dc=example,dc=com"]),
+                ],
+            },
+            Entry {
+                dn: "cn=admins,ou=groups,dc=example,dc=com",
+                password: None,
+                attrs: vec![
+                    ("objectClass", vec!["groupOfUniqueNames"]),
+                    ("cn", vec!["admins"]),
+                    ("uniqueMember", vec!["uid=bob,ou=people,dc=example,dc=com"]),
+                ],
+            },
+            Entry {
+                dn: "cn=listeners,ou=groups,dc=example,dc=com",
+                password: None,
+                attrs: vec![
+                    ("objectClass", vec!["posixGroup"]),
+                    ("cn", vec!["listeners"]),
+                    ("memberUid", vec!["carol", "bob"]),
+                ],
+            },
+        ]
+    }
+
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let mut out = vec![tag];
+        if content.len() < 0x80 {
+            out.push(content.len() as u8);
+        } else {
+            let len = (content.len() as u32).to_be_bytes();
+            let skip = len.iter().take_while(|&&b| b == 0).count();
+            out.push(0x80 | (4 - skip) as u8);
+            out.extend_from_slice(&len[skip..]);
+        }
+        out.extend_from_slice(content);
+        out
+    }
+
+    // The (tag, content) elements packed one after another in `buf`
+    fn children(mut buf: &[u8]) -> Vec<(u8, &[u8])> {
+        let mut out = Vec::new();
+        while buf.len() >= 2 {
+            let tag = buf[0];
+            let (len, header) = if buf[1] & 0x80 == 0 {
+                (buf[1] as usize, 2)
+            } else {
+                let n = (buf[1] & 0x7f) as usize;
+                (buf[2..2 + n].iter().fold(0, |len, &b| (len << 8) | b as usize), 2 + n)
+            };
+            out.push((tag, &buf[header..header + len]));
+            buf = &buf[header + len..];
+        }
+        out
+    }
+
+    fn text(bytes: &[u8]) -> &str {
+        std::str::from_utf8(bytes).unwrap()
+    }
+
+    async fn read_message(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+        let tag = stream.read_u8().await?;
+        let first = stream.read_u8().await?;
+        let len = if first & 0x80 == 0 {
+            first as usize
+        } else {
+            let mut len = 0;
+            for _ in 0..(first & 0x7f) {
+                len = (len << 8) | stream.read_u8().await? as usize;
+            }
+            len
+        };
+        let mut content = vec![0; len];
+        stream.read_exact(&mut content).await?;
+        assert_eq!(tag, 0x30, "LDAPMessage is a SEQUENCE");
+        Ok(content)
+    }
+
+    fn ldap_result(tag: u8, rc: u8) -> Vec<u8> {
+        tlv(tag, &[tlv(0x0a, &[rc]), tlv(0x04, b""), tlv(0x04, b"")].concat())
+    }
+
+    fn matches(filter: (u8, &[u8]), entry: &Entry) -> bool {
+        match filter.0 {
+            0xa0 => children(filter.1).into_iter().all(|f| matches(f, entry)),
+            0xa1 => children(filter.1).into_iter().any(|f| matches(f, entry)),
+            0xa2 => !matches(children(filter.1)[0], entry),
+            0xa3 => {
+                let parts = children(filter.1);
+                let (name, value) = (text(parts[0].1), text(parts[1].1));
+                entry.values(name).any(|v| v.eq_ignore_ascii_case(value))
+            }
+            0x87 => {
+                let name = text(filter.1);
+                name.eq_ignore_ascii_case("objectClass") || entry.values(name).next().is_some()
+            }
+            other => panic!("filter tag {:#x} isn't supported by the stand-in", other),
+        }
+    }
+
+    fn in_scope(entry: &Entry, base: &str, scope: u8) -> bool {
+        let (dn, base) = (entry.dn.to_lowercase(), base.to_lowercase());
+        match scope {
+            0 => dn == base,
+            _ => base.is_empty() || dn == base || dn.ends_with(&format!(",{}", base)),
+        }
+    }
+
+    fn search_entry(entry: &Entry, wanted: &[&str]) -> Vec<u8> {
+        let attrs: Vec<u8> = entry.attrs.iter()
+            .filter(|(name, _)| wanted.is_empty() || wanted.iter().any(|w| w.eq_ignore_ascii_case(name)))
+            .flat_map(|(name, values)| {
+                let values: Vec<u8> = values.iter().flat_map(|v| tlv(0x04, v.as_bytes())).collect();
+                tlv(0x30, &[tlv(0x04, name.as_bytes()), tlv(0x31, &values)].concat())
+            })
+            .collect();
+        tlv(0x64, &[tlv(0x04, entry.dn.as_bytes()), tlv(0x30, &attrs)].concat())
+    }
+
+    async fn serve(directory: Directory, mut stream: TcpStream) -> std::io::Result<()> {
+        let mut bound = String::new();
+        loop {
+            let message = read_message(&mut stream).await?;
+            let parts = children(&message);
+            let id = tlv(0x02, parts[0].1);
+            let (op, body) = parts[1];
+            let mut replies = Vec::new();
+            match op {
+                // BindRequest: version, name, [0] simple password
+                0x60 => {
+                    let fields = children(body);
+                    let (dn, password) = (text(fields[1].1), text(fields[2].1));
+                    let accepted = password.is_empty() || directory.entries.iter().any(|entry| {
+                        entry.dn.eq_ignore_ascii_case(dn) && entry.password == Some(password)
+                    });
+                    bound = if accepted { dn.to_lowercase() } else { String::new() };
+                    replies.push(ldap_result(0x61, if accepted { 0 } else { INVALID_CREDENTIALS as u8 }));
+                }
+                // SearchRequest: base, scope, deref, size, time, typesOnly, filter, attributes
+                0x63 => {
+                    let fields = children(body);
+                    let base = text(fields[0].1);
+                    let scope = fields[1].1[0];
+                    let wanted: Vec<&str> = children(fields[7].1).into_iter().map(|(_, name)| text(name)).collect();
+                    directory.searches.lock().unwrap().push((bound.clone(), base.to_lowercase()));
+                    for entry in directory.entries.iter() {
+                        if in_scope(entry, base, scope) && matches(fields[6], entry) {
+                            replies.push(search_entry(entry, &wanted));
+                        }
+                    }
+                    replies.push(ldap_result(0x65, 0));
+                }
+                // UnbindRequest, or anything the stand-in doesn't speak
+                _ => return Ok(()),
+            }
+            for reply in replies {
+                stream.write_all(&tlv(0x30, &[id.clone(), reply].concat())).await?;
+            }
+        }
+    }
+
+    async fn start() -> (LdapConfig, Directory) {
+        let directory = Directory { entries: Arc::new(directory()), searches: Arc::default() };
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = directory.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(server.clone(), stream));
+            }
+        });
+        let config = LdapConfig {
+            url: format!("ldap://127.0.0.1:{}", port),
+            starttls: false,
+            tls_skip_verify: false,
+            user_dn_template: Some(format!("uid={{username}},{}", PEOPLE)),
+            bind_dn: None,
+            bind_password: None,
+            search_base: None,
+            user_filter: "(uid={username})".to_string(),
+            username_attribute: "uid".to_string(),
+            email_attribute: "mail".to_string(),
+            name_attribute: "cn".to_string(),
+            group_attribute: "memberOf".to_string(),
+            group_search_base: None,
+            user_group: None,
+            admin_group: None,
+            link_existing: false,
+        };
+        (config, directory)
+    }
+
+    // Search-then-bind through the reader service account
+    fn with_service_account(config: LdapConfig) -> LdapConfig {
+        LdapConfig {
+            user_dn_template: None,
+            bind_dn: Some(READER.to_string()),
+            bind_password: Some("reader-pw".to_string()),
+            search_base: Some("dc=example,dc=com".to_string()),
+            user_filter: "(&(objectClass=inetOrgPerson)(uid={username}))".to_string(),
+            ..config
+        }
+    }
+
+    #[tokio::test]
+    async fn dn_template_bind_reads_the_users_entry() {
+        let (config, _) = start().await;
+        let identity = authenticate(&config, "alice", "alice-pw").await.unwrap().unwrap();
+        assert_eq!(identity.dn, "uid=alice,ou=people,dc=example,dc=com");
+        assert_eq!(identity.username, "alice");
+        assert_eq!(identity.email.as_deref(), Some("alice@example.com"));
+        assert_eq!(identity.fullname, "Alice Liddell");
+        assert_eq!(identity.is_admin, None);
+    }
+
+    #[tokio::test]
+    async fn dn_template_bind_refuses_wrong_passwords_and_unknown_users() {
+        let (config, _) = start().await;
+        assert!(authenticate(&config, "alice", "bob-pw").await.unwrap().is_none());
+        assert!(authenticate(&config, "mallory", "alice-pw").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn empty_passwords_never_reach_the_directory() {
+        let (config, directory) = start().await;
+        // Anonymous binds succeed on most servers, the stand-in included
+        assert!(authenticate(&config, "alice", "").await.unwrap().is_none());
+        assert!(authenticate(&config, " ", "alice-pw").await.unwrap().is_none());
+        assert!(directory.searches.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn entries_without_name_or_email_fall_back_to_the_username() {
+        let (config, _) = start().await;
+        let identity = authenticate(&config, "carol", "carol-pw").await.unwrap().unwrap();
+        assert_eq!(identity.username, "carol");
+        assert_eq!(identity.fullname, "carol");
+        assert_eq!(identity.email, None);
+    }
+
+    #[tokio::test]
+    async fn search_then_bind_finds_users_as_the_service_account() {
+        let (config, directory) = start().await;
+        let config = with_service_account(config);
+        let identity = authenticate(&config, "bob", "bob-pw").await.unwrap().unwrap();
+        assert_eq!(identity.dn, "uid=bob,ou=people,dc=example,dc=com");
+        assert_eq!(identity.email.as_deref(), Some("bob@example.com"));
+        let searches = directory.searches.lock().unwrap();
+        assert_eq!(searches[0], (READER.to_string(), "dc=example,dc=com".to_string()));
+    }
+
+    #[tokio::test]
+    async fn search_then_bind_refuses_wrong_passwords_and_unknown_users() {
+        let (config, _) = start().await;
+        let config = with_service_account(config);
+        assert!(authenticate(&config, "bob", "alice-pw").await.unwrap().is_none());
+        assert!(authenticate(&config, "mallory", "bob-pw").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn search_then_bind_refuses_ambiguous_users() {
+        let (config, _) = start().await;
+        let config = with_service_account(config);
+        assert!(authenticate(&config, "dave", "dave-pw").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn rejected_service_account_is_an_error() {
+        let (config, _) = start().await;
+        let config = LdapConfig { bind_password: Some("wrong".to_string()), ..with_service_account(config) };
+        assert!(authenticate(&config, "bob", "bob-pw").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn user_group_from_the_entry_gates_logins() {
+        let (config, _) = start().await;
+        let config = LdapConfig { user_group: Some("podcasters".to_string()), ..config };
+        assert!(authenticate(&config, "alice", "alice-pw").await.unwrap().is_some());
+        assert!(authenticate(&config, "bob", "bob-pw").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn user_group_from_the_group_search_gates_logins() {
+        let (config, _) = start().await;
+        let config = LdapConfig {
+            group_attribute: "none".to_string(),
+            group_search_base: Some(GROUPS.to_string()),
+            user_group: Some("CN=Podcasters, OU=Groups, DC=example, DC=com".to_string()),
+            ..with_service_account(config)
+        };
+        assert!(authenticate(&config, "alice", "alice-pw").await.unwrap().is_some());
+        assert!(authenticate(&config, "bob", "bob-pw").await.unwrap().is_none());
+
+        // posixGroup membership is by uid
+        let config = LdapConfig { user_group: Some("listeners".to_string()), ..config };
+        assert!(authenticate(&config, "carol", "carol-pw").await.unwrap().is_some());
+        assert!(authenticate(&config, "alice", "alice-pw").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn admin_group_sets_the_admin_flag() {
+        let (config, _) = start().await;
+        let config = LdapConfig {
+            group_search_base: Some(GROUPS.to_string()),
+            admin_group: Some("admins".to_string()),
+            ..with_service_account(config)
+        };
+        let bob = authenticate(&config, "bob", "bob-pw").await.unwrap().unwrap();
+        assert_eq!(bob.is_admin, Some(true));
+        let alice = authenticate(&config, "alice", "alice-pw").await.unwrap().unwrap();
+        assert_eq!(alice.is_admin, Some(false));
+    }
+
+    #[tokio::test]
+    async fn groups_are_read_as_the_service_account() {
+        let (config, directory) = start().await;
+        let config = LdapConfig {
+            group_search_base: Some(GROUPS.to_string()),
+            user_group: Some("listeners".to_string()),
+            ..with_service_account(config)
+        };
+        assert!(authenticate(&config, "bob", "bob-pw").await.unwrap().is_some());
+        let searches = directory.searches.lock().unwrap();
+        assert_eq!(searches.last().unwrap(), &(READER.to_string(), GROUPS.to_string()));
+    }
+
+    #[tokio::test]
+    async fn groups_are_read_as_the_user_with_a_dn_template() {
+        let (config, directory) = start().await;
+        let config = LdapConfig {
+            group_search_base: Some(GROUPS.to_string()),
+            admin_group: Some("admins".to_string()),
+            ..config
+        };
+        let bob = authenticate(&config, "bob", "bob-pw").await.unwrap().unwrap();
+        assert_eq!(bob.is_admin, Some(true));
+        let searches = directory.searches.lock().unwrap();
+        assert_eq!(searches.last().unwrap().0, "uid=bob,ou=people,dc=example,dc=com");
+    }
+
+    #[test]
+    fn groups_match_by_dn_or_name() {
+        let groups = vec!["cn=Podcasters,ou=groups,dc=example,dc=com".to_string()];
+        assert!(in_group(&groups, "podcasters"));
+        assert!(in_group(&groups, " PODCASTERS "));
+        assert!(in_group(&groups, "CN=podcasters, ou=Groups, dc=example, dc=com"));
+        assert!(!in_group(&groups, "cn=podcasters,ou=other,dc=example,dc=com"));
+        assert!(!in_group(&groups, "admins"));
+        assert!(!in_group(&[], "podcasters"));
+    }
+}
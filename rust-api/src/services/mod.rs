@@ -1,7 +1,32 @@
+pub mod api_scopes;
+pub mod audio_processing;
 pub mod auth;
+pub mod chapters;
+pub mod delivery;
+pub mod download_queue;
+pub mod download_template;
+pub mod email_digest;
+pub mod episode_dedup;
+pub mod feed_fetch;
+pub mod feed_health;
+pub mod ldap;
+pub mod notifications;
+pub mod oidc;
+pub mod outbound;
 pub mod podcast;
+pub mod proxy_auth;
+pub mod rate_limit;
+pub mod refresh_schedule;
+pub mod retention;
 pub mod scheduler;
+pub mod search;
+pub mod sessions;
+pub mod storage;
 pub mod task_manager;
 pub mod tasks;
+pub mod transcripts;
+pub mod webauthn;
+pub mod webhooks;
+pub mod websub;
 
 // Common service utilities and shared functionality
\ No newline at end of file
@@ -0,0 +1,1094 @@
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::{
+    database::DatabasePool,
+    error::{AppError, AppResult},
+    services::{
+        delivery::{self, default_enabled, lease_until, Delivery},
+        outbound,
+    },
+};
+
+/// Give up on a delivery after this many attempts; retries wait 1, 4, 16 then 64 minutes
+pub const MAX_ATTEMPTS: i32 = 5;
+const MAX_TEMPLATE_LEN: usize = 2000;
+const MAX_REDIRECTS: usize = 3;
+// Discord rejects message content longer than this
+const DISCORD_MAX_CONTENT: usize = 2000;
+/// Stands in for tokens, passwords and secret URLs in responses. Sending it back in an
+/// update keeps the stored value.
+pub const REDACTED: &str = "********";
+
+/// Kinds of event a destination can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationEventKind {
+    NewEpisode,
+    DownloadFinished,
+    DownloadFailed,
+    FeedBroken,
+    ImportFinished,
+    SyncFailed,
+    PersonEpisode,
+    WeeklyDigest,
+    /// Test messages; sent on request, never subscribed to
+    Test,
+}
+
+impl NotificationEventKind {
+    /// Events destinations can subscribe to
+    pub const SUBSCRIBABLE: [NotificationEventKind; 8] = [
+        NotificationEventKind::NewEpisode,
+        NotificationEventKind::DownloadFinished,
+        NotificationEventKind::DownloadFailed,
+        NotificationEventKind::FeedBroken,
+        NotificationEventKind::ImportFinished,
+        NotificationEventKind::SyncFailed,
+        NotificationEventKind::PersonEpisode,
+        NotificationEventKind::WeeklyDigest,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationEventKind::NewEpisode => "new_episode",
+            NotificationEventKind::DownloadFinished => "download_finished",
+            NotificationEventKind::DownloadFailed => "download_failed",
+            NotificationEventKind::FeedBroken => "feed_broken",
+            NotificationEventKind::ImportFinished => "import_finished",
+            NotificationEventKind::SyncFailed => "sync_failed",
+            NotificationEventKind::PersonEpisode => "person_episode",
+            NotificationEventKind::WeeklyDigest => "weekly_digest",
+            NotificationEventKind::Test => "test",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        Self::SUBSCRIBABLE.into_iter().chain([NotificationEventKind::Test]).find(|k| k.as_str() == kind)
+    }
+}
+
+/// Events a destination receives when none were chosen, as before events could be picked
+fn default_events() -> Vec<NotificationEventKind> {
+    vec![NotificationEventKind::NewEpisode]
+}
+
+/// Subscribed events as stored in NotificationDestinations.Events; None is the default set
+pub fn parse_events(events: Option<&str>) -> Vec<NotificationEventKind> {
+    match events {
+        Some(events) => delivery::parse_list(events, NotificationEventKind::parse),
+        None => default_events(),
+    }
+}
+
+pub fn format_events(events: &[NotificationEventKind]) -> String {
+    delivery::format_list(events, NotificationEventKind::as_str)
+}
+
+/// Something a user can be notified about
+#[derive(Debug, Clone)]
+pub enum NotificationEvent {
+    NewEpisode { podcast_name: String, episode_title: String },
+    DownloadFinished { podcast_name: String, episode_title: String },
+    DownloadFailed { podcast_name: String, episode_title: String, error: String },
+    /// A feed has failed every refresh for `days` days
+    FeedBroken { podcast_name: String, days: i64, error: Option<String> },
+    ImportFinished { imported: usize, failed: usize },
+    /// `service` is "gpodder" or "nextcloud"
+    SyncFailed { service: String, error: String },
+    /// A new episode on a show featuring someone the user follows
+    PersonEpisode { person_name: String, podcast_name: String, episode_title: String },
+    /// Episodes published in the past week across the user's subscriptions
+    WeeklyDigest { new_episodes: i64, podcasts: usize, summary: String },
+    Test,
+}
+
+impl NotificationEvent {
+    pub fn kind(&self) -> NotificationEventKind {
+        match self {
+            NotificationEvent::NewEpisode { .. } => NotificationEventKind::NewEpisode,
+            NotificationEvent::DownloadFinished { .. } => NotificationEventKind::DownloadFinished,
+            NotificationEvent::DownloadFailed { .. } => NotificationEventKind::DownloadFailed,
+            NotificationEvent::FeedBroken { .. } => NotificationEventKind::FeedBroken,
+            NotificationEvent::ImportFinished { .. } => NotificationEventKind::ImportFinished,
+            NotificationEvent::SyncFailed { .. } => NotificationEventKind::SyncFailed,
+            NotificationEvent::PersonEpisode { .. } => NotificationEventKind::PersonEpisode,
+            NotificationEvent::WeeklyDigest { .. } => NotificationEventKind::WeeklyDigest,
+            NotificationEvent::Test => NotificationEventKind::Test,
+        }
+    }
+
+    fn default_title(&self) -> &'static str {
+        match self {
+            NotificationEvent::NewEpisode { .. } => "New Podcast Episode",
+            NotificationEvent::DownloadFinished { .. } => "Download Finished",
+            NotificationEvent::DownloadFailed { .. } => "Download Failed",
+            NotificationEvent::FeedBroken { .. } => "Feed Broken",
+            NotificationEvent::ImportFinished { .. } => "OPML Import Finished",
+            NotificationEvent::SyncFailed { .. } => "Sync Failed",
+            NotificationEvent::PersonEpisode { .. } => "New Episode With {{person}}",
+            NotificationEvent::WeeklyDigest { .. } => "Your Week in Podcasts",
+            NotificationEvent::Test => "PinePods Test",
+        }
+    }
+
+    fn default_body(&self) -> &'static str {
+        match self {
+            NotificationEvent::NewEpisode { .. } => "New episode available for {{podcast}}: {{episode}}",
+            NotificationEvent::DownloadFinished { .. } => "Downloaded {{episode}} from {{podcast}}",
+            NotificationEvent::DownloadFailed { .. } => "Couldn't download {{episode}} from {{podcast}}: {{error}}",
+            NotificationEvent::FeedBroken { .. } => "The feed for {{podcast}} has failed to refresh for {{days}} days: {{error}}",
+            NotificationEvent::ImportFinished { .. } => "Imported {{imported}} podcasts ({{failed}} failed)",
+            NotificationEvent::SyncFailed { .. } => "Syncing with {{service}} failed: {{error}}",
+            NotificationEvent::PersonEpisode { .. } => "{{person}} is on {{podcast}}: {{episode}}",
+            NotificationEvent::WeeklyDigest { .. } => "{{new_episodes}} new episodes from {{podcasts}} podcasts this week: {{summary}}",
+            NotificationEvent::Test => "Test notification from PinePods",
+        }
+    }
+
+    /// Values templates can refer to as `{{name}}`
+    pub fn variables(&self) -> Vec<(&'static str, String)> {
+        let mut variables = vec![("event", self.kind().as_str().to_string())];
+        match self {
+            NotificationEvent::NewEpisode { podcast_name, episode_title }
+            | NotificationEvent::DownloadFinished { podcast_name, episode_title } => {
+                variables.push(("podcast", podcast_name.clone()));
+                variables.push(("episode", episode_title.clone()));
+            }
+            NotificationEvent::DownloadFailed { podcast_name, episode_title, error } => {
+                variables.push(("podcast", podcast_name.clone()));
+                variables.push(("episode", episode_title.clone()));
+                variables.push(("error", error.clone()));
+            }
+            NotificationEvent::FeedBroken { podcast_name, days, error } => {
+                variables.push(("podcast", podcast_name.clone()));
+                variables.push(("days", days.to_string()));
+                variables.push(("error", error.clone().unwrap_or_else(|| "unknown error".to_string())));
+            }
+            NotificationEvent::ImportFinished { imported, failed } => {
+                variables.push(("imported", imported.to_string()));
+                variables.push(("failed", failed.to_string()));
+            }
+            NotificationEvent::SyncFailed { service, error } => {
+                variables.push(("service", service.clone()));
+                variables.push(("error", error.clone()));
+            }
+            NotificationEvent::PersonEpisode { person_name, podcast_name, episode_title } => {
+                variables.push(("person", person_name.clone()));
+                variables.push(("podcast", podcast_name.clone()));
+                variables.push(("episode", episode_title.clone()));
+            }
+            NotificationEvent::WeeklyDigest { new_episodes, podcasts, summary } => {
+                variables.push(("new_episodes", new_episodes.to_string()));
+                variables.push(("podcasts", podcasts.to_string()));
+                variables.push(("summary", summary.clone()));
+            }
+            NotificationEvent::Test => {}
+        }
+        variables
+    }
+}
+
+/// A notification ready to send
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub title: String,
+    pub body: String,
+}
+
+impl Message {
+    /// Render an event with a destination's templates, falling back to the event's own wording
+    pub fn for_event(event: &NotificationEvent, title_template: Option<&str>, body_template: Option<&str>) -> Self {
+        let variables = event.variables();
+        Message {
+            title: render(title_template.filter(|t| !t.trim().is_empty()).unwrap_or(event.default_title()), &variables),
+            body: render(body_template.filter(|t| !t.trim().is_empty()).unwrap_or(event.default_body()), &variables),
+        }
+    }
+}
+
+/// Fill `{{name}}` placeholders; unknown names are left as written
+pub fn render(template: &str, variables: &[(&'static str, String)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = after[..end].trim();
+        match variables.iter().find(|(key, _)| *key == name) {
+            Some((_, value)) => rendered.push_str(value),
+            None => rendered.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
+
+/// A channel notifications can be sent over
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    /// Problems with the settings themselves, reported before they are saved
+    fn validate(&self) -> Result<(), String>;
+
+    /// The URL messages are sent to, for channels that make their own requests
+    fn endpoint(&self) -> Option<&str> {
+        None
+    }
+
+    async fn send(&self, client: &reqwest::Client, db: &DatabasePool, message: &Message) -> AppResult<()>;
+}
+
+fn require(value: &str, field: &str) -> Result<(), String> {
+    if value.trim().is_empty() {
+        return Err(format!("{} is required", field));
+    }
+    Ok(())
+}
+
+fn require_url(value: &str, field: &str) -> Result<(), String> {
+    require(value, field)?;
+    match reqwest::Url::parse(value.trim()) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => Ok(()),
+        _ => Err(format!("{} must be an http(s) URL", field)),
+    }
+}
+
+fn non_empty(value: &Option<String>) -> Option<&str> {
+    value.as_deref().map(str::trim).filter(|v| !v.is_empty())
+}
+
+// Only the status is reported: the body of whatever answered is not the user's to read
+fn check_response(provider: &str, response: reqwest::Response) -> AppResult<()> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    Err(AppError::external_error(format!("{} returned {}", provider, status)))
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+/// ntfy topic, on ntfy.sh or a self-hosted server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NtfyNotifier {
+    #[serde(default = "default_ntfy_server")]
+    pub server_url: String,
+    pub topic: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Used instead of username/password when set
+    #[serde(default)]
+    pub access_token: Option<String>,
+}
+
+#[async_trait]
+impl Notifier for NtfyNotifier {
+    fn validate(&self) -> Result<(), String> {
+        require_url(&self.server_url, "server_url")?;
+        require(&self.topic, "topic")
+    }
+
+    fn endpoint(&self) -> Option<&str> {
+        Some(&self.server_url)
+    }
+
+    async fn send(&self, client: &reqwest::Client, _db: &DatabasePool, message: &Message) -> AppResult<()> {
+        // JSON publishing keeps non-ASCII titles out of headers
+        let mut request = client.post(self.server_url.trim().trim_end_matches('/')).json(&serde_json::json!({
+            "topic": self.topic.trim(),
+            "title": message.title,
+            "message": message.body,
+        }));
+        if let Some(token) = non_empty(&self.access_token) {
+            request = request.bearer_auth(token);
+        } else if let (Some(user), Some(pass)) = (non_empty(&self.username), non_empty(&self.password)) {
+            request = request.basic_auth(user, Some(pass));
+        }
+        check_response("ntfy", request.send().await?)
+    }
+}
+
+/// Gotify application
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GotifyNotifier {
+    pub url: String,
+    /// Application token
+    pub token: String,
+    #[serde(default)]
+    pub priority: Option<i32>,
+}
+
+#[async_trait]
+impl Notifier for GotifyNotifier {
+    fn validate(&self) -> Result<(), String> {
+        require_url(&self.url, "url")?;
+        require(&self.token, "token")
+    }
+
+    fn endpoint(&self) -> Option<&str> {
+        Some(&self.url)
+    }
+
+    async fn send(&self, client: &reqwest::Client, _db: &DatabasePool, message: &Message) -> AppResult<()> {
+        let response = client
+            .post(format!("{}/message", self.url.trim().trim_end_matches('/')))
+            .header("X-Gotify-Key", self.token.trim())
+            .json(&serde_json::json!({
+                "title": message.title,
+                "message": message.body,
+                "priority": self.priority.unwrap_or(5),
+            }))
+            .send()
+            .await?;
+        check_response("Gotify", response)
+    }
+}
+
+fn default_http_method() -> String {
+    "POST".to_string()
+}
+
+/// Any HTTP endpoint: GET with a `message` query parameter, or POST with a JSON body.
+/// Telegram bot API URLs get the `chat_id`/`text` body Telegram expects.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HttpNotifier {
+    pub url: String,
+    /// Sent as a bearer token; for Telegram, `bot_token:chat_id` supplies the chat ID
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default = "default_http_method")]
+    pub method: String,
+}
+
+#[async_trait]
+impl Notifier for HttpNotifier {
+    fn validate(&self) -> Result<(), String> {
+        require_url(&self.url, "url")?;
+        if !matches!(self.method.to_uppercase().as_str(), "GET" | "POST") {
+            return Err("method must be GET or POST".to_string());
+        }
+        Ok(())
+    }
+
+    fn endpoint(&self) -> Option<&str> {
+        Some(&self.url)
+    }
+
+    async fn send(&self, client: &reqwest::Client, _db: &DatabasePool, message: &Message) -> AppResult<()> {
+        let url = self.url.trim();
+        let text = format!("{}\n{}", message.title, message.body);
+        let is_telegram = url.contains("api.telegram.org");
+
+        let mut request = if self.method.eq_ignore_ascii_case("GET") {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            client.get(format!("{}{}message={}", url, separator, urlencoding::encode(&text)))
+        } else if is_telegram {
+            // Chat ID from a `bot_token:chat_id` token
+            let chat_id = non_empty(&self.token).and_then(|token| token.rsplit(':').next()).unwrap_or_default();
+            client.post(url).json(&serde_json::json!({ "chat_id": chat_id, "text": text }))
+        } else {
+            client.post(url).json(&serde_json::json!({
+                "title": message.title,
+                "message": message.body,
+                "text": text,
+            }))
+        };
+        // Telegram carries its token in the URL path
+        if let Some(token) = non_empty(&self.token).filter(|_| !is_telegram) {
+            request = request.bearer_auth(token);
+        }
+        check_response("HTTP endpoint", request.send().await?)
+    }
+}
+
+/// An Apprise API server, which fans out to any Apprise URL (`tgram://`, `pover://`,
+/// `mailto://`, ...). `url` is its notify endpoint: `/notify/{key}` for a saved
+/// configuration, or `/notify` together with `urls`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AppriseNotifier {
+    pub url: String,
+    /// Apprise URLs to notify, separated by commas or spaces
+    #[serde(default)]
+    pub urls: Option<String>,
+    /// Only notify URLs in the saved configuration carrying this tag
+    #[serde(default)]
+    pub tag: Option<String>,
+}
+
+#[async_trait]
+impl Notifier for AppriseNotifier {
+    fn validate(&self) -> Result<(), String> {
+        require_url(&self.url, "url")
+    }
+
+    fn endpoint(&self) -> Option<&str> {
+        Some(&self.url)
+    }
+
+    async fn send(&self, client: &reqwest::Client, _db: &DatabasePool, message: &Message) -> AppResult<()> {
+        let mut payload = serde_json::json!({
+            "title": message.title,
+            "body": message.body,
+        });
+        if let Some(urls) = non_empty(&self.urls) {
+            payload["urls"] = serde_json::json!(urls);
+        }
+        if let Some(tag) = non_empty(&self.tag) {
+            payload["tag"] = serde_json::json!(tag);
+        }
+        let response = client.post(self.url.trim()).json(&payload).send().await?;
+        check_response("Apprise", response)
+    }
+}
+
+/// A Matrix room, posted to as the user the access token belongs to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatrixNotifier {
+    pub homeserver: String,
+    pub access_token: String,
+    /// Room ID (`!abc:example.org`); the account must already be in the room
+    pub room_id: String,
+}
+
+#[async_trait]
+impl Notifier for MatrixNotifier {
+    fn validate(&self) -> Result<(), String> {
+        require_url(&self.homeserver, "homeserver")?;
+        require(&self.access_token, "access_token")?;
+        require(&self.room_id, "room_id")
+    }
+
+    fn endpoint(&self) -> Option<&str> {
+        Some(&self.homeserver)
+    }
+
+    async fn send(&self, client: &reqwest::Client, _db: &DatabasePool, message: &Message) -> AppResult<()> {
+        let url = format!(
+            "{}/_matrix/client/v3/rooms/{}/send/m.room.message/{}",
+            self.homeserver.trim().trim_end_matches('/'),
+            urlencoding::encode(self.room_id.trim()),
+            uuid::Uuid::new_v4(),
+        );
+        let response = client
+            .put(url)
+            .bearer_auth(self.access_token.trim())
+            .json(&serde_json::json!({
+                "msgtype": "m.text",
+                "body": format!("{}\n{}", message.title, message.body),
+            }))
+            .send()
+            .await?;
+        check_response("Matrix", response)
+    }
+}
+
+/// Email through the server's SMTP settings. Since mail goes out from the server's
+/// account, only admins may send to an address other than their own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailNotifier {
+    pub to: String,
+}
+
+impl EmailNotifier {
+    /// Whether mail goes to `address`, ignoring case and surrounding space
+    pub fn sends_to(&self, address: &str) -> bool {
+        self.to.trim().eq_ignore_ascii_case(address.trim())
+    }
+}
+
+#[async_trait]
+impl Notifier for EmailNotifier {
+    fn validate(&self) -> Result<(), String> {
+        match self.to.trim().parse::<lettre::Address>() {
+            Ok(_) => Ok(()),
+            Err(_) => Err("to must be an email address".to_string()),
+        }
+    }
+
+    async fn send(&self, _client: &reqwest::Client, db: &DatabasePool, message: &Message) -> AppResult<()> {
+        use crate::handlers::settings::{send_email_with_settings, SendEmailRequest};
+
+        let settings = db.get_email_settings().await?
+            .ok_or_else(|| AppError::bad_request("Email is not configured on this server"))?;
+        let request = SendEmailRequest {
+            to_email: self.to.trim().to_string(),
+            subject: message.title.clone(),
+            message: message.body.clone(),
+        };
+        send_email_with_settings(&settings, &request).await.map(|_| ())
+    }
+}
+
+/// Which incoming-webhook payload to send
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// `{"content": ...}`
+    Discord,
+    /// `{"text": ...}`, also understood by Mattermost, Rocket.Chat and Teams workflows
+    Slack,
+}
+
+/// Discord- or Slack-compatible incoming webhook
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookNotifier {
+    pub url: String,
+    /// Guessed from the URL when not set
+    #[serde(default)]
+    pub format: Option<WebhookFormat>,
+}
+
+impl WebhookNotifier {
+    fn format(&self) -> WebhookFormat {
+        self.format.unwrap_or_else(|| {
+            let is_discord = reqwest::Url::parse(self.url.trim()).ok()
+                .and_then(|url| url.host_str().map(|host| host.ends_with("discord.com") || host.ends_with("discordapp.com")))
+                .unwrap_or(false);
+            if is_discord { WebhookFormat::Discord } else { WebhookFormat::Slack }
+        })
+    }
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+    fn validate(&self) -> Result<(), String> {
+        require_url(&self.url, "url")
+    }
+
+    fn endpoint(&self) -> Option<&str> {
+        Some(&self.url)
+    }
+
+    async fn send(&self, client: &reqwest::Client, _db: &DatabasePool, message: &Message) -> AppResult<()> {
+        let payload = match self.format() {
+            WebhookFormat::Discord => {
+                let content: String = format!("**{}**\n{}", message.title, message.body).chars().take(DISCORD_MAX_CONTENT).collect();
+                serde_json::json!({ "content": content })
+            }
+            WebhookFormat::Slack => serde_json::json!({ "text": format!("*{}*\n{}", message.title, message.body) }),
+        };
+        let response = client.post(self.url.trim()).json(&payload).send().await?;
+        check_response("Webhook", response)
+    }
+}
+
+/// A destination's provider and its settings, stored as JSON tagged with `provider`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum ProviderConfig {
+    Ntfy(NtfyNotifier),
+    Gotify(GotifyNotifier),
+    Http(HttpNotifier),
+    Apprise(AppriseNotifier),
+    Matrix(MatrixNotifier),
+    Email(EmailNotifier),
+    Webhook(WebhookNotifier),
+}
+
+impl ProviderConfig {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProviderConfig::Ntfy(_) => "ntfy",
+            ProviderConfig::Gotify(_) => "gotify",
+            ProviderConfig::Http(_) => "http",
+            ProviderConfig::Apprise(_) => "apprise",
+            ProviderConfig::Matrix(_) => "matrix",
+            ProviderConfig::Email(_) => "email",
+            ProviderConfig::Webhook(_) => "webhook",
+        }
+    }
+
+    pub fn notifier(&self) -> &dyn Notifier {
+        match self {
+            ProviderConfig::Ntfy(notifier) => notifier,
+            ProviderConfig::Gotify(notifier) => notifier,
+            ProviderConfig::Http(notifier) => notifier,
+            ProviderConfig::Apprise(notifier) => notifier,
+            ProviderConfig::Matrix(notifier) => notifier,
+            ProviderConfig::Email(notifier) => notifier,
+            ProviderConfig::Webhook(notifier) => notifier,
+        }
+    }
+
+    /// Refuse endpoints on the server's own network, which only destinations an admin
+    /// set up may reach (a self-hosted ntfy or Gotify on the LAN, say)
+    pub fn check_endpoint(&self, allow_private: bool) -> Result<(), String> {
+        match self.notifier().endpoint() {
+            Some(endpoint) if !allow_private => {
+                let url = url::Url::parse(endpoint.trim()).map_err(|e| format!("{} is not a valid URL: {}", self.name(), e))?;
+                outbound::check_url(&url).map_err(|e| format!("{} URL is not allowed: {}", self.name(), e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// A copy safe to return to clients, with secrets replaced by `REDACTED`
+    pub fn redacted(&self) -> Self {
+        let mut config = self.clone();
+        for secret in config.secrets_mut().into_iter().flatten() {
+            if !secret.trim().is_empty() {
+                *secret = REDACTED.to_string();
+            }
+        }
+        config
+    }
+
+    /// Put back secrets a client sent as `REDACTED`, from the settings stored for the
+    /// same provider
+    pub fn restore_secrets(&mut self, stored: &ProviderConfig) {
+        let mut stored = stored.clone();
+        if self.name() != stored.name() {
+            return;
+        }
+        for (secret, stored) in self.secrets_mut().into_iter().zip(stored.secrets_mut()) {
+            if let Some(secret) = secret.filter(|secret| secret.as_str() == REDACTED) {
+                *secret = stored.map(std::mem::take).unwrap_or_default();
+            }
+        }
+    }
+
+    // Fields that grant access to the destination, in a fixed order per provider; Discord
+    // and Slack webhook URLs and Apprise URLs carry their credentials in the URL itself
+    fn secrets_mut(&mut self) -> Vec<Option<&mut String>> {
+        match self {
+            ProviderConfig::Ntfy(n) => vec![n.password.as_mut(), n.access_token.as_mut()],
+            ProviderConfig::Gotify(n) => vec![Some(&mut n.token)],
+            ProviderConfig::Http(n) => vec![n.token.as_mut()],
+            ProviderConfig::Apprise(n) => vec![n.urls.as_mut()],
+            ProviderConfig::Matrix(n) => vec![Some(&mut n.access_token)],
+            ProviderConfig::Email(_) => Vec::new(),
+            ProviderConfig::Webhook(n) => vec![Some(&mut n.url)],
+        }
+    }
+
+    /// Settings from the per-platform notification settings (as `get_notification_settings`
+    /// returns them); None when the platform is unknown or its required fields are empty
+    pub fn from_legacy(platform: &str, settings: &serde_json::Value) -> Option<Self> {
+        let field = |name: &str| settings.get(name).and_then(|v| v.as_str()).map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+        match platform {
+            "ntfy" => Some(ProviderConfig::Ntfy(NtfyNotifier {
+                server_url: field("ntfy_server_url").unwrap_or_else(default_ntfy_server),
+                topic: field("ntfy_topic")?,
+                username: field("ntfy_username"),
+                password: field("ntfy_password"),
+                access_token: field("ntfy_access_token"),
+            })),
+            "gotify" => Some(ProviderConfig::Gotify(GotifyNotifier {
+                url: field("gotify_url")?,
+                token: field("gotify_token")?,
+                priority: None,
+            })),
+            "http" => Some(ProviderConfig::Http(HttpNotifier {
+                url: field("http_url")?,
+                token: field("http_token"),
+                method: field("http_method").unwrap_or_else(default_http_method),
+            })),
+            _ => None,
+        }
+    }
+}
+
+/// Where a user's notifications go
+#[derive(Debug, Clone, Serialize)]
+pub struct NotificationDestination {
+    pub destination_id: i32,
+    pub user_id: i32,
+    pub name: String,
+    #[serde(flatten)]
+    pub config: ProviderConfig,
+    pub title_template: Option<String>,
+    pub body_template: Option<String>,
+    pub enabled: bool,
+    pub events: Vec<NotificationEventKind>,
+    /// Set on destinations kept in step with the per-platform notification settings
+    pub legacy_platform: Option<String>,
+}
+
+impl NotificationDestination {
+    /// The destination as returned to clients, without its secrets
+    pub fn redacted(mut self) -> Self {
+        self.config = self.config.redacted();
+        self
+    }
+}
+
+/// The editable part of a destination
+#[derive(Debug, Clone, Deserialize)]
+pub struct DestinationSettings {
+    pub name: String,
+    #[serde(flatten)]
+    pub config: ProviderConfig,
+    /// Title with `{{placeholders}}`; the event's default title when empty
+    #[serde(default)]
+    pub title_template: Option<String>,
+    /// Body with `{{placeholders}}`; the event's default wording when empty
+    #[serde(default)]
+    pub body_template: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Events sent to this destination; new episodes only when not given
+    #[serde(default = "default_events")]
+    pub events: Vec<NotificationEventKind>,
+}
+
+impl DestinationSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() || self.name.len() > 255 {
+            return Err("name must be between 1 and 255 characters".to_string());
+        }
+        for template in [&self.title_template, &self.body_template].into_iter().flatten() {
+            if template.len() > MAX_TEMPLATE_LEN {
+                return Err(format!("Templates can be at most {} characters", MAX_TEMPLATE_LEN));
+            }
+        }
+        if self.events.contains(&NotificationEventKind::Test) {
+            return Err("test is not an event destinations can subscribe to".to_string());
+        }
+        self.config.notifier().validate()
+    }
+}
+
+/// State of a logged delivery
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// Being sent, or waiting for a retry
+    Pending,
+    Sent,
+    /// Out of attempts
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+}
+
+/// One message sent (or being sent) to a destination, from the delivery log
+#[derive(Debug, Clone, Serialize)]
+pub struct NotificationDelivery {
+    pub delivery_id: i32,
+    pub destination_id: i32,
+    pub destination_name: String,
+    pub event_type: String,
+    pub title: String,
+    pub body: String,
+    pub status: String,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+/// A logged delivery due another attempt
+pub struct DueDelivery {
+    pub delivery_id: i32,
+    pub attempts: i32,
+    pub message: Message,
+    pub config: ProviderConfig,
+    /// Whether the destination belongs to an admin, and so may be on the local network
+    pub allow_private: bool,
+}
+
+impl DueDelivery {
+    // Destinations admins set up may be on the local network, so this client goes anywhere
+    fn local_client() -> AppResult<reqwest::Client> {
+        Ok(reqwest::Client::builder()
+            .timeout(delivery::SEND_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::limited(MAX_REDIRECTS))
+            .build()?)
+    }
+
+    /// Send with `client`, which only reaches public addresses, or the local client for
+    /// an admin's destination
+    async fn send(&self, db: &DatabasePool, client: &reqwest::Client) -> AppResult<()> {
+        self.config.check_endpoint(self.allow_private).map_err(AppError::bad_request)?;
+        if self.allow_private {
+            self.config.notifier().send(&Self::local_client()?, db, &self.message).await
+        } else {
+            self.config.notifier().send(client, db, &self.message).await
+        }
+    }
+}
+
+#[async_trait]
+impl Delivery for DueDelivery {
+    async fn load_due(db: &DatabasePool, now: NaiveDateTime, limit: i64) -> AppResult<Vec<Self>> {
+        db.get_due_notification_deliveries(now, limit).await
+    }
+
+    fn client() -> AppResult<reqwest::Client> {
+        Ok(outbound::client_builder()
+            .timeout(delivery::SEND_TIMEOUT)
+            .redirect(outbound::redirect_policy(MAX_REDIRECTS))
+            .build()?)
+    }
+
+    async fn attempt(self, db: &DatabasePool, client: &reqwest::Client) {
+        let attempts = self.attempts + 1;
+        let result = match self.send(db, client).await {
+            Ok(()) => db.mark_notification_sent(self.delivery_id, attempts).await,
+            Err(e) => {
+                let retry_at = delivery::next_attempt_at(attempts, MAX_ATTEMPTS, Utc::now().naive_utc());
+                tracing::warn!(
+                    "{} notification {} failed (attempt {}/{}): {}",
+                    self.config.name(), self.delivery_id, attempts, MAX_ATTEMPTS, e
+                );
+                db.mark_notification_failed(self.delivery_id, attempts, &e.to_string(), retry_at).await
+            }
+        };
+        if let Err(e) = result {
+            tracing::warn!("Failed to record notification delivery {}: {}", self.delivery_id, e);
+        }
+    }
+}
+
+/// Send an event to each of the user's enabled destinations. Deliveries are logged and
+/// sent in the background; failures are retried by `retry_due_deliveries`. Returns how
+/// many destinations the event went to.
+pub async fn notify(db: &DatabasePool, user_id: i32, event: &NotificationEvent) -> AppResult<usize> {
+    let kind = event.kind();
+    let destinations: Vec<_> = db.get_notification_destinations(user_id).await?
+        .into_iter()
+        .filter(|d| d.enabled && d.events.contains(&kind))
+        .collect();
+    if destinations.is_empty() {
+        return Ok(0);
+    }
+    // Admins may mail anyone and reach destinations on the local network
+    let is_admin = db.user_admin_check(user_id).await?;
+    // Looked up on the first email destination
+    let mut own_email: Option<String> = None;
+    let mut deliveries = Vec::new();
+    for destination in destinations {
+        // Email destinations saved before recipients were checked may mail someone else
+        if let ProviderConfig::Email(email) = &destination.config {
+            if !is_admin {
+                if own_email.is_none() {
+                    own_email = Some(db.get_user_details_by_id(user_id).await?.Email.unwrap_or_default());
+                }
+                let own_email = own_email.as_deref().unwrap_or_default();
+                if own_email.trim().is_empty() || !email.sends_to(own_email) {
+                    tracing::warn!("Skipping email destination {}: it isn't the user's own address", destination.destination_id);
+                    continue;
+                }
+            }
+        }
+        let message = Message::for_event(event, destination.title_template.as_deref(), destination.body_template.as_deref());
+        let delivery_id = db.create_notification_delivery(destination.destination_id, user_id, kind.as_str(), &message, lease_until()).await?;
+        deliveries.push(DueDelivery { delivery_id, attempts: 0, message, config: destination.config, allow_private: is_admin });
+    }
+
+    let count = deliveries.len();
+    if count > 0 {
+        let db = db.clone();
+        tokio::spawn(async move {
+            let client = match DueDelivery::client() {
+                Ok(client) => client,
+                Err(e) => {
+                    tracing::warn!("Failed to build notification client: {}", e);
+                    return;
+                }
+            };
+            for due in deliveries {
+                due.attempt(&db, &client).await;
+            }
+        });
+    }
+    Ok(count)
+}
+
+/// Retry deliveries whose backoff has run out; called from the scheduler
+pub async fn retry_due_deliveries(db: &DatabasePool) -> AppResult<usize> {
+    delivery::retry_due::<DueDelivery>(db).await
+}
+
+/// Send a test message straight to one of the user's destinations, without logging it
+pub async fn send_test(db: &DatabasePool, user_id: i32, config: &ProviderConfig, title_template: Option<&str>, body_template: Option<&str>) -> AppResult<()> {
+    let test = DueDelivery {
+        delivery_id: 0,
+        attempts: 0,
+        message: Message::for_event(&NotificationEvent::Test, title_template, body_template),
+        config: config.clone(),
+        allow_private: db.user_admin_check(user_id).await?,
+    };
+    test.send(db, &DueDelivery::client()?).await
+}
+
+/// Tell owners about feeds that have failed every refresh for `days` days, once per
+/// outage; a successful refresh clears the marker. Called from the scheduler.
+pub async fn notify_broken_feeds(db: &DatabasePool, days: i64) -> AppResult<usize> {
+    let now = Utc::now().naive_utc();
+    let broken = db.get_unreported_broken_feeds(now - chrono::Duration::days(days)).await?;
+    for feed in &broken {
+        let event = NotificationEvent::FeedBroken {
+            podcast_name: feed.podcast_name.clone(),
+            days: (now - feed.failing_since).num_days().max(days),
+            error: feed.last_error.clone(),
+        };
+        if let Err(e) = notify(db, feed.user_id, &event).await {
+            tracing::warn!("Failed to notify user {} about broken feed {}: {}", feed.user_id, feed.podcast_id, e);
+        }
+        db.mark_broken_feed_reported(feed.podcast_id, now).await?;
+    }
+    Ok(broken.len())
+}
+
+/// A feed failing long enough to report, from `get_unreported_broken_feeds`
+pub struct BrokenFeed {
+    pub podcast_id: i32,
+    pub user_id: i32,
+    pub podcast_name: String,
+    pub failing_since: NaiveDateTime,
+    pub last_error: Option<String>,
+}
+
+// Podcasts named in the digest before it switches to "and N more"
+const DIGEST_PODCASTS_LISTED: usize = 5;
+
+/// Send the weekly digest event to users with a destination subscribed to it
+pub async fn send_weekly_digests(db: &DatabasePool) -> AppResult<usize> {
+    let since = Utc::now().naive_utc() - chrono::Duration::days(7);
+    let mut sent = 0;
+    for user_id in db.get_users_subscribed_to_event(NotificationEventKind::WeeklyDigest).await? {
+        // Podcasts with new episodes, most first
+        let counts = db.get_new_episode_counts(user_id, since).await?;
+        let new_episodes: i64 = counts.iter().map(|(_, count)| count).sum();
+        if new_episodes == 0 {
+            continue;
+        }
+
+        let mut summary = counts.iter()
+            .take(DIGEST_PODCASTS_LISTED)
+            .map(|(podcast, count)| format!("{} ({})", podcast, count))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if counts.len() > DIGEST_PODCASTS_LISTED {
+            summary.push_str(&format!(" and {} more", counts.len() - DIGEST_PODCASTS_LISTED));
+        }
+        let event = NotificationEvent::WeeklyDigest { new_episodes, podcasts: counts.len(), summary };
+        match notify(db, user_id, &event).await {
+            Ok(_) => sent += 1,
+            Err(e) => tracing::warn!("Failed to send weekly digest to user {}: {}", user_id, e),
+        }
+    }
+    Ok(sent)
+}
+
+/// Notify a user without failing the caller; for events raised from background work
+pub async fn notify_quietly(db: &DatabasePool, user_id: i32, event: NotificationEvent) {
+    if let Err(e) = notify(db, user_id, &event).await {
+        tracing::warn!("Failed to send {} notification to user {}: {}", event.kind().as_str(), user_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ntfy(password: Option<&str>, access_token: Option<&str>) -> ProviderConfig {
+        ProviderConfig::Ntfy(NtfyNotifier {
+            server_url: default_ntfy_server(),
+            topic: "pinepods".to_string(),
+            username: Some("me".to_string()),
+            password: password.map(str::to_string),
+            access_token: access_token.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn responses_never_carry_secrets() {
+        let configs = [
+            ntfy(Some("hunter2"), Some("tk_secret")),
+            ProviderConfig::Gotify(GotifyNotifier { url: "https://gotify.example".to_string(), token: "tk_secret".to_string(), priority: None }),
+            ProviderConfig::Http(HttpNotifier { url: "https://example.com".to_string(), token: Some("tk_secret".to_string()), method: default_http_method() }),
+            ProviderConfig::Apprise(AppriseNotifier { url: "https://apprise.example/notify".to_string(), urls: Some("tgram://tk_secret/1".to_string()), tag: None }),
+            ProviderConfig::Matrix(MatrixNotifier { homeserver: "https://matrix.example".to_string(), access_token: "tk_secret".to_string(), room_id: "!r:example".to_string() }),
+            ProviderConfig::Webhook(WebhookNotifier { url: "https://discord.com/api/webhooks/1/tk_secret".to_string(), format: None }),
+        ];
+        for config in configs {
+            let json = serde_json::to_string(&config.redacted()).unwrap();
+            assert!(!json.contains("hunter2") && !json.contains("tk_secret"), "{} leaked: {}", config.name(), json);
+            assert!(json.contains(REDACTED));
+        }
+    }
+
+    #[test]
+    fn only_admin_destinations_reach_the_local_network() {
+        let gotify = |url: &str| ProviderConfig::Gotify(GotifyNotifier { url: url.to_string(), token: "t".to_string(), priority: None });
+        assert!(gotify("http://192.168.1.5:8080").check_endpoint(false).is_err());
+        assert!(gotify("http://localhost:8080").check_endpoint(false).is_err());
+        assert!(gotify("http://169.254.169.254/latest").check_endpoint(false).is_err());
+        assert!(gotify("http://192.168.1.5:8080").check_endpoint(true).is_ok());
+        assert!(gotify("https://gotify.example").check_endpoint(false).is_ok());
+        assert!(ProviderConfig::Email(EmailNotifier { to: "me@example.com".to_string() }).check_endpoint(false).is_ok());
+    }
+
+    #[test]
+    fn failures_report_the_status_but_not_the_body() {
+        let response = axum::http::Response::builder().status(500).body("root:x:0:0:secret").unwrap();
+        let error = check_response("Gotify", reqwest::Response::from(response)).unwrap_err().to_string();
+        assert!(error.contains("500"), "{}", error);
+        assert!(!error.contains("secret"), "{}", error);
+    }
+
+    #[test]
+    fn redaction_keeps_empty_fields_and_settings() {
+        let ProviderConfig::Ntfy(redacted) = ntfy(None, Some("")).redacted() else { unreachable!() };
+        assert_eq!(redacted.password, None);
+        assert_eq!(redacted.access_token.as_deref(), Some(""));
+        assert_eq!(redacted.username.as_deref(), Some("me"));
+        assert_eq!(redacted.topic, "pinepods");
+    }
+
+    #[test]
+    fn redacted_secrets_sent_back_keep_the_stored_values() {
+        let stored = ntfy(Some("hunter2"), Some("tk_secret"));
+        let mut update = ntfy(Some(REDACTED), Some("new_token"));
+        update.restore_secrets(&stored);
+        let ProviderConfig::Ntfy(update) = update else { unreachable!() };
+        assert_eq!(update.password.as_deref(), Some("hunter2"));
+        assert_eq!(update.access_token.as_deref(), Some("new_token"));
+
+        // A different provider can't pick up another provider's secrets
+        let mut gotify = ProviderConfig::Gotify(GotifyNotifier { url: "https://g.example".to_string(), token: REDACTED.to_string(), priority: None });
+        gotify.restore_secrets(&stored);
+        assert!(matches!(gotify, ProviderConfig::Gotify(ref g) if g.token == REDACTED));
+    }
+
+    #[test]
+    fn email_recipient_comparison_ignores_case() {
+        let email = EmailNotifier { to: " Me@Example.com ".to_string() };
+        assert!(email.sends_to("me@example.com"));
+        assert!(!email.sends_to("someone@example.com"));
+    }
+
+    #[test]
+    fn templates_fill_known_placeholders_only() {
+        let variables = [("podcast", "Show".to_string())];
+        assert_eq!(render("New on {{ podcast }}: {{episode}}", &variables), "New on Show: {{episode}}");
+        assert_eq!(render("unclosed {{podcast", &variables), "unclosed {{podcast");
+    }
+
+    #[test]
+    fn events_default_to_new_episodes() {
+        assert_eq!(parse_events(None), vec![NotificationEventKind::NewEpisode]);
+        let events = parse_events(Some("feed_broken,bogus,weekly_digest"));
+        assert_eq!(events, vec![NotificationEventKind::FeedBroken, NotificationEventKind::WeeklyDigest]);
+        assert_eq!(format_events(&events), "feed_broken,weekly_digest");
+    }
+}
@@ -0,0 +1,464 @@
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Header, Validation};
+use serde::Deserialize;
+use std::time::Duration;
+use crate::{
+    error::{AppError, AppResult},
+    redis_client::RedisClient,
+};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+// Key sets are refetched sooner when a token names a key we haven't seen
+const JWKS_CACHE_SECONDS: u64 = 3600;
+const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+
+/// The parts of a provider's `.well-known/openid-configuration` PinePods uses
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: Option<String>,
+    pub jwks_uri: String,
+    pub end_session_endpoint: Option<String>,
+}
+
+/// What is stored for a provider configured by issuer, used to verify its tokens
+#[derive(Debug, Clone)]
+pub struct OidcDiscovery {
+    pub issuer: String,
+    pub jwks_uri: String,
+    pub end_session_url: Option<String>,
+}
+
+/// A verified ID token
+#[derive(Debug, Clone)]
+pub struct IdToken {
+    pub subject: String,
+    /// The provider's session, when it reports one; back-channel logout names it
+    pub sid: Option<String>,
+    pub claims: serde_json::Value,
+}
+
+/// A verified back-channel logout token. At least one of the two is set.
+#[derive(Debug, Clone)]
+pub struct LogoutToken {
+    pub subject: Option<String>,
+    pub sid: Option<String>,
+}
+
+fn client() -> AppResult<reqwest::Client> {
+    Ok(reqwest::Client::builder()
+        .user_agent("PinePods/1.0")
+        .timeout(FETCH_TIMEOUT)
+        .build()?)
+}
+
+/// Fetch and check the discovery document for `issuer`
+pub async fn discover(issuer: &str) -> AppResult<ProviderMetadata> {
+    let issuer = issuer.trim().trim_end_matches('/');
+    if !issuer.starts_with("https://") && !issuer.starts_with("http://") {
+        return Err(AppError::bad_request("Issuer URL must start with https:// or http://"));
+    }
+    let url = format!("{}/.well-known/openid-configuration", issuer);
+    let response = client()?.get(&url).send().await
+        .map_err(|e| AppError::external_error(format!("Failed to fetch {}: {}", url, e)))?;
+    if !response.status().is_success() {
+        return Err(AppError::external_error(format!("{} returned {}", url, response.status())));
+    }
+    let metadata: ProviderMetadata = response.json().await
+        .map_err(|e| AppError::external_error(format!("Invalid discovery document at {}: {}", url, e)))?;
+
+    // Tokens carry the issuer exactly as the document states it, so a document
+    // claiming to be someone else can't be trusted for anything
+    if metadata.issuer.trim_end_matches('/') != issuer {
+        return Err(AppError::external_error(format!(
+            "Discovery document at {} is for issuer {}", url, metadata.issuer
+        )));
+    }
+    if metadata.userinfo_endpoint.is_none() {
+        return Err(AppError::external_error("The provider does not publish a userinfo endpoint"));
+    }
+    Ok(metadata)
+}
+
+async fn fetch_key_set(redis: &RedisClient, jwks_uri: &str) -> AppResult<JwkSet> {
+    let response = client()?.get(jwks_uri).send().await?.error_for_status()?;
+    let body = response.text().await?;
+    let keys: JwkSet = serde_json::from_str(&body)
+        .map_err(|e| AppError::external_error(format!("Invalid key set at {}: {}", jwks_uri, e)))?;
+    if let Err(e) = redis.set_ex(&format!("oidc_jwks:{}", jwks_uri), &body, JWKS_CACHE_SECONDS).await {
+        tracing::warn!("Failed to cache OIDC key set: {}", e);
+    }
+    Ok(keys)
+}
+
+// Without a kid the token can only be matched to a key set holding a single key
+fn pick_key(keys: &JwkSet, kid: Option<&str>) -> Option<AppResult<DecodingKey>> {
+    let jwk = match kid {
+        Some(kid) => keys.find(kid),
+        None if keys.keys.len() == 1 => keys.keys.first(),
+        None => None,
+    }?;
+    Some(DecodingKey::from_jwk(jwk).map_err(|e| AppError::external_error(format!("Unusable key in the provider's key set: {}", e))))
+}
+
+// The key that signed `token`, from the cached key set or, when it isn't there
+// (the provider rotated keys), a fresh copy
+async fn signing_key(redis: &RedisClient, jwks_uri: &str, kid: Option<&str>) -> AppResult<DecodingKey> {
+    let cached = redis.get::<String>(&format!("oidc_jwks:{}", jwks_uri)).await.ok().flatten()
+        .and_then(|body| serde_json::from_str::<JwkSet>(&body).ok());
+    if let Some(key) = cached.as_ref().and_then(|keys| pick_key(keys, kid)) {
+        return key;
+    }
+    pick_key(&fetch_key_set(redis, jwks_uri).await?, kid)
+        .unwrap_or_else(|| Err(AppError::Auth("No key in the provider's key set matches the token".to_string())))
+}
+
+// Read a token's header, refusing HMAC tokens: they would be checked against a key
+// the token's sender could have chosen
+fn token_header(token: &str) -> AppResult<Header> {
+    let header = decode_header(token).map_err(|e| AppError::Auth(format!("Malformed token: {}", e)))?;
+    if matches!(header.alg, Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512) {
+        return Err(AppError::Auth(format!("Tokens signed with {:?} are not accepted", header.alg)));
+    }
+    Ok(header)
+}
+
+// Check the signature, issuer, audience and expiry of a token signed with `key`
+fn check_token(key: &DecodingKey, alg: Algorithm, discovery: &OidcDiscovery, client_id: &str, token: &str, required: &[&str]) -> AppResult<serde_json::Value> {
+    let mut validation = Validation::new(alg);
+    validation.set_issuer(&[&discovery.issuer]);
+    validation.set_audience(&[client_id]);
+    validation.set_required_spec_claims(required);
+    let data = decode::<serde_json::Value>(token, key, &validation)
+        .map_err(|e| AppError::Auth(format!("Token rejected: {}", e)))?;
+    Ok(data.claims)
+}
+
+// Check a token from the provider against its published keys
+async fn verify(redis: &RedisClient, discovery: &OidcDiscovery, client_id: &str, token: &str, required: &[&str]) -> AppResult<serde_json::Value> {
+    let header = token_header(token)?;
+    let key = signing_key(redis, &discovery.jwks_uri, header.kid.as_deref()).await?;
+    check_token(&key, header.alg, discovery, client_id, token, required)
+}
+
+fn string_claim(claims: &serde_json::Value, name: &str) -> Option<String> {
+    claims.get(name).and_then(|v| v.as_str()).filter(|v| !v.is_empty()).map(str::to_string)
+}
+
+// The checks an ID token needs beyond its signature
+fn id_token(claims: serde_json::Value, nonce: Option<&str>) -> AppResult<IdToken> {
+    if let Some(nonce) = nonce {
+        if claims.get("nonce").and_then(|v| v.as_str()) != Some(nonce) {
+            return Err(AppError::Auth("ID token nonce does not match the login".to_string()));
+        }
+    }
+    Ok(IdToken {
+        subject: string_claim(&claims, "sub").ok_or_else(|| AppError::Auth("ID token has no subject".to_string()))?,
+        sid: string_claim(&claims, "sid"),
+        claims,
+    })
+}
+
+/// Verify the ID token from a code exchange. When the login was started with a nonce,
+/// the token must carry the same one.
+pub async fn verify_id_token(redis: &RedisClient, discovery: &OidcDiscovery, client_id: &str, token: &str, nonce: Option<&str>) -> AppResult<IdToken> {
+    id_token(verify(redis, discovery, client_id, token, &["exp", "iss", "aud", "sub"]).await?, nonce)
+}
+
+// The checks a logout token needs beyond its signature
+fn logout_token(claims: serde_json::Value) -> AppResult<LogoutToken> {
+    if claims.get("events").and_then(|events| events.get(BACKCHANNEL_LOGOUT_EVENT)).is_none() {
+        return Err(AppError::Auth("Not a back-channel logout token".to_string()));
+    }
+    // A nonce means this is an ID token being replayed as a logout token
+    if claims.get("nonce").is_some() {
+        return Err(AppError::Auth("Logout tokens must not carry a nonce".to_string()));
+    }
+    let logout = LogoutToken {
+        subject: string_claim(&claims, "sub"),
+        sid: string_claim(&claims, "sid"),
+    };
+    if logout.subject.is_none() && logout.sid.is_none() {
+        return Err(AppError::Auth("Logout token names neither a session nor a subject".to_string()));
+    }
+    Ok(logout)
+}
+
+/// Verify a logout token posted to the back-channel logout endpoint
+pub async fn verify_logout_token(redis: &RedisClient, discovery: &OidcDiscovery, client_id: &str, token: &str) -> AppResult<LogoutToken> {
+    logout_token(verify(redis, discovery, client_id, token, &["iss", "aud"]).await?)
+}
+
+/// The provider's end-session URL for RP-initiated logout, sending the browser back to
+/// `post_logout_redirect_uri` afterwards
+pub fn end_session_url(endpoint: &str, id_token: &str, client_id: &str, post_logout_redirect_uri: Option<&str>) -> AppResult<String> {
+    let mut url = url::Url::parse(endpoint)
+        .map_err(|e| AppError::internal(format!("Invalid end session URL {}: {}", endpoint, e)))?;
+    {
+        let mut query = url.query_pairs_mut();
+        query.append_pair("id_token_hint", id_token);
+        query.append_pair("client_id", client_id);
+        if let Some(redirect) = post_logout_redirect_uri {
+            query.append_pair("post_logout_redirect_uri", redirect);
+        }
+    }
+    Ok(url.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_lc_rs::{
+        rand::SystemRandom,
+        signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING},
+    };
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use jsonwebtoken::{encode, EncodingKey};
+    use serde_json::json;
+
+    const ISSUER: &str = "https://id.example.com/realms/pods";
+    const CLIENT_ID: &str = "pinepods";
+
+    // A provider signing key, with the JWK it publishes
+    struct Signer {
+        alg: Algorithm,
+        key: EncodingKey,
+        jwk: serde_json::Value,
+    }
+
+    impl Signer {
+        fn es256(kid: &str) -> Self {
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new()).unwrap();
+            let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap();
+            let point = pair.public_key().as_ref();
+            Signer {
+                alg: Algorithm::ES256,
+                key: EncodingKey::from_ec_der(pkcs8.as_ref()),
+                jwk: json!({
+                    "kty": "EC", "crv": "P-256", "use": "sig", "alg": "ES256", "kid": kid,
+                    "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+                    "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+                }),
+            }
+        }
+
+        fn eddsa(kid: &str) -> Self {
+            let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+            let pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+            Signer {
+                alg: Algorithm::EdDSA,
+                key: EncodingKey::from_ed_der(pkcs8.as_ref()),
+                jwk: json!({
+                    "kty": "OKP", "crv": "Ed25519", "use": "sig", "alg": "EdDSA", "kid": kid,
+                    "x": URL_SAFE_NO_PAD.encode(pair.public_key().as_ref()),
+                }),
+            }
+        }
+
+        fn sign(&self, claims: &serde_json::Value, with_kid: bool) -> String {
+            let mut header = Header::new(self.alg);
+            if with_kid {
+                header.kid = self.jwk["kid"].as_str().map(str::to_string);
+            }
+            encode(&header, claims, &self.key).unwrap()
+        }
+    }
+
+    fn key_set(signers: &[&Signer]) -> JwkSet {
+        serde_json::from_value(json!({ "keys": signers.iter().map(|s| s.jwk.clone()).collect::<Vec<_>>() })).unwrap()
+    }
+
+    fn discovery() -> OidcDiscovery {
+        OidcDiscovery {
+            issuer: ISSUER.to_string(),
+            jwks_uri: format!("{}/protocol/openid-connect/certs", ISSUER),
+            end_session_url: None,
+        }
+    }
+
+    fn now() -> i64 {
+        chrono::Utc::now().timestamp()
+    }
+
+    fn id_claims() -> serde_json::Value {
+        json!({
+            "iss": ISSUER, "aud": CLIENT_ID, "sub": "user-1", "sid": "session-1",
+            "iat": now(), "exp": now() + 300, "nonce": "nonce-1",
+        })
+    }
+
+    fn logout_claims() -> serde_json::Value {
+        json!({
+            "iss": ISSUER, "aud": CLIENT_ID, "sub": "user-1", "sid": "session-1", "iat": now(), "jti": "logout-1",
+            "events": { BACKCHANNEL_LOGOUT_EVENT: {} },
+        })
+    }
+
+    // What `verify` does, with the key set in hand instead of behind Redis
+    fn check(keys: &JwkSet, token: &str, required: &[&str]) -> AppResult<serde_json::Value> {
+        let header = token_header(token)?;
+        let key = pick_key(keys, header.kid.as_deref())
+            .unwrap_or_else(|| Err(AppError::Auth("No key in the provider's key set matches the token".to_string())))?;
+        check_token(&key, header.alg, &discovery(), CLIENT_ID, token, required)
+    }
+
+    fn check_id(keys: &JwkSet, token: &str, nonce: Option<&str>) -> AppResult<IdToken> {
+        id_token(check(keys, token, &["exp", "iss", "aud", "sub"])?, nonce)
+    }
+
+    #[test]
+    fn id_tokens_signed_by_the_provider_verify() {
+        for signer in [Signer::es256("ec-1"), Signer::eddsa("ed-1")] {
+            let keys = key_set(&[&Signer::es256("other"), &signer]);
+            let token = check_id(&keys, &signer.sign(&id_claims(), true), Some("nonce-1")).unwrap();
+            assert_eq!(token.subject, "user-1");
+            assert_eq!(token.sid.as_deref(), Some("session-1"));
+            assert_eq!(token.claims["nonce"], "nonce-1");
+        }
+    }
+
+    #[test]
+    fn tokens_for_someone_else_or_out_of_date_are_rejected() {
+        let signer = Signer::es256("ec-1");
+        let keys = key_set(&[&signer]);
+        let with = |name: &str, value: serde_json::Value| {
+            let mut claims = id_claims();
+            claims[name] = value;
+            signer.sign(&claims, true)
+        };
+        assert!(check_id(&keys, &with("iss", json!("https://evil.example.com")), None).is_err());
+        assert!(check_id(&keys, &with("aud", json!("another-client")), None).is_err());
+        assert!(check_id(&keys, &with("exp", json!(now() - 3600)), None).is_err());
+        assert!(check_id(&keys, &with("aud", json!(["another-client", CLIENT_ID])), None).is_ok());
+
+        let mut claims = id_claims();
+        claims.as_object_mut().unwrap().remove("sub");
+        assert!(check_id(&keys, &signer.sign(&claims, true), None).is_err());
+    }
+
+    #[test]
+    fn signatures_by_other_keys_are_rejected() {
+        let (signer, imposter) = (Signer::es256("ec-1"), Signer::es256("ec-1"));
+        let keys = key_set(&[&signer]);
+        assert!(check_id(&keys, &imposter.sign(&id_claims(), true), None).is_err());
+        // A kid the provider doesn't publish
+        assert!(check_id(&keys, &Signer::es256("ec-2").sign(&id_claims(), true), None).is_err());
+
+        let token = signer.sign(&id_claims(), true);
+        let (payload_start, signature_start) = (token.find('.').unwrap() + 1, token.rfind('.').unwrap());
+        let mut claims = id_claims();
+        claims["sub"] = json!("admin");
+        let forged_payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap());
+        let forged = format!("{}{}{}", &token[..payload_start], forged_payload, &token[signature_start..]);
+        assert!(check_id(&keys, &forged, None).is_err());
+    }
+
+    #[test]
+    fn tokens_without_a_kid_need_a_single_key_set() {
+        let signer = Signer::es256("ec-1");
+        let token = signer.sign(&id_claims(), false);
+        assert!(check_id(&key_set(&[&signer]), &token, None).is_ok());
+        assert!(check_id(&key_set(&[&signer, &Signer::eddsa("ed-1")]), &token, None).is_err());
+    }
+
+    #[test]
+    fn hmac_tokens_are_refused() {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some("ec-1".to_string());
+        let token = encode(&header, &id_claims(), &EncodingKey::from_secret(b"guessable")).unwrap();
+        assert!(matches!(token_header(&token), Err(AppError::Auth(message)) if message.contains("HS256")));
+        assert!(token_header("not.a.token").is_err());
+    }
+
+    #[test]
+    fn nonces_must_match_the_login() {
+        let signer = Signer::eddsa("ed-1");
+        let keys = key_set(&[&signer]);
+        let token = signer.sign(&id_claims(), true);
+        assert!(check_id(&keys, &token, Some("nonce-2")).is_err());
+        assert!(check_id(&keys, &token, None).is_ok());
+
+        let mut claims = id_claims();
+        claims.as_object_mut().unwrap().remove("nonce");
+        assert!(check_id(&keys, &signer.sign(&claims, true), Some("nonce-1")).is_err());
+    }
+
+    #[test]
+    fn logout_tokens_verify() {
+        let signer = Signer::es256("ec-1");
+        let keys = key_set(&[&signer]);
+        let logout = logout_token(check(&keys, &signer.sign(&logout_claims(), true), &["iss", "aud"]).unwrap()).unwrap();
+        assert_eq!(logout.subject.as_deref(), Some("user-1"));
+        assert_eq!(logout.sid.as_deref(), Some("session-1"));
+
+        let mut sid_only = logout_claims();
+        sid_only.as_object_mut().unwrap().remove("sub");
+        assert_eq!(logout_token(sid_only).unwrap().subject, None);
+    }
+
+    #[test]
+    fn logout_tokens_must_look_like_logout_tokens() {
+        let without = |name: &str| {
+            let mut claims = logout_claims();
+            claims.as_object_mut().unwrap().remove(name);
+            claims
+        };
+        assert!(logout_token(without("events")).is_err());
+        assert!(logout_token(json!({ "sub": "user-1", "events": { "something-else": {} } })).is_err());
+        // An ID token replayed as a logout token
+        let mut replayed = logout_claims();
+        replayed["nonce"] = json!("nonce-1");
+        assert!(logout_token(replayed).is_err());
+        let mut anonymous = without("sub");
+        anonymous.as_object_mut().unwrap().remove("sid");
+        assert!(logout_token(anonymous).is_err());
+    }
+
+    #[test]
+    fn end_session_urls_carry_the_hint_and_redirect() {
+        let url = end_session_url("https://id.example.com/logout?ui=1", "a.b.c", CLIENT_ID, Some("https://pods.example.com/?signed_out=1")).unwrap();
+        assert_eq!(
+            url,
+            "https://id.example.com/logout?ui=1&id_token_hint=a.b.c&client_id=pinepods&post_logout_redirect_uri=https%3A%2F%2Fpods.example.com%2F%3Fsigned_out%3D1"
+        );
+        assert!(!end_session_url("https://id.example.com/logout", "a.b.c", CLIENT_ID, None).unwrap().contains("post_logout"));
+        assert!(end_session_url("not a url", "a.b.c", CLIENT_ID, None).is_err());
+    }
+
+    #[tokio::test]
+    async fn discovery_documents_must_be_for_the_issuer() {
+        use axum::{routing::get, Json, Router};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let document = |issuer: String, userinfo: bool| {
+            let mut document = json!({
+                "issuer": issuer,
+                "authorization_endpoint": format!("{}/auth", issuer),
+                "token_endpoint": format!("{}/token", issuer),
+                "jwks_uri": format!("{}/certs", issuer),
+            });
+            if userinfo {
+                document["userinfo_endpoint"] = json!(format!("{}/userinfo", issuer));
+            }
+            document
+        };
+        let good = document(format!("{}/good/", base), true);
+        let imposter = document("https://id.example.com".to_string(), true);
+        let no_userinfo = document(format!("{}/bare", base), false);
+        let app = Router::new()
+            .route("/good/.well-known/openid-configuration", get(move || async move { Json(good) }))
+            .route("/imposter/.well-known/openid-configuration", get(move || async move { Json(imposter) }))
+            .route("/bare/.well-known/openid-configuration", get(move || async move { Json(no_userinfo) }));
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        // A trailing slash on either side still names the same issuer
+        let metadata = discover(&format!(" {}/good ", base)).await.unwrap();
+        assert_eq!(metadata.issuer, format!("{}/good/", base));
+        assert!(discover(&format!("{}/imposter", base)).await.is_err());
+        assert!(discover(&format!("{}/bare", base)).await.is_err());
+        assert!(discover(&format!("{}/missing", base)).await.is_err());
+        assert!(discover("ftp://id.example.com").await.is_err());
+    }
+}
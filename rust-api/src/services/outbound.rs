@@ -0,0 +1,168 @@
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+
+// Requests to URLs users supply (webhooks, episode enclosures) must not reach the
+// server's own network: loopback, private, link-local and other special-purpose ranges
+// are refused both for literal addresses and for whatever a host name resolves to.
+
+/// Whether an address is on the public internet
+pub fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(mapped) => is_public_ipv4(mapped),
+            None => is_public_ipv6(ip),
+        },
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, c, _] = ip.octets();
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_broadcast()
+        || ip.is_multicast()
+        || ip.is_documentation()
+        // "This network", carrier-grade NAT, IETF protocol assignments, benchmarking, reserved
+        || a == 0
+        || (a == 100 && (64..128).contains(&b))
+        || (a == 192 && b == 0 && c == 0)
+        || (a == 198 && (18..20).contains(&b))
+        || a >= 240)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    !(ip.is_unspecified()
+        || ip.is_loopback()
+        || ip.is_multicast()
+        // Unique local fc00::/7, link-local fe80::/10, documentation 2001:db8::/32
+        || (first & 0xfe00) == 0xfc00
+        || (first & 0xffc0) == 0xfe80
+        || (first == 0x2001 && ip.segments()[1] == 0x0db8)
+        // IPv4-compatible, NAT64, 6to4 and Teredo addresses embed an IPv4 address that may be private
+        || ip.segments()[..6].iter().all(|&s| s == 0)
+        || (first == 0x0064 && ip.segments()[1] == 0xff9b)
+        || first == 0x2002
+        || (first == 0x2001 && ip.segments()[1] == 0x0000))
+}
+
+/// Check an outbound URL before it's requested: http(s) only, and a literal address or
+/// `localhost` must be public. Names are checked again when they are resolved.
+pub fn check_url(url: &url::Url) -> Result<(), String> {
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err("URL must use http or https".to_string());
+    }
+    match url.host() {
+        Some(url::Host::Ipv4(ip)) if !is_public_ipv4(ip) => Err(format!("{} is not a public address", ip)),
+        Some(url::Host::Ipv6(ip)) if !is_public_ip(IpAddr::V6(ip)) => Err(format!("{} is not a public address", ip)),
+        Some(url::Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            if domain == "localhost" || domain.ends_with(".localhost") {
+                Err(format!("{} is not a public address", domain))
+            } else {
+                Ok(())
+            }
+        }
+        Some(_) => Ok(()),
+        None => Err("URL has no host".to_string()),
+    }
+}
+
+/// Resolves host names, refusing any that lead only to non-public addresses
+#[derive(Debug, Default)]
+pub struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} does not resolve to a public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Follow at most `max` redirects, each of which must pass `check_url`
+pub fn redirect_policy(max: usize) -> reqwest::redirect::Policy {
+    reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= max {
+            attempt.error("too many redirects")
+        } else if let Err(e) = check_url(attempt.url()) {
+            attempt.error(e)
+        } else {
+            attempt.follow()
+        }
+    })
+}
+
+/// A client builder whose connections only go to public addresses. Callers still run
+/// `check_url` on the first URL, and use `redirect_policy` if they follow redirects.
+pub fn client_builder() -> reqwest::ClientBuilder {
+    reqwest::Client::builder().dns_resolver(Arc::new(PublicResolver))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn private_and_special_addresses_are_not_public() {
+        for value in [
+            "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.10", "169.254.169.254", "0.0.0.0",
+            "100.64.0.1", "255.255.255.255", "224.0.0.1", "198.18.0.1", "::1", "::", "fe80::1",
+            "fd00::1", "::ffff:127.0.0.1", "::ffff:10.0.0.1", "64:ff9b::a00:1", "::10.0.0.1",
+            "2002:7f00:1::1", "2002:a00:1::", "2001:0:4136:e378:8000:63bf:f5ff:fffe",
+        ] {
+            assert!(!is_public_ip(ip(value)), "{} should not be public", value);
+        }
+    }
+
+    #[test]
+    fn internet_addresses_are_public() {
+        for value in ["1.1.1.1", "93.184.216.34", "100.128.0.1", "2606:4700:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_public_ip(ip(value)), "{} should be public", value);
+        }
+    }
+
+    #[test]
+    fn urls_with_private_hosts_are_refused() {
+        let check = |value: &str| check_url(&url::Url::parse(value).unwrap());
+        assert!(check("http://127.0.0.1:8080/hook").is_err());
+        assert!(check("http://[::1]/hook").is_err());
+        assert!(check("http://169.254.169.254/latest/meta-data").is_err());
+        assert!(check("http://localhost/hook").is_err());
+        assert!(check("http://api.localhost./hook").is_err());
+        assert!(check("ftp://example.com/file").is_err());
+        assert!(check("https://example.com/hook").is_ok());
+        assert!(check("https://8.8.8.8/hook").is_ok());
+    }
+
+    #[tokio::test]
+    async fn resolver_refuses_names_that_only_lead_to_loopback() {
+        let name: Name = "localhost".parse().unwrap();
+        assert!(PublicResolver.resolve(name).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn clients_cannot_connect_to_local_servers_by_name() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let client = client_builder().redirect(redirect_policy(3)).build().unwrap();
+        let result = client.get(format!("http://localhost:{}/", port)).send().await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use axum::http::HeaderMap;
+use std::net::IpAddr;
+use crate::config::ProxyAuthConfig;
+
+/// A user signed in by the authenticating proxy
+#[derive(Debug, Clone)]
+pub struct ProxyIdentity {
+    pub username: String,
+    pub email: Option<String>,
+    pub fullname: Option<String>,
+    pub groups: Vec<String>,
+}
+
+impl ProxyIdentity {
+    pub fn in_group(&self, group: &str) -> bool {
+        self.groups.iter().any(|g| g == group)
+    }
+}
+
+/// Address a request reached PinePods from. The bundled nginx connects over loopback and
+/// sets X-Real-IP to the address it saw, overwriting anything the client sent; X-Forwarded-For
+/// is never used since its first entry is whatever the client claims.
+pub fn source_ip(peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+    if !peer.is_loopback() {
+        return peer;
+    }
+    headers.get("X-Real-IP")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse().ok())
+        .unwrap_or(peer)
+}
+
+/// The user asserted by the proxy, if the request carries the user header and came from a
+/// trusted proxy. The header is ignored from anywhere else, so clients can't name themselves.
+pub fn identity(config: &ProxyAuthConfig, peer: IpAddr, headers: &HeaderMap) -> Option<ProxyIdentity> {
+    let header = |name: &str| {
+        headers.get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+
+    let username = header(&config.user_header)?;
+    let source = source_ip(peer, headers);
+    if !config.trusted_proxies.iter().any(|net| net.contains(&source)) {
+        tracing::warn!("Ignoring {} header from untrusted address {}", config.user_header, source);
+        return None;
+    }
+
+    Some(ProxyIdentity {
+        username: username.to_lowercase(),
+        email: header(&config.email_header),
+        fullname: header(&config.name_header),
+        groups: header(&config.groups_header)
+            .map(|groups| groups.split(',').map(|g| g.trim().to_string()).filter(|g| !g.is_empty()).collect())
+            .unwrap_or_default(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ProxyAuthConfig {
+        ProxyAuthConfig {
+            trusted_proxies: vec!["127.0.0.1/32".parse().unwrap(), "10.0.0.0/24".parse().unwrap()],
+            user_header: "Remote-User".to_string(),
+            email_header: "Remote-Email".to_string(),
+            name_header: "Remote-Name".to_string(),
+            groups_header: "Remote-Groups".to_string(),
+            user_group: None,
+            admin_group: None,
+            auto_create: true,
+            link_existing: false,
+            require_mfa: false,
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn real_ip_is_only_taken_from_the_local_proxy() {
+        let forwarded = headers(&[("X-Real-IP", " 10.0.0.5 "), ("X-Forwarded-For", "127.0.0.1")]);
+        assert_eq!(source_ip(ip("127.0.0.1"), &forwarded), ip("10.0.0.5"));
+        assert_eq!(source_ip(ip("::1"), &forwarded), ip("10.0.0.5"));
+        assert_eq!(source_ip(ip("203.0.113.9"), &forwarded), ip("203.0.113.9"));
+        assert_eq!(source_ip(ip("127.0.0.1"), &headers(&[("X-Real-IP", "garbage")])), ip("127.0.0.1"));
+        assert_eq!(source_ip(ip("127.0.0.1"), &HeaderMap::new()), ip("127.0.0.1"));
+    }
+
+    #[test]
+    fn trusted_proxies_can_name_the_user() {
+        let request = headers(&[
+            ("X-Real-IP", "10.0.0.5"),
+            ("Remote-User", " Alice "),
+            ("Remote-Email", "alice@example.com"),
+            ("Remote-Name", "Alice Liddell"),
+            ("Remote-Groups", "pinepods, admins,,  "),
+        ]);
+        let identity = identity(&config(), ip("127.0.0.1"), &request).unwrap();
+        assert_eq!(identity.username, "alice");
+        assert_eq!(identity.email.as_deref(), Some("alice@example.com"));
+        assert_eq!(identity.fullname.as_deref(), Some("Alice Liddell"));
+        assert_eq!(identity.groups, vec!["pinepods", "admins"]);
+        assert!(identity.in_group("admins"));
+        assert!(!identity.in_group("Admins"));
+    }
+
+    #[test]
+    fn user_headers_from_elsewhere_are_ignored() {
+        let request = headers(&[("Remote-User", "admin")]);
+        assert!(identity(&config(), ip("203.0.113.9"), &request).is_none());
+        // The local proxy relayed a request from an untrusted address
+        let relayed = headers(&[("Remote-User", "admin"), ("X-Real-IP", "192.168.1.20")]);
+        assert!(identity(&config(), ip("127.0.0.1"), &relayed).is_none());
+        // Directly from a trusted proxy, no X-Real-IP needed
+        assert!(identity(&config(), ip("10.0.0.7"), &request).is_some());
+    }
+
+    #[test]
+    fn requests_without_a_user_are_not_proxy_logins() {
+        assert!(identity(&config(), ip("127.0.0.1"), &HeaderMap::new()).is_none());
+        assert!(identity(&config(), ip("127.0.0.1"), &headers(&[("Remote-User", "  ")])).is_none());
+
+        let bare = identity(&config(), ip("127.0.0.1"), &headers(&[("Remote-User", "bob"), ("Remote-Email", "")])).unwrap();
+        assert_eq!(bare.email, None);
+        assert_eq!(bare.fullname, None);
+        assert!(bare.groups.is_empty());
+    }
+}
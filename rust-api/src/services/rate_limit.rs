@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use crate::error::{AppError, AppResult};
+
+/// Every budget is counted over a fixed one minute window
+pub const RATE_LIMIT_WINDOW_SECONDS: u64 = 60;
+
+// Credential and reset-code endpoints, budgeted per client IP only
+const LOGIN_ROUTES: &[&str] = &[
+    "/api/data/get_key",
+    "/api/data/verify_mfa_and_get_key",
+    "/api/data/passkey_login/start",
+    "/api/data/passkey_login/finish",
+    "/api/data/reset_password_create_code",
+    "/api/data/verify_and_reset_password",
+    "/api/data/verify_mfa",
+    "/api/auth/proxy_login",
+];
+
+// Endpoints that fan out to remote feeds or run heavy queries
+const EXPENSIVE_ROUTES: &[&str] = &[
+    "/api/data/refresh_pods",
+    "/api/data/refresh_gpodder_subscriptions",
+    "/api/data/refresh_nextcloud_subscriptions",
+    "/api/data/refresh_hosts",
+    "/api/data/search_data",
+    "/api/data/search_youtube_channels",
+    "/api/data/import_opml",
+    "/ws/api/data/episodes",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitTier {
+    Login,
+    Expensive,
+    Default,
+}
+
+impl RateLimitTier {
+    pub fn classify(path: &str) -> Self {
+        let matches = |route: &&str| path.strip_prefix(*route).is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+        if LOGIN_ROUTES.iter().any(matches) {
+            RateLimitTier::Login
+        } else if EXPENSIVE_ROUTES.iter().any(matches) {
+            RateLimitTier::Expensive
+        } else {
+            RateLimitTier::Default
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RateLimitTier::Login => "login",
+            RateLimitTier::Expensive => "expensive",
+            RateLimitTier::Default => "default",
+        }
+    }
+}
+
+/// Who a request is counted against
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimitClient<'a> {
+    /// A valid API key, so users sharing an address (behind a reverse proxy, say) get
+    /// budgets of their own
+    ApiKey(&'a str),
+    Ip(IpAddr),
+}
+
+/// Redis bucket name for a client's budget in a tier. Keys are hashed so the bucket names
+/// don't hand out working credentials.
+pub fn bucket(tier: RateLimitTier, client: RateLimitClient) -> String {
+    match client {
+        RateLimitClient::ApiKey(api_key) => format!("{}:key:{}", tier.as_str(), hex::encode(Sha256::digest(api_key.as_bytes()))),
+        RateLimitClient::Ip(ip) => format!("{}:ip:{}", tier.as_str(), ip),
+    }
+}
+
+/// Per-minute request budgets, stored in AppSettings and editable by admins. Limiting is
+/// on by default, with budgets loose enough that normal use never reaches them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RateLimitSettings {
+    pub enabled: bool,
+    pub login_per_minute: u32,
+    pub expensive_per_minute: u32,
+    pub default_per_minute: u32,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        RateLimitSettings {
+            enabled: true,
+            login_per_minute: 10,
+            expensive_per_minute: 30,
+            default_per_minute: 600,
+        }
+    }
+}
+
+impl RateLimitSettings {
+    pub fn limit_for(&self, tier: RateLimitTier) -> u32 {
+        match tier {
+            RateLimitTier::Login => self.login_per_minute,
+            RateLimitTier::Expensive => self.expensive_per_minute,
+            RateLimitTier::Default => self.default_per_minute,
+        }
+    }
+
+    pub fn validate(&self) -> AppResult<()> {
+        for (name, value) in [
+            ("login_per_minute", self.login_per_minute),
+            ("expensive_per_minute", self.expensive_per_minute),
+            ("default_per_minute", self.default_per_minute),
+        ] {
+            if !(1..=100_000).contains(&value) {
+                return Err(AppError::bad_request(format!("{} must be between 1 and 100000", name)));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_fall_into_tiers() {
+        assert_eq!(RateLimitTier::classify("/api/data/get_key"), RateLimitTier::Login);
+        assert_eq!(RateLimitTier::classify("/api/data/passkey_login/finish"), RateLimitTier::Login);
+        assert_eq!(RateLimitTier::classify("/api/data/refresh_pods"), RateLimitTier::Expensive);
+        assert_eq!(RateLimitTier::classify("/api/data/search_data/extra"), RateLimitTier::Expensive);
+        assert_eq!(RateLimitTier::classify("/api/data/get_keys"), RateLimitTier::Default);
+        assert_eq!(RateLimitTier::classify("/api/data/return_episodes"), RateLimitTier::Default);
+    }
+
+    #[test]
+    fn limits_follow_the_tier() {
+        let settings = RateLimitSettings { enabled: true, login_per_minute: 1, expensive_per_minute: 2, default_per_minute: 3 };
+        assert_eq!(settings.limit_for(RateLimitTier::Login), 1);
+        assert_eq!(settings.limit_for(RateLimitTier::Expensive), 2);
+        assert_eq!(settings.limit_for(RateLimitTier::Default), 3);
+    }
+
+    #[test]
+    fn limiting_is_on_by_default() {
+        assert!(RateLimitSettings::default().enabled);
+        assert!(RateLimitSettings::default().validate().is_ok());
+    }
+
+    #[test]
+    fn limits_must_be_positive_and_bounded() {
+        let mut settings = RateLimitSettings { login_per_minute: 0, ..RateLimitSettings::default() };
+        assert!(settings.validate().is_err());
+        settings.login_per_minute = 100_001;
+        assert!(settings.validate().is_err());
+    }
+
+    #[test]
+    fn buckets_never_contain_the_api_key() {
+        let name = bucket(RateLimitTier::Default, RateLimitClient::ApiKey("pinepods-secret-key"));
+        assert!(!name.contains("pinepods-secret-key"));
+        assert!(name.starts_with("default:key:"));
+        assert_eq!(name, bucket(RateLimitTier::Default, RateLimitClient::ApiKey("pinepods-secret-key")));
+        assert_ne!(name, bucket(RateLimitTier::Expensive, RateLimitClient::ApiKey("pinepods-secret-key")));
+        assert_ne!(name, bucket(RateLimitTier::Default, RateLimitClient::ApiKey("another-key")));
+        assert_eq!(bucket(RateLimitTier::Login, RateLimitClient::Ip("203.0.113.7".parse().unwrap())), "login:ip:203.0.113.7");
+    }
+}
@@ -0,0 +1,268 @@
+use chrono::{Duration, NaiveDateTime};
+use regex::Regex;
+use serde::Serialize;
+
+/// Bounds on how often a single feed is polled
+pub const MIN_REFRESH_MINUTES: i64 = 15;
+pub const MAX_REFRESH_MINUTES: i64 = 7 * 24 * 60;
+
+// Used when a feed has no episodes and no hints to go on
+const DEFAULT_REFRESH_MINUTES: i64 = 6 * 60;
+// Check roughly four times per expected gap between episodes
+const CADENCE_DIVISOR: i64 = 4;
+// Shows that have been silent this long are polled at most daily
+const DORMANT_AFTER_DAYS: i64 = 90;
+// Each consecutive failure doubles the interval, up to 2^6
+const MAX_BACKOFF_EXPONENT: u32 = 6;
+
+/// Update hints published in the feed itself
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct FeedScheduleHints {
+    /// `<ttl>` in minutes: how long the feed may be cached
+    pub ttl_minutes: Option<i64>,
+    /// `<sy:updatePeriod>` / `<sy:updateFrequency>` or `<podcast:updateFrequency rrule>`, in minutes
+    pub update_minutes: Option<i64>,
+    /// `<podcast:updateFrequency complete="true">`: no new episodes will be published
+    pub complete: bool,
+}
+
+impl FeedScheduleHints {
+    pub fn parse(feed_content: &str) -> Self {
+        // Only look at the channel header, item-level tags would be misleading
+        let header = match feed_content.find("<item") {
+            Some(pos) => &feed_content[..pos],
+            None => feed_content,
+        };
+
+        let ttl_minutes = capture(header, r"<ttl>\s*(\d+)\s*</ttl>").and_then(|v| v.parse().ok()).filter(|v: &i64| *v > 0);
+
+        let sy_period = capture(header, r"<sy:updatePeriod>\s*(\w+)\s*</sy:updatePeriod>").and_then(|p| period_minutes(&p));
+        let sy_frequency: i64 = capture(header, r"<sy:updateFrequency>\s*(\d+)\s*</sy:updateFrequency>")
+            .and_then(|v| v.parse().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(1);
+        let sy_minutes = sy_period.map(|period| period / sy_frequency);
+
+        let update_frequency = Regex::new(r"(?s)<podcast:updateFrequency\b([^>]*)>").unwrap().captures(header).map(|c| c[1].to_string());
+        let complete = update_frequency.as_deref().is_some_and(|attrs| {
+            Regex::new(r#"complete\s*=\s*["']true["']"#).unwrap().is_match(attrs)
+        });
+        let rrule_minutes = update_frequency
+            .as_deref()
+            .and_then(|attrs| capture(attrs, r#"rrule\s*=\s*["']([^"']+)["']"#))
+            .and_then(|rrule| rrule_minutes(&rrule));
+
+        FeedScheduleHints {
+            ttl_minutes,
+            update_minutes: rrule_minutes.or(sy_minutes),
+            complete,
+        }
+    }
+}
+
+fn capture(text: &str, pattern: &str) -> Option<String> {
+    Regex::new(pattern).ok()?.captures(text).map(|c| c[1].to_string())
+}
+
+fn period_minutes(period: &str) -> Option<i64> {
+    match period.to_lowercase().as_str() {
+        "hourly" => Some(60),
+        "daily" => Some(24 * 60),
+        "weekly" => Some(7 * 24 * 60),
+        "monthly" => Some(30 * 24 * 60),
+        "yearly" => Some(365 * 24 * 60),
+        _ => None,
+    }
+}
+
+// FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH -> two weeks split over two days
+fn rrule_minutes(rrule: &str) -> Option<i64> {
+    let mut freq = None;
+    let mut interval = 1;
+    let mut by_day = 1;
+    for part in rrule.split(';') {
+        let Some((key, value)) = part.split_once('=') else {
+            continue;
+        };
+        match key.trim().to_uppercase().as_str() {
+            "FREQ" => freq = period_minutes(value.trim()),
+            "INTERVAL" => interval = value.trim().parse().ok().filter(|v: &i64| *v > 0).unwrap_or(1),
+            "BYDAY" => by_day = value.split(',').count().max(1) as i64,
+            _ => {}
+        }
+    }
+    freq.map(|minutes| minutes * interval / by_day)
+}
+
+/// Everything that goes into choosing when a feed is polled next
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleInputs {
+    /// Publish dates of the most recent episodes, any order
+    pub recent_pub_dates: Vec<NaiveDateTime>,
+    pub hints: FeedScheduleHints,
+    pub consecutive_failures: u32,
+    /// Per-podcast interval chosen by the user, in minutes
+    pub override_minutes: Option<i64>,
+}
+
+/// Interval in minutes until the next refresh of a feed
+pub fn refresh_interval_minutes(inputs: &ScheduleInputs, now: NaiveDateTime) -> i64 {
+    let base = match inputs.override_minutes {
+        Some(minutes) => minutes,
+        None => inferred_interval(inputs, now),
+    };
+
+    let backoff = 1i64 << inputs.consecutive_failures.min(MAX_BACKOFF_EXPONENT);
+    (base.saturating_mul(backoff)).clamp(MIN_REFRESH_MINUTES, MAX_REFRESH_MINUTES)
+}
+
+fn inferred_interval(inputs: &ScheduleInputs, now: NaiveDateTime) -> i64 {
+    if inputs.hints.complete {
+        return MAX_REFRESH_MINUTES;
+    }
+
+    let mut dates = inputs.recent_pub_dates.clone();
+    dates.sort_unstable_by(|a, b| b.cmp(a));
+
+    // Follow whichever of the stated schedule and the observed history is more frequent
+    let mut interval = [inputs.hints.update_minutes, median_gap_minutes(&dates)]
+        .into_iter()
+        .flatten()
+        .map(|period| period / CADENCE_DIVISOR)
+        .min()
+        .unwrap_or(DEFAULT_REFRESH_MINUTES);
+
+    // Polling faster than the feed says it may be cached is wasted work
+    if let Some(ttl) = inputs.hints.ttl_minutes {
+        interval = interval.max(ttl.min(24 * 60));
+    }
+
+    let silent_days = dates.first().map(|latest| (now - *latest).num_days());
+    if silent_days.is_some_and(|days| days >= DORMANT_AFTER_DAYS) {
+        interval = interval.max(24 * 60);
+    }
+
+    interval
+}
+
+fn median_gap_minutes(sorted_desc: &[NaiveDateTime]) -> Option<i64> {
+    let mut gaps: Vec<i64> = sorted_desc
+        .windows(2)
+        .map(|pair| (pair[0] - pair[1]).num_minutes())
+        .filter(|gap| *gap > 0)
+        .collect();
+    if gaps.is_empty() {
+        return None;
+    }
+    gaps.sort_unstable();
+    Some(gaps[gaps.len() / 2])
+}
+
+/// Next refresh time, spread by a few minutes per podcast so feeds with the same
+/// cadence don't all come due on the same scheduler tick
+pub fn next_refresh_at(podcast_id: i32, interval_minutes: i64, now: NaiveDateTime) -> NaiveDateTime {
+    let jitter = (podcast_id.rem_euclid(5)) as i64;
+    now + Duration::minutes(interval_minutes + jitter)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(days_ago: i64, now: NaiveDateTime) -> NaiveDateTime {
+        now - Duration::days(days_ago)
+    }
+
+    fn now() -> NaiveDateTime {
+        chrono::NaiveDate::from_ymd_opt(2025, 6, 1).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn daily_show_is_checked_about_every_six_hours() {
+        let now = now();
+        let inputs = ScheduleInputs {
+            recent_pub_dates: (1..10).map(|d| at(d, now)).collect(),
+            ..Default::default()
+        };
+        assert_eq!(refresh_interval_minutes(&inputs, now), 24 * 60 / CADENCE_DIVISOR);
+    }
+
+    #[test]
+    fn feed_without_history_uses_the_default() {
+        assert_eq!(refresh_interval_minutes(&ScheduleInputs::default(), now()), DEFAULT_REFRESH_MINUTES);
+    }
+
+    #[test]
+    fn stated_schedule_wins_when_more_frequent_than_history() {
+        let now = now();
+        let inputs = ScheduleInputs {
+            recent_pub_dates: (0..5).map(|w| at(w * 7, now)).collect(),
+            hints: FeedScheduleHints { update_minutes: Some(24 * 60), ..Default::default() },
+            ..Default::default()
+        };
+        assert_eq!(refresh_interval_minutes(&inputs, now), 6 * 60);
+    }
+
+    #[test]
+    fn ttl_slows_polling_down() {
+        let inputs = ScheduleInputs {
+            hints: FeedScheduleHints { ttl_minutes: Some(12 * 60), update_minutes: Some(60), complete: false },
+            ..Default::default()
+        };
+        assert_eq!(refresh_interval_minutes(&inputs, now()), 12 * 60);
+    }
+
+    #[test]
+    fn dormant_and_complete_feeds_are_polled_rarely() {
+        let now = now();
+        let dormant = ScheduleInputs {
+            recent_pub_dates: (100..110).map(|d| at(d, now)).collect(),
+            ..Default::default()
+        };
+        assert_eq!(refresh_interval_minutes(&dormant, now), 24 * 60);
+
+        let complete = ScheduleInputs {
+            hints: FeedScheduleHints { complete: true, ..Default::default() },
+            ..Default::default()
+        };
+        assert_eq!(refresh_interval_minutes(&complete, now), MAX_REFRESH_MINUTES);
+    }
+
+    #[test]
+    fn failures_back_off_within_bounds() {
+        let inputs = |failures| ScheduleInputs { override_minutes: Some(60), consecutive_failures: failures, ..Default::default() };
+        assert_eq!(refresh_interval_minutes(&inputs(0), now()), 60);
+        assert_eq!(refresh_interval_minutes(&inputs(2), now()), 240);
+        assert_eq!(refresh_interval_minutes(&inputs(40), now()), 60 << MAX_BACKOFF_EXPONENT);
+    }
+
+    #[test]
+    fn intervals_are_clamped() {
+        let fast = ScheduleInputs { override_minutes: Some(1), ..Default::default() };
+        assert_eq!(refresh_interval_minutes(&fast, now()), MIN_REFRESH_MINUTES);
+        let slow = ScheduleInputs { override_minutes: Some(MAX_REFRESH_MINUTES), consecutive_failures: 3, ..Default::default() };
+        assert_eq!(refresh_interval_minutes(&slow, now()), MAX_REFRESH_MINUTES);
+    }
+
+    #[test]
+    fn feed_hints_are_read_from_the_channel_header() {
+        let feed = r#"<rss><channel><ttl>90</ttl><sy:updatePeriod>daily</sy:updatePeriod><sy:updateFrequency>2</sy:updateFrequency>
+            <item><ttl>5</ttl></item></channel></rss>"#;
+        let hints = FeedScheduleHints::parse(feed);
+        assert_eq!(hints.ttl_minutes, Some(90));
+        assert_eq!(hints.update_minutes, Some(12 * 60));
+        assert!(!hints.complete);
+
+        let podcast = r#"<channel><podcast:updateFrequency complete="true" rrule="FREQ=WEEKLY;INTERVAL=2;BYDAY=MO,TH">x</podcast:updateFrequency></channel>"#;
+        let hints = FeedScheduleHints::parse(podcast);
+        assert!(hints.complete);
+        assert_eq!(hints.update_minutes, Some(7 * 24 * 60));
+    }
+
+    #[test]
+    fn next_refresh_is_spread_by_podcast() {
+        let now = now();
+        assert_eq!(next_refresh_at(10, 60, now), now + Duration::minutes(60));
+        assert_eq!(next_refresh_at(13, 60, now), now + Duration::minutes(63));
+    }
+}
@@ -0,0 +1,177 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+/// Longest "delete after" period accepted, about ten years
+pub const MAX_RETENTION_DAYS: i32 = 3650;
+
+/// How long a podcast's downloaded episodes are kept. Every rule that is set applies,
+/// so a download goes as soon as any of them says so.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Keep only this many of the newest downloaded episodes (by publish date)
+    pub keep_newest: Option<i32>,
+    /// Delete downloads once the episode is marked completed
+    pub delete_after_completion: bool,
+    /// Delete downloads this many days after they were downloaded
+    pub delete_after_days: Option<i32>,
+    /// Never delete episodes the user saved, whatever the other rules say
+    pub keep_saved: bool,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            keep_newest: None,
+            delete_after_completion: false,
+            delete_after_days: None,
+            keep_saved: true,
+        }
+    }
+}
+
+impl RetentionPolicy {
+    /// Whether the policy can delete anything at all
+    pub fn is_active(&self) -> bool {
+        self.keep_newest.is_some() || self.delete_after_completion || self.delete_after_days.is_some()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.keep_newest.is_some_and(|n| n < 0) {
+            return Err("keep_newest cannot be negative".to_string());
+        }
+        if self.delete_after_days.is_some_and(|days| !(1..=MAX_RETENTION_DAYS).contains(&days)) {
+            return Err(format!("delete_after_days must be between 1 and {}", MAX_RETENTION_DAYS));
+        }
+        Ok(())
+    }
+
+    /// Downloads this policy removes, by download ID
+    pub fn expired(&self, downloads: &[RetainedDownload], now: NaiveDateTime) -> Vec<i32> {
+        let mut expired = HashSet::new();
+
+        if let Some(keep) = self.keep_newest {
+            let mut newest_first: Vec<&RetainedDownload> = downloads.iter().collect();
+            // Undated episodes count as oldest; ties fall back to the newer download
+            newest_first.sort_by(|a, b| b.pub_date.cmp(&a.pub_date).then(b.download_id.cmp(&a.download_id)));
+            expired.extend(newest_first.iter().skip(keep.max(0) as usize).map(|d| d.download_id));
+        }
+        for download in downloads {
+            if self.delete_after_completion && download.completed {
+                expired.insert(download.download_id);
+            }
+            if let (Some(days), Some(downloaded_at)) = (self.delete_after_days, download.downloaded_at) {
+                if (now - downloaded_at).num_days() >= days as i64 {
+                    expired.insert(download.download_id);
+                }
+            }
+        }
+
+        let mut result: Vec<i32> = downloads
+            .iter()
+            .filter(|d| expired.contains(&d.download_id) && !(self.keep_saved && d.saved))
+            .map(|d| d.download_id)
+            .collect();
+        result.sort_unstable();
+        result
+    }
+}
+
+/// A downloaded episode as retention rules see it
+#[derive(Debug, Clone)]
+pub struct RetainedDownload {
+    pub download_id: i32,
+    pub episode_id: i32,
+    pub size_bytes: i64,
+    pub pub_date: Option<NaiveDateTime>,
+    pub downloaded_at: Option<NaiveDateTime>,
+    pub completed: bool,
+    pub saved: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(n: u32) -> Option<NaiveDateTime> {
+        chrono::NaiveDate::from_ymd_opt(2025, 1, n).and_then(|d| d.and_hms_opt(12, 0, 0))
+    }
+
+    fn download(download_id: i32, published: u32) -> RetainedDownload {
+        RetainedDownload {
+            download_id,
+            episode_id: download_id * 10,
+            size_bytes: 1024,
+            pub_date: day(published),
+            downloaded_at: day(published),
+            completed: false,
+            saved: false,
+        }
+    }
+
+    fn policy() -> RetentionPolicy {
+        RetentionPolicy { keep_saved: false, ..RetentionPolicy::default() }
+    }
+
+    #[test]
+    fn default_policy_keeps_everything() {
+        let policy = RetentionPolicy::default();
+        assert!(!policy.is_active());
+        assert!(policy.keep_saved);
+        let downloads = [download(1, 1), RetainedDownload { completed: true, ..download(2, 2) }];
+        assert!(policy.expired(&downloads, day(30).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn only_the_newest_episodes_are_kept() {
+        let downloads = [download(1, 3), download(2, 1), download(3, 5), RetainedDownload { pub_date: None, ..download(4, 9) }];
+        let keep_two = RetentionPolicy { keep_newest: Some(2), ..policy() };
+        assert_eq!(keep_two.expired(&downloads, day(10).unwrap()), vec![2, 4]);
+
+        let keep_none = RetentionPolicy { keep_newest: Some(0), ..policy() };
+        assert_eq!(keep_none.expired(&downloads, day(10).unwrap()), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn same_day_episodes_keep_the_newer_download() {
+        let downloads = [download(1, 3), download(2, 3)];
+        let keep_one = RetentionPolicy { keep_newest: Some(1), ..policy() };
+        assert_eq!(keep_one.expired(&downloads, day(10).unwrap()), vec![1]);
+    }
+
+    #[test]
+    fn completed_and_old_downloads_are_removed() {
+        let downloads = [
+            RetainedDownload { completed: true, ..download(1, 9) },
+            download(2, 1),
+            download(3, 8),
+            RetainedDownload { downloaded_at: None, ..download(4, 1) },
+        ];
+        let rules = RetentionPolicy { delete_after_completion: true, delete_after_days: Some(7), ..policy() };
+        assert_eq!(rules.expired(&downloads, day(10).unwrap()), vec![1, 2]);
+    }
+
+    #[test]
+    fn saved_episodes_survive_every_rule() {
+        let downloads = [RetainedDownload { saved: true, completed: true, ..download(1, 1) }, download(2, 2)];
+        let rules = RetentionPolicy { keep_newest: Some(0), delete_after_completion: true, delete_after_days: Some(1), keep_saved: true };
+        assert_eq!(rules.expired(&downloads, day(30).unwrap()), vec![2]);
+        assert_eq!(RetentionPolicy { keep_saved: false, ..rules }.expired(&downloads, day(30).unwrap()), vec![1, 2]);
+    }
+
+    #[test]
+    fn out_of_range_values_are_refused() {
+        assert!(RetentionPolicy { keep_newest: Some(-1), ..policy() }.validate().is_err());
+        assert!(RetentionPolicy { delete_after_days: Some(0), ..policy() }.validate().is_err());
+        assert!(RetentionPolicy { delete_after_days: Some(MAX_RETENTION_DAYS + 1), ..policy() }.validate().is_err());
+        assert!(RetentionPolicy { keep_newest: Some(0), delete_after_days: Some(MAX_RETENTION_DAYS), ..policy() }.validate().is_ok());
+    }
+
+    #[test]
+    fn missing_fields_take_their_defaults() {
+        let policy: RetentionPolicy = serde_json::from_str(r#"{"keep_newest":5}"#).unwrap();
+        assert_eq!(policy, RetentionPolicy { keep_newest: Some(5), ..RetentionPolicy::default() });
+        assert!(policy.is_active());
+    }
+}
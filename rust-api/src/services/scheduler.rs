@@ -1,6 +1,7 @@
 use crate::{
     error::AppResult,
     handlers::{refresh, tasks},
+    services::{email_digest, notifications, webhooks, websub},
     AppState,
 };
 use std::sync::Arc;
@@ -20,13 +21,13 @@ impl BackgroundScheduler {
     pub async fn start(&self, app_state: Arc<AppState>) -> AppResult<()> {
         info!("🕒 Starting background task scheduler...");
 
-        // Schedule podcast refresh every 30 minutes
+        // Check for podcasts due a refresh every 5 minutes; each feed has its own adaptive interval
         let refresh_state = app_state.clone();
-        let refresh_job = Job::new_async("0 */30 * * * *", move |_uuid, _l| {
+        let refresh_job = Job::new_async("0 */5 * * * *", move |_uuid, _l| {
             let state = refresh_state.clone();
             Box::pin(async move {
                 info!("🔄 Running scheduled podcast refresh");
-                if let Err(e) = Self::run_refresh_pods(state.clone()).await {
+                if let Err(e) = refresh::refresh_due_podcasts_internal(&state).await {
                     error!("❌ Scheduled podcast refresh failed: {}", e);
                 } else {
                     info!("✅ Scheduled podcast refresh completed");
@@ -34,6 +35,81 @@ impl BackgroundScheduler {
             })
         })?;
 
+        // Schedule subscription sync every 30 minutes
+        let sync_state = app_state.clone();
+        let sync_job = Job::new_async("0 */30 * * * *", move |_uuid, _l| {
+            let state = sync_state.clone();
+            Box::pin(async move {
+                info!("🔄 Running scheduled subscription sync");
+                Self::run_sync_tasks(state.clone()).await;
+                info!("✅ Scheduled subscription sync completed");
+            })
+        })?;
+
+        // Send due WebSub subscribe/renew requests every 10 minutes (no-op unless WEBSUB_ENABLED)
+        let websub_state = app_state.clone();
+        let websub_job = Job::new_async("0 */10 * * * *", move |_uuid, _l| {
+            let state = websub_state.clone();
+            Box::pin(async move {
+                match websub::sync_hub_subscriptions(&state).await {
+                    Ok(0) => {}
+                    Ok(sent) => info!("📡 Sent {} WebSub hub requests", sent),
+                    Err(e) => error!("❌ WebSub subscription sync failed: {}", e),
+                }
+            })
+        })?;
+
+        // Retry failed notification deliveries whose backoff has run out, every minute
+        let notification_state = app_state.clone();
+        let notification_job = Job::new_async("0 * * * * *", move |_uuid, _l| {
+            let state = notification_state.clone();
+            Box::pin(async move {
+                match notifications::retry_due_deliveries(&state.db_pool).await {
+                    Ok(0) => {}
+                    Ok(retried) => info!("🔔 Retried {} notification deliveries", retried),
+                    Err(e) => error!("❌ Notification retry failed: {}", e),
+                }
+            })
+        })?;
+
+        // Retry webhook deliveries whose backoff has run out, every minute
+        let webhook_state = app_state.clone();
+        let webhook_job = Job::new_async("30 * * * * *", move |_uuid, _l| {
+            let state = webhook_state.clone();
+            Box::pin(async move {
+                match webhooks::retry_due_deliveries(&state.db_pool).await {
+                    Ok(0) => {}
+                    Ok(retried) => info!("🪝 Retried {} webhook deliveries", retried),
+                    Err(e) => error!("❌ Webhook retry failed: {}", e),
+                }
+            })
+        })?;
+
+        // Send the weekly digest notification on Monday mornings
+        let digest_state = app_state.clone();
+        let digest_job = Job::new_async("0 0 9 * * Mon", move |_uuid, _l| {
+            let state = digest_state.clone();
+            Box::pin(async move {
+                match notifications::send_weekly_digests(&state.db_pool).await {
+                    Ok(sent) => info!("📬 Sent {} weekly digest notifications", sent),
+                    Err(e) => error!("❌ Weekly digest failed: {}", e),
+                }
+            })
+        })?;
+
+        // Email digests to users whose send time has come, checked at the top of every hour
+        let email_digest_state = app_state.clone();
+        let email_digest_job = Job::new_async("0 0 * * * *", move |_uuid, _l| {
+            let state = email_digest_state.clone();
+            Box::pin(async move {
+                match email_digest::send_due_digests(&state.db_pool).await {
+                    Ok(0) => {}
+                    Ok(sent) => info!("📧 Sent {} email digests", sent),
+                    Err(e) => error!("❌ Email digest run failed: {}", e),
+                }
+            })
+        })?;
+
         // Schedule nightly tasks at midnight
         let nightly_state = app_state.clone();
         let nightly_job = Job::new_async("0 0 0 * * *", move |_uuid, _l| {
@@ -64,6 +140,12 @@ impl BackgroundScheduler {
 
         // Add jobs to scheduler
         self.scheduler.add(refresh_job).await?;
+        self.scheduler.add(sync_job).await?;
+        self.scheduler.add(websub_job).await?;
+        self.scheduler.add(notification_job).await?;
+        self.scheduler.add(webhook_job).await?;
+        self.scheduler.add(digest_job).await?;
+        self.scheduler.add(email_digest_job).await?;
         self.scheduler.add(nightly_job).await?;
         self.scheduler.add(cleanup_job).await?;
 
@@ -76,25 +158,11 @@ impl BackgroundScheduler {
 
     // Direct function calls instead of HTTP requests
     async fn run_refresh_pods(state: Arc<AppState>) -> AppResult<()> {
-        // Call refresh_pods function directly
-        match refresh::refresh_pods_admin_internal(&state).await {
+        // Refresh podcasts that are due, then sync subscriptions
+        match refresh::refresh_due_podcasts_internal(&state).await {
             Ok(_) => {
                 info!("✅ Podcast refresh completed");
-                
-                // Also run gpodder sync  
-                if let Err(e) = refresh::refresh_gpodder_subscriptions_admin_internal(&state).await {
-                    warn!("⚠️ GPodder sync failed during scheduled refresh: {}", e);
-                }
-                
-                // Also run nextcloud sync
-                if let Err(e) = refresh::refresh_nextcloud_subscriptions_admin_internal(&state).await {
-                    warn!("⚠️ Nextcloud sync failed during scheduled refresh: {}", e);
-                }
-                
-                // Update playlist episode counts (replaces complex playlist content updates)
-                if let Err(e) = state.db_pool.update_playlist_episode_counts().await {
-                    warn!("⚠️ Playlist episode count update failed during scheduled refresh: {}", e);
-                }
+                Self::run_sync_tasks(state).await;
             }
             Err(e) => {
                 error!("❌ Podcast refresh failed: {}", e);
@@ -104,6 +172,23 @@ impl BackgroundScheduler {
         Ok(())
     }
 
+    async fn run_sync_tasks(state: Arc<AppState>) {
+        // Also run gpodder sync  
+        if let Err(e) = refresh::refresh_gpodder_subscriptions_admin_internal(&state).await {
+            warn!("⚠️ GPodder sync failed during scheduled refresh: {}", e);
+        }
+        
+        // Also run nextcloud sync
+        if let Err(e) = refresh::refresh_nextcloud_subscriptions_admin_internal(&state).await {
+            warn!("⚠️ Nextcloud sync failed during scheduled refresh: {}", e);
+        }
+        
+        // Update playlist episode counts (replaces complex playlist content updates)
+        if let Err(e) = state.db_pool.update_playlist_episode_counts().await {
+            warn!("⚠️ Playlist episode count update failed during scheduled refresh: {}", e);
+        }
+    }
+
     async fn run_nightly_tasks(state: Arc<AppState>) -> AppResult<()> {
         // Call nightly tasks directly
         if let Err(e) = tasks::refresh_hosts_internal(&state).await {
@@ -119,6 +204,25 @@ impl BackgroundScheduler {
     }
 
     async fn run_cleanup_tasks(state: Arc<AppState>) -> AppResult<()> {
+        // Apply download retention rules; runs as a task so freed space shows up in the task list
+        if let Err(e) = state.task_spawner.spawn_retention_cleanup(0, None).await {
+            warn!("⚠️ Download retention cleanup failed to start: {}", e);
+        }
+
+        // Tell owners about feeds that have been failing for a while
+        match notifications::notify_broken_feeds(&state.db_pool, state.config.notifications.feed_broken_days).await {
+            Ok(0) => {}
+            Ok(reported) => info!("🔔 Reported {} broken feeds", reported),
+            Err(e) => warn!("⚠️ Broken feed notifications failed: {}", e),
+        }
+
+        // Drop the keys of login sessions that have timed out
+        match state.db_pool.delete_expired_login_sessions().await {
+            Ok(0) => {}
+            Ok(removed) => info!("🔑 Removed {} expired login sessions", removed),
+            Err(e) => warn!("⚠️ Expired login session cleanup failed: {}", e),
+        }
+
         // Call cleanup tasks directly
         match tasks::cleanup_tasks_internal(&state).await {
             Ok(_) => {
@@ -0,0 +1,419 @@
+use serde::{Deserialize, Serialize};
+use chrono::{NaiveDate, NaiveDateTime};
+use crate::error::{AppError, AppResult};
+
+/// Text search configuration used for the generated search vectors (see migration 038).
+/// `simple` avoids English-only stemming so non-English libraries still match.
+pub const PG_TEXT_SEARCH_CONFIG: &str = "simple";
+
+// Longest snippet returned for MySQL, where ts_headline isn't available
+const SNIPPET_MAX_CHARS: usize = 240;
+
+// ts_headline marks matches with these private-use characters rather than `<mark>`, so the
+// text around them can be escaped before the tags go in
+const HEADLINE_START: char = '\u{E000}';
+const HEADLINE_STOP: char = '\u{E001}';
+/// `StartSel`/`StopSel` options for ts_headline; pass its output through `finish_headline`
+pub const HEADLINE_OPTIONS: &str = "StartSel=\u{E000}, StopSel=\u{E001}";
+
+/// Parsed representation of a user search string.
+///
+/// Supported syntax:
+/// - `word` must match
+/// - `word*` prefix match
+/// - `"some phrase"` words must appear next to each other
+/// - `-word` excludes episodes containing the word
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SearchQuery {
+    pub terms: Vec<String>,
+    pub prefixes: Vec<String>,
+    pub phrases: Vec<Vec<String>>,
+    pub excluded: Vec<String>,
+}
+
+impl SearchQuery {
+    pub fn parse(input: &str) -> Self {
+        let mut query = SearchQuery::default();
+        let mut chars = input.chars().peekable();
+
+        while let Some(&ch) = chars.peek() {
+            if ch.is_whitespace() {
+                chars.next();
+                continue;
+            }
+
+            if ch == '"' {
+                chars.next();
+                let mut phrase = String::new();
+                for c in chars.by_ref() {
+                    if c == '"' {
+                        break;
+                    }
+                    phrase.push(c);
+                }
+                let words: Vec<String> = phrase.split_whitespace().filter_map(normalize_word).collect();
+                match words.len() {
+                    0 => {}
+                    1 => query.terms.extend(words),
+                    _ => query.phrases.push(words),
+                }
+                continue;
+            }
+
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+
+            let excluded = token.starts_with('-') && token.len() > 1;
+            let prefix = token.ends_with('*');
+            let Some(word) = normalize_word(&token) else {
+                continue;
+            };
+
+            if excluded {
+                query.excluded.push(word);
+            } else if prefix {
+                query.prefixes.push(word);
+            } else {
+                query.terms.push(word);
+            }
+        }
+
+        query
+    }
+
+    /// True when there is nothing positive to match on (exclusions alone can't drive a search)
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty() && self.prefixes.is_empty() && self.phrases.is_empty()
+    }
+
+    /// Build a `to_tsquery` expression, e.g. `rust & async:* & (memory <-> safety) & !java`
+    pub fn to_tsquery(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut parts: Vec<String> = Vec::new();
+        parts.extend(self.terms.iter().cloned());
+        parts.extend(self.prefixes.iter().map(|p| format!("{}:*", p)));
+        parts.extend(self.phrases.iter().map(|words| format!("({})", words.join(" <-> "))));
+        parts.extend(self.excluded.iter().map(|e| format!("!{}", e)));
+
+        Some(parts.join(" & "))
+    }
+
+    /// Build a MySQL `IN BOOLEAN MODE` expression, e.g. `+rust +async* +"memory safety" -java`
+    pub fn to_mysql_boolean(&self) -> Option<String> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let mut parts: Vec<String> = Vec::new();
+        parts.extend(self.terms.iter().map(|t| format!("+{}", t)));
+        parts.extend(self.prefixes.iter().map(|p| format!("+{}*", p)));
+        parts.extend(self.phrases.iter().map(|words| format!("+\"{}\"", words.join(" "))));
+        parts.extend(self.excluded.iter().map(|e| format!("-{}", e)));
+
+        Some(parts.join(" "))
+    }
+
+    /// Words worth highlighting in snippets (excluded words never appear in hits)
+    pub fn highlight_words(&self) -> Vec<String> {
+        let mut words: Vec<String> = self.terms.clone();
+        words.extend(self.prefixes.iter().cloned());
+        words.extend(self.phrases.iter().flatten().cloned());
+        words
+    }
+}
+
+// Strip everything but letters and digits so user input can't inject query operators
+fn normalize_word(raw: &str) -> Option<String> {
+    let cleaned: String = raw
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(|c| c.to_lowercase())
+        .collect();
+
+    if cleaned.is_empty() {
+        None
+    } else {
+        Some(cleaned)
+    }
+}
+
+/// Optional filters and paging accepted by `/search_data`
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SearchFilters {
+    pub podcast_id: Option<i32>,
+    pub start_date: Option<String>,
+    pub end_date: Option<String>,
+    pub completed: Option<bool>,
+    pub saved: Option<bool>,
+    pub downloaded: Option<bool>,
+    pub include_transcripts: Option<bool>,
+    pub sort: Option<String>, // "relevance" (default) or "date"
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
+}
+
+impl SearchFilters {
+    pub fn page(&self) -> i32 {
+        self.page.unwrap_or(1).max(1)
+    }
+
+    pub fn per_page(&self) -> i32 {
+        self.per_page.unwrap_or(50).clamp(1, 100)
+    }
+
+    pub fn offset(&self) -> i64 {
+        ((self.page() - 1) as i64) * self.per_page() as i64
+    }
+
+    /// Transcript segments are searched unless the caller opts out
+    pub fn include_transcripts(&self) -> bool {
+        self.include_transcripts.unwrap_or(true)
+    }
+
+    pub fn sort_by_date(&self) -> bool {
+        matches!(self.sort.as_deref(), Some("date"))
+    }
+
+    /// Inclusive lower bound on the episode publish date
+    pub fn start_datetime(&self) -> AppResult<Option<NaiveDateTime>> {
+        parse_filter_date(self.start_date.as_deref(), "start_date")
+            .map(|date| date.and_then(|d| d.and_hms_opt(0, 0, 0)))
+    }
+
+    /// Exclusive upper bound (start of the day after `end_date`)
+    pub fn end_datetime(&self) -> AppResult<Option<NaiveDateTime>> {
+        parse_filter_date(self.end_date.as_deref(), "end_date")
+            .map(|date| date.and_then(|d| d.succ_opt()).and_then(|d| d.and_hms_opt(0, 0, 0)))
+    }
+}
+
+fn parse_filter_date(value: Option<&str>, field: &str) -> AppResult<Option<NaiveDate>> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        Some(v) => NaiveDate::parse_from_str(v, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| AppError::bad_request(format!("Invalid {}: expected YYYY-MM-DD", field))),
+        None => Ok(None),
+    }
+}
+
+/// One page of ranked search hits
+#[derive(Debug, Serialize)]
+pub struct SearchResults {
+    pub data: Vec<serde_json::Value>,
+    pub total: i64,
+    pub page: i32,
+    pub per_page: i32,
+}
+
+/// Values bound into dynamically built search SQL, in placeholder order
+#[derive(Debug, Clone)]
+pub enum SearchBind {
+    Int(i32),
+    BigInt(i64),
+    Bool(bool),
+    Text(String),
+    Timestamp(NaiveDateTime),
+}
+
+/// Escape text for HTML; snippets are rendered as markup so their `<mark>` tags show
+pub fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Turn ts_headline output made with `HEADLINE_OPTIONS` into escaped HTML with `<mark>`
+/// around the matches
+pub fn finish_headline(headline: &str) -> String {
+    escape_html(headline)
+        .replace(HEADLINE_START, "<mark>")
+        .replace(HEADLINE_STOP, "</mark>")
+}
+
+/// Cut a plain-text excerpt around the first matching word, escape it for HTML and wrap
+/// matches in `<mark>`. Used on MySQL; Postgres builds the same shape with ts_headline.
+pub fn build_snippet(text: &str, words: &[String]) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let lower = text.to_lowercase();
+
+    // Lowercasing can change byte lengths for some scripts; only trust positions when it doesn't
+    let first_hit = if lower.len() == text.len() {
+        words.iter().filter_map(|w| lower.find(w.as_str())).min()
+    } else {
+        None
+    };
+
+    let start = match first_hit {
+        Some(pos) => floor_char_boundary(&text, pos.saturating_sub(SNIPPET_MAX_CHARS / 3)),
+        None => 0,
+    };
+    let end = floor_char_boundary(&text, (start + SNIPPET_MAX_CHARS).min(text.len()));
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push('…');
+    }
+    snippet.push_str(&highlight(&text[start..end], words));
+    if end < text.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+fn highlight(text: &str, words: &[String]) -> String {
+    let lower = text.to_lowercase();
+    if lower.len() != text.len() || words.is_empty() {
+        return escape_html(text);
+    }
+
+    let mut out = String::with_capacity(text.len() + 16);
+    let mut pos = 0;
+    while pos < text.len() {
+        let hit = words
+            .iter()
+            .filter(|w| !w.is_empty() && lower.get(pos..).is_some_and(|rest| rest.starts_with(w.as_str())))
+            .map(|w| w.len())
+            .max();
+        let at_word_start = pos == 0 || !text[..pos].chars().next_back().is_some_and(char::is_alphanumeric);
+
+        match hit {
+            Some(len) if at_word_start && text.is_char_boundary(pos + len) => {
+                // Extend to the end of the word so prefix matches highlight the whole token
+                let mut end = pos + len;
+                while let Some(c) = text[end..].chars().next() {
+                    if !c.is_alphanumeric() {
+                        break;
+                    }
+                    end += c.len_utf8();
+                }
+                out.push_str("<mark>");
+                out.push_str(&escape_html(&text[pos..end]));
+                out.push_str("</mark>");
+                pos = end;
+            }
+            _ => {
+                let c = text[pos..].chars().next().unwrap_or(' ');
+                out.push_str(&escape_html(c.encode_utf8(&mut [0; 4])));
+                pos += c.len_utf8();
+            }
+        }
+    }
+    out
+}
+
+fn floor_char_boundary(text: &str, mut index: usize) -> usize {
+    while index > 0 && !text.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(list: &[&str]) -> Vec<String> {
+        list.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_terms_prefixes_phrases_and_exclusions() {
+        let query = SearchQuery::parse(r#"Rust async* "memory  safety" -Java"#);
+        assert_eq!(query.terms, words(&["rust"]));
+        assert_eq!(query.prefixes, words(&["async"]));
+        assert_eq!(query.phrases, vec![words(&["memory", "safety"])]);
+        assert_eq!(query.excluded, words(&["java"]));
+    }
+
+    #[test]
+    fn single_word_phrase_is_a_term_and_empty_pieces_are_dropped() {
+        let query = SearchQuery::parse(r#""hello" "" - * "unclosed phrase"#);
+        assert_eq!(query.terms, words(&["hello"]));
+        assert_eq!(query.phrases, vec![words(&["unclosed", "phrase"])]);
+        assert!(query.excluded.is_empty() && query.prefixes.is_empty());
+    }
+
+    #[test]
+    fn operators_in_input_are_stripped() {
+        let query = SearchQuery::parse("a&b|c:* !d (e) <->");
+        assert_eq!(query.terms, words(&["d", "e"]));
+        assert_eq!(query.prefixes, words(&["abc"]));
+        assert_eq!(query.to_tsquery().unwrap(), "d & e & abc:*");
+    }
+
+    #[test]
+    fn exclusions_alone_are_empty() {
+        let query = SearchQuery::parse("-java -kotlin");
+        assert!(query.is_empty());
+        assert_eq!(query.to_tsquery(), None);
+        assert_eq!(query.to_mysql_boolean(), None);
+    }
+
+    #[test]
+    fn builds_backend_queries() {
+        let query = SearchQuery::parse(r#"rust async* "memory safety" -java"#);
+        assert_eq!(query.to_tsquery().unwrap(), "rust & async:* & (memory <-> safety) & !java");
+        assert_eq!(query.to_mysql_boolean().unwrap(), r#"+rust +async* +"memory safety" -java"#);
+        assert_eq!(query.highlight_words(), words(&["rust", "async", "memory", "safety"]));
+    }
+
+    #[test]
+    fn snippet_marks_whole_words_from_the_start_of_a_match() {
+        let snippet = build_snippet("Learning   Rustaceans and trust in rust", &words(&["rust"]));
+        assert_eq!(snippet, "Learning <mark>Rustaceans</mark> and trust in <mark>rust</mark>");
+    }
+
+    #[test]
+    fn snippet_escapes_markup_in_episode_text() {
+        let snippet = build_snippet(r#"<script>alert("x")</script> rust & <b>go</b>"#, &words(&["rust", "script"]));
+        assert_eq!(
+            snippet,
+            "&lt;<mark>script</mark>&gt;alert(&quot;x&quot;)&lt;/<mark>script</mark>&gt; <mark>rust</mark> &amp; &lt;b&gt;go&lt;/b&gt;"
+        );
+        assert_eq!(build_snippet("<img src=x onerror=alert(1)>", &[]), "&lt;img src=x onerror=alert(1)&gt;");
+    }
+
+    #[test]
+    fn long_text_is_cut_around_the_first_match() {
+        let text = format!("{} needle {}", "word ".repeat(200), "tail ".repeat(200));
+        let snippet = build_snippet(&text, &words(&["needle"]));
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains("<mark>needle</mark>"));
+        assert!(snippet.chars().count() <= SNIPPET_MAX_CHARS + 2 + "<mark></mark>".len());
+    }
+
+    #[test]
+    fn headline_is_escaped_before_marks_go_in() {
+        let headline = format!("<b>{}Rust{}</b> & more", HEADLINE_START, HEADLINE_STOP);
+        assert_eq!(finish_headline(&headline), "&lt;b&gt;<mark>Rust</mark>&lt;/b&gt; &amp; more");
+        assert!(HEADLINE_OPTIONS.contains(HEADLINE_START) && HEADLINE_OPTIONS.contains(HEADLINE_STOP));
+    }
+
+    #[test]
+    fn filters_page_and_parse_dates() {
+        let filters = SearchFilters { page: Some(3), per_page: Some(500), end_date: Some("2024-02-29".to_string()), ..Default::default() };
+        assert_eq!(filters.per_page(), 100);
+        assert_eq!(filters.offset(), 200);
+        assert_eq!(filters.end_datetime().unwrap().unwrap().to_string(), "2024-03-01 00:00:00");
+        let bad = SearchFilters { start_date: Some("yesterday".to_string()), ..Default::default() };
+        assert!(bad.start_datetime().is_err());
+    }
+}
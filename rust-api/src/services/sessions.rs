@@ -0,0 +1,197 @@
+use axum::http::HeaderMap;
+use chrono::NaiveDateTime;
+use serde::Serialize;
+use std::net::IpAddr;
+use crate::{config::SessionConfig, redis_client::RedisClient, services::proxy_auth};
+
+const MAX_DEVICE_NAME_CHARS: usize = 255;
+
+/// A signed-in device. Each session owns the API key issued when it signed in.
+#[derive(Debug, Clone, Serialize)]
+pub struct LoginSession {
+    pub session_id: i32,
+    #[serde(skip)]
+    pub api_key_id: i32,
+    pub device_name: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub last_seen_at: Option<NaiveDateTime>,
+    /// When the session ends unless it's used before then
+    pub idle_expires_at: Option<NaiveDateTime>,
+    /// When the session ends regardless
+    pub expires_at: Option<NaiveDateTime>,
+    /// Whether this is the session making the request
+    pub current: bool,
+}
+
+/// Where a sign-in came from
+#[derive(Debug, Clone)]
+pub struct LoginClient {
+    pub device_name: String,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+impl LoginClient {
+    /// Apps can name themselves with an X-Device-Name header; otherwise the name
+    /// is made from the user agent
+    pub fn from_request(peer: IpAddr, headers: &HeaderMap) -> Self {
+        let header = |name: &str| {
+            headers.get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+        };
+        let user_agent = header("User-Agent");
+        let device_name = header("X-Device-Name")
+            .or_else(|| user_agent.as_deref().and_then(describe_user_agent))
+            .unwrap_or_else(|| "Unknown device".to_string())
+            .chars()
+            .take(MAX_DEVICE_NAME_CHARS)
+            .collect();
+
+        LoginClient {
+            device_name,
+            user_agent,
+            ip_address: Some(proxy_auth::source_ip(peer, headers).to_string()),
+        }
+    }
+}
+
+/// A readable name for a browser's user agent, e.g. "Firefox on Linux"
+pub fn describe_user_agent(user_agent: &str) -> Option<String> {
+    // Order matters: Edge and Opera also claim to be Chrome, and Chrome claims to be Safari
+    let browser = [
+        ("Edg/", "Edge"),
+        ("OPR/", "Opera"),
+        ("Firefox/", "Firefox"),
+        ("Chrome/", "Chrome"),
+        ("Safari/", "Safari"),
+    ]
+    .iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| *name);
+    let os = [
+        ("Android", "Android"),
+        ("iPhone", "iOS"),
+        ("iPad", "iPadOS"),
+        ("Windows", "Windows"),
+        ("Mac OS X", "macOS"),
+        ("CrOS", "ChromeOS"),
+        ("Linux", "Linux"),
+    ]
+    .iter()
+    .find(|(token, _)| user_agent.contains(token))
+    .map(|(_, name)| *name);
+
+    match (browser, os) {
+        (Some(browser), Some(os)) => Some(format!("{} on {}", browser, os)),
+        (Some(name), None) | (None, Some(name)) => Some(name.to_string()),
+        // Apps and scripts usually lead with their own name
+        (None, None) => user_agent.split(['/', ' ']).next().filter(|name| !name.is_empty()).map(str::to_string),
+    }
+}
+
+/// When a session started at `now` times out: the idle deadline, then the absolute one
+pub fn deadlines(config: &SessionConfig, now: NaiveDateTime) -> (Option<NaiveDateTime>, Option<NaiveDateTime>) {
+    (
+        config.idle_timeout().map(|idle| now + idle),
+        config.max_age().map(|max_age| now + max_age),
+    )
+}
+
+/// Drop the cached validation of revoked session keys so they stop working right away
+pub async fn forget_keys(redis: &RedisClient, api_keys: &[String]) {
+    for api_key in api_keys {
+        if let Err(e) = redis.forget_api_key_validation(api_key).await {
+            tracing::warn!("Failed to clear cached validation for a revoked session: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn browsers_are_named_with_their_platform() {
+        let cases = [
+            ("Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0", "Firefox on Linux"),
+            (
+                "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.36 Edg/129.0.0.0",
+                "Edge on Windows",
+            ),
+            (
+                "Mozilla/5.0 (Macintosh; Intel Mac OS X 10_15_7) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.36 OPR/114.0.0.0",
+                "Opera on macOS",
+            ),
+            (
+                "Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Mobile Safari/537.36",
+                "Chrome on Android",
+            ),
+            (
+                "Mozilla/5.0 (iPhone; CPU iPhone OS 17_6 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.6 Mobile/15E148 Safari/604.1",
+                "Safari on iOS",
+            ),
+            ("Mozilla/5.0 (X11; CrOS x86_64 14541.0.0) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/129.0.0.0 Safari/537.36", "Chrome on ChromeOS"),
+        ];
+        for (user_agent, expected) in cases {
+            assert_eq!(describe_user_agent(user_agent).as_deref(), Some(expected), "{}", user_agent);
+        }
+    }
+
+    #[test]
+    fn apps_are_named_after_themselves() {
+        assert_eq!(describe_user_agent("okhttp/4.12.0").as_deref(), Some("okhttp"));
+        assert_eq!(describe_user_agent("PinePods Desktop (Windows)").as_deref(), Some("Windows"));
+        assert_eq!(describe_user_agent("curl/8.5.0").as_deref(), Some("curl"));
+        assert_eq!(describe_user_agent("/weird"), None);
+    }
+
+    #[test]
+    fn apps_can_name_their_device() {
+        let peer: IpAddr = "127.0.0.1".parse().unwrap();
+        let named = LoginClient::from_request(peer, &headers(&[
+            ("X-Device-Name", " Kitchen tablet "),
+            ("User-Agent", "okhttp/4.12.0"),
+            ("X-Real-IP", "192.168.1.20"),
+        ]));
+        assert_eq!(named.device_name, "Kitchen tablet");
+        assert_eq!(named.user_agent.as_deref(), Some("okhttp/4.12.0"));
+        assert_eq!(named.ip_address.as_deref(), Some("192.168.1.20"));
+
+        let from_agent = LoginClient::from_request(peer, &headers(&[("User-Agent", "curl/8.5.0"), ("X-Device-Name", "")]));
+        assert_eq!(from_agent.device_name, "curl");
+
+        let unknown = LoginClient::from_request("203.0.113.9".parse().unwrap(), &headers(&[("X-Real-IP", "10.0.0.1")]));
+        assert_eq!(unknown.device_name, "Unknown device");
+        assert_eq!(unknown.user_agent, None);
+        assert_eq!(unknown.ip_address.as_deref(), Some("203.0.113.9"));
+    }
+
+    #[test]
+    fn device_names_are_capped() {
+        let long_name = "x".repeat(MAX_DEVICE_NAME_CHARS + 10);
+        let client = LoginClient::from_request("127.0.0.1".parse().unwrap(), &headers(&[("X-Device-Name", &long_name)]));
+        assert_eq!(client.device_name.chars().count(), MAX_DEVICE_NAME_CHARS);
+    }
+
+    #[test]
+    fn deadlines_follow_the_configured_limits() {
+        let now = chrono::NaiveDate::from_ymd_opt(2025, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap();
+        let both = SessionConfig { idle_timeout_hours: Some(12), max_age_days: Some(30) };
+        assert_eq!(deadlines(&both, now), (Some(now + chrono::Duration::hours(12)), Some(now + chrono::Duration::days(30))));
+
+        let none = SessionConfig { idle_timeout_hours: None, max_age_days: None };
+        assert_eq!(deadlines(&none, now), (None, None));
+    }
+}
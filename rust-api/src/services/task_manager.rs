@@ -14,6 +14,8 @@ pub enum TaskStatus {
     Completed,
     #[serde(rename = "FAILED")]
     Failed,
+    #[serde(rename = "PAUSED")]
+    Paused,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -155,7 +157,7 @@ impl TaskManager {
         task.message = message.clone();
         task.updated_at = chrono::Utc::now();
 
-        if progress > 0.0 && matches!(task.status, TaskStatus::Pending) {
+        if progress > 0.0 && matches!(task.status, TaskStatus::Pending | TaskStatus::Paused) {
             task.status = TaskStatus::Running;
         }
 
@@ -254,6 +256,39 @@ impl TaskManager {
         Ok(())
     }
 
+    /// Mark a task paused (or back to pending on resume) and tell connected clients
+    pub async fn set_task_paused(
+        &self,
+        task_id: &str,
+        paused: bool,
+        item_id: Option<i32>,
+        message: String,
+    ) -> AppResult<()> {
+        let mut task = self.get_task(task_id).await?;
+        task.status = if paused { TaskStatus::Paused } else { TaskStatus::Pending };
+        task.message = Some(message.clone());
+        task.updated_at = chrono::Utc::now();
+
+        self.save_task(&task).await?;
+
+        let update = TaskUpdate {
+            task_id: task_id.to_string(),
+            user_id: task.user_id,
+            task_type: task.task_type.clone(),
+            item_id,
+            progress: task.progress,
+            status: task.status.clone(),
+            details: serde_json::json!({
+                "status_text": message
+            }),
+            started_at: task.created_at.to_rfc3339(),
+            completed_at: None,
+        };
+
+        let _ = self.progress_sender.send(update);
+        Ok(())
+    }
+
     pub async fn get_task(&self, task_id: &str) -> AppResult<TaskInfo> {
         let key = format!("task:{}", task_id);
         let mut conn = self.redis.get_connection().await?;
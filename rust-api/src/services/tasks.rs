@@ -1,6 +1,11 @@
 use crate::{
     error::AppResult,
-    services::task_manager::TaskManager,
+    services::{
+        download_queue::DownloadQueue,
+        download_template::DownloadTemplate,
+        storage::{self, Storage},
+        task_manager::TaskManager,
+    },
     database::DatabasePool,
 };
 use futures::Future;
@@ -8,165 +13,17 @@ use serde_json::Value;
 use std::sync::Arc;
 use sqlx::Row;
 
-// New function that actually downloads an episode and waits for completion
-async fn download_episode_and_wait(
-    db_pool: &crate::database::DatabasePool,
-    episode_id: i32,
-    user_id: i32,
-) -> Result<String, crate::error::AppError> {
-    tracing::info!("Starting actual download for episode {} for user {}", episode_id, user_id);
-    
-    // Get episode metadata from database
-    let episode_info = match db_pool {
-        crate::database::DatabasePool::Postgres(pool) => {
-            let row = sqlx::query(r#"
-                SELECT e.episodeurl, e.episodetitle, p.podcastname, 
-                       e.episodepubdate
-                FROM "Episodes" e
-                JOIN "Podcasts" p ON e.podcastid = p.podcastid
-                WHERE e.episodeid = $1
-            "#)
-            .bind(episode_id)
-            .fetch_one(pool)
-            .await?;
-            
-            (
-                row.try_get::<String, _>("episodeurl")?,
-                row.try_get::<String, _>("episodetitle")?,
-                row.try_get::<String, _>("podcastname")?,
-                row.try_get::<Option<chrono::NaiveDateTime>, _>("episodepubdate")?,
-            )
-        }
-        crate::database::DatabasePool::MySQL(pool) => {
-            let row = sqlx::query("
-                SELECT e.EpisodeURL, e.EpisodeTitle, p.PodcastName, e.EpisodePubDate
-                FROM Episodes e
-                JOIN Podcasts p ON e.PodcastID = p.PodcastID
-                WHERE e.EpisodeID = ?
-            ")
-            .bind(episode_id)
-            .fetch_one(pool)
-            .await?;
-            
-            (
-                row.try_get::<String, _>("EpisodeURL")?,
-                row.try_get::<String, _>("EpisodeTitle")?,
-                row.try_get::<String, _>("PodcastName")?,
-                row.try_get::<Option<chrono::NaiveDateTime>, _>("EpisodePubDate")?,
-            )
-        }
-    };
-    
-    let (episode_url, episode_title, podcast_name, pub_date) = episode_info;
-    
-    // Create download directory structure
-    let safe_podcast_name = podcast_name.chars()
-        .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' { c } else { '_' })
-        .collect::<String>()
-        .trim()
-        .to_string();
-    
-    let safe_episode_title = episode_title.chars()
-        .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' { c } else { '_' })
-        .collect::<String>()
-        .trim()
-        .to_string();
-    
-    let download_dir = std::path::Path::new("/opt/pinepods/downloads").join(&safe_podcast_name);
-    if !download_dir.exists() {
-        std::fs::create_dir_all(&download_dir)
-            .map_err(|e| crate::error::AppError::Internal(format!("Failed to create download directory: {}", e)))?;
-        
-        // Set ownership using PUID/PGID environment variables
-        let puid: u32 = std::env::var("PUID").unwrap_or_else(|_| "1000".to_string()).parse().unwrap_or(1000);
-        let pgid: u32 = std::env::var("PGID").unwrap_or_else(|_| "1000".to_string()).parse().unwrap_or(1000);
-        
-        // Set directory ownership (ignore errors for NFS mounts)
-        let _ = std::process::Command::new("chown")
-            .args(&[format!("{}:{}", puid, pgid), download_dir.to_string_lossy().to_string()])
-            .output();
-    }
-    
-    let pub_date_str = if let Some(date) = pub_date {
-        date.format("%Y-%m-%d").to_string()
-    } else {
-        chrono::Utc::now().format("%Y-%m-%d").to_string()
-    };
-    
-    let filename = format!("{}_{}_{}_{}.mp3", pub_date_str, safe_episode_title, user_id, episode_id);
-    let file_path = download_dir.join(&filename);
-    
-    // Download the file
-    let client = reqwest::Client::new();
-    let mut response = client.get(&episode_url)
-        .send()
-        .await
-        .map_err(|e| crate::error::AppError::Internal(format!("Failed to start download: {}", e)))?;
-    
-    if !response.status().is_success() {
-        return Err(crate::error::AppError::Internal(format!("Server returned error: {}", response.status())));
-    }
-    
-    let mut file = std::fs::File::create(&file_path)
-        .map_err(|e| crate::error::AppError::Internal(format!("Failed to create file: {}", e)))?;
-    
-    // Download the content
-    while let Some(chunk) = response.chunk().await.map_err(|e| crate::error::AppError::Internal(format!("Download failed: {}", e)))? {
-        std::io::Write::write_all(&mut file, &chunk)
-            .map_err(|e| crate::error::AppError::Internal(format!("Failed to write file: {}", e)))?;
-    }
-    
-    // Close the file before setting ownership
-    drop(file);
-    
-    // Set file ownership using PUID/PGID environment variables
-    let puid: u32 = std::env::var("PUID").unwrap_or_else(|_| "1000".to_string()).parse().unwrap_or(1000);
-    let pgid: u32 = std::env::var("PGID").unwrap_or_else(|_| "1000".to_string()).parse().unwrap_or(1000);
-    
-    // Set file ownership (ignore errors for NFS mounts)
-    let _ = std::process::Command::new("chown")
-        .args(&[format!("{}:{}", puid, pgid), file_path.to_string_lossy().to_string()])
-        .output();
-    
-    // Record download in database  
-    let file_size = tokio::fs::metadata(&file_path).await
-        .map(|m| m.len() as i64)
-        .unwrap_or(0);
-        
-    match db_pool {
-        crate::database::DatabasePool::Postgres(pool) => {
-            sqlx::query(r#"INSERT INTO "DownloadedEpisodes" (userid, episodeid, downloadedsize, downloadedlocation) VALUES ($1, $2, $3, $4)"#)
-                .bind(user_id)
-                .bind(episode_id)
-                .bind(file_size)
-                .bind(file_path.to_string_lossy().to_string())
-                .execute(pool)
-                .await?;
-        }
-        crate::database::DatabasePool::MySQL(pool) => {
-            sqlx::query("INSERT INTO DownloadedEpisodes (UserID, EpisodeID, DownloadedSize, DownloadedLocation) VALUES (?, ?, ?, ?)")
-                .bind(user_id)
-                .bind(episode_id)
-                .bind(file_size)
-                .bind(file_path.to_string_lossy().to_string())
-                .execute(pool)
-                .await?;
-        }
-    }
-    
-    tracing::info!("Successfully downloaded episode {} - {}", episode_id, episode_title);
-    Ok(episode_title)
-}
-
 #[derive(Clone)]
 pub struct TaskSpawner {
     task_manager: Arc<TaskManager>,
     db_pool: DatabasePool,
+    storage: Arc<Storage>,
+    download_queue: Arc<DownloadQueue>,
 }
 
 impl TaskSpawner {
-    pub fn new(task_manager: Arc<TaskManager>, db_pool: DatabasePool) -> Self {
-        Self { task_manager, db_pool }
+    pub fn new(task_manager: Arc<TaskManager>, db_pool: DatabasePool, storage: Arc<Storage>, download_queue: Arc<DownloadQueue>) -> Self {
+        Self { task_manager, db_pool, storage, download_queue }
     }
 
     pub async fn spawn_task<F, Fut>(
@@ -224,6 +81,113 @@ impl TaskSpawner {
         .await
     }
 
+    // Apply per-podcast retention rules, deleting expired downloads of one user (or everyone)
+    pub async fn spawn_retention_cleanup(&self, task_user_id: i32, only_user: Option<i32>) -> AppResult<String> {
+        let db_pool = self.db_pool.clone();
+        let storage = self.storage.clone();
+        self.spawn_progress_task(
+            "retention_cleanup".to_string(),
+            task_user_id,
+            move |reporter| async move {
+                let podcasts: Vec<(i32, i32, String)> = db_pool.get_podcasts_with_retention().await?
+                    .into_iter()
+                    .filter(|(_, user_id, _)| only_user.is_none_or(|only| only == *user_id))
+                    .collect();
+                let now = chrono::Utc::now().naive_utc();
+                let (mut deleted, mut freed_bytes, mut failed) = (0, 0i64, 0);
+
+                for (index, (podcast_id, user_id, podcast_name)) in podcasts.iter().enumerate() {
+                    let progress = 100.0 * (index as f64) / (podcasts.len() as f64);
+                    reporter.update_progress(progress, Some(format!("Applying retention to {}", podcast_name))).await?;
+
+                    let Some(policy) = db_pool.get_retention_policy(*podcast_id, *user_id).await?.filter(|p| p.is_active()) else {
+                        continue;
+                    };
+                    let downloads = db_pool.get_retained_downloads(*podcast_id, *user_id).await?;
+                    for download_id in policy.expired(&downloads, now) {
+                        let Some(download) = downloads.iter().find(|d| d.download_id == download_id) else {
+                            continue;
+                        };
+                        let removed = match db_pool.delete_episode(*user_id, download.episode_id, false).await {
+                            Ok(Some(location)) => storage.delete(&location).await,
+                            Ok(None) => Ok(()),
+                            Err(e) => Err(e),
+                        };
+                        match removed {
+                            Ok(()) => {
+                                deleted += 1;
+                                freed_bytes += download.size_bytes;
+                            }
+                            Err(e) => {
+                                tracing::warn!("Retention cleanup failed for episode {} of user {}: {}", download.episode_id, user_id, e);
+                                failed += 1;
+                            }
+                        }
+                    }
+                }
+
+                let freed_mb = freed_bytes as f64 / (1024.0 * 1024.0);
+                reporter.update_progress(100.0, Some(format!("Deleted {} downloads, freed {:.1} MB", deleted, freed_mb))).await?;
+                Ok(serde_json::json!({
+                    "podcasts": podcasts.len(),
+                    "deleted": deleted,
+                    "failed": failed,
+                    "freed_bytes": freed_bytes,
+                }))
+            },
+        ).await
+    }
+
+    // Move existing downloads of a user (or everyone) to where their download template now puts them
+    pub async fn spawn_reorganize_downloads(&self, user_id: i32, all_users: bool) -> AppResult<String> {
+        let db_pool = self.db_pool.clone();
+        let storage = self.storage.clone();
+        self.spawn_progress_task(
+            "reorganize_downloads".to_string(),
+            user_id,
+            move |reporter| async move {
+                let downloads = db_pool.get_stored_downloads((!all_users).then_some(user_id)).await?;
+                let mut templates: std::collections::HashMap<i32, DownloadTemplate> = std::collections::HashMap::new();
+                let (mut moved, mut unchanged, mut failed) = (0, 0, 0);
+
+                for (index, download) in downloads.iter().enumerate() {
+                    let progress = 100.0 * (index as f64) / (downloads.len() as f64);
+                    reporter.update_progress(progress, Some(format!("Organizing download {}/{}", index + 1, downloads.len()))).await?;
+
+                    let template = match templates.get(&download.user_id) {
+                        Some(template) => template.clone(),
+                        None => {
+                            let template = DownloadTemplate::for_user(&db_pool, download.user_id).await?;
+                            templates.insert(download.user_id, template.clone());
+                            template
+                        }
+                    };
+                    let key = template.render(&download.naming());
+
+                    match storage.relocate(&download.location, &key).await {
+                        Ok(location) if location == download.location => unchanged += 1,
+                        Ok(location) => {
+                            // The file already moved, so a failed update must be visible in the logs
+                            if let Err(e) = db_pool.update_download_location(download.download_id, &location).await {
+                                tracing::error!("Moved {} to {} but could not record it: {}", download.location, location, e);
+                                failed += 1;
+                            } else {
+                                moved += 1;
+                            }
+                        }
+                        Err(e) => {
+                            tracing::warn!("Failed to move download {}: {}", download.location, e);
+                            failed += 1;
+                        }
+                    }
+                }
+
+                reporter.update_progress(100.0, Some(format!("Moved {} downloads", moved))).await?;
+                Ok(serde_json::json!({"moved": moved, "unchanged": unchanged, "failed": failed}))
+            },
+        ).await
+    }
+
     pub async fn spawn_progress_task<F, Fut>(
         &self,
         task_type: String,
@@ -267,289 +231,12 @@ impl ProgressReporter for TaskProgressReporter {
 impl TaskSpawner {
     // Download task spawners for podcast episodes and YouTube videos
     pub async fn spawn_download_podcast_episode(&self, episode_id: i32, user_id: i32) -> AppResult<String> {
-        let db_pool = self.db_pool.clone();
-        
-        // Create task with episode_id as item_id for frontend compatibility
-        let task_id = self.task_manager.create_task_with_item_id(
-            "download_episode".to_string(),
-            user_id,
-            Some(episode_id),
-        ).await?;
-        
-        let task_manager = self.task_manager.clone();
-        let task_id_clone = task_id.clone();
-        let task_manager_for_completion = task_manager.clone();
-        let task_id_for_completion = task_id_clone.clone();
-
-        tokio::spawn(async move {
-            let result = async move {
-                tracing::info!("Downloading podcast episode {} for user {}", episode_id, user_id);
-                
-                // Update progress to starting with item_id
-                task_manager.update_task_progress_with_details(&task_id_clone, 0.0, Some("Starting download...".to_string()), Some(episode_id), Some("podcast_download".to_string()), None).await?;
-                
-                // Get complete episode metadata from database
-                let episode_info = match &db_pool {
-                    crate::database::DatabasePool::Postgres(pool) => {
-                        let row = sqlx::query(r#"
-                            SELECT e."episodeurl", e."episodetitle", p."podcastname", 
-                                   e."episodepubdate", p."author", e."episodeartwork", p."artworkurl",
-                                   e."episodedescription"
-                            FROM "Episodes" e
-                            JOIN "Podcasts" p ON e."podcastid" = p."podcastid"
-                            WHERE e."episodeid" = $1
-                        "#)
-                        .bind(episode_id)
-                        .fetch_one(pool)
-                        .await?;
-                        
-                        (
-                            row.try_get::<String, _>("episodeurl")?,
-                            row.try_get::<String, _>("episodetitle")?,
-                            row.try_get::<String, _>("podcastname")?,
-                            row.try_get::<Option<chrono::NaiveDateTime>, _>("episodepubdate")?,
-                            row.try_get::<Option<String>, _>("author")?,
-                            row.try_get::<Option<String>, _>("episodeartwork")?,
-                            row.try_get::<Option<String>, _>("artworkurl")?,
-                            row.try_get::<Option<String>, _>("episodedescription")?
-                        )
-                    }
-                    crate::database::DatabasePool::MySQL(pool) => {
-                        let row = sqlx::query("
-                            SELECT e.EpisodeURL, e.EpisodeTitle, p.PodcastName,
-                                   e.EpisodePubDate, p.Author, e.EpisodeArtwork, p.ArtworkURL,
-                                   e.EpisodeDescription
-                            FROM Episodes e
-                            JOIN Podcasts p ON e.PodcastID = p.PodcastID
-                            WHERE e.EpisodeID = ?
-                        ")
-                        .bind(episode_id)
-                        .fetch_one(pool)
-                        .await?;
-                        
-                        (
-                            row.try_get::<String, _>("EpisodeURL")?,
-                            row.try_get::<String, _>("EpisodeTitle")?,
-                            row.try_get::<String, _>("PodcastName")?,
-                            row.try_get::<Option<chrono::NaiveDateTime>, _>("EpisodePubDate")?,
-                            row.try_get::<Option<String>, _>("Author")?,
-                            row.try_get::<Option<String>, _>("EpisodeArtwork")?,
-                            row.try_get::<Option<String>, _>("ArtworkURL")?,
-                            row.try_get::<Option<String>, _>("EpisodeDescription")?
-                        )
-                    }
-                };
-                
-                let (episode_url, episode_title, podcast_name, pub_date, author, episode_artwork, artwork_url, description) = episode_info;
-                
-                let status_message = format!("Preparing {}", episode_title);
-                task_manager.update_task_progress_with_details(&task_id_clone, 10.0, Some(status_message.clone()), Some(episode_id), Some("podcast_download".to_string()), Some(episode_title.clone())).await?;
-                
-                // Create download directory structure like Python version
-                let safe_podcast_name = podcast_name.chars()
-                    .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' { c } else { '_' })
-                    .collect::<String>()
-                    .trim()
-                    .to_string();
-                
-                let safe_episode_title = episode_title.chars()
-                    .map(|c| if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' { c } else { '_' })
-                    .collect::<String>()
-                    .trim()
-                    .to_string();
-                
-                // Create podcast-specific directory (like Python version)
-                let download_dir = std::path::Path::new("/opt/pinepods/downloads").join(&safe_podcast_name);
-                if !download_dir.exists() {
-                    std::fs::create_dir_all(&download_dir)
-                        .map_err(|e| crate::error::AppError::internal(&format!("Failed to create download directory: {}", e)))?;
-                    
-                    // Set ownership using PUID/PGID environment variables
-                    let puid: u32 = std::env::var("PUID").unwrap_or_else(|_| "1000".to_string()).parse().unwrap_or(1000);
-                    let pgid: u32 = std::env::var("PGID").unwrap_or_else(|_| "1000".to_string()).parse().unwrap_or(1000);
-                    
-                    // Set directory ownership (ignore errors for NFS mounts)
-                    let _ = std::process::Command::new("chown")
-                        .args(&[format!("{}:{}", puid, pgid), download_dir.to_string_lossy().to_string()])
-                        .output();
-                }
-                
-                // Format date for filename (like Python version)
-                let pub_date_str = if let Some(date) = pub_date {
-                    date.format("%Y-%m-%d").to_string()
-                } else {
-                    chrono::Utc::now().format("%Y-%m-%d").to_string()
-                };
-                
-                // Create filename with date, title, and IDs (like Python version)
-                let filename = format!("{}_{}_{}_{}.mp3", pub_date_str, safe_episode_title, user_id, episode_id);
-                let file_path = download_dir.join(&filename);
-                
-                let status_message = format!("Connecting to {}", episode_title);
-                task_manager.update_task_progress_with_details(&task_id_clone, 20.0, Some(status_message), Some(episode_id), Some("podcast_download".to_string()), Some(episode_title.clone())).await?;
-                
-                // Download the file
-                let client = reqwest::Client::new();
-                let mut response = client.get(&episode_url)
-                    .send()
-                    .await
-                    .map_err(|e| crate::error::AppError::internal(&format!("Failed to start download: {}", e)))?;
-                
-                if !response.status().is_success() {
-                    return Err(crate::error::AppError::internal(&format!("Server returned error: {}", response.status())));
-                }
-                
-                let total_size = response.content_length().unwrap_or(0);
-                let mut downloaded = 0;
-                let mut file = std::fs::File::create(&file_path)
-                    .map_err(|e| crate::error::AppError::internal(&format!("Failed to create file: {}", e)))?;
-                
-                let status_message = format!("Starting download {}", episode_title);
-                task_manager.update_task_progress_with_details(&task_id_clone, 25.0, Some(status_message), Some(episode_id), Some("podcast_download".to_string()), Some(episode_title.clone())).await?;
-                
-                // Download in chunks with progress updates (throttled)
-                use std::io::Write;
-                let mut last_reported_progress = 0.0;
-                
-                while let Some(chunk) = response.chunk().await
-                    .map_err(|e| crate::error::AppError::internal(&format!("Download failed: {}", e)))?
-                {
-                    file.write_all(&chunk)
-                        .map_err(|e| crate::error::AppError::internal(&format!("Failed to write file: {}", e)))?;
-                    
-                    downloaded += chunk.len() as u64;
-                    
-                    if total_size > 0 {
-                        let progress = 25.0 + (downloaded as f64 / total_size as f64) * 65.0; // 25% to 90%
-                        
-                        // Only send WebSocket updates every 10% to avoid overwhelming the browser
-                        if progress - last_reported_progress >= 10.0 || downloaded == total_size {
-                            let status_message = format!("Downloading {}", episode_title);
-                            task_manager.update_task_progress_with_details(
-                                &task_id_clone, 
-                                progress, 
-                                Some(status_message), 
-                                Some(episode_id), 
-                                Some("podcast_download".to_string()),
-                                Some(episode_title.clone())
-                            ).await?;
-                            last_reported_progress = progress;
-                        }
-                    }
-                }
-                
-                file.flush()
-                    .map_err(|e| crate::error::AppError::internal(&format!("Failed to flush file: {}", e)))?;
-                
-                drop(file); // Close the file handle before metadata operations
-                
-                // Set file ownership using PUID/PGID environment variables
-                let puid: u32 = std::env::var("PUID").unwrap_or_else(|_| "1000".to_string()).parse().unwrap_or(1000);
-                let pgid: u32 = std::env::var("PGID").unwrap_or_else(|_| "1000".to_string()).parse().unwrap_or(1000);
-                
-                // Set file ownership (ignore errors for NFS mounts)
-                let _ = std::process::Command::new("chown")
-                    .args(&[format!("{}:{}", puid, pgid), file_path.to_string_lossy().to_string()])
-                    .output();
-                
-                let status_message = format!("Processing {}", episode_title);
-                task_manager.update_task_progress_with_details(&task_id_clone, 85.0, Some(status_message), Some(episode_id), Some("podcast_download".to_string()), Some(episode_title.clone())).await?;
-                
-                // Add metadata to the downloaded file
-                if let Err(e) = add_podcast_metadata(
-                    &file_path,
-                    &episode_title,
-                    author.as_deref().unwrap_or("Unknown"),
-                    &podcast_name,
-                    pub_date.as_ref(),
-                    episode_artwork.as_deref().or(artwork_url.as_deref())
-                ).await {
-                    tracing::warn!("Failed to add metadata to {}: {}", file_path.display(), e);
-                }
-                
-                let status_message = format!("Finalizing {}", episode_title);
-                task_manager.update_task_progress_with_details(&task_id_clone, 90.0, Some(status_message), Some(episode_id), Some("podcast_download".to_string()), Some(episode_title.clone())).await?;
-                
-                // Update database with download info
-                match &db_pool {
-                    crate::database::DatabasePool::Postgres(pool) => {
-                        sqlx::query(r#"
-                            INSERT INTO "DownloadedEpisodes" (userid, episodeid, downloadedsize, downloadedlocation)
-                            VALUES ($1, $2, $3, $4)
-                        "#)
-                        .bind(user_id)
-                        .bind(episode_id)
-                        .bind(downloaded as i64)
-                        .bind(file_path.to_string_lossy().as_ref())
-                        .execute(pool)
-                        .await?;
-
-                        // Update UserStats table to increment EpisodesDownloaded count
-                        sqlx::query(r#"
-                            UPDATE "UserStats" SET episodesdownloaded = episodesdownloaded + 1 WHERE userid = $1
-                        "#)
-                        .bind(user_id)
-                        .execute(pool)
-                        .await?;
-                    }
-                    crate::database::DatabasePool::MySQL(pool) => {
-                        sqlx::query("
-                            INSERT INTO DownloadedEpisodes (UserID, EpisodeID, DownloadedSize, DownloadedLocation)
-                            VALUES (?, ?, ?, ?)
-                        ")
-                        .bind(user_id)
-                        .bind(episode_id)
-                        .bind(downloaded as i64)
-                        .bind(file_path.to_string_lossy().as_ref())
-                        .execute(pool)
-                        .await?;
-
-                        // Update UserStats table to increment EpisodesDownloaded count
-                        sqlx::query("
-                            UPDATE UserStats SET EpisodesDownloaded = EpisodesDownloaded + 1 WHERE UserID = ?
-                        ")
-                        .bind(user_id)
-                        .execute(pool)
-                        .await?;
-                    }
-                }
-                
-                let status_message = format!("Downloaded {}", episode_title);
-                task_manager.update_task_progress_with_details(&task_id_clone, 100.0, Some(status_message), Some(episode_id), Some("podcast_download".to_string()), Some(episode_title.clone())).await?;
-                
-                Ok(serde_json::json!({
-                    "episode_id": episode_id,
-                    "user_id": user_id,
-                    "status": "downloaded",
-                    "file_path": file_path.to_string_lossy(),
-                    "file_size": downloaded
-                }))
-            };
-
-            match result.await {
-                Ok(result) => {
-                    if let Err(e) = task_manager_for_completion
-                        .complete_task(&task_id_for_completion, Some(result), None)
-                        .await
-                    {
-                        tracing::error!("Failed to mark task {} as completed: {}", task_id_for_completion, e);
-                    }
-                }
-                Err(e) => {
-                    if let Err(err) = task_manager_for_completion
-                        .fail_task(&task_id_for_completion, e.to_string())
-                        .await
-                    {
-                        tracing::error!("Failed to mark task {} as failed: {}", task_id_for_completion, err);
-                    }
-                }
-            }
-        });
-
-        Ok(task_id)
+        tracing::info!("Queueing podcast episode {} for user {}", episode_id, user_id);
+        self.download_queue.enqueue(user_id, episode_id).await
     }
 
     pub async fn spawn_download_youtube_video(&self, video_id: i32, user_id: i32) -> AppResult<String> {
+        let storage = self.storage.clone();
         self.spawn_task(
             "download_video".to_string(),
             user_id,
@@ -588,18 +275,20 @@ impl TaskSpawner {
                     }
                 };
                 
-                let output_path = format!("/opt/pinepods/downloads/youtube/{}.mp3", youtube_video_id);
-                
                 // Check if file already exists
-                if tokio::fs::metadata(&output_path).await.is_ok() {
+                if let Some(location) = storage.find_youtube_audio(&youtube_video_id).await? {
                     tracing::info!("Video {} already downloaded", video_title);
                     return Ok(serde_json::json!({
                         "video_id": video_id,
                         "status": "already_downloaded",
-                        "path": output_path
+                        "path": location
                     }));
                 }
                 
+                let key = storage::youtube_key(&youtube_video_id);
+                let staged = storage.active().staging_path(&key).await?;
+                let output_path = staged.to_string_lossy().to_string();
+                
                 // Download the video using the YouTube handler function
                 match crate::handlers::youtube::download_youtube_audio(&youtube_video_id, &output_path).await {
                     Ok(_) => {
@@ -615,12 +304,13 @@ impl TaskSpawner {
                         } else {
                             tracing::warn!("Could not read duration from MP3 file: {}", output_path);
                         }
+                        let location = storage.active().commit(&key, &staged).await?;
                         
                         Ok(serde_json::json!({
                             "video_id": video_id,
                             "user_id": user_id,
                             "status": "downloaded",
-                            "path": output_path,
+                            "path": location,
                             "title": video_title
                         }))
                     }
@@ -637,8 +327,8 @@ impl TaskSpawner {
         // Create the task first
         let task_id = self.task_manager.create_task("download_all_episodes".to_string(), user_id).await?;
         let task_manager = self.task_manager.clone();
-        let task_spawner = self.clone();
         let db_pool = self.db_pool.clone();
+        let download_queue = self.download_queue.clone();
         let task_id_clone = task_id.clone();
         let task_manager_for_completion = task_manager.clone();
         let task_id_for_completion = task_id_clone.clone();
@@ -701,65 +391,34 @@ impl TaskSpawner {
                     }));
                 }
                 
-                // Download episodes ONE at a time sequentially
-                let mut successful_downloads = 0;
+                // Hand the episodes to the download queue, which runs them in parallel
+                let mut queued_episodes = 0;
                 
                 for (index, episode_id) in episode_ids.iter().enumerate() {
-                    tracing::info!("Starting download {}/{}: episode {}", index + 1, total_episodes, episode_id);
+                    match download_queue.enqueue(user_id, *episode_id).await {
+                        Ok(_) => queued_episodes += 1,
+                        Err(e) => tracing::warn!("Failed to queue episode {} for download: {}", episode_id, e),
+                    }
                     
-                    // Update progress before starting download
-                    let progress = (index as f64 / total_episodes as f64) * 100.0;
+                    let progress = ((index + 1) as f64 / total_episodes as f64) * 100.0;
                     task_manager.update_task_progress_with_details(
                         &task_id_clone, 
                         progress, 
-                        Some(format!("Starting download {}/{} episodes...", index + 1, total_episodes)), 
+                        Some(format!("Queued {}/{} episodes for download", index + 1, total_episodes)), 
                         None, 
                         Some("bulk_download".to_string()), 
                         None
                     ).await?;
-                    
-                    // Actually download the episode and wait for it to complete
-                    match download_episode_and_wait(&db_pool, *episode_id, user_id).await {
-                        Ok(episode_title) => {
-                            successful_downloads += 1;
-                            tracing::info!("Successfully downloaded episode {} - {}", episode_id, episode_title);
-                            
-                            // Update progress after actual completion
-                            let completed_progress = ((index + 1) as f64 / total_episodes as f64) * 100.0;
-                            task_manager.update_task_progress_with_details(
-                                &task_id_clone, 
-                                completed_progress, 
-                                Some(format!("Downloaded {}/{} episodes: {}", index + 1, total_episodes, episode_title)), 
-                                None, 
-                                Some("bulk_download".to_string()), 
-                                None
-                            ).await?;
-                        }
-                        Err(e) => {
-                            tracing::warn!("Failed to download episode {}: {}", episode_id, e);
-                        }
-                    }
                 }
                 
-                tracing::info!("Successfully started {} out of {} episode downloads", successful_downloads, total_episodes);
-                
-                task_manager.update_task_progress_with_details(
-                    &task_id_clone, 
-                    100.0, 
-                    Some(format!("Successfully started {}/{} episode downloads", successful_downloads, total_episodes)), 
-                    None, 
-                    Some("bulk_download".to_string()), 
-                    None
-                ).await?;
-                
-                tracing::info!("Successfully started {} out of {} episode downloads for podcast {} for user {}", successful_downloads, total_episodes, podcast_id, user_id);
+                tracing::info!("Queued {} out of {} episode downloads for podcast {} for user {}", queued_episodes, total_episodes, podcast_id, user_id);
                 
                 Ok(serde_json::json!({
                     "podcast_id": podcast_id,
                     "user_id": user_id,
-                    "status": "episodes_queued_sequentially",
+                    "status": "episodes_queued",
                     "total_episodes": total_episodes,
-                    "queued_episodes": successful_downloads
+                    "queued_episodes": queued_episodes
                 }))
             }).await;
 
@@ -781,6 +440,7 @@ impl TaskSpawner {
     }
 
     pub async fn spawn_download_all_youtube_videos(&self, channel_id: i32, user_id: i32) -> AppResult<String> {
+        let storage = self.storage.clone();
         self.spawn_task(
             "download_all_videos".to_string(),
             user_id,
@@ -828,25 +488,27 @@ impl TaskSpawner {
                 
                 for (index, (youtube_video_id, video_title)) in videos_data.iter().enumerate() {
                     
-                    let output_path = format!("/opt/pinepods/downloads/youtube/{}.mp3", youtube_video_id);
-                    
                     // Update progress
                     let progress = (index as f64 / total_videos as f64) * 100.0;
                     task_manager.update_task_progress(&task_id, progress, Some(format!("Downloading: {}", video_title))).await?;
                     
                     // Check if file already exists
-                    if tokio::fs::metadata(&output_path).await.is_ok() {
+                    if storage.find_youtube_audio(youtube_video_id).await?.is_some() {
                         tracing::info!("Video {} already downloaded", video_title);
                         already_downloaded += 1;
                         continue;
                     }
                     
+                    let key = storage::youtube_key(youtube_video_id);
+                    let staged = storage.active().staging_path(&key).await?;
+                    let output_path = staged.to_string_lossy().to_string();
+                    
                     // Download the video
                     match crate::handlers::youtube::download_youtube_audio(youtube_video_id, &output_path).await {
                         Ok(_) => {
                             tracing::info!("Successfully downloaded: {}", video_title);
-                            downloaded += 1;
                             
+
                             // Get duration from the downloaded MP3 file and update database
                             if let Some(duration) = crate::handlers::youtube::get_mp3_duration(&output_path) {
                                 if let Err(e) = db_pool.update_youtube_video_duration(youtube_video_id, duration).await {
@@ -857,6 +519,14 @@ impl TaskSpawner {
                             } else {
                                 tracing::warn!("Could not read duration from MP3 file: {}", output_path);
                             }
+                            
+                            match storage.active().commit(&key, &staged).await {
+                                Ok(_) => downloaded += 1,
+                                Err(e) => {
+                                    tracing::error!("Failed to store {}: {}", video_title, e);
+                                    failed += 1;
+                                }
+                            }
                         }
                         Err(e) => {
                             tracing::error!("Failed to download {}: {}", video_title, e);
@@ -883,14 +553,17 @@ impl TaskSpawner {
     }
 }
 
-// Function to add metadata to downloaded MP3 files
-async fn add_podcast_metadata(
+// Function to add metadata to downloaded MP3 files, including ID3v2 CHAP/CTOC chapter frames
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn add_podcast_metadata(
     file_path: &std::path::Path,
     title: &str,
     artist: &str,
     album: &str,
     date: Option<&chrono::NaiveDateTime>,
     artwork_url: Option<&str>,
+    chapters: &[crate::services::chapters::Chapter],
+    duration_secs: Option<f64>,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     use id3::TagLike;  // Import the trait to use methods
     use chrono::Datelike;  // For year(), month(), day() methods
@@ -937,6 +610,8 @@ async fn add_podcast_metadata(
         }
     }
     
+    crate::services::chapters::set_id3_chapters(&mut tag, chapters, duration_secs);
+    
     // Write the tag to the file
     tag.write_to_path(file_path, id3::Version::Id3v24)?;
     
@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use chrono::NaiveDateTime;
+use regex::Regex;
+use serde::Serialize;
+use crate::error::{AppError, AppResult};
+
+// Segments are merged up to these limits so word-level JSON transcripts don't explode into thousands of rows
+const MAX_SEGMENT_SECONDS: f64 = 30.0;
+const MAX_SEGMENT_CHARS: usize = 600;
+
+// Transcripts larger than this are almost certainly not text
+const MAX_TRANSCRIPT_BYTES: usize = 8 * 1024 * 1024;
+
+// Downloads that fail for a passing reason are retried by later refreshes, an hour after
+// the first failure and twice as long after each further one
+const MAX_FETCH_ATTEMPTS: i32 = 5;
+const RETRY_BASE_HOURS: i64 = 1;
+
+/// A `<podcast:transcript>` element from a feed item
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptLink {
+    pub url: String,
+    pub mime_type: Option<String>,
+    pub language: Option<String>,
+    pub rel: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Json,
+    Vtt,
+    Srt,
+    Html,
+}
+
+impl TranscriptFormat {
+    /// Work out the format from the declared mime type, falling back to the URL extension
+    pub fn detect(link: &TranscriptLink) -> Option<Self> {
+        let mime = link.mime_type.as_deref().unwrap_or("").to_lowercase();
+        let path = link.url.split(['?', '#']).next().unwrap_or("").to_lowercase();
+
+        if mime.contains("json") || path.ends_with(".json") {
+            Some(TranscriptFormat::Json)
+        } else if mime.contains("vtt") || path.ends_with(".vtt") {
+            Some(TranscriptFormat::Vtt)
+        } else if mime.contains("srt") || mime.contains("subrip") || path.ends_with(".srt") {
+            Some(TranscriptFormat::Srt)
+        } else if mime.contains("html") || path.ends_with(".html") || path.ends_with(".htm") {
+            Some(TranscriptFormat::Html)
+        } else {
+            None
+        }
+    }
+
+    // Lower is better: timestamps and speakers are most reliable in JSON, least in HTML
+    fn preference(self) -> u8 {
+        match self {
+            TranscriptFormat::Json => 0,
+            TranscriptFormat::Vtt => 1,
+            TranscriptFormat::Srt => 2,
+            TranscriptFormat::Html => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TranscriptSegment {
+    pub start_time: f64,
+    pub end_time: Option<f64>,
+    pub speaker: Option<String>,
+    pub text: String,
+}
+
+/// Collect transcript links for every item in a feed, keyed by enclosure URL
+pub fn extract_feed_transcript_links(feed_content: &str) -> HashMap<String, Vec<TranscriptLink>> {
+    let item_regex = Regex::new(r"(?s)<item[\s>].*?</item>").unwrap();
+    let enclosure_regex = Regex::new(r#"<enclosure\b[^>]*?\burl\s*=\s*["']([^"']+)["']"#).unwrap();
+    let transcript_regex = Regex::new(r"<(?:podcast:)?transcript\b([^>]*)/?>").unwrap();
+
+    let mut links = HashMap::new();
+    for item in item_regex.find_iter(feed_content) {
+        let item = item.as_str();
+        let Some(enclosure_url) = enclosure_regex.captures(item).map(|c| decode_xml_entities(&c[1])) else {
+            continue;
+        };
+
+        let transcripts: Vec<TranscriptLink> = transcript_regex
+            .captures_iter(item)
+            .filter_map(|caps| {
+                let attrs = &caps[1];
+                Some(TranscriptLink {
+                    url: xml_attribute(attrs, "url")?,
+                    mime_type: xml_attribute(attrs, "type"),
+                    language: xml_attribute(attrs, "language"),
+                    rel: xml_attribute(attrs, "rel"),
+                })
+            })
+            .collect();
+
+        if !transcripts.is_empty() {
+            links.insert(enclosure_url, transcripts);
+        }
+    }
+    links
+}
+
+fn xml_attribute(attrs: &str, name: &str) -> Option<String> {
+    let pattern = format!(r#"\b{}\s*=\s*["']([^"']*)["']"#, regex::escape(name));
+    Regex::new(&pattern)
+        .ok()?
+        .captures(attrs)
+        .map(|c| decode_xml_entities(&c[1]))
+        .filter(|v| !v.trim().is_empty())
+}
+
+fn decode_xml_entities(value: &str) -> String {
+    value
+        .replace("&amp;", "&")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+}
+
+/// Pick the transcript we can parse best out of the ones a feed offers
+pub fn pick_preferred(links: &[TranscriptLink]) -> Option<(&TranscriptLink, TranscriptFormat)> {
+    links
+        .iter()
+        .filter_map(|link| TranscriptFormat::detect(link).map(|format| (link, format)))
+        .min_by_key(|(_, format)| format.preference())
+}
+
+/// Why a transcript couldn't be stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchFailure {
+    pub message: String,
+    /// Whether trying again later might work: timeouts, connection errors, 5xx and 429 responses
+    pub transient: bool,
+}
+
+impl FetchFailure {
+    fn permanent(message: impl Into<String>) -> Self {
+        FetchFailure { message: message.into(), transient: false }
+    }
+
+    fn from_status(status: reqwest::StatusCode) -> Self {
+        FetchFailure {
+            message: format!("Transcript request returned HTTP {}", status),
+            transient: status.is_server_error()
+                || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                || status == reqwest::StatusCode::REQUEST_TIMEOUT,
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchFailure {
+    fn from(e: reqwest::Error) -> Self {
+        match e.status() {
+            Some(status) => FetchFailure::from_status(status),
+            None => FetchFailure {
+                transient: e.is_timeout() || e.is_connect() || e.is_body(),
+                message: format!("Transcript request failed: {}", e),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for FetchFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// When to try a failed download again, given the attempts made so far including this
+/// one; None when the failure won't go away or the attempts are used up
+pub fn retry_after(failure: &FetchFailure, attempts: i32, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    (failure.transient && (1..MAX_FETCH_ATTEMPTS).contains(&attempts))
+        .then(|| now + chrono::Duration::hours(RETRY_BASE_HOURS << (attempts - 1)))
+}
+
+/// Download and parse a transcript into merged, timestamped segments
+pub async fn fetch_transcript_segments(link: &TranscriptLink, format: TranscriptFormat) -> Result<Vec<TranscriptSegment>, FetchFailure> {
+    let client = reqwest::Client::builder()
+        .user_agent("PinePods/1.0")
+        .timeout(Duration::from_secs(20))
+        .build()?;
+
+    let response = client.get(&link.url).send().await?;
+    if !response.status().is_success() {
+        return Err(FetchFailure::from_status(response.status()));
+    }
+    if response.content_length().is_some_and(|len| len as usize > MAX_TRANSCRIPT_BYTES) {
+        return Err(FetchFailure::permanent("Transcript is too large"));
+    }
+
+    let body = response.text().await?;
+    if body.len() > MAX_TRANSCRIPT_BYTES {
+        return Err(FetchFailure::permanent("Transcript is too large"));
+    }
+
+    let segments = parse_transcript(format, &body).map_err(|e| FetchFailure::permanent(e.to_string()))?;
+    Ok(coalesce_segments(segments))
+}
+
+pub fn parse_transcript(format: TranscriptFormat, body: &str) -> AppResult<Vec<TranscriptSegment>> {
+    let segments = match format {
+        TranscriptFormat::Json => parse_json(body)?,
+        TranscriptFormat::Vtt | TranscriptFormat::Srt => parse_cues(body),
+        TranscriptFormat::Html => parse_html(body),
+    };
+    Ok(segments.into_iter().filter(|s| !s.text.is_empty()).collect())
+}
+
+// Podcasting 2.0 JSON: {"version": "1.0.0", "segments": [{"speaker", "startTime", "endTime", "body"}]}
+fn parse_json(body: &str) -> AppResult<Vec<TranscriptSegment>> {
+    let value: serde_json::Value = serde_json::from_str(body)?;
+    let segments = value
+        .get("segments")
+        .and_then(|s| s.as_array())
+        .ok_or_else(|| AppError::FeedParsing("Transcript JSON has no segments array".to_string()))?;
+
+    Ok(segments
+        .iter()
+        .filter_map(|segment| {
+            let start_time = json_seconds(segment.get("startTime")?)?;
+            Some(TranscriptSegment {
+                start_time,
+                end_time: segment.get("endTime").and_then(json_seconds),
+                speaker: segment.get("speaker").and_then(|s| s.as_str()).map(|s| s.trim().to_string()).filter(|s| !s.is_empty()),
+                text: clean_text(segment.get("body")?.as_str()?),
+            })
+        })
+        .collect())
+}
+
+fn json_seconds(value: &serde_json::Value) -> Option<f64> {
+    value.as_f64().or_else(|| value.as_str().and_then(parse_timestamp))
+}
+
+// SRT and WebVTT share the "start --> end" cue layout; VTT adds a header and <v Speaker> voice tags
+fn parse_cues(body: &str) -> Vec<TranscriptSegment> {
+    let voice_regex = Regex::new(r"<v(?:\.[^\s>]+)?\s+([^>]+)>").unwrap();
+    let mut segments = Vec::new();
+    let normalized = body.replace("\r\n", "\n").replace('\r', "\n");
+
+    for block in normalized.split("\n\n") {
+        let mut lines = block.lines().map(str::trim).filter(|l| !l.is_empty());
+        let Some(timing) = lines.by_ref().find(|l| l.contains("-->")) else {
+            continue;
+        };
+
+        let mut parts = timing.split("-->");
+        let start = parts.next().and_then(|s| parse_timestamp(s.trim()));
+        // VTT cue settings may follow the end timestamp
+        let end = parts.next().and_then(|s| s.split_whitespace().next()).and_then(parse_timestamp);
+        let Some(start_time) = start else {
+            continue;
+        };
+
+        let raw: Vec<&str> = lines.collect();
+        let raw_text = raw.join(" ");
+        let speaker = voice_regex.captures(&raw_text).map(|c| c[1].trim().to_string());
+
+        segments.push(TranscriptSegment {
+            start_time,
+            end_time: end,
+            speaker,
+            text: clean_text(&raw_text),
+        });
+    }
+    segments
+}
+
+// Podcasting 2.0 HTML: <cite>Speaker:</cite> <time>0:00</time> <p>text</p>
+fn parse_html(body: &str) -> Vec<TranscriptSegment> {
+    let block_regex = Regex::new(r"(?is)(?:<cite>(.*?)</cite>\s*)?<time>(.*?)</time>\s*<p>(.*?)</p>").unwrap();
+
+    block_regex
+        .captures_iter(body)
+        .filter_map(|caps| {
+            let start_time = parse_timestamp(caps.get(2)?.as_str().trim())?;
+            Some(TranscriptSegment {
+                start_time,
+                end_time: None,
+                speaker: caps
+                    .get(1)
+                    .map(|m| clean_text(m.as_str()).trim_end_matches(':').trim().to_string())
+                    .filter(|s| !s.is_empty()),
+                text: clean_text(caps.get(3)?.as_str()),
+            })
+        })
+        .collect()
+}
+
+/// Parse `HH:MM:SS.mmm`, `MM:SS,mmm`, `SS.mmm` and friends into seconds
+pub fn parse_timestamp(value: &str) -> Option<f64> {
+    let value = value.trim().replace(',', ".");
+    if value.is_empty() {
+        return None;
+    }
+
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        let part: f64 = part.trim().parse().ok()?;
+        if part < 0.0 {
+            return None;
+        }
+        seconds = seconds * 60.0 + part;
+    }
+    Some(seconds)
+}
+
+// Drop markup and collapse whitespace
+fn clean_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for ch in text.chars() {
+        match ch {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            }
+            _ if !in_tag => out.push(ch),
+            _ => {}
+        }
+    }
+    decode_xml_entities(&out).split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Merge consecutive segments from the same speaker into chunks of a searchable size
+pub fn coalesce_segments(segments: Vec<TranscriptSegment>) -> Vec<TranscriptSegment> {
+    let mut merged: Vec<TranscriptSegment> = Vec::new();
+
+    for segment in segments {
+        if let Some(current) = merged.last_mut() {
+            let same_speaker = segment.speaker.is_none() || segment.speaker == current.speaker;
+            let span = segment.end_time.unwrap_or(segment.start_time) - current.start_time;
+            if same_speaker && span <= MAX_SEGMENT_SECONDS && current.text.len() + segment.text.len() < MAX_SEGMENT_CHARS {
+                current.text.push(' ');
+                current.text.push_str(&segment.text);
+                current.end_time = segment.end_time.or(current.end_time);
+                continue;
+            }
+        }
+        merged.push(segment);
+    }
+
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(url: &str, mime_type: Option<&str>) -> TranscriptLink {
+        TranscriptLink { url: url.to_string(), mime_type: mime_type.map(str::to_string), language: None, rel: None }
+    }
+
+    fn segment(start_time: f64, end_time: Option<f64>, speaker: Option<&str>, text: &str) -> TranscriptSegment {
+        TranscriptSegment { start_time, end_time, speaker: speaker.map(str::to_string), text: text.to_string() }
+    }
+
+    #[test]
+    fn formats_come_from_the_mime_type_then_the_extension() {
+        assert_eq!(TranscriptFormat::detect(&link("https://x.test/t", Some("application/json"))), Some(TranscriptFormat::Json));
+        assert_eq!(TranscriptFormat::detect(&link("https://x.test/t", Some("text/vtt"))), Some(TranscriptFormat::Vtt));
+        assert_eq!(TranscriptFormat::detect(&link("https://x.test/t", Some("application/x-subrip"))), Some(TranscriptFormat::Srt));
+        assert_eq!(TranscriptFormat::detect(&link("https://x.test/t.SRT?v=2", None)), Some(TranscriptFormat::Srt));
+        assert_eq!(TranscriptFormat::detect(&link("https://x.test/t.htm#top", None)), Some(TranscriptFormat::Html));
+        assert_eq!(TranscriptFormat::detect(&link("https://x.test/t.txt", Some("text/plain"))), None);
+    }
+
+    #[test]
+    fn json_is_preferred_over_cues_over_html() {
+        let links = vec![
+            link("https://x.test/t.html", None),
+            link("https://x.test/t.txt", None),
+            link("https://x.test/t.srt", None),
+            link("https://x.test/t.json", None),
+        ];
+        let (picked, format) = pick_preferred(&links).unwrap();
+        assert_eq!(picked.url, "https://x.test/t.json");
+        assert_eq!(format, TranscriptFormat::Json);
+        assert!(pick_preferred(&links[1..2]).is_none());
+    }
+
+    #[test]
+    fn feed_links_are_keyed_by_enclosure() {
+        let feed = r#"<rss><channel>
+            <item><title>One</title>
+              <enclosure url="https://x.test/1.mp3?a=1&amp;b=2" type="audio/mpeg"/>
+              <podcast:transcript url="https://x.test/1.vtt" type="text/vtt" language="en"/>
+              <podcast:transcript url='https://x.test/1.json' type='application/json' rel='captions' />
+            </item>
+            <item><title>Two</title><enclosure url="https://x.test/2.mp3"/></item>
+            <item><podcast:transcript url="https://x.test/orphan.srt"/></item>
+        </channel></rss>"#;
+        let links = extract_feed_transcript_links(feed);
+        assert_eq!(links.len(), 1);
+        let one = &links["https://x.test/1.mp3?a=1&b=2"];
+        assert_eq!(one.len(), 2);
+        assert_eq!(one[0].language.as_deref(), Some("en"));
+        assert_eq!(one[1].url, "https://x.test/1.json");
+        assert_eq!(one[1].rel.as_deref(), Some("captions"));
+    }
+
+    #[test]
+    fn timestamps_parse_in_every_layout() {
+        assert_eq!(parse_timestamp("01:02:03.500"), Some(3723.5));
+        assert_eq!(parse_timestamp("02:03,250"), Some(123.25));
+        assert_eq!(parse_timestamp("7.5"), Some(7.5));
+        assert_eq!(parse_timestamp(" 0:00 "), Some(0.0));
+        assert_eq!(parse_timestamp(""), None);
+        assert_eq!(parse_timestamp("1:-2"), None);
+        assert_eq!(parse_timestamp("soon"), None);
+    }
+
+    #[test]
+    fn json_segments_parse() {
+        let body = r#"{"version": "1.0.0", "segments": [
+            {"speaker": "Alice", "startTime": 0.5, "endTime": 2.0, "body": "Hello &amp; welcome"},
+            {"speaker": " ", "startTime": "00:00:02.5", "body": "<b>Bold</b> move"},
+            {"startTime": 4, "body": ""},
+            {"body": "no start"}
+        ]}"#;
+        let segments = parse_transcript(TranscriptFormat::Json, body).unwrap();
+        assert_eq!(segments, vec![
+            segment(0.5, Some(2.0), Some("Alice"), "Hello & welcome"),
+            segment(2.5, None, None, "Bold move"),
+        ]);
+        assert!(parse_transcript(TranscriptFormat::Json, r#"{"version": "1.0.0"}"#).is_err());
+        assert!(parse_transcript(TranscriptFormat::Json, "not json").is_err());
+    }
+
+    #[test]
+    fn srt_cues_parse() {
+        let body = "1\r\n00:00:01,000 --> 00:00:03,500\r\nFirst line\r\nsecond line\r\n\r\n2\r\n00:00:04,000 --> 00:00:05,000\r\n<i>Aside</i>\r\n\r\n3\r\nbroken --> cue\r\nDropped\r\n";
+        let segments = parse_transcript(TranscriptFormat::Srt, body).unwrap();
+        assert_eq!(segments, vec![
+            segment(1.0, Some(3.5), None, "First line second line"),
+            segment(4.0, Some(5.0), None, "Aside"),
+        ]);
+    }
+
+    #[test]
+    fn vtt_cues_parse_with_voices_and_settings() {
+        let body = "WEBVTT\n\nNOTE written by hand\n\nintro\n00:01.000 --> 00:04.000 align:start position:10%\n<v.loud Bob Smith>Hi there</v>\n\n00:00:05.000 --> 00:00:06.000\nNo speaker\n";
+        let segments = parse_transcript(TranscriptFormat::Vtt, body).unwrap();
+        assert_eq!(segments, vec![
+            segment(1.0, Some(4.0), Some("Bob Smith"), "Hi there"),
+            segment(5.0, Some(6.0), None, "No speaker"),
+        ]);
+    }
+
+    #[test]
+    fn html_blocks_parse() {
+        let body = "<html><body>\n<cite>Alice:</cite>\n<time>0:00</time>\n<p>Welcome to <em>the</em> show.</p>\n<time>1:05</time><p>Unattributed</p>\n<time>later</time><p>Dropped</p>\n</body></html>";
+        let segments = parse_transcript(TranscriptFormat::Html, body).unwrap();
+        assert_eq!(segments, vec![
+            segment(0.0, None, Some("Alice"), "Welcome to the show."),
+            segment(65.0, None, None, "Unattributed"),
+        ]);
+    }
+
+    #[test]
+    fn segments_merge_until_the_speaker_or_limits_change() {
+        let merged = coalesce_segments(vec![
+            segment(0.0, Some(1.0), Some("A"), "one"),
+            segment(1.0, Some(2.0), None, "two"),
+            segment(2.0, Some(3.0), Some("B"), "three"),
+            segment(3.0, Some(40.0), Some("B"), "four"),
+        ]);
+        assert_eq!(merged, vec![
+            segment(0.0, Some(2.0), Some("A"), "one two"),
+            segment(2.0, Some(3.0), Some("B"), "three"),
+            segment(3.0, Some(40.0), Some("B"), "four"),
+        ]);
+
+        let long = "x".repeat(MAX_SEGMENT_CHARS / 2 + 1);
+        let merged = coalesce_segments(vec![segment(0.0, None, None, &long), segment(1.0, None, None, &long)]);
+        assert_eq!(merged.len(), 2);
+    }
+
+    #[test]
+    fn server_errors_and_rate_limits_are_transient() {
+        use reqwest::StatusCode;
+        assert!(FetchFailure::from_status(StatusCode::BAD_GATEWAY).transient);
+        assert!(FetchFailure::from_status(StatusCode::SERVICE_UNAVAILABLE).transient);
+        assert!(FetchFailure::from_status(StatusCode::TOO_MANY_REQUESTS).transient);
+        assert!(FetchFailure::from_status(StatusCode::REQUEST_TIMEOUT).transient);
+        assert!(!FetchFailure::from_status(StatusCode::NOT_FOUND).transient);
+        assert!(!FetchFailure::from_status(StatusCode::FORBIDDEN).transient);
+        assert!(!FetchFailure::permanent("Transcript is too large").transient);
+    }
+
+    #[tokio::test]
+    async fn unreachable_servers_are_transient() {
+        // Nothing listens on a port that was just released
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let failure = fetch_transcript_segments(&link(&format!("http://127.0.0.1:{}/t.vtt", port), None), TranscriptFormat::Vtt)
+            .await
+            .unwrap_err();
+        assert!(failure.transient, "{}", failure);
+    }
+
+    #[test]
+    fn transient_failures_are_retried_with_growing_delays() {
+        let now = chrono::Utc::now().naive_utc();
+        let transient = FetchFailure { message: "HTTP 503".to_string(), transient: true };
+        let delays: Vec<i64> = (1..MAX_FETCH_ATTEMPTS)
+            .map(|attempts| (retry_after(&transient, attempts, now).unwrap() - now).num_hours())
+            .collect();
+        assert_eq!(delays, vec![1, 2, 4, 8]);
+        assert_eq!(retry_after(&transient, MAX_FETCH_ATTEMPTS, now), None);
+        assert_eq!(retry_after(&FetchFailure::permanent("HTTP 404"), 1, now), None);
+    }
+}
@@ -0,0 +1,774 @@
+use aws_lc_rs::signature::{self, RsaPublicKeyComponents, UnparsedPublicKey};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use crate::{
+    config::WebAuthnConfig,
+    error::{AppError, AppResult},
+};
+
+/// How long a browser has to answer a registration or sign-in challenge
+pub const CHALLENGE_TTL_SECONDS: u64 = 300;
+// Longest credential ID the WebAuthnCredentials table takes, base64url-encoded
+const MAX_CREDENTIAL_ID_BYTES: usize = 512;
+/// Recovery codes issued at a time
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+// COSE algorithms offered to authenticators, most preferred first
+const ES256: i64 = -7;
+const EDDSA: i64 = -8;
+const RS256: i64 = -257;
+
+// Authenticator data flags
+const USER_PRESENT: u8 = 0x01;
+const USER_VERIFIED: u8 = 0x04;
+const ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// A passkey or security key a user has enrolled
+#[derive(Debug, Clone, Serialize)]
+pub struct WebAuthnCredential {
+    pub passkey_id: i32,
+    #[serde(skip)]
+    pub user_id: i32,
+    pub credential_id: String,
+    #[serde(skip)]
+    pub public_key: String,
+    #[serde(skip)]
+    pub sign_count: i64,
+    pub name: String,
+    pub transports: Vec<String>,
+    pub created_at: Option<NaiveDateTime>,
+    pub last_used_at: Option<NaiveDateTime>,
+}
+
+/// A credential that passed registration, ready to store
+pub struct NewCredential {
+    pub credential_id: String,
+    /// COSE_Key, base64url-encoded
+    pub public_key: String,
+    pub sign_count: i64,
+    pub transports: Vec<String>,
+}
+
+/// A PublicKeyCredential as produced by its toJSON(), binary fields base64url-encoded
+#[derive(Debug, Clone, Deserialize)]
+pub struct PublicKeyCredential {
+    pub id: String,
+    pub response: CredentialResponse,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CredentialResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    /// Registration only
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: Option<String>,
+    #[serde(default)]
+    pub transports: Vec<String>,
+    /// Sign-in only
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: Option<String>,
+    pub signature: Option<String>,
+    #[serde(rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+    #[serde(rename = "crossOrigin", default)]
+    cross_origin: bool,
+}
+
+fn invalid(msg: &str) -> AppError {
+    AppError::Auth(format!("Passkey rejected: {}", msg))
+}
+
+pub fn encode(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+// Browsers send base64url, but some client libraries pad it or use the standard alphabet
+pub fn decode(value: &str) -> AppResult<Vec<u8>> {
+    let value: String = value.trim_end_matches('=').chars()
+        .map(|c| match c { '+' => '-', '/' => '_', c => c })
+        .collect();
+    URL_SAFE_NO_PAD.decode(value).map_err(|_| invalid("malformed base64"))
+}
+
+/// A fresh random challenge, base64url-encoded
+pub fn generate_challenge() -> String {
+    encode(&rand::random::<[u8; 32]>())
+}
+
+/// The WebAuthn user handle for a PinePods user
+pub fn user_handle(user_id: i32) -> String {
+    encode(user_id.to_string().as_bytes())
+}
+
+/// Transports as stored, comma-separated
+pub fn parse_transports(transports: Option<&str>) -> Vec<String> {
+    transports.unwrap_or_default().split(',').map(str::trim).filter(|t| !t.is_empty()).map(str::to_string).collect()
+}
+
+/// A set of fresh one-time recovery codes, formatted like `k3v9p-2xq7m`
+pub fn generate_recovery_codes() -> Vec<String> {
+    use rand::Rng;
+    // No 0/o or 1/l so codes survive being read aloud or copied by hand
+    const ALPHABET: &[u8] = b"23456789abcdefghijkmnpqrstuvwxyz";
+    let mut rng = rand::rng();
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let mut code: String = (0..10).map(|_| ALPHABET[rng.random_range(0..ALPHABET.len())] as char).collect();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
+
+/// What is stored for a recovery code. Case, dashes and spaces don't matter when it's typed back.
+pub fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+fn credential_descriptors(credentials: &[WebAuthnCredential]) -> Vec<serde_json::Value> {
+    credentials.iter()
+        .map(|c| serde_json::json!({ "type": "public-key", "id": c.credential_id, "transports": c.transports }))
+        .collect()
+}
+
+/// PublicKeyCredentialCreationOptions for enrolling a new passkey, in the JSON form
+/// PublicKeyCredential.parseCreationOptionsFromJSON() takes
+pub fn creation_options(config: &WebAuthnConfig, challenge: &str, user_id: i32, username: &str, display_name: &str, existing: &[WebAuthnCredential]) -> serde_json::Value {
+    let algorithms: Vec<_> = [ES256, EDDSA, RS256].iter()
+        .map(|alg| serde_json::json!({ "type": "public-key", "alg": alg }))
+        .collect();
+    serde_json::json!({
+        "rp": { "id": config.rp_id, "name": "PinePods" },
+        "user": { "id": user_handle(user_id), "name": username, "displayName": display_name },
+        "challenge": challenge,
+        "pubKeyCredParams": algorithms,
+        "timeout": CHALLENGE_TTL_SECONDS * 1000,
+        "attestation": "none",
+        "excludeCredentials": credential_descriptors(existing),
+        "authenticatorSelection": { "residentKey": "preferred", "userVerification": "preferred" },
+    })
+}
+
+/// PublicKeyCredentialRequestOptions for signing in. With no credentials listed the browser
+/// offers any passkey it holds for this site, which is how passwordless sign-in works.
+pub fn request_options(config: &WebAuthnConfig, challenge: &str, allowed: &[WebAuthnCredential]) -> serde_json::Value {
+    serde_json::json!({
+        "rpId": config.rp_id,
+        "challenge": challenge,
+        "timeout": CHALLENGE_TTL_SECONDS * 1000,
+        "allowCredentials": credential_descriptors(allowed),
+        "userVerification": if allowed.is_empty() { "required" } else { "preferred" },
+    })
+}
+
+// Check the client data the browser signed over and return its raw bytes
+fn check_client_data(config: &WebAuthnConfig, encoded: &str, kind: &str, challenge: &str) -> AppResult<Vec<u8>> {
+    let raw = decode(encoded)?;
+    let client_data: ClientData = serde_json::from_slice(&raw).map_err(|_| invalid("malformed client data"))?;
+    if client_data.kind != kind {
+        return Err(invalid("wrong ceremony type"));
+    }
+    if decode(&client_data.challenge)? != decode(challenge)? {
+        return Err(invalid("challenge does not match"));
+    }
+    if !config.origins.contains(&client_data.origin) {
+        return Err(invalid(&format!("unexpected origin {}", client_data.origin)));
+    }
+    if client_data.cross_origin {
+        return Err(invalid("cross-origin requests are not accepted"));
+    }
+    Ok(raw)
+}
+
+// Just enough CBOR for attestation objects and COSE keys
+#[derive(Debug, Clone, PartialEq)]
+enum Cbor {
+    Int(i128),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Cbor>),
+    Map(Vec<(Cbor, Cbor)>),
+    Simple,
+}
+
+impl Cbor {
+    fn get(&self, key: &Cbor) -> Option<&Cbor> {
+        match self {
+            Cbor::Map(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    fn int(&self, key: i128) -> Option<i128> {
+        match self.get(&Cbor::Int(key)) {
+            Some(Cbor::Int(value)) => Some(*value),
+            _ => None,
+        }
+    }
+
+    fn bytes(&self, key: i128) -> Option<&[u8]> {
+        match self.get(&Cbor::Int(key)) {
+            Some(Cbor::Bytes(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+fn take<'a>(data: &'a [u8], pos: &mut usize, len: usize) -> AppResult<&'a [u8]> {
+    let end = pos.checked_add(len).filter(|end| *end <= data.len()).ok_or_else(|| invalid("truncated data"))?;
+    let slice = &data[*pos..end];
+    *pos = end;
+    Ok(slice)
+}
+
+fn read_cbor(data: &[u8], pos: &mut usize, depth: usize) -> AppResult<Cbor> {
+    if depth > 16 {
+        return Err(invalid("data nested too deeply"));
+    }
+    let initial = take(data, pos, 1)?[0];
+    let (major, info) = (initial >> 5, initial & 0x1f);
+    let argument = match info {
+        0..=23 => info as u64,
+        24 => take(data, pos, 1)?[0] as u64,
+        25 => u16::from_be_bytes(take(data, pos, 2)?.try_into().unwrap_or_default()) as u64,
+        26 => u32::from_be_bytes(take(data, pos, 4)?.try_into().unwrap_or_default()) as u64,
+        27 => u64::from_be_bytes(take(data, pos, 8)?.try_into().unwrap_or_default()),
+        // Indefinite lengths aren't allowed in the canonical CBOR authenticators send
+        _ => return Err(invalid("unsupported CBOR encoding")),
+    };
+    let length = |argument: u64| usize::try_from(argument).map_err(|_| invalid("oversized CBOR item"));
+    Ok(match major {
+        0 => Cbor::Int(argument as i128),
+        1 => Cbor::Int(-1 - argument as i128),
+        2 => Cbor::Bytes(take(data, pos, length(argument)?)?.to_vec()),
+        3 => Cbor::Text(String::from_utf8(take(data, pos, length(argument)?)?.to_vec()).map_err(|_| invalid("malformed CBOR text"))?),
+        4 => {
+            let count = length(argument)?;
+            let mut items = Vec::with_capacity(count.min(64));
+            for _ in 0..count {
+                items.push(read_cbor(data, pos, depth + 1)?);
+            }
+            Cbor::Array(items)
+        }
+        5 => {
+            let count = length(argument)?;
+            let mut entries = Vec::with_capacity(count.min(64));
+            for _ in 0..count {
+                let key = read_cbor(data, pos, depth + 1)?;
+                entries.push((key, read_cbor(data, pos, depth + 1)?));
+            }
+            Cbor::Map(entries)
+        }
+        // Tags wrap a single item, which is all that matters here
+        6 => read_cbor(data, pos, depth + 1)?,
+        _ => Cbor::Simple,
+    })
+}
+
+struct AuthenticatorData {
+    rp_id_hash: [u8; 32],
+    flags: u8,
+    sign_count: u32,
+    /// Credential ID and COSE key, present when registering
+    attested: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+fn parse_authenticator_data(data: &[u8]) -> AppResult<AuthenticatorData> {
+    let mut pos = 0;
+    let rp_id_hash = take(data, &mut pos, 32)?.try_into().unwrap_or_default();
+    let flags = take(data, &mut pos, 1)?[0];
+    let sign_count = u32::from_be_bytes(take(data, &mut pos, 4)?.try_into().unwrap_or_default());
+    let attested = if flags & ATTESTED_CREDENTIAL != 0 {
+        let _aaguid = take(data, &mut pos, 16)?;
+        let id_length = u16::from_be_bytes(take(data, &mut pos, 2)?.try_into().unwrap_or_default()) as usize;
+        let credential_id = take(data, &mut pos, id_length)?.to_vec();
+        let key_start = pos;
+        read_cbor(data, &mut pos, 0)?;
+        Some((credential_id, data[key_start..pos].to_vec()))
+    } else {
+        None
+    };
+    Ok(AuthenticatorData { rp_id_hash, flags, sign_count, attested })
+}
+
+fn check_authenticator_data(config: &WebAuthnConfig, auth_data: &AuthenticatorData, require_verification: bool) -> AppResult<()> {
+    if auth_data.rp_id_hash.as_slice() != Sha256::digest(config.rp_id.as_bytes()).as_slice() {
+        return Err(invalid("credential belongs to another site"));
+    }
+    if auth_data.flags & USER_PRESENT == 0 {
+        return Err(invalid("user presence was not confirmed"));
+    }
+    if require_verification && auth_data.flags & USER_VERIFIED == 0 {
+        return Err(invalid("the authenticator did not verify the user"));
+    }
+    Ok(())
+}
+
+enum PublicKey {
+    P256(Vec<u8>),
+    Ed25519(Vec<u8>),
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+}
+
+impl PublicKey {
+    // Read a COSE_Key, refusing key types PinePods doesn't offer
+    fn parse(cose_key: &[u8]) -> AppResult<Self> {
+        let key = read_cbor(cose_key, &mut 0, 0)?;
+        let unsupported = || invalid("unsupported key type");
+        // COSE integers are unsigned big-endian, possibly with a leading zero
+        let unsigned = |bytes: &[u8]| bytes.iter().position(|b| *b != 0).map(|start| bytes[start..].to_vec()).unwrap_or_default();
+        match (key.int(1), key.int(3)) {
+            // EC2 on P-256
+            (Some(2), Some(alg)) if alg == ES256 as i128 && key.int(-1) == Some(1) => {
+                let (x, y) = key.bytes(-2).zip(key.bytes(-3)).ok_or_else(unsupported)?;
+                Ok(PublicKey::P256([&[0x04], x, y].concat()))
+            }
+            // OKP on Ed25519
+            (Some(1), Some(alg)) if alg == EDDSA as i128 && key.int(-1) == Some(6) => {
+                Ok(PublicKey::Ed25519(key.bytes(-2).ok_or_else(unsupported)?.to_vec()))
+            }
+            (Some(3), Some(alg)) if alg == RS256 as i128 => {
+                let (n, e) = key.bytes(-1).zip(key.bytes(-2)).ok_or_else(unsupported)?;
+                Ok(PublicKey::Rsa { n: unsigned(n), e: unsigned(e) })
+            }
+            _ => Err(unsupported()),
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match self {
+            PublicKey::P256(point) => UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, point).verify(message, signature).is_ok(),
+            PublicKey::Ed25519(x) => UnparsedPublicKey::new(&signature::ED25519, x).verify(message, signature).is_ok(),
+            PublicKey::Rsa { n, e } => RsaPublicKeyComponents { n, e }
+                .verify(&signature::RSA_PKCS1_2048_8192_SHA256, message, signature)
+                .is_ok(),
+        }
+    }
+}
+
+/// Check a registration response against the challenge it answers. Attestation
+/// statements aren't checked since "none" is requested; any authenticator may enrol.
+pub fn verify_registration(config: &WebAuthnConfig, challenge: &str, credential: &PublicKeyCredential) -> AppResult<NewCredential> {
+    check_client_data(config, &credential.response.client_data_json, "webauthn.create", challenge)?;
+
+    let attestation_object = credential.response.attestation_object.as_deref()
+        .ok_or_else(|| invalid("missing attestation object"))?;
+    let attestation = read_cbor(&decode(attestation_object)?, &mut 0, 0)?;
+    let Some(Cbor::Bytes(auth_data)) = attestation.get(&Cbor::Text("authData".to_string())) else {
+        return Err(invalid("attestation object has no authenticator data"));
+    };
+    let auth_data = parse_authenticator_data(auth_data)?;
+    check_authenticator_data(config, &auth_data, false)?;
+
+    let (credential_id, public_key) = auth_data.attested.ok_or_else(|| invalid("no credential was created"))?;
+    if credential_id.is_empty() || credential_id.len() > MAX_CREDENTIAL_ID_BYTES {
+        return Err(invalid("credential ID has an unsupported length"));
+    }
+    if decode(&credential.id)? != credential_id {
+        return Err(invalid("credential ID does not match the authenticator data"));
+    }
+    // Refuse keys that could never sign in
+    PublicKey::parse(&public_key)?;
+
+    Ok(NewCredential {
+        credential_id: encode(&credential_id),
+        public_key: encode(&public_key),
+        sign_count: auth_data.sign_count as i64,
+        transports: credential.response.transports.clone(),
+    })
+}
+
+/// Check a sign-in response made with `stored` against the challenge it answers,
+/// returning the authenticator's new signature counter
+pub fn verify_assertion(config: &WebAuthnConfig, challenge: &str, credential: &PublicKeyCredential, stored: &WebAuthnCredential, require_verification: bool) -> AppResult<i64> {
+    let client_data = check_client_data(config, &credential.response.client_data_json, "webauthn.get", challenge)?;
+
+    let raw_auth_data = decode(credential.response.authenticator_data.as_deref().ok_or_else(|| invalid("missing authenticator data"))?)?;
+    let auth_data = parse_authenticator_data(&raw_auth_data)?;
+    check_authenticator_data(config, &auth_data, require_verification)?;
+
+    let signature = decode(credential.response.signature.as_deref().ok_or_else(|| invalid("missing signature"))?)?;
+    let message = [raw_auth_data.as_slice(), Sha256::digest(&client_data).as_slice()].concat();
+    if !PublicKey::parse(&decode(&stored.public_key)?)?.verify(&message, &signature) {
+        return Err(invalid("signature does not verify"));
+    }
+
+    // Authenticators that count must count up; a counter going backwards means the
+    // key may have been cloned. Many passkeys always report zero.
+    let sign_count = auth_data.sign_count as i64;
+    if (sign_count != 0 || stored.sign_count != 0) && sign_count <= stored.sign_count {
+        return Err(invalid("signature counter went backwards"));
+    }
+    Ok(sign_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aws_lc_rs::{
+        rand::SystemRandom,
+        rsa,
+        signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING, RSA_PKCS1_SHA256},
+    };
+
+    const RP_ID: &str = "pods.example.com";
+    const ORIGIN: &str = "https://pods.example.com";
+    const CHALLENGE: &str = "q2X9Uv8QmFqjY7xkQ1m3J2nV0fS8bZc4h6L5tR7wPeA";
+
+    fn config() -> WebAuthnConfig {
+        WebAuthnConfig { rp_id: RP_ID.to_string(), origins: vec![ORIGIN.to_string()] }
+    }
+
+    // Canonical CBOR, as authenticators write it
+    fn head(major: u8, value: u64) -> Vec<u8> {
+        match value {
+            0..=23 => vec![major << 5 | value as u8],
+            24..=0xff => vec![major << 5 | 24, value as u8],
+            0x100..=0xffff => [vec![major << 5 | 25], (value as u16).to_be_bytes().to_vec()].concat(),
+            _ => [vec![major << 5 | 26], (value as u32).to_be_bytes().to_vec()].concat(),
+        }
+    }
+
+    fn int(value: i64) -> Vec<u8> {
+        if value >= 0 { head(0, value as u64) } else { head(1, (-1 - value) as u64) }
+    }
+
+    fn bytes(value: &[u8]) -> Vec<u8> {
+        [head(2, value.len() as u64), value.to_vec()].concat()
+    }
+
+    fn text(value: &str) -> Vec<u8> {
+        [head(3, value.len() as u64), value.as_bytes().to_vec()].concat()
+    }
+
+    fn map(entries: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+        let mut out = head(5, entries.len() as u64);
+        for (key, value) in entries {
+            out.extend_from_slice(key);
+            out.extend_from_slice(value);
+        }
+        out
+    }
+
+    // An authenticator's credential key, generated fresh for each test
+    enum TestKey {
+        Es256(EcdsaKeyPair),
+        EdDsa(Ed25519KeyPair),
+        Rs256(rsa::KeyPair),
+    }
+
+    impl TestKey {
+        fn all() -> Vec<TestKey> {
+            vec![
+                TestKey::Es256(EcdsaKeyPair::generate(&ECDSA_P256_SHA256_ASN1_SIGNING).unwrap()),
+                TestKey::EdDsa(Ed25519KeyPair::generate().unwrap()),
+                TestKey::Rs256(rsa::KeyPair::generate(rsa::KeySize::Rsa2048).unwrap()),
+            ]
+        }
+
+        fn es256() -> TestKey {
+            TestKey::Es256(EcdsaKeyPair::generate(&ECDSA_P256_SHA256_ASN1_SIGNING).unwrap())
+        }
+
+        fn cose_key(&self) -> Vec<u8> {
+            match self {
+                TestKey::Es256(key) => {
+                    let point = key.public_key().as_ref();
+                    map(&[
+                        (int(1), int(2)),
+                        (int(3), int(ES256)),
+                        (int(-1), int(1)),
+                        (int(-2), bytes(&point[1..33])),
+                        (int(-3), bytes(&point[33..65])),
+                    ])
+                }
+                TestKey::EdDsa(key) => map(&[
+                    (int(1), int(1)),
+                    (int(3), int(EDDSA)),
+                    (int(-1), int(6)),
+                    (int(-2), bytes(key.public_key().as_ref())),
+                ]),
+                TestKey::Rs256(key) => {
+                    let components = rsa::PublicKeyComponents::<Vec<u8>>::from(key.public_key());
+                    map(&[
+                        (int(1), int(3)),
+                        (int(3), int(RS256)),
+                        (int(-1), bytes(&components.n)),
+                        (int(-2), bytes(&components.e)),
+                    ])
+                }
+            }
+        }
+
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            let rng = SystemRandom::new();
+            match self {
+                TestKey::Es256(key) => key.sign(&rng, message).unwrap().as_ref().to_vec(),
+                TestKey::EdDsa(key) => key.sign(message).as_ref().to_vec(),
+                TestKey::Rs256(key) => {
+                    let mut signature = vec![0; key.public_modulus_len()];
+                    key.sign(&RSA_PKCS1_SHA256, &rng, message, &mut signature).unwrap();
+                    signature
+                }
+            }
+        }
+    }
+
+    fn client_data(kind: &str, challenge: &str, origin: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({ "type": kind, "challenge": challenge, "origin": origin })).unwrap()
+    }
+
+    fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32, attested: Option<(&[u8], &[u8])>) -> Vec<u8> {
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags | if attested.is_some() { ATTESTED_CREDENTIAL } else { 0 });
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        if let Some((credential_id, cose_key)) = attested {
+            data.extend_from_slice(&[0; 16]);
+            data.extend_from_slice(&(credential_id.len() as u16).to_be_bytes());
+            data.extend_from_slice(credential_id);
+            data.extend_from_slice(cose_key);
+        }
+        data
+    }
+
+    fn response(client_data: &[u8]) -> CredentialResponse {
+        CredentialResponse {
+            client_data_json: encode(client_data),
+            attestation_object: None,
+            transports: vec!["internal".to_string()],
+            authenticator_data: None,
+            signature: None,
+            user_handle: None,
+        }
+    }
+
+    fn registration(key: &TestKey, credential_id: &[u8], rp_id: &str, challenge: &str) -> PublicKeyCredential {
+        let auth_data = authenticator_data(rp_id, USER_PRESENT | USER_VERIFIED, 0, Some((credential_id, &key.cose_key())));
+        let attestation = map(&[
+            (text("fmt"), text("none")),
+            (text("attStmt"), map(&[])),
+            (text("authData"), bytes(&auth_data)),
+        ]);
+        let mut response = response(&client_data("webauthn.create", challenge, ORIGIN));
+        response.attestation_object = Some(encode(&attestation));
+        PublicKeyCredential { id: encode(credential_id), response }
+    }
+
+    fn assertion(key: &TestKey, rp_id: &str, challenge: &str, flags: u8, sign_count: u32) -> PublicKeyCredential {
+        let client_data = client_data("webauthn.get", challenge, ORIGIN);
+        let auth_data = authenticator_data(rp_id, flags, sign_count, None);
+        let signature = key.sign(&[auth_data.as_slice(), Sha256::digest(&client_data).as_slice()].concat());
+        let mut response = response(&client_data);
+        response.authenticator_data = Some(encode(&auth_data));
+        response.signature = Some(encode(&signature));
+        PublicKeyCredential { id: encode(b"credential-1"), response }
+    }
+
+    fn stored(key: &TestKey, sign_count: i64) -> WebAuthnCredential {
+        WebAuthnCredential {
+            passkey_id: 1,
+            user_id: 1,
+            credential_id: encode(b"credential-1"),
+            public_key: encode(&key.cose_key()),
+            sign_count,
+            name: "Test key".to_string(),
+            transports: Vec::new(),
+            created_at: None,
+            last_used_at: None,
+        }
+    }
+
+    fn rejection<T>(result: AppResult<T>) -> String {
+        match result {
+            Err(AppError::Auth(message)) => message,
+            Err(e) => panic!("unexpected error {:?}", e),
+            Ok(_) => panic!("the response was accepted"),
+        }
+    }
+
+    #[test]
+    fn every_offered_algorithm_registers_and_signs_in() {
+        for key in TestKey::all() {
+            let new = verify_registration(&config(), CHALLENGE, &registration(&key, b"credential-1", RP_ID, CHALLENGE)).unwrap();
+            assert_eq!(new.credential_id, encode(b"credential-1"));
+            assert_eq!(new.public_key, encode(&key.cose_key()));
+            assert_eq!(new.transports, vec!["internal"]);
+
+            let stored = WebAuthnCredential { public_key: new.public_key, ..stored(&key, new.sign_count) };
+            let signed_in = assertion(&key, RP_ID, CHALLENGE, USER_PRESENT | USER_VERIFIED, 1);
+            assert_eq!(verify_assertion(&config(), CHALLENGE, &signed_in, &stored, true).unwrap(), 1);
+        }
+    }
+
+    #[test]
+    fn signatures_by_another_key_are_refused() {
+        let (key, other) = (TestKey::es256(), TestKey::es256());
+        let signed_in = assertion(&other, RP_ID, CHALLENGE, USER_PRESENT, 0);
+        assert!(rejection(verify_assertion(&config(), CHALLENGE, &signed_in, &stored(&key, 0), false)).contains("signature"));
+
+        let mut tampered = assertion(&key, RP_ID, CHALLENGE, USER_PRESENT, 0);
+        tampered.response.authenticator_data = Some(encode(&authenticator_data(RP_ID, USER_PRESENT, 7, None)));
+        assert!(rejection(verify_assertion(&config(), CHALLENGE, &tampered, &stored(&key, 0), false)).contains("signature"));
+    }
+
+    #[test]
+    fn counter_must_go_up_once_it_is_used() {
+        let key = TestKey::es256();
+        let sign_in = |count: u32, stored_count: i64| {
+            verify_assertion(&config(), CHALLENGE, &assertion(&key, RP_ID, CHALLENGE, USER_PRESENT, count), &stored(&key, stored_count), false)
+        };
+        assert_eq!(sign_in(6, 5).unwrap(), 6);
+        assert!(rejection(sign_in(5, 5)).contains("counter"));
+        assert!(rejection(sign_in(4, 5)).contains("counter"));
+        assert!(rejection(sign_in(0, 5)).contains("counter"));
+        // Passkeys that don't count always report zero
+        assert_eq!(sign_in(0, 0).unwrap(), 0);
+    }
+
+    #[test]
+    fn credentials_for_another_rp_id_are_refused() {
+        let key = TestKey::es256();
+        let registered = registration(&key, b"credential-1", "evil.example.com", CHALLENGE);
+        assert!(rejection(verify_registration(&config(), CHALLENGE, &registered)).contains("another site"));
+        let signed_in = assertion(&key, "evil.example.com", CHALLENGE, USER_PRESENT, 0);
+        assert!(rejection(verify_assertion(&config(), CHALLENGE, &signed_in, &stored(&key, 0), false)).contains("another site"));
+    }
+
+    #[test]
+    fn answers_to_another_challenge_are_refused() {
+        let key = TestKey::es256();
+        let other = generate_challenge();
+        let registered = registration(&key, b"credential-1", RP_ID, &other);
+        assert!(rejection(verify_registration(&config(), CHALLENGE, &registered)).contains("challenge"));
+        let signed_in = assertion(&key, RP_ID, &other, USER_PRESENT, 0);
+        assert!(rejection(verify_assertion(&config(), CHALLENGE, &signed_in, &stored(&key, 0), false)).contains("challenge"));
+    }
+
+    #[test]
+    fn client_data_must_match_the_ceremony_and_origin() {
+        let key = TestKey::es256();
+        let mut signed_in = assertion(&key, RP_ID, CHALLENGE, USER_PRESENT, 0);
+        signed_in.response.client_data_json = encode(&client_data("webauthn.create", CHALLENGE, ORIGIN));
+        assert!(rejection(verify_assertion(&config(), CHALLENGE, &signed_in, &stored(&key, 0), false)).contains("ceremony"));
+
+        let mut registered = registration(&key, b"credential-1", RP_ID, CHALLENGE);
+        registered.response.client_data_json = encode(&client_data("webauthn.create", CHALLENGE, "https://evil.example.com"));
+        assert!(rejection(verify_registration(&config(), CHALLENGE, &registered)).contains("origin"));
+    }
+
+    #[test]
+    fn user_verification_is_enforced_when_required() {
+        let key = TestKey::es256();
+        let signed_in = assertion(&key, RP_ID, CHALLENGE, USER_PRESENT, 0);
+        assert!(rejection(verify_assertion(&config(), CHALLENGE, &signed_in, &stored(&key, 0), true)).contains("verify the user"));
+        assert!(verify_assertion(&config(), CHALLENGE, &signed_in, &stored(&key, 0), false).is_ok());
+        let absent = assertion(&key, RP_ID, CHALLENGE, USER_VERIFIED, 0);
+        assert!(rejection(verify_assertion(&config(), CHALLENGE, &absent, &stored(&key, 0), false)).contains("presence"));
+    }
+
+    #[test]
+    fn registration_credential_id_must_match_the_authenticator_data() {
+        let key = TestKey::es256();
+        let mut registered = registration(&key, b"credential-1", RP_ID, CHALLENGE);
+        registered.id = encode(b"credential-2");
+        assert!(rejection(verify_registration(&config(), CHALLENGE, &registered)).contains("does not match"));
+        let oversized = registration(&key, &[7; MAX_CREDENTIAL_ID_BYTES + 1], RP_ID, CHALLENGE);
+        assert!(rejection(verify_registration(&config(), CHALLENGE, &oversized)).contains("length"));
+    }
+
+    #[test]
+    fn keys_that_were_not_offered_are_refused() {
+        // EC2 on P-384 with ES384
+        let key = map(&[(int(1), int(2)), (int(3), int(-35)), (int(-1), int(2)), (int(-2), bytes(&[1; 48])), (int(-3), bytes(&[2; 48]))]);
+        assert!(PublicKey::parse(&key).is_err());
+        // Right algorithm, missing coordinates
+        let key = map(&[(int(1), int(2)), (int(3), int(ES256)), (int(-1), int(1))]);
+        assert!(PublicKey::parse(&key).is_err());
+    }
+
+    #[test]
+    fn cbor_values_decode() {
+        let data = map(&[
+            (int(1), int(-257)),
+            (text("k"), bytes(b"\x00\x01")),
+            (int(300), [head(4, 2), int(70000), text("x")].concat()),
+            (int(-1), [vec![0xc2], bytes(b"\x05")].concat()),
+            (int(2), vec![0xf5]),
+        ]);
+        let value = read_cbor(&data, &mut 0, 0).unwrap();
+        assert_eq!(value.int(1), Some(-257));
+        assert_eq!(value.get(&Cbor::Text("k".to_string())), Some(&Cbor::Bytes(vec![0, 1])));
+        assert_eq!(value.get(&Cbor::Int(300)), Some(&Cbor::Array(vec![Cbor::Int(70000), Cbor::Text("x".to_string())])));
+        assert_eq!(value.bytes(-1), Some(&[5u8][..]));
+        assert_eq!(value.get(&Cbor::Int(2)), Some(&Cbor::Simple));
+        assert_eq!(read_cbor(&[0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], &mut 0, 0).unwrap(), Cbor::Int(-(1 << 64)));
+    }
+
+    #[test]
+    fn truncated_cbor_is_an_error() {
+        let data = registration(&TestKey::es256(), b"credential-1", RP_ID, CHALLENGE).response.attestation_object.unwrap();
+        let data = decode(&data).unwrap();
+        for end in 0..data.len() {
+            assert!(read_cbor(&data[..end], &mut 0, 0).is_err(), "{} bytes decoded", end);
+        }
+        assert!(read_cbor(&data, &mut 0, 0).is_ok());
+    }
+
+    #[test]
+    fn oversized_and_unsupported_cbor_is_an_error() {
+        // Byte string, text and array claiming far more than is there
+        assert!(read_cbor(&[0x5b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], &mut 0, 0).is_err());
+        assert!(read_cbor(&[0x7a, 0xff, 0xff, 0xff, 0xff, b'a'], &mut 0, 0).is_err());
+        assert!(read_cbor(&[0x9b, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00], &mut 0, 0).is_err());
+        assert!(read_cbor(&[0xbb, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff], &mut 0, 0).is_err());
+        // Indefinite lengths and reserved additional information
+        assert!(read_cbor(&[0x5f, 0x41, 0x00, 0xff], &mut 0, 0).is_err());
+        assert!(read_cbor(&[0x1c], &mut 0, 0).is_err());
+        // Invalid UTF-8 text
+        assert!(read_cbor(&[0x62, 0xc3, 0x28], &mut 0, 0).is_err());
+        // Deep nesting, through arrays and tags
+        assert!(read_cbor(&[[0x81; 20].as_slice(), &[0x00]].concat(), &mut 0, 0).is_err());
+        assert!(read_cbor(&[[0xc6; 40].as_slice(), &[0x00]].concat(), &mut 0, 0).is_err());
+    }
+
+    #[test]
+    fn random_and_mutated_input_never_panics() {
+        use rand::{Rng, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0x5eed);
+        for _ in 0..20_000 {
+            let data: Vec<u8> = (0..rng.random_range(0..64)).map(|_| rng.random()).collect();
+            let _ = read_cbor(&data, &mut 0, 0);
+            let _ = parse_authenticator_data(&data);
+            let _ = PublicKey::parse(&data);
+        }
+
+        let key = TestKey::es256();
+        let registered = registration(&key, b"credential-1", RP_ID, CHALLENGE);
+        let attestation = decode(registered.response.attestation_object.as_deref().unwrap()).unwrap();
+        for _ in 0..5_000 {
+            let mut mutated = attestation.clone();
+            for _ in 0..rng.random_range(1..4) {
+                let at = rng.random_range(0..mutated.len());
+                mutated[at] = rng.random();
+            }
+            let mut candidate = registered.clone();
+            candidate.response.attestation_object = Some(encode(&mutated));
+            let _ = verify_registration(&config(), CHALLENGE, &candidate);
+        }
+    }
+}
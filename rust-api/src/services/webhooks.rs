@@ -0,0 +1,426 @@
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize, Serializer};
+use sha2::Sha256;
+use crate::{
+    database::DatabasePool,
+    error::AppResult,
+    services::{
+        delivery::{self, default_enabled, lease_until, Delivery},
+        outbound,
+    },
+};
+
+/// Give up on a delivery after this many attempts; retries wait 1, 4, 16, 64 then 256 minutes
+pub const MAX_ATTEMPTS: i32 = 6;
+// Characters of the secret left visible once it has been shown
+const SECRET_HINT_CHARS: usize = 4;
+
+/// `sha256=<hex>` HMAC of `<timestamp>.<body>`, keyed with the webhook's secret
+pub const SIGNATURE_HEADER: &str = "X-PinePods-Signature";
+/// Unix time the payload was signed at, part of the signed content
+pub const TIMESTAMP_HEADER: &str = "X-PinePods-Timestamp";
+pub const EVENT_HEADER: &str = "X-PinePods-Event";
+pub const DELIVERY_HEADER: &str = "X-PinePods-Delivery";
+
+/// Events a webhook can receive
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventKind {
+    EpisodeAdded,
+    EpisodePlayed,
+    EpisodeCompleted,
+    EpisodeSaved,
+    EpisodeQueued,
+    EpisodeDownloaded,
+    SubscriptionAdded,
+    SubscriptionRemoved,
+    /// Sent by the test button; not something a webhook subscribes to
+    Test,
+}
+
+impl WebhookEventKind {
+    /// Every event a webhook can subscribe to
+    pub const SUBSCRIBABLE: [WebhookEventKind; 8] = [
+        WebhookEventKind::EpisodeAdded,
+        WebhookEventKind::EpisodePlayed,
+        WebhookEventKind::EpisodeCompleted,
+        WebhookEventKind::EpisodeSaved,
+        WebhookEventKind::EpisodeQueued,
+        WebhookEventKind::EpisodeDownloaded,
+        WebhookEventKind::SubscriptionAdded,
+        WebhookEventKind::SubscriptionRemoved,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WebhookEventKind::EpisodeAdded => "episode_added",
+            WebhookEventKind::EpisodePlayed => "episode_played",
+            WebhookEventKind::EpisodeCompleted => "episode_completed",
+            WebhookEventKind::EpisodeSaved => "episode_saved",
+            WebhookEventKind::EpisodeQueued => "episode_queued",
+            WebhookEventKind::EpisodeDownloaded => "episode_downloaded",
+            WebhookEventKind::SubscriptionAdded => "subscription_added",
+            WebhookEventKind::SubscriptionRemoved => "subscription_removed",
+            WebhookEventKind::Test => "test",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        Self::SUBSCRIBABLE.into_iter().find(|k| k.as_str() == kind)
+    }
+}
+
+/// Stored comma-separated event list, as subscribed events; unknown names are dropped
+pub fn parse_events(events: &str) -> Vec<WebhookEventKind> {
+    delivery::parse_list(events, WebhookEventKind::parse)
+}
+
+pub fn format_events(events: &[WebhookEventKind]) -> String {
+    delivery::format_list(events, WebhookEventKind::as_str)
+}
+
+fn default_events() -> Vec<WebhookEventKind> {
+    WebhookEventKind::SUBSCRIBABLE.to_vec()
+}
+
+/// What an event is about; episode details are looked up when the event is sent
+pub enum WebhookSubject {
+    Episode { episode_id: i32, is_youtube: bool },
+    /// Podcast details, captured by the caller since a removed podcast can't be looked up
+    Podcast(serde_json::Value),
+}
+
+/// A webhook as stored. The signing secret is only returned when it's generated; after
+/// that responses carry its last few characters as `secret_hint`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Webhook {
+    pub webhook_id: i32,
+    pub user_id: i32,
+    pub name: String,
+    pub url: String,
+    #[serde(rename = "secret_hint", serialize_with = "serialize_secret_hint")]
+    pub secret: String,
+    pub events: Vec<WebhookEventKind>,
+    /// Receives every user's events; admins only
+    pub is_global: bool,
+    pub enabled: bool,
+    pub created_at: Option<NaiveDateTime>,
+}
+
+fn serialize_secret_hint<S: Serializer>(secret: &str, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&secret_hint(secret))
+}
+
+/// A new signing secret: 256 random bits, hex encoded
+pub fn generate_secret() -> String {
+    format!("whsec_{}", hex::encode(rand::random::<[u8; 32]>()))
+}
+
+/// A masked secret that still lets the owner tell secrets apart
+pub fn secret_hint(secret: &str) -> String {
+    let visible: String = secret.chars().rev().take(SECRET_HINT_CHARS).collect::<Vec<_>>().into_iter().rev().collect();
+    format!("••••{}", visible)
+}
+
+/// Settings a webhook is created or updated with
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookSettings {
+    pub name: String,
+    pub url: String,
+    /// Events sent to this webhook; all of them when not given
+    #[serde(default = "default_events")]
+    pub events: Vec<WebhookEventKind>,
+    #[serde(default)]
+    pub is_global: bool,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+impl WebhookSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() || self.name.len() > 255 {
+            return Err("name must be between 1 and 255 characters".to_string());
+        }
+        let url = url::Url::parse(self.url.trim()).map_err(|_| "url must be an http or https URL".to_string())?;
+        outbound::check_url(&url).map_err(|e| format!("url is not allowed: {}", e))?;
+        if self.events.is_empty() {
+            return Err("Choose at least one event".to_string());
+        }
+        if self.events.contains(&WebhookEventKind::Test) {
+            return Err("test is not an event webhooks can subscribe to".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// One event posted (or being posted) to a webhook, from the delivery history
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookDelivery {
+    pub delivery_id: i32,
+    pub webhook_id: i32,
+    pub event_type: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i32,
+    /// HTTP status of the last response, if the endpoint answered at all
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub next_attempt_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+    pub delivered_at: Option<NaiveDateTime>,
+}
+
+/// A logged delivery due another attempt
+pub struct DueWebhookDelivery {
+    pub delivery_id: i32,
+    pub attempts: i32,
+    pub event_type: String,
+    pub payload: String,
+    pub url: String,
+    pub secret: String,
+}
+
+/// How a single attempt went
+#[derive(Debug, Clone, Serialize)]
+pub struct AttemptOutcome {
+    pub delivered: bool,
+    pub response_status: Option<i32>,
+    pub error: Option<String>,
+}
+
+#[async_trait]
+impl Delivery for DueWebhookDelivery {
+    async fn load_due(db: &DatabasePool, now: NaiveDateTime, limit: i64) -> AppResult<Vec<Self>> {
+        db.get_due_webhook_deliveries(now, limit).await
+    }
+
+    fn client() -> AppResult<reqwest::Client> {
+        // A redirect turns the POST into a GET, so it counts as a failure. Endpoints on the
+        // server's own network are refused when their address is resolved.
+        Ok(outbound::client_builder()
+            .timeout(delivery::SEND_TIMEOUT)
+            .redirect(reqwest::redirect::Policy::none())
+            .build()?)
+    }
+
+    async fn attempt(self, db: &DatabasePool, client: &reqwest::Client) {
+        attempt(db, client, self, true).await;
+    }
+}
+
+/// Signature of a payload sent at `timestamp`, as carried in `SIGNATURE_HEADER`
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn payload(kind: WebhookEventKind, user_id: i32, data: serde_json::Value) -> String {
+    serde_json::json!({
+        "event": kind.as_str(),
+        "timestamp": Utc::now().to_rfc3339(),
+        "user_id": user_id,
+        "data": data,
+    }).to_string()
+}
+
+/// Post an event to the webhooks of `user_id` (the episode's owner when not given) and to
+/// global webhooks. Runs in the background so callers never wait on, or fail because of,
+/// a webhook.
+pub fn emit(db: &DatabasePool, kind: WebhookEventKind, user_id: Option<i32>, subject: WebhookSubject) {
+    let db = db.clone();
+    tokio::spawn(async move {
+        if let Err(e) = dispatch(&db, kind, user_id, subject).await {
+            tracing::warn!("Failed to send {} webhooks: {}", kind.as_str(), e);
+        }
+    });
+}
+
+async fn dispatch(db: &DatabasePool, kind: WebhookEventKind, user_id: Option<i32>, subject: WebhookSubject) -> AppResult<()> {
+    // Skip the lookups when nobody is listening
+    if let Some(user_id) = user_id {
+        if db.get_webhooks_for_event(user_id, kind).await?.is_empty() {
+            return Ok(());
+        }
+    }
+
+    let (user_id, data) = match subject {
+        WebhookSubject::Episode { episode_id, is_youtube } => {
+            let Some((owner_id, data)) = db.get_webhook_episode(episode_id, is_youtube).await? else {
+                return Ok(());
+            };
+            (user_id.unwrap_or(owner_id), data)
+        }
+        WebhookSubject::Podcast(data) => match user_id {
+            Some(user_id) => (user_id, data),
+            None => return Ok(()),
+        },
+    };
+
+    let webhooks = db.get_webhooks_for_event(user_id, kind).await?;
+    if webhooks.is_empty() {
+        return Ok(());
+    }
+    let body = payload(kind, user_id, data);
+    let client = DueWebhookDelivery::client()?;
+    for webhook in webhooks {
+        let delivery_id = db.create_webhook_delivery(webhook.webhook_id, kind.as_str(), &body, lease_until()).await?;
+        attempt(db, &client, DueWebhookDelivery {
+            delivery_id,
+            attempts: 0,
+            event_type: kind.as_str().to_string(),
+            payload: body.clone(),
+            url: webhook.url,
+            secret: webhook.secret,
+        }, true).await;
+    }
+    Ok(())
+}
+
+/// Retry deliveries whose backoff has run out; called from the scheduler
+pub async fn retry_due_deliveries(db: &DatabasePool) -> AppResult<usize> {
+    delivery::retry_due::<DueWebhookDelivery>(db).await
+}
+
+/// Post a test event to a webhook now and report how it went. The delivery shows up in
+/// the history but isn't retried.
+pub async fn test_fire(db: &DatabasePool, webhook: &Webhook) -> AppResult<AttemptOutcome> {
+    let kind = WebhookEventKind::Test;
+    let body = payload(kind, webhook.user_id, serde_json::json!({
+        "webhook_id": webhook.webhook_id,
+        "message": "This is a test event from PinePods",
+    }));
+    let delivery_id = db.create_webhook_delivery(webhook.webhook_id, kind.as_str(), &body, lease_until()).await?;
+    Ok(attempt(db, &DueWebhookDelivery::client()?, DueWebhookDelivery {
+        delivery_id,
+        attempts: 0,
+        event_type: kind.as_str().to_string(),
+        payload: body,
+        url: webhook.url.clone(),
+        secret: webhook.secret.clone(),
+    }, false).await)
+}
+
+async fn post(client: &reqwest::Client, delivery: &DueWebhookDelivery) -> Result<i32, (Option<i32>, String)> {
+    // Webhooks created before addresses were checked may still point inward
+    let url = url::Url::parse(&delivery.url).map_err(|e| (None, e.to_string()))?;
+    outbound::check_url(&url).map_err(|e| (None, e))?;
+
+    let timestamp = Utc::now().timestamp();
+    let response = client.post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(reqwest::header::USER_AGENT, "PinePods-Webhooks")
+        .header(EVENT_HEADER, &delivery.event_type)
+        .header(DELIVERY_HEADER, delivery.delivery_id.to_string())
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(SIGNATURE_HEADER, sign(&delivery.secret, timestamp, &delivery.payload))
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    // Only the status is kept; the body of an arbitrary endpoint has no business in the
+    // delivery history
+    let status = response.status();
+    if status.is_success() {
+        return Ok(status.as_u16() as i32);
+    }
+    Err((Some(status.as_u16() as i32), format!("Endpoint returned {}", status)))
+}
+
+async fn attempt(db: &DatabasePool, client: &reqwest::Client, delivery: DueWebhookDelivery, retry: bool) -> AttemptOutcome {
+    let attempts = delivery.attempts + 1;
+    let (outcome, result) = match post(client, &delivery).await {
+        Ok(response_status) => (
+            AttemptOutcome { delivered: true, response_status: Some(response_status), error: None },
+            db.mark_webhook_delivered(delivery.delivery_id, attempts, response_status).await,
+        ),
+        Err((response_status, error)) => {
+            let retry_at = if retry {
+                delivery::next_attempt_at(attempts, MAX_ATTEMPTS, Utc::now().naive_utc())
+            } else {
+                None
+            };
+            tracing::warn!(
+                "Webhook delivery {} ({}) failed (attempt {}/{}): {}",
+                delivery.delivery_id, delivery.event_type, attempts, MAX_ATTEMPTS, error
+            );
+            let result = db.mark_webhook_failed(delivery.delivery_id, attempts, response_status, &error, retry_at).await;
+            (AttemptOutcome { delivered: false, response_status, error: Some(error) }, result)
+        }
+    };
+    if let Err(e) = result {
+        tracing::warn!("Failed to record webhook delivery {}: {}", delivery.delivery_id, e);
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(url: &str) -> WebhookSettings {
+        WebhookSettings {
+            name: "Home automation".to_string(),
+            url: url.to_string(),
+            events: default_events(),
+            is_global: false,
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn listed_webhooks_only_show_a_hint_of_the_secret() {
+        let webhook = Webhook {
+            webhook_id: 1,
+            user_id: 2,
+            name: "n".to_string(),
+            url: "https://example.com/hook".to_string(),
+            secret: "abcdefghijklmnopWXYZ".to_string(),
+            events: default_events(),
+            is_global: false,
+            enabled: true,
+            created_at: None,
+        };
+        let json = serde_json::to_value(&webhook).unwrap();
+        assert!(json.get("secret").is_none());
+        assert_eq!(json["secret_hint"], "••••WXYZ");
+        assert!(!json.to_string().contains("abcdefghijklmnop"));
+    }
+
+    #[test]
+    fn webhooks_cannot_point_at_the_servers_network() {
+        assert!(settings("https://hooks.example.com/pinepods").validate().is_ok());
+        assert!(settings("http://127.0.0.1:8123/api/webhook/x").validate().is_err());
+        assert!(settings("http://192.168.1.5/hook").validate().is_err());
+        assert!(settings("http://169.254.169.254/latest").validate().is_err());
+        assert!(settings("http://localhost:8040/hook").validate().is_err());
+    }
+
+    #[test]
+    fn signature_covers_timestamp_and_body() {
+        let signature = sign("secret", 1700000000, r#"{"event":"test"}"#);
+        assert!(signature.starts_with("sha256="));
+        assert_eq!(signature.len(), "sha256=".len() + 64);
+        assert_ne!(signature, sign("secret", 1700000001, r#"{"event":"test"}"#));
+        assert_ne!(signature, sign("other", 1700000000, r#"{"event":"test"}"#));
+    }
+
+    #[test]
+    fn generated_secrets_are_long_and_unique() {
+        let secret = generate_secret();
+        assert_eq!(secret.len(), "whsec_".len() + 64);
+        assert_ne!(secret, generate_secret());
+    }
+
+    #[test]
+    fn events_round_trip_and_drop_unknown_names() {
+        let events = parse_events("episode_added, episode_played,bogus,test");
+        assert_eq!(events, vec![WebhookEventKind::EpisodeAdded, WebhookEventKind::EpisodePlayed]);
+        assert_eq!(format_events(&events), "episode_added,episode_played");
+    }
+}
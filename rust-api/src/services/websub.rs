@@ -0,0 +1,433 @@
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Sha256, Sha384, Sha512};
+use crate::{error::{AppError, AppResult}, AppState};
+
+/// Lease asked of hubs; they are free to grant a shorter one
+pub const REQUESTED_LEASE_SECONDS: i64 = 7 * 24 * 60 * 60;
+/// Active subscriptions are renewed once their lease has less than this left
+pub const RENEW_BEFORE_SECONDS: i64 = 24 * 60 * 60;
+/// Subscribe requests that were never verified are retried after this long
+pub const RETRY_AFTER_SECONDS: i64 = 60 * 60;
+/// Largest number of feed URLs accepted in one Podping notification
+pub const MAX_PODPING_IRIS: usize = 100;
+// Hub requests sent per run of the subscription job
+const SYNC_BATCH_SIZE: i64 = 50;
+
+/// Lifecycle of a row in WebSubSubscriptions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubscriptionState {
+    /// Hub found in the feed, no subscribe request sent yet
+    Discovered,
+    /// Subscribe request accepted by the hub, waiting for intent verification
+    Pending,
+    /// Hub verified the subscription and is pushing updates
+    Active,
+    /// Hub refused the subscription
+    Denied,
+    /// Feed stopped advertising the hub; an unsubscribe request is due
+    Unsubscribing,
+}
+
+impl SubscriptionState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SubscriptionState::Discovered => "discovered",
+            SubscriptionState::Pending => "pending",
+            SubscriptionState::Active => "active",
+            SubscriptionState::Denied => "denied",
+            SubscriptionState::Unsubscribing => "unsubscribing",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "discovered" => Some(SubscriptionState::Discovered),
+            "pending" => Some(SubscriptionState::Pending),
+            "active" => Some(SubscriptionState::Active),
+            "denied" => Some(SubscriptionState::Denied),
+            "unsubscribing" => Some(SubscriptionState::Unsubscribing),
+            _ => None,
+        }
+    }
+
+    /// `hub.mode` this state expects the hub to verify
+    pub fn expected_mode(self) -> &'static str {
+        match self {
+            SubscriptionState::Unsubscribing => "unsubscribe",
+            _ => "subscribe",
+        }
+    }
+}
+
+/// A stored WebSub subscription for one podcast
+#[derive(Debug, Clone)]
+pub struct WebSubSubscription {
+    pub podcast_id: i32,
+    pub hub_url: String,
+    pub topic_url: String,
+    pub callback_token: String,
+    pub secret: String,
+    pub state: SubscriptionState,
+}
+
+/// Hub and topic advertised by a feed
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeedHubLinks {
+    pub hub_url: String,
+    /// `rel="self"` link; hubs key subscriptions on it, so it wins over the URL we fetched
+    pub topic_url: Option<String>,
+}
+
+impl FeedHubLinks {
+    /// Find `<atom:link rel="hub">` (or a plain `<link rel="hub">`) in the channel header
+    pub fn discover(feed_content: &str) -> Option<Self> {
+        let header = match feed_content.find("<item") {
+            Some(pos) => &feed_content[..pos],
+            None => feed_content,
+        };
+
+        let link_tag = Regex::new(r"(?is)<(?:atom:)?link\b([^>]*)/?>").unwrap();
+        let attribute = Regex::new(r#"(?s)([\w:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap();
+
+        let mut hub_url = None;
+        let mut topic_url = None;
+        for tag in link_tag.captures_iter(header) {
+            let mut rel = None;
+            let mut href = None;
+            for attr in attribute.captures_iter(&tag[1]) {
+                let value = attr.get(2).or_else(|| attr.get(3)).map(|m| decode_entities(m.as_str().trim()));
+                match attr[1].to_lowercase().as_str() {
+                    "rel" => rel = value,
+                    "href" => href = value,
+                    _ => {}
+                }
+            }
+            let (Some(rel), Some(href)) = (rel, href.filter(|h| is_http_url(h))) else {
+                continue;
+            };
+            let rels: Vec<String> = rel.split_whitespace().map(str::to_lowercase).collect();
+            if hub_url.is_none() && rels.iter().any(|r| r == "hub") {
+                hub_url = Some(href);
+            } else if topic_url.is_none() && rels.iter().any(|r| r == "self") {
+                topic_url = Some(href);
+            }
+        }
+
+        hub_url.map(|hub_url| FeedHubLinks { hub_url, topic_url })
+    }
+}
+
+fn decode_entities(value: &str) -> String {
+    value
+        .replace("&amp;", "&")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+}
+
+fn is_http_url(value: &str) -> bool {
+    url::Url::parse(value).is_ok_and(|u| matches!(u.scheme(), "http" | "https"))
+}
+
+/// Random value for callback tokens and signing secrets
+pub fn generate_secret() -> String {
+    use rand::Rng;
+    use rand::distr::Alphanumeric;
+
+    rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(48)
+        .map(char::from)
+        .collect()
+}
+
+/// Callback URL handed to the hub; the token identifies the subscription
+pub fn callback_url(base_url: &str, callback_token: &str) -> String {
+    format!("{}/api/websub/callback/{}", base_url.trim_end_matches('/'), callback_token)
+}
+
+/// Send a subscribe or unsubscribe request. Hubs answer 202 and verify the intent
+/// asynchronously by calling back with a challenge.
+pub async fn send_hub_request(
+    client: &reqwest::Client,
+    subscription: &WebSubSubscription,
+    callback: &str,
+    mode: &str,
+) -> AppResult<()> {
+    let lease = REQUESTED_LEASE_SECONDS.to_string();
+    let mut form = vec![
+        ("hub.mode", mode),
+        ("hub.topic", subscription.topic_url.as_str()),
+        ("hub.callback", callback),
+    ];
+    if mode == "subscribe" {
+        form.push(("hub.secret", subscription.secret.as_str()));
+        form.push(("hub.lease_seconds", lease.as_str()));
+    }
+
+    let response = client
+        .post(&subscription.hub_url)
+        .form(&form)
+        .timeout(std::time::Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(AppError::Http)?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    let body = response.text().await.unwrap_or_default();
+    let body: String = body.chars().take(200).collect();
+    Err(AppError::external_error(format!("Hub {} answered {}: {}", subscription.hub_url, status, body.trim())))
+}
+
+/// Check an `X-Hub-Signature` header (`sha256=<hex>` etc.) against the pushed body
+pub fn verify_signature(secret: &str, signature_header: &str, body: &[u8]) -> bool {
+    let Some((method, signature)) = signature_header.trim().split_once('=') else {
+        return false;
+    };
+    let Ok(signature) = hex::decode(signature.trim()) else {
+        return false;
+    };
+
+    macro_rules! verify_with {
+        ($digest:ty) => {{
+            let Ok(mut mac) = Hmac::<$digest>::new_from_slice(secret.as_bytes()) else {
+                return false;
+            };
+            mac.update(body);
+            mac.verify_slice(&signature).is_ok()
+        }};
+    }
+
+    match method.to_lowercase().as_str() {
+        "sha1" => verify_with!(Sha1),
+        "sha256" => verify_with!(Sha256),
+        "sha384" => verify_with!(Sha384),
+        "sha512" => verify_with!(Sha512),
+        _ => false,
+    }
+}
+
+/// Podping payload (the `custom_json` body written to Hive), relayed to us by a watcher
+#[derive(Debug, Clone, Deserialize)]
+pub struct PodpingNotification {
+    #[serde(default)]
+    pub medium: Option<String>,
+    /// Feed URLs that changed; version 0.x payloads called this `urls`
+    #[serde(default, alias = "urls")]
+    pub iris: Vec<String>,
+}
+
+impl PodpingNotification {
+    /// Feed URLs worth refreshing. Every `reason` (update, live, liveEnd) means the feed
+    /// changed, so only the medium is checked.
+    pub fn feed_urls(&self) -> Vec<String> {
+        let podcast_medium = self
+            .medium
+            .as_deref()
+            .is_none_or(|m| matches!(m.to_lowercase().as_str(), "podcast" | "podcastl" | "music" | "musicl"));
+        if !podcast_medium {
+            return Vec::new();
+        }
+
+        let mut urls: Vec<String> = Vec::new();
+        for iri in &self.iris {
+            let iri = iri.trim();
+            if is_http_url(iri) && !urls.iter().any(|u| u == iri) {
+                urls.push(iri.to_string());
+            }
+        }
+        urls.truncate(MAX_PODPING_IRIS);
+        urls
+    }
+}
+
+/// Send due subscribe, renew and unsubscribe requests to hubs. Does nothing until a
+/// public callback URL is configured, since hubs could never verify the intent.
+pub async fn sync_hub_subscriptions(state: &AppState) -> AppResult<usize> {
+    let Some(base_url) = state.config.push.websub_callback_base() else {
+        return Ok(0);
+    };
+
+    let due = state.db_pool.get_websub_subscriptions_due(SYNC_BATCH_SIZE).await?;
+    if due.is_empty() {
+        return Ok(0);
+    }
+
+    let client = reqwest::Client::builder()
+        .user_agent("PinePods/1.0")
+        .build()
+        .map_err(AppError::Http)?;
+
+    let mut sent = 0;
+    for subscription in due {
+        let mode = subscription.state.expected_mode();
+        let callback = callback_url(base_url, &subscription.callback_token);
+        let result = send_hub_request(&client, &subscription, &callback, mode).await;
+
+        // New subscriptions wait for verification; renewals stay active meanwhile
+        let next_state = match (&result, subscription.state) {
+            (Ok(()), SubscriptionState::Discovered) => SubscriptionState::Pending,
+            (_, current) => current,
+        };
+        let error = result.err().map(|e| e.to_string());
+        match &error {
+            Some(e) => tracing::warn!("WebSub {} for podcast {} failed: {}", mode, subscription.podcast_id, e),
+            None => sent += 1,
+        }
+        state.db_pool.mark_websub_requested(subscription.podcast_id, next_state, error.as_deref()).await?;
+    }
+
+    Ok(sent)
+}
+
+/// Refresh one podcast because a hub or Podping said its feed changed
+pub async fn refresh_pushed_podcast(state: &AppState, podcast_id: i32) {
+    let result = crate::services::podcast::refresh_podcast(state, podcast_id).await;
+    if let Err(e) = &result {
+        tracing::warn!("Push-triggered refresh of podcast {} failed: {}", podcast_id, e);
+    }
+    if let Err(e) = state.db_pool.schedule_next_refresh(podcast_id, Some(result.is_ok())).await {
+        tracing::warn!("Failed to schedule next refresh for podcast {}: {}", podcast_id, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn states_round_trip() {
+        for state in [
+            SubscriptionState::Discovered,
+            SubscriptionState::Pending,
+            SubscriptionState::Active,
+            SubscriptionState::Denied,
+            SubscriptionState::Unsubscribing,
+        ] {
+            assert_eq!(SubscriptionState::parse(state.as_str()), Some(state));
+        }
+        assert_eq!(SubscriptionState::parse(" Active "), Some(SubscriptionState::Active));
+        assert_eq!(SubscriptionState::parse("expired"), None);
+        assert_eq!(SubscriptionState::Unsubscribing.expected_mode(), "unsubscribe");
+        assert_eq!(SubscriptionState::Pending.expected_mode(), "subscribe");
+    }
+
+    #[test]
+    fn hub_and_self_links_are_found_in_the_channel_header() {
+        let feed = r#"<rss><channel>
+            <atom:link href="https://example.com/feed.xml?a=1&amp;b=2" rel="self" type="application/rss+xml"/>
+            <atom:link rel='hub' href='https://pubsubhubbub.appspot.com/' />
+            <item><link rel="hub" href="https://other.example/hub"/></item>
+        </channel></rss>"#;
+        assert_eq!(
+            FeedHubLinks::discover(feed),
+            Some(FeedHubLinks {
+                hub_url: "https://pubsubhubbub.appspot.com/".to_string(),
+                topic_url: Some("https://example.com/feed.xml?a=1&b=2".to_string()),
+            })
+        );
+    }
+
+    #[test]
+    fn hubs_inside_items_or_with_bad_urls_are_ignored() {
+        let in_item = r#"<rss><channel><item><atom:link rel="hub" href="https://hub.example/"/></item></channel></rss>"#;
+        assert_eq!(FeedHubLinks::discover(in_item), None);
+
+        let not_http = r#"<channel><link rel="hub" href="javascript:alert(1)"/><link rel="hub" href="/relative"/></channel>"#;
+        assert_eq!(FeedHubLinks::discover(not_http), None);
+
+        let plain_link = r#"<channel><link>https://example.com/</link><link rel="HUB alternate" href="http://hub.example/"></channel>"#;
+        let links = FeedHubLinks::discover(plain_link).unwrap();
+        assert_eq!(links.hub_url, "http://hub.example/");
+        assert_eq!(links.topic_url, None);
+    }
+
+    #[test]
+    fn callback_urls_join_the_base_and_token() {
+        assert_eq!(callback_url("https://pods.example/", "abc"), "https://pods.example/api/websub/callback/abc");
+        assert_eq!(callback_url("https://pods.example", "abc"), "https://pods.example/api/websub/callback/abc");
+    }
+
+    #[test]
+    fn secrets_are_long_and_random() {
+        let secret = generate_secret();
+        assert_eq!(secret.len(), 48);
+        assert!(secret.chars().all(|c| c.is_ascii_alphanumeric()));
+        assert_ne!(secret, generate_secret());
+    }
+
+    #[test]
+    fn signatures_are_checked_against_the_body() {
+        let body = b"<rss>updated</rss>";
+        let signature = sign("s3cret", body);
+        assert!(verify_signature("s3cret", &format!("sha256={}", signature), body));
+        assert!(verify_signature("s3cret", &format!(" SHA256={} ", signature.to_uppercase()), body));
+        assert!(!verify_signature("other", &format!("sha256={}", signature), body));
+        assert!(!verify_signature("s3cret", &format!("sha256={}", signature), b"<rss>forged</rss>"));
+        assert!(!verify_signature("s3cret", &format!("sha1={}", signature), body));
+        assert!(!verify_signature("s3cret", &format!("md5={}", signature), body));
+        assert!(!verify_signature("s3cret", &signature, body));
+        assert!(!verify_signature("s3cret", "sha256=not-hex", body));
+        assert!(!verify_signature("s3cret", "", body));
+    }
+
+    #[test]
+    fn sha1_signatures_from_older_hubs_are_accepted() {
+        let body = b"payload";
+        let mut mac = Hmac::<Sha1>::new_from_slice(b"key").unwrap();
+        mac.update(body);
+        let signature = hex::encode(mac.finalize().into_bytes());
+        assert!(verify_signature("key", &format!("sha1={}", signature), body));
+    }
+
+    #[test]
+    fn podping_urls_are_deduplicated_and_filtered() {
+        let notification: PodpingNotification = serde_json::from_str(
+            r#"{"version":"1.0","medium":"podcast","reason":"update",
+                "iris":["https://a.example/feed", " https://a.example/feed ", "ipfs://xyz", "https://b.example/rss"]}"#,
+        )
+        .unwrap();
+        assert_eq!(notification.feed_urls(), vec!["https://a.example/feed", "https://b.example/rss"]);
+    }
+
+    #[test]
+    fn older_podping_payloads_use_urls() {
+        let notification: PodpingNotification =
+            serde_json::from_str(r#"{"version":"0.3","urls":["https://a.example/feed"]}"#).unwrap();
+        assert_eq!(notification.feed_urls(), vec!["https://a.example/feed"]);
+    }
+
+    #[test]
+    fn podpings_for_other_media_are_ignored() {
+        let notification: PodpingNotification =
+            serde_json::from_str(r#"{"medium":"blog","iris":["https://a.example/feed"]}"#).unwrap();
+        assert!(notification.feed_urls().is_empty());
+
+        let music: PodpingNotification =
+            serde_json::from_str(r#"{"medium":"MusicL","iris":["https://a.example/feed"]}"#).unwrap();
+        assert_eq!(music.feed_urls().len(), 1);
+    }
+
+    #[test]
+    fn podping_urls_are_capped() {
+        let notification = PodpingNotification {
+            medium: None,
+            iris: (0..MAX_PODPING_IRIS + 20).map(|i| format!("https://example.com/{}", i)).collect(),
+        };
+        assert_eq!(notification.feed_urls().len(), MAX_PODPING_IRIS);
+    }
+}
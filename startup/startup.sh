@@ -56,6 +56,72 @@ export OIDC_ROLES_CLAIM=${OIDC_ROLES_CLAIM}
 export OIDC_USER_ROLE=${OIDC_USER_ROLE}
 export OIDC_ADMIN_ROLE=${OIDC_ADMIN_ROLE}
 
+# Export LDAP / Active Directory login settings (checked when a local password doesn't match)
+export LDAP_URL=${LDAP_URL}
+export LDAP_STARTTLS=${LDAP_STARTTLS:-'false'}
+export LDAP_TLS_SKIP_VERIFY=${LDAP_TLS_SKIP_VERIFY:-'false'}
+export LDAP_USER_DN_TEMPLATE=${LDAP_USER_DN_TEMPLATE}
+export LDAP_BIND_DN=${LDAP_BIND_DN}
+export LDAP_BIND_PASSWORD=${LDAP_BIND_PASSWORD}
+export LDAP_SEARCH_BASE=${LDAP_SEARCH_BASE}
+export LDAP_USER_FILTER=${LDAP_USER_FILTER:-'(uid={username})'}
+export LDAP_USERNAME_ATTRIBUTE=${LDAP_USERNAME_ATTRIBUTE:-'uid'}
+export LDAP_EMAIL_ATTRIBUTE=${LDAP_EMAIL_ATTRIBUTE:-'mail'}
+export LDAP_NAME_ATTRIBUTE=${LDAP_NAME_ATTRIBUTE:-'cn'}
+export LDAP_GROUP_ATTRIBUTE=${LDAP_GROUP_ATTRIBUTE:-'memberOf'}
+export LDAP_GROUP_SEARCH_BASE=${LDAP_GROUP_SEARCH_BASE}
+export LDAP_USER_GROUP=${LDAP_USER_GROUP}
+export LDAP_ADMIN_GROUP=${LDAP_ADMIN_GROUP}
+export LDAP_LINK_EXISTING=${LDAP_LINK_EXISTING:-'false'}
+
+# Export trusted reverse-proxy login settings (Authelia, oauth2-proxy and similar gateways)
+export PROXY_AUTH_ENABLED=${PROXY_AUTH_ENABLED:-'false'}
+export PROXY_AUTH_TRUSTED_PROXIES=${PROXY_AUTH_TRUSTED_PROXIES}
+export PROXY_AUTH_USER_HEADER=${PROXY_AUTH_USER_HEADER:-'Remote-User'}
+export PROXY_AUTH_EMAIL_HEADER=${PROXY_AUTH_EMAIL_HEADER:-'Remote-Email'}
+export PROXY_AUTH_NAME_HEADER=${PROXY_AUTH_NAME_HEADER:-'Remote-Name'}
+export PROXY_AUTH_GROUPS_HEADER=${PROXY_AUTH_GROUPS_HEADER:-'Remote-Groups'}
+export PROXY_AUTH_USER_GROUP=${PROXY_AUTH_USER_GROUP}
+export PROXY_AUTH_ADMIN_GROUP=${PROXY_AUTH_ADMIN_GROUP}
+export PROXY_AUTH_AUTO_CREATE=${PROXY_AUTH_AUTO_CREATE:-'true'}
+export PROXY_AUTH_LINK_EXISTING=${PROXY_AUTH_LINK_EXISTING:-'false'}
+export PROXY_AUTH_REQUIRE_MFA=${PROXY_AUTH_REQUIRE_MFA:-'false'}
+
+# Export passkey (WebAuthn) settings; both default to the address in HOSTNAME
+export WEBAUTHN_RP_ID=${WEBAUTHN_RP_ID}
+export WEBAUTHN_ORIGINS=${WEBAUTHN_ORIGINS}
+
+# Export login session limits; 0 turns a limit off
+export SESSION_IDLE_TIMEOUT_HOURS=${SESSION_IDLE_TIMEOUT_HOURS:-'720'}
+export SESSION_MAX_AGE_DAYS=${SESSION_MAX_AGE_DAYS:-'90'}
+
+# Export push feed update settings (WebSub hubs and Podping relays)
+export WEBSUB_ENABLED=${WEBSUB_ENABLED:-'false'}
+export WEBSUB_CALLBACK_URL=${WEBSUB_CALLBACK_URL}
+export PODPING_TOKEN=${PODPING_TOKEN}
+
+# Export download storage settings (local directory or S3-compatible object store)
+export STORAGE_BACKEND=${STORAGE_BACKEND:-'local'}
+export DOWNLOAD_ROOT=${DOWNLOAD_ROOT:-'/opt/pinepods/downloads'}
+export DEFAULT_DOWNLOAD_QUOTA_MB=${DEFAULT_DOWNLOAD_QUOTA_MB}
+export S3_ENDPOINT=${S3_ENDPOINT}
+export S3_BUCKET=${S3_BUCKET}
+export S3_REGION=${S3_REGION:-'us-east-1'}
+export S3_ACCESS_KEY_ID=${S3_ACCESS_KEY_ID}
+export S3_SECRET_ACCESS_KEY=${S3_SECRET_ACCESS_KEY}
+export S3_PREFIX=${S3_PREFIX}
+export S3_PATH_STYLE=${S3_PATH_STYLE:-'true'}
+
+# Export download queue settings (parallel downloads and bandwidth cap)
+export DOWNLOAD_WORKERS=${DOWNLOAD_WORKERS:-'3'}
+export DOWNLOAD_HOST_CONCURRENCY=${DOWNLOAD_HOST_CONCURRENCY:-'2'}
+export DOWNLOAD_BANDWIDTH_LIMIT_KBPS=${DOWNLOAD_BANDWIDTH_LIMIT_KBPS}
+export DOWNLOAD_MAX_ATTEMPTS=${DOWNLOAD_MAX_ATTEMPTS:-'5'}
+export FFMPEG_PATH=${FFMPEG_PATH:-'ffmpeg'}
+
+# Export notification settings (days a feed must keep failing before its owner is notified)
+export FEED_BROKEN_NOTIFY_DAYS=${FEED_BROKEN_NOTIFY_DAYS:-'3'}
+
 # Print admin info if default admin is used
 if [[ $FULLNAME == 'Pinepods Admin' ]]; then
   echo "Admin User Information:"
@@ -123,7 +189,7 @@ export TZ
 echo "Creating required directories..."
 mkdir -p /pinepods/cache
 mkdir -p /opt/pinepods/backups
-mkdir -p /opt/pinepods/downloads
+mkdir -p "$DOWNLOAD_ROOT"
 mkdir -p /opt/pinepods/certs
 mkdir -p /var/log/pinepods  # Make sure log directory exists
 
@@ -163,7 +229,7 @@ fi
 # Only do this if PUID and PGID are set
 if [[ -n "$PUID" && -n "$PGID" ]]; then
     echo "Setting permissions for download and backup directories...(Be patient this might take a while if you have a lot of downloads)"
-    chown -R ${PUID}:${PGID} /opt/pinepods/downloads
+    chown -R ${PUID}:${PGID} "$DOWNLOAD_ROOT"
     chown -R ${PUID}:${PGID} /opt/pinepods/backups
 else
     echo "Skipping permission setting as PUID/PGID are not set"
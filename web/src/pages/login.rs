@@ -1947,6 +1947,10 @@ pub fn login() -> Html {
                                                                     let state = random_bytes.iter()
                                                                         .map(|b| format!("{:02x}", b))
                                                                         .collect::<String>();
+                                                                    crypto.get_random_values_with_u8_array(&mut random_bytes).unwrap();
+                                                                    let nonce = random_bytes.iter()
+                                                                        .map(|b| format!("{:02x}", b))
+                                                                        .collect::<String>();
 
                                                                     let auth_url_clone = auth_url.clone();
                                                                     let client_id_clone = client_id.clone();
@@ -1961,12 +1965,13 @@ pub fn login() -> Html {
                                                                             state_clone.clone(),
                                                                             client_id_clone.clone(),
                                                                             Some(origin.clone()),
+                                                                            Some(nonce.clone()),
                                                                         ).await {
                                                                             Ok(_) => {
                                                                                 let redirect_uri = format!("{}/api/auth/callback", origin);
                                                                                 let auth_url = format!(
-                                                                                    "{}?client_id={}&redirect_uri={}&scope={}&response_type=code&state={}",
-                                                                                    auth_url_clone, client_id_clone, redirect_uri, scope_clone, state_clone
+                                                                                    "{}?client_id={}&redirect_uri={}&scope={}&response_type=code&state={}&nonce={}",
+                                                                                    auth_url_clone, client_id_clone, redirect_uri, scope_clone, state_clone, nonce
                                                                                 );
                                                                                 window.location().set_href(&auth_url).unwrap();
                                                                             },
@@ -963,6 +963,7 @@ pub struct StoreStateRequest {
     pub state: String,
     pub client_id: String,
     pub origin_url: Option<String>,
+    pub nonce: Option<String>,
 }
 
 // Then create the function to make the request
@@ -972,12 +973,14 @@ pub async fn call_store_oidc_state(
     state: String,
     client_id: String,
     origin_url: Option<String>,
+    nonce: Option<String>,
 ) -> Result<(), Error> {
     let url = format!("{}/api/auth/store_state", server_name);
     let request_body = StoreStateRequest {
         state,
         client_id,
         origin_url,
+        nonce,
     };
 
     let response = Request::post(&url).json(&request_body)?.send().await?;